@@ -0,0 +1,59 @@
+// Copy and share buttons for the HTML UI (see soyjot-actix/src/http_resp.rs).
+// Buttons with data-copy="{id}" copy the text of element {id}, and stay hidden
+// in browsers without the clipboard API. Elements with data-share="{hash}" get a button
+// for each chat that clipboard {hash} can be shared to (see soyjot-actix/src/share.rs).
+"use strict";
+
+for (const button of document.querySelectorAll("button[data-copy]")) {
+  if (!navigator.clipboard) {
+    continue;
+  }
+
+  const label = button.textContent;
+  button.hidden = false;
+  button.addEventListener("click", async () => {
+    const el = document.getElementById(button.dataset.copy);
+
+    try {
+      await navigator.clipboard.writeText(el.textContent);
+      button.textContent = "Copied";
+    } catch (err) {
+      button.textContent = "Copy failed";
+    }
+
+    setTimeout(() => {
+      button.textContent = label;
+    }, 2000);
+  });
+}
+
+for (const el of document.querySelectorAll("[data-share]")) {
+  const url = `/api/drop/${el.dataset.share}/share`;
+
+  fetch(url)
+    .then((resp) => resp.json())
+    .then(({ targets }) => {
+      for (const target of targets) {
+        const button = document.createElement("button");
+        const label = `Share to ${target[0].toUpperCase()}${target.slice(1)}`;
+        button.type = "button";
+        button.textContent = label;
+
+        button.addEventListener("click", async () => {
+          // Signed links are shared with their signature
+          const query = new URLSearchParams(location.search);
+          query.set("target", target);
+
+          const resp = await fetch(`${url}?${query}`, { method: "POST" });
+          button.textContent = resp.ok ? "Shared" : "Share failed";
+          setTimeout(() => {
+            button.textContent = label;
+          }, 2000);
+        });
+
+        el.append(" ", button);
+      }
+    })
+    // Without the API scope or share config, there is nothing to share to
+    .catch(() => {});
+}
@@ -0,0 +1,118 @@
+// Client-side encryption for /app/secure (see soyjot-actix/src/secure.rs).
+// Text is encrypted with AES-256-GCM before it is posted, and only the ciphertext
+// and IV ever reach the server. The key lives in the link's fragment.
+"use strict";
+
+const CIPHER = "aes-256-gcm";
+const IV_BYTES = 12;
+
+const $ = (id) => document.getElementById(id);
+
+function toBase64Url(bytes) {
+  let bin = "";
+  for (const byte of bytes) {
+    bin += String.fromCharCode(byte);
+  }
+
+  return btoa(bin).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+function fromBase64Url(text) {
+  const bin = atob(text.replace(/-/g, "+").replace(/_/g, "/"));
+  return Uint8Array.from(bin, (c) => c.charCodeAt(0));
+}
+
+function showError(err) {
+  const el = $("secure-error");
+  el.textContent = `Error: ${err.message || err}`;
+  el.hidden = false;
+}
+
+// errorOf returns the error message of a failed API response
+async function errorOf(resp) {
+  try {
+    return (await resp.json()).error || resp.statusText;
+  } catch (_) {
+    return resp.statusText;
+  }
+}
+
+async function send() {
+  const text = $("secure-text").value;
+  if (!text) {
+    return;
+  }
+
+  const key = await crypto.subtle.generateKey({ name: "AES-GCM", length: 256 }, true, [
+    "encrypt",
+    "decrypt",
+  ]);
+  const iv = crypto.getRandomValues(new Uint8Array(IV_BYTES));
+  const plaintext = new TextEncoder().encode(text);
+  const ciphertext = await crypto.subtle.encrypt({ name: "AES-GCM", iv }, key, plaintext);
+
+  const query = new URLSearchParams({
+    encryption: `${CIPHER}:${toBase64Url(iv)}`,
+    content_type: "application/octet-stream",
+  });
+  const resp = await fetch(`/api/drop?${query}`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ mem: Array.from(new Uint8Array(ciphertext)) }),
+  });
+
+  if (!resp.ok) {
+    throw new Error(await errorOf(resp));
+  }
+
+  const posted = await resp.json();
+  const raw = new Uint8Array(await crypto.subtle.exportKey("raw", key));
+  const link = `${location.origin}/app/secure#${posted.clipboard}/${toBase64Url(raw)}`;
+
+  $("secure-link").href = link;
+  $("secure-link").textContent = link;
+  $("secure-owner").textContent = posted.owner_key || "none";
+  $("secure-new").hidden = true;
+  $("secure-result").hidden = false;
+}
+
+async function open(hash, keyText) {
+  const metaResp = await fetch(`/api/drop/${encodeURIComponent(hash)}/meta`);
+  if (!metaResp.ok) {
+    throw new Error(await errorOf(metaResp));
+  }
+
+  const meta = await metaResp.json();
+  const [cipher, iv] = (meta.encryption || "").split(":");
+  if (cipher !== CIPHER || !iv) {
+    throw new Error(`clipboard ${hash} is not encrypted with ${CIPHER}`);
+  }
+
+  // Fetching the ciphertext counts as a view
+  const resp = await fetch(`/api/drop/${encodeURIComponent(hash)}`);
+  if (!resp.ok) {
+    throw new Error(await errorOf(resp));
+  }
+
+  const ciphertext = await resp.arrayBuffer();
+  const key = await crypto.subtle.importKey("raw", fromBase64Url(keyText), "AES-GCM", false, [
+    "decrypt",
+  ]);
+  const plaintext = await crypto.subtle.decrypt(
+    { name: "AES-GCM", iv: fromBase64Url(iv) },
+    key,
+    ciphertext,
+  );
+
+  $("secure-clipboard").textContent = new TextDecoder().decode(plaintext);
+  $("secure-clipboard").hidden = false;
+}
+
+const [hash, key] = location.hash.slice(1).split("/");
+
+if (hash && key) {
+  $("secure-new").hidden = true;
+  open(hash, key).catch(showError);
+} else {
+  $("secure-send").addEventListener("click", () => send().catch(showError));
+}
@@ -0,0 +1,349 @@
+//! Admin dashboard at `/app/admin`, listing live clipboards with buttons to delete them,
+//! and how much memory and disk they use.
+//!
+//! The dashboard is only mounted with `Scope::App`, and responds with 404 Not Found unless
+//! `AppConfig::admin_token` is set. Requests must carry the token either as a bearer token,
+//! or as the password of HTTP basic auth, so that browsers prompt for it.
+
+use std::time::Duration;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::Engine;
+
+use soyjot::config::AppConfig;
+use soyjot::html::{self, wrap_html};
+use soyjot::store::index::IndexEntry;
+use soyjot::store::{owner, Store};
+
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+/// Path of the dashboard, which deleted clipboards redirect back to
+const PATH: &str = "/app/admin";
+
+const REALM: &str = r#"Basic realm="actix-drop admin""#;
+
+/// routes returns the dashboard scope, which must be mounted before the `/app` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope(PATH)
+        .route("", web::get().to(dashboard))
+        .route("/drop/{id}/delete", web::post().to(delete_clipboard))
+}
+
+async fn dashboard(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    if let Some(resp) = unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    let mut entries = store.index();
+    entries.sort_by_key(|entry| entry.expires_at);
+
+    let usage = Usage {
+        mem_bytes: store.mem_bytes(),
+        disk_bytes: store.disk_bytes(),
+        max_disk_bytes: conf.load().max_disk_bytes,
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(wrap_html(&dashboard_html(&entries, &usage)))
+}
+
+/// delete_clipboard removes a clipboard, and redirects back to the dashboard.
+/// Browsers resend basic auth credentials with cross-site forms too,
+/// so requests from other origins are rejected.
+async fn delete_clipboard(
+    req: HttpRequest,
+    path: web::Path<String>,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    if let Some(resp) = unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    if !same_origin(&req) {
+        return HttpResponse::Forbidden()
+            .content_type("text/html")
+            .body(wrap_html("<p>Cross-origin requests are not allowed</p>"));
+    }
+
+    let hash = path.into_inner();
+
+    match store.remove_clipboard(&hash).await {
+        Ok(_) => HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, PATH))
+            .finish(),
+
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type("text/html")
+            .body(wrap_html(&format!(
+                "<p>Error deleting clipboard <code>{}</code>: {}</p>",
+                html::escape(&hash),
+                html::escape(&err.to_string()),
+            ))),
+    }
+}
+
+/// unauthorized checks the admin token of `req`, and returns the response to send instead
+/// if it's missing or wrong. If no admin token is configured, the dashboard does not exist.
+fn unauthorized(req: &HttpRequest, conf: &SharedConfig) -> Option<HttpResponse> {
+    let Some(token) = conf.load().admin_token.clone() else {
+        return Some(HttpResponse::NotFound().finish());
+    };
+
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(credentials);
+
+    match given {
+        Some(given) if tokens_eq(given.as_bytes(), token.as_bytes()) => None,
+        _ => Some(
+            HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, REALM))
+                .content_type("text/html")
+                .body(wrap_html("<p>Admin token required</p>")),
+        ),
+    }
+}
+
+/// is_admin reports whether `req` carries the admin token, as a bearer token or basic auth password
+pub(crate) fn is_admin(req: &HttpRequest, conf: &AppConfig) -> bool {
+    conf.admin_token.as_ref().is_some_and(|token| {
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(credentials)
+            .is_some_and(|given| tokens_eq(given.as_bytes(), token.as_bytes()))
+    })
+}
+
+/// owner_or_admin returns whose clipboards `req` may list or search: `Some(None)` for requests
+/// with the admin token, or the digest of the owner key it sent (see `http_server::owner_key`).
+/// Requests with neither get `None`.
+pub(crate) fn owner_or_admin(req: &HttpRequest, conf: &SharedConfig) -> Option<Option<String>> {
+    match is_admin(req, &conf.load()) {
+        true => Some(None),
+        false => http_server::owner_key(req).map(|key| Some(owner::digest(&key))),
+    }
+}
+
+/// credentials returns the token of a bearer `Authorization` header,
+/// or the password of a basic one. Basic auth usernames are ignored.
+pub(crate) fn credentials(authorization: &str) -> Option<String> {
+    let (scheme, value) = authorization.split_once(' ')?;
+
+    match scheme.to_ascii_lowercase().as_str() {
+        "bearer" => Some(value.trim().to_string()),
+        "basic" => {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .ok()?;
+
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (_, password) = decoded.split_once(':')?;
+
+            Some(password.to_string())
+        }
+
+        _ => None,
+    }
+}
+
+/// tokens_eq compares tokens in time independent of where they differ
+pub(crate) fn tokens_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// same_origin reports whether `req` has no `Origin` header, e.g. from scripts,
+/// or one that matches its host.
+fn same_origin(req: &HttpRequest) -> bool {
+    let Some(origin) = req.headers().get(header::ORIGIN) else {
+        return true;
+    };
+
+    let origin = origin.to_str().unwrap_or_default();
+    let host = origin.split_once("://").map(|(_, host)| host);
+
+    host == Some(req.connection_info().host())
+}
+
+/// Usage is the storage used by live clipboards, as shown on the dashboard
+struct Usage {
+    mem_bytes: u64,
+    disk_bytes: u64,
+    max_disk_bytes: Option<u64>,
+}
+
+fn dashboard_html(entries: &[IndexEntry], usage: &Usage) -> String {
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let views = match entry.max_views {
+                Some(max) => format!("{} / {max}", entry.views),
+                None => entry.views.to_string(),
+            };
+
+            format!(
+                r#"<tr>
+                <td><a href="/app/drop/{0}"><code>{0}</code></a></td>
+                <td>{1}</td><td>{2}</td><td>{3}</td><td>{views}</td>
+                <td><form action="{PATH}/drop/{0}/delete" method="post"><button type="submit">Delete</button></form></td>
+                </tr>"#,
+                entry.hash,
+                entry.size,
+                entry.storage,
+                match entry.pinned {
+                    true => "pinned".to_string(),
+                    false => format_ttl(entry.remaining()),
+                },
+            )
+        })
+        .collect::<String>();
+
+    let disk = match usage.max_disk_bytes {
+        Some(max) => format!("{} / {max}", usage.disk_bytes),
+        None => usage.disk_bytes.to_string(),
+    };
+
+    format!(
+        r#"<p>Live clipboards: {}</p>
+        <p>Memory bytes: {}</p>
+        <p>Disk bytes: {disk}</p>
+        <table>
+        <thead><tr><th>Clipboard</th><th>Bytes</th><th>Storage</th><th>TTL</th><th>Views</th><th></th></tr></thead>
+        <tbody>{rows}</tbody>
+        </table>"#,
+        entries.len(),
+        usage.mem_bytes,
+    )
+}
+
+/// format_ttl formats the time left before a clipboard expires, e.g. `1h 2m 3s`
+pub(crate) fn format_ttl(remaining: Option<Duration>) -> String {
+    let Some(remaining) = remaining else {
+        return "expiring".to_string();
+    };
+
+    let secs = remaining.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    match (h, m) {
+        (0, 0) => format!("{s}s"),
+        (0, _) => format!("{m}m {s}s"),
+        _ => format!("{h}h {m}m {s}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+    use base64::Engine;
+
+    use soyjot::config::AppConfig;
+    use soyjot::store::clipboard::Clipboard;
+    use soyjot::store::{Store, StoreOpts};
+
+    use crate::reload;
+
+    #[actix_web::test]
+    async fn test_admin() {
+        let store = web::Data::new(Store::new());
+        Store::store_new_clipboard(
+            store.clone().into_inner(),
+            "adm1",
+            "adm1",
+            Clipboard::Mem("foo".into()),
+            Duration::from_secs(90),
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        let app = |admin_token: Option<&str>| {
+            let conf = reload::shared(AppConfig {
+                admin_token: admin_token.map(String::from),
+                ..AppConfig::default()
+            });
+
+            test::init_service(
+                App::new()
+                    .app_data(conf)
+                    .app_data(store.clone())
+                    .service(super::routes()),
+            )
+        };
+
+        // Without a token, there is no dashboard
+        let disabled = app(None).await;
+        let req = test::TestRequest::get().uri("/app/admin").to_request();
+        let resp = test::call_service(&disabled, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let app = app(Some("s3cret")).await;
+        for auth in [None, Some("Bearer wrong")] {
+            let mut req = test::TestRequest::get().uri("/app/admin");
+            if let Some(auth) = auth {
+                req = req.insert_header((header::AUTHORIZATION, auth));
+            }
+
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+        }
+
+        // Browsers send the token as the basic auth password
+        let basic = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode("admin:s3cret")
+        );
+        let req = test::TestRequest::get()
+            .uri("/app/admin")
+            .insert_header((header::AUTHORIZATION, basic.as_str()))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Live clipboards: 1"), "{body}");
+        assert!(body.contains("Memory bytes: 3"), "{body}");
+        assert!(body.contains("Disk bytes: 0<"), "{body}");
+        assert!(body.contains("<code>adm1</code>"));
+        assert!(body.contains("<td>3</td><td>mem</td><td>1m "), "{body}");
+
+        // Deleting from another origin is forbidden
+        let req = test::TestRequest::post()
+            .uri("/app/admin/drop/adm1/delete")
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(store.meta("adm1").is_some());
+
+        let req = test::TestRequest::post()
+            .uri("/app/admin/drop/adm1/delete")
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(header::LOCATION).unwrap(), "/app/admin");
+        assert!(store.meta("adm1").is_none());
+
+        assert_eq!(super::format_ttl(None), "expiring");
+        assert_eq!(super::format_ttl(Some(Duration::from_secs(5))), "5s");
+        assert_eq!(super::format_ttl(Some(Duration::from_secs(65))), "1m 5s");
+        assert_eq!(
+            super::format_ttl(Some(Duration::from_secs(3723))),
+            "1h 2m 3s"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Export and import of all live clipboards, to migrate them to another instance
+//! or to back them up before an upgrade.
+//!
+//! `GET /api/admin/export` responds with an `Archive` of every live clipboard, with its
+//! content and metadata (see `IndexEntry`), and `POST /api/admin/import` stores the clipboards
+//! of one, keeping their expiry, owner and remaining views. Clipboards already on the instance
+//! and those that expired since the export are skipped. Both need the admin token,
+//! and respond with 404 Not Found unless `AppConfig::admin_token` is set.
+
+use std::sync::Arc;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::error::StoreError;
+use soyjot::store::index::{self, IndexEntry};
+use soyjot::store::Store;
+
+use crate::admin;
+use crate::http_resp::{ErrorResponse, ResponseJson};
+use crate::http_server;
+use crate::middleware;
+use crate::reload::SharedConfig;
+use crate::replication;
+
+type R = ResponseJson;
+
+/// Version of the archive format, which imports must match
+const VERSION: u32 = 1;
+
+/// Largest archive accepted by imports in bytes, with the contents base64-encoded
+const MAX_ARCHIVE_SIZE: usize = 1 << 30;
+
+/// Archive is the JSON body of exports and imports
+#[derive(Serialize, Deserialize, Debug)]
+struct Archive {
+    version: u32,
+    /// Export time in RFC 3339
+    exported_at: String,
+    clipboards: Vec<Archived>,
+}
+
+/// Archived is a clipboard in an `Archive`
+#[derive(Serialize, Deserialize, Debug)]
+struct Archived {
+    entry: IndexEntry,
+    /// Clipboard content, base64-encoded
+    content: String,
+}
+
+/// routes returns the export and import routes, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope("/api/admin")
+        .app_data(web::JsonConfig::default().limit(MAX_ARCHIVE_SIZE))
+        .route("/export", web::get().to(export))
+        .route("/import", web::post().to(import))
+}
+
+/// unauthorized checks that `req` carries the admin token, and returns the response
+/// to send instead if not
+pub(crate) fn unauthorized(req: &HttpRequest, conf: &SharedConfig) -> Option<HttpResponse> {
+    let conf = conf.load();
+    if conf.admin_token.is_none() {
+        return Some(HttpResponse::NotFound().json(json!({ "error": "admin API disabled" })));
+    }
+
+    match admin::is_admin(req, &conf) {
+        true => None,
+        false => Some(http_server::unauthorized::<R>("admin token required")),
+    }
+}
+
+/// export responds with an archive of the live clipboards, as a JSON download.
+/// Reading clipboards for the export does not count views.
+async fn export(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    if let Some(resp) = unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    let exported_at = index::to_rfc3339(index::to_timestamp(std::time::SystemTime::now()));
+
+    let mut entries = store.index();
+    entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+    let mut clipboards = Vec::with_capacity(entries.len());
+    for entry in entries {
+        // Gone since the index was read
+        let Some(clipboard) = store.export_clipboard(&entry.hash).await else {
+            continue;
+        };
+
+        clipboards.push(Archived {
+            entry,
+            content: STANDARD.encode(&*clipboard),
+        });
+    }
+
+    let archive = Archive {
+        version: VERSION,
+        exported_at,
+        clipboards,
+    };
+
+    let read = archive
+        .clipboards
+        .iter()
+        .map(|archived| archived.entry.hash.clone())
+        .collect();
+
+    let mut resp = HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            r#"attachment; filename="drops-export.json""#,
+        ))
+        .json(archive);
+
+    resp.extensions_mut().insert(middleware::Read(read));
+    resp
+}
+
+/// import stores the clipboards of an archive, and responds with how many were imported
+/// and skipped, and the errors of those that failed
+async fn import(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    web::Json(archive): web::Json<Archive>,
+) -> HttpResponse {
+    if let Some(resp) = unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    if archive.version != VERSION {
+        let err = StoreError::InvalidArchive(format!("unsupported version {}", archive.version));
+        return http_server::store_error::<R>("", err);
+    }
+
+    let store = store.into_inner();
+    let (mut imported, mut skipped, mut errors) = (0, 0, Vec::new());
+    for archived in archive.clipboards {
+        let hash = archived.entry.hash.clone();
+
+        match import_clipboard(store.clone(), archived).await {
+            Ok(true) => imported += 1,
+            Ok(false) => skipped += 1,
+            Err(err) => errors.push(json!(ErrorResponse::new(&hash, err))),
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "imported": imported,
+        "skipped": skipped,
+        "errors": errors,
+    }))
+}
+
+/// import_clipboard stores an archived clipboard as it was exported, and reports whether
+/// it was stored. Clipboards already in `store` and expired ones are skipped.
+async fn import_clipboard(store: Arc<Store>, archived: Archived) -> Result<bool, StoreError> {
+    let Archived { mut entry, content } = archived;
+
+    // Keys end up in file paths
+    if !replication::valid_key(&entry.hash) {
+        return Err(StoreError::InvalidArchive("bad clipboard key".to_string()));
+    }
+
+    if store.export_meta(&entry.hash).is_some() {
+        return Ok(false);
+    }
+
+    let (Some(digest), Ok(content)) = (entry.digest.clone(), STANDARD.decode(content)) else {
+        return Err(StoreError::InvalidArchive(
+            "bad clipboard digest or content".to_string(),
+        ));
+    };
+
+    // Views are counted anew, so the clipboard only has the views it had left
+    entry.max_views = match entry.max_views {
+        Some(max) if entry.views >= max => return Ok(false),
+        max => max.map(|max| max - entry.views),
+    };
+
+    let hash = entry.hash.clone();
+    let clipboard = store.place(Clipboard::new_with_data(&entry.storage, content));
+    let Some((dur, opts)) = replication::stored_as(entry) else {
+        return Ok(false);
+    };
+
+    Store::store_new_clipboard(store, &hash, &digest, clipboard, dur, opts).await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::store::clipboard::Clipboard;
+    use soyjot::store::{index, Store, StoreOpts};
+
+    use crate::reload;
+
+    #[actix_web::test]
+    async fn test_export_import() {
+        let app = |store: &web::Data<Store>| {
+            let conf = reload::shared(AppConfig {
+                admin_token: Some("s3cret".to_string()),
+                ..AppConfig::default()
+            });
+
+            test::init_service(
+                App::new()
+                    .app_data(conf)
+                    .app_data(store.clone())
+                    .service(super::routes()),
+            )
+        };
+
+        let source = web::Data::new(Store::new());
+        let at = index::to_timestamp(SystemTime::now()) + 60;
+        for (hash, data, max_views, available_at) in [
+            ("exp1", "foo", None, None),
+            ("exp2", "bar", Some(3), None),
+            ("exp3", "held", None, Some(at)),
+        ] {
+            let opts = StoreOpts {
+                max_views,
+                available_at,
+                content_type: Some("text/plain".to_string()),
+                ..StoreOpts::default()
+            };
+
+            Store::store_new_clipboard(
+                source.clone().into_inner(),
+                hash,
+                &format!("{hash}00"),
+                Clipboard::new_with_data("mem", data),
+                Duration::from_secs(90),
+                opts,
+            )
+            .await
+            .unwrap();
+        }
+        source.get_clipboard("exp2").await.unwrap();
+
+        let export = app(&source).await;
+        let req = test::TestRequest::get()
+            .uri("/api/admin/export")
+            .to_request();
+        let resp = test::call_service(&export, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/admin/export")
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .to_request();
+        let archive: serde_json::Value = test::call_and_read_body_json(&export, req).await;
+        assert_eq!(archive["version"], 1);
+        assert_eq!(archive["clipboards"].as_array().unwrap().len(), 3);
+        assert_eq!(archive["clipboards"][0]["entry"]["hash"], "exp1");
+        assert_eq!(archive["clipboards"][0]["content"], "Zm9v");
+
+        // Exports do not count views
+        assert_eq!(source.meta("exp1").unwrap().views, 0);
+
+        let target = web::Data::new(Store::new());
+        Store::store_new_clipboard(
+            target.clone().into_inner(),
+            "exp1",
+            "exp100",
+            Clipboard::new_with_data("mem", "kept"),
+            Duration::from_secs(90),
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        let import = app(&target).await;
+        let req = test::TestRequest::post()
+            .uri("/api/admin/import")
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .set_json(&archive)
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&import, req).await;
+        assert_eq!(body["imported"], 2);
+        assert_eq!(body["skipped"], 1);
+        assert_eq!(body["errors"].as_array().unwrap().len(), 0);
+
+        // Clipboards on the instance are kept
+        assert_eq!(&*target.peek_clipboard("exp1").await.unwrap(), b"kept");
+
+        let imported = target.meta("exp2").unwrap();
+        let exported = source.meta("exp2").unwrap();
+        assert_eq!(imported.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(imported.owner, exported.owner);
+        assert_eq!(imported.expires_at, exported.expires_at);
+        assert_eq!(imported.max_views, Some(2));
+        assert_eq!(&*target.peek_clipboard("exp2").await.unwrap(), b"bar");
+
+        // Held clipboards are exported and imported held
+        assert!(target.meta("exp3").is_none());
+        assert_eq!(target.export_meta("exp3").unwrap().available_at, Some(at));
+
+        let mut archive = archive;
+        archive["version"] = 2.into();
+        let req = test::TestRequest::post()
+            .uri("/api/admin/import")
+            .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+            .set_json(&archive)
+            .to_request();
+        let resp = test::call_service(&import, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,135 @@
+//! Static files of the HTML UI, e.g. its stylesheet and scripts, which are compiled into the binary.
+//!
+//! Every file in `ASSETS` is served at its path with `Scope::App` (see `register`),
+//! with a strong `ETag` of its SHA-256 digest, so that browsers revalidate cached copies
+//! with `If-None-Match`, and with `Cache-Control` so they only do so after `MAX_AGE`.
+
+use actix_web::http::header;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+
+use soyjot::hash::{HashAlgo, Hasher};
+
+/// Stylesheet of the HTML UI
+pub const CSS: &[u8] = include_bytes!("../../assets/style.css");
+
+/// Script of the HTML UI, e.g. for copy buttons
+pub const SCRIPT: &[u8] = include_bytes!("../../assets/script.js");
+
+const FAVICON: &[u8] = include_bytes!("../../assets/favicon.svg");
+
+const LOGO: &[u8] = include_bytes!("../../assets/logo.svg");
+
+/// Seconds browsers may use a cached asset before revalidating it.
+/// Assets only change with new releases, so this is kept short enough for upgrades to show.
+const MAX_AGE: u32 = 24 * 60 * 60;
+
+/// Asset is a static file served at `path`
+pub struct Asset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub body: &'static [u8],
+}
+
+/// Static files of the HTML UI
+pub const ASSETS: &[Asset] = &[
+    Asset {
+        path: "/style.css",
+        content_type: "text/css",
+        body: CSS,
+    },
+    Asset {
+        path: "/script.js",
+        content_type: "text/javascript",
+        body: SCRIPT,
+    },
+    Asset {
+        path: "/favicon.svg",
+        content_type: "image/svg+xml",
+        body: FAVICON,
+    },
+    Asset {
+        path: "/logo.svg",
+        content_type: "image/svg+xml",
+        body: LOGO,
+    },
+];
+
+/// register registers a route for every asset in `ASSETS`, e.g. with `App::configure`
+pub fn register(cfg: &mut web::ServiceConfig) {
+    for asset in ASSETS {
+        let mut hasher = Hasher::new(HashAlgo::Sha256);
+        hasher.update(asset.body);
+        let etag = header::EntityTag::new_strong(hasher.finalize());
+
+        cfg.route(
+            asset.path,
+            web::get().to(move |req: HttpRequest| {
+                let etag = etag.clone();
+                async move { serve(&req, asset, etag) }
+            }),
+        );
+    }
+}
+
+/// serve sends `asset`, or 304 Not Modified if the client's `If-None-Match` already has `etag`
+fn serve(req: &HttpRequest, asset: &Asset, etag: header::EntityTag) -> HttpResponse {
+    let cached = match req.get_header::<header::IfNoneMatch>() {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+
+    let mut resp = match cached {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+
+    resp.insert_header(header::ETag(etag))
+        .insert_header(header::CacheControl(vec![
+            header::CacheDirective::Public,
+            header::CacheDirective::MaxAge(MAX_AGE),
+        ]));
+
+    match cached {
+        true => resp.finish(),
+        false => resp.content_type(asset.content_type).body(asset.body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+
+    use super::ASSETS;
+
+    #[actix_web::test]
+    async fn test_assets() {
+        let app = test::init_service(App::new().configure(super::register)).await;
+
+        for asset in ASSETS {
+            let req = test::TestRequest::get().uri(asset.path).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{}", asset.path);
+
+            let headers = resp.headers();
+            assert_eq!(
+                headers.get(header::CONTENT_TYPE).unwrap(),
+                asset.content_type
+            );
+            assert_eq!(
+                headers.get(header::CACHE_CONTROL).unwrap(),
+                "public, max-age=86400"
+            );
+            let etag = headers.get(header::ETAG).unwrap().clone();
+            assert_eq!(test::read_body(resp).await, asset.body);
+
+            let req = test::TestRequest::get()
+                .uri(asset.path)
+                .insert_header((header::IF_NONE_MATCH, etag))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        }
+    }
+}
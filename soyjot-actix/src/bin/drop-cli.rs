@@ -0,0 +1,186 @@
+//! drop-cli is a command-line client for actix-drop.
+//!
+//! ```text
+//! drop-cli [--persist]   read a clipboard from stdin, post it, and print its URL
+//! drop-cli get <hash>    print clipboard <hash> to stdout
+//! ```
+//!
+//! The server is read from `server_url` (or `http_addr` and `http_port`)
+//! in the same config files and `DROP_` envs used by the server,
+//! which can be overridden with the same flags, e.g. `--config` or `--port`.
+
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use colored::Colorize;
+use serde_json::json;
+
+use soyjot::config::{AppConfig, ConfigArgs};
+use soyjot::store::clipboard::{MEM, PERSIST};
+
+// Largest clipboard drop-cli accepts from the server
+const BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Command-line client for actix-drop. Without a subcommand, stdin is posted as a clipboard.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Post the clipboard to persisted storage instead of memory
+    #[arg(long)]
+    persist: bool,
+
+    #[command(subcommand)]
+    get: Option<Get>,
+
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+#[derive(Subcommand)]
+enum Get {
+    /// Print clipboard <hash> to stdout
+    Get { hash: String },
+}
+
+enum Command {
+    Post { store: &'static str },
+    Get { hash: String },
+}
+
+fn parse_args<I, T>(args: I) -> Result<(Command, ConfigArgs), clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let cli = Cli::try_parse_from(args)?;
+
+    let cmd = match (cli.get, cli.persist) {
+        (None, false) => Command::Post { store: MEM },
+        (None, true) => Command::Post { store: PERSIST },
+        (Some(Get::Get { hash }), false) => Command::Get { hash },
+        (Some(_), true) => {
+            return Err(clap::Error::raw(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--persist cannot be used with get\n",
+            ))
+        }
+    };
+
+    Ok((cmd, cli.config))
+}
+
+#[actix_web::main]
+async fn main() -> ExitCode {
+    let (cmd, args) = match parse_args(std::env::args_os()) {
+        Ok(parsed) => parsed,
+        Err(err) => err.exit(),
+    };
+
+    let server = AppConfig::init_with(&args).server_url();
+    let result = match cmd {
+        Command::Post { store } => post(&server, store).await,
+        Command::Get { hash } => get(&server, &hash).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{} {err}", "drop-cli:".red());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// post posts stdin to `{server}/api/drop` and prints the clipboard's URL
+async fn post(server: &str, store: &str) -> Result<(), String> {
+    let mut data = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut data)
+        .map_err(|err| format!("failed to read stdin: {err}"))?;
+
+    // Clipboard data can be either a string or an array of bytes
+    let body = match String::from_utf8(data) {
+        Ok(text) => json!({ store: text }),
+        Err(err) => json!({ store: err.into_bytes() }),
+    };
+
+    let mut resp = awc::Client::default()
+        .post(format!("{server}/api/drop"))
+        .send_json(&body)
+        .await
+        .map_err(|err| format!("failed to post clipboard: {err}"))?;
+
+    let resp: serde_json::Value = resp
+        .json()
+        .await
+        .map_err(|err| format!("bad response from server: {err}"))?;
+
+    if let Some(err) = resp["error"].as_str() {
+        return Err(format!("server error: {err}"));
+    }
+
+    let hash = resp["clipboard"]
+        .as_str()
+        .ok_or_else(|| format!("no clipboard hash in response: {resp}"))?;
+
+    println!("{server}/app/drop/{hash}");
+
+    Ok(())
+}
+
+/// get writes clipboard `hash` from `{server}/raw/drop/{hash}` to stdout
+async fn get(server: &str, hash: &str) -> Result<(), String> {
+    let mut resp = awc::Client::default()
+        .get(format!("{server}/raw/drop/{hash}"))
+        .send()
+        .await
+        .map_err(|err| format!("failed to get clipboard: {err}"))?;
+
+    let body = resp
+        .body()
+        .limit(BODY_LIMIT)
+        .await
+        .map_err(|err| format!("failed to read clipboard: {err}"))?;
+
+    if !resp.status().is_success() {
+        return Err(format!(
+            "server returned {}: {}",
+            resp.status(),
+            String::from_utf8_lossy(&body).trim_end(),
+        ));
+    }
+
+    std::io::stdout()
+        .write_all(&body)
+        .map_err(|err| format!("failed to write clipboard: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Command};
+
+    fn parse(args: &[&str]) -> Option<Command> {
+        let args = std::iter::once("drop-cli").chain(args.iter().copied());
+        parse_args(args).ok().map(|(cmd, _)| cmd)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert!(matches!(parse(&[]), Some(Command::Post { store: "mem" })));
+        assert!(matches!(
+            parse(&["--persist"]),
+            Some(Command::Post { store: "persist" })
+        ));
+        assert!(matches!(parse(&["get", "abcd"]), Some(Command::Get { hash }) if hash == "abcd"));
+
+        assert!(parse(&["get"]).is_none());
+        assert!(parse(&["get", "abcd", "efgh"]).is_none());
+        assert!(parse(&["--foo"]).is_none());
+        assert!(parse(&["--persist", "get", "abcd"]).is_none());
+
+        let args = ["drop-cli", "--port", "9090", "get", "abcd"];
+        let (_, conf) = parse_args(args).unwrap();
+        assert_eq!(conf.port, Some(9090));
+    }
+}
@@ -0,0 +1,264 @@
+//! Bundles of clipboards: `POST /api/bundle` stores a new clipboard that is
+//! the concatenation of existing ones, e.g. to merge partial pastes into one to share.
+//!
+//! The JSON body lists the IDs of the clipboards in order, and the separator put
+//! between them, e.g. `{"ids": ["a1b2", "c3d4"], "separator": "\n---\n"}`.
+//! The bundle is stored like a clipboard posted to `/api/drop` with the same query,
+//! and reading each of its parts counts a view. Encrypted clipboards, and ones limited
+//! to a number of views, cannot be bundled, and like batch fetches in `drops`, bundles are refused with `AppConfig::require_signed_links`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::filters::Filters;
+use soyjot::hash::HashConfig;
+use soyjot::store::clipboard::{self, Clipboard};
+use soyjot::store::error::StoreError;
+use soyjot::store::{index, Store};
+use soyjot::tenant;
+
+use crate::admin;
+use crate::drops::MAX_BATCH;
+use crate::http_resp::ResponseJson;
+use crate::http_server::{self, PostQuery};
+use crate::middleware;
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+/// Largest bundle, in bytes, unless an upload token allows less
+pub const MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Separator put between clipboards, unless the bundle is posted with one
+const SEPARATOR: &str = "\n";
+
+/// BundleRequest is the JSON body of `POST /api/bundle`
+#[derive(Deserialize, Debug)]
+struct BundleRequest {
+    /// IDs of the clipboards to bundle, in order, up to `MAX_BATCH`
+    ids: Vec<String>,
+    separator: Option<String>,
+}
+
+/// routes returns the bundle route, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Resource {
+    web::resource("/api/bundle").route(web::post().to(add_bundle))
+}
+
+/// add_bundle stores the clipboards of `bundle` joined by its separator as a new clipboard,
+/// and responds like a batch upload of one clipboard to `/api/drops` would
+async fn add_bundle(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    hashing: web::Data<HashConfig>,
+    filters: Option<web::Data<Filters>>,
+    query: web::Query<PostQuery>,
+    req: HttpRequest,
+    web::Json(bundle): web::Json<BundleRequest>,
+) -> HttpResponse {
+    let conf = conf.load();
+    if conf.require_signed_links == Some(true) {
+        return http_server::send_error::<R>("", StoreError::InvalidSignature);
+    }
+
+    if bundle.ids.is_empty() {
+        return http_server::store_error::<R>("", StoreError::Empty);
+    }
+    if bundle.ids.len() > MAX_BATCH {
+        return http_server::store_error::<R>("", StoreError::TooMany(MAX_BATCH));
+    }
+
+    let opts = match http_server::post_opts(query, &req, admin::is_admin(&req, &conf)) {
+        Ok(opts) => opts,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let max_size = opts.max_size.map_or(MAX_SIZE, |max| max.min(MAX_SIZE));
+    let separator = bundle.separator.as_deref().unwrap_or(SEPARATOR);
+    let data = match concat(&store, &bundle.ids, separator.as_bytes(), max_size).await {
+        Ok(data) => data,
+        Err((id, err)) => return http_server::send_error::<R>(&id, err),
+    };
+
+    let clipboard = Clipboard::new_with_data(clipboard::MEM, data);
+    let clipboard = match http_server::filter_chain(filters).apply_clipboard(clipboard) {
+        Ok(clipboard) => clipboard,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let digest = hashing.digest(&clipboard);
+    let hash = http_server::new_key(&store, &hashing, &digest, None);
+    let clipboard = store.place(clipboard);
+    let store = store.into_inner();
+
+    let stored = http_server::store_clipboard(
+        store.clone(),
+        &hash,
+        &digest,
+        clipboard,
+        conf.timeout_duration(),
+        opts,
+    )
+    .await;
+
+    match stored {
+        Ok((hash, storage, owner_key)) => {
+            let short = store.shortest_prefix(&hash);
+            let mut resp = HttpResponse::Ok().json(json!({
+                "clipboard": hash,
+                "storage": storage,
+                "short": short,
+                "url": format!("/api/drop/{hash}"),
+                "short_url": format!("/api/d/{short}"),
+                "owner_key": owner_key,
+                "expires_at": store.expires_at(&hash).map(index::to_rfc3339),
+            }));
+
+            resp.extensions_mut().insert(middleware::Read(bundle.ids));
+            http_server::created(resp, &hash, owner_key.as_deref())
+        }
+
+        Err(err) => http_server::store_error::<R>(&hash, err),
+    }
+}
+
+/// concat reads the clipboards `ids` and joins them with `separator`, up to `max_size` bytes,
+/// or returns the error of the first clipboard that cannot be bundled, with its ID.
+/// All clipboards are checked before any is read, so refused bundles count no views.
+async fn concat(
+    store: &Store,
+    ids: &[String],
+    separator: &[u8],
+    max_size: u64,
+) -> Result<Vec<u8>, (String, StoreError)> {
+    let too_large = || (String::new(), StoreError::TooLarge(max_size));
+
+    let mut size = 0;
+    for (i, id) in ids.iter().enumerate() {
+        if !tenant::is_global(id) {
+            return Err((id.clone(), StoreError::NoSuch));
+        }
+        let Some(meta) = store.meta(id) else {
+            return Err((id.clone(), store.not_found(id)));
+        };
+
+        // Bundles would read them without counting views of the bundle
+        let refused = if meta.encryption.is_some() {
+            Some("is encrypted")
+        } else if store.is_view_limited(id) {
+            Some("is limited to a number of views")
+        } else {
+            None
+        };
+        if let Some(reason) = refused {
+            let err = StoreError::InvalidRequest(format!("clipboard {id} {reason}"));
+            return Err((id.clone(), err));
+        }
+
+        size += meta.size + if i > 0 { separator.len() as u64 } else { 0 };
+        if size > max_size {
+            return Err(too_large());
+        }
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    for (i, id) in ids.iter().enumerate() {
+        let Some(clipboard) = store.get_clipboard(id).await else {
+            return Err((id.clone(), store.not_found(id)));
+        };
+
+        let bytes: &[u8] = clipboard.as_ref();
+        let sep = if i > 0 { separator } else { &[] };
+        if (data.len() + sep.len() + bytes.len()) as u64 > max_size {
+            return Err(too_large());
+        }
+
+        data.extend_from_slice(sep);
+        data.extend_from_slice(bytes);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::Store;
+
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    #[actix_web::test]
+    async fn test_bundle() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let mut ids = Vec::new();
+        for (content, query) in [
+            ("part 1", ""),
+            ("part 2", ""),
+            ("secret", "?encryption=x"),
+            ("once", "?max_views=1"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/drop{query}"))
+                .set_json(serde_json::json!({ "mem": content }))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            ids.push(body["clipboard"].as_str().unwrap().to_string());
+        }
+
+        let bundle = |body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/api/bundle?ttl=60")
+                .set_json(body)
+                .to_request()
+        };
+
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            bundle(serde_json::json!({ "ids": [ids[1], ids[0]], "separator": "\n--\n" })),
+        )
+        .await;
+        let url = body["url"].as_str().unwrap();
+        let req = test::TestRequest::get().uri(url).to_request();
+        let content = test::call_and_read_body(&app, req).await;
+        assert_eq!(content, "part 2\n--\npart 1");
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, bundle(serde_json::json!({ "ids": [ids[0]] })))
+                .await;
+        assert_eq!(
+            body["clipboard"], ids[0],
+            "bundles of the same content get the same key"
+        );
+
+        for (ids, status) in [
+            (serde_json::json!([]), StatusCode::BAD_REQUEST),
+            (serde_json::json!([ids[0], "f00d"]), StatusCode::NOT_FOUND),
+            (serde_json::json!([ids[0], ids[2]]), StatusCode::BAD_REQUEST),
+            (serde_json::json!([ids[3], ids[0]]), StatusCode::BAD_REQUEST),
+        ] {
+            let resp = test::call_service(&app, bundle(serde_json::json!({ "ids": ids }))).await;
+            assert_eq!(resp.status(), status, "{ids}");
+        }
+
+        // Refused bundles read nothing, so view-limited clipboards are still there
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{}", ids[3]))
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "once".as_bytes());
+    }
+}
@@ -0,0 +1,624 @@
+//! Chunked uploads of large clipboards, for clients on flaky connections.
+//!
+//! Clients open an upload with `POST /api/uploads`, which takes the same query as
+//! `POST /api/drop`, upload token included, and responds with the upload ID and a key.
+//! Only requests sending the key in `UPLOAD_KEY_HEADER` may add to the upload.
+//! The content is sent in chunks with `POST /api/drop/{upload}/chunks/{n}`, numbered from 0.
+//! Chunks may arrive in any order, and a chunk sent again replaces the one received before,
+//! so that failed chunks can be retried on their own. `GET /api/drop/{upload}/chunks` lists
+//! the chunks received so far, e.g. to resume an upload. `POST /api/drop/{upload}/finalize`
+//! joins the chunks into a persisted clipboard, stored with the options the upload was opened
+//! with, and only then is the clipboard stored and visible.
+//!
+//! Chunks are kept as temporary files until they are joined, and count against
+//! `StoreConfig::max_disk_bytes` and the quota of the client that opened the upload
+//! meanwhile (see `Store::reserve_tmp`). Uploads are only kept in memory, so they do not
+//! survive restarts, and uploads without a new chunk for `UPLOAD_TIMEOUT` are dropped.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use soyjot::filters::Filters;
+use soyjot::hash::HashConfig;
+use soyjot::id::Alphabet;
+use soyjot::store::error::StoreError;
+use soyjot::store::{owner, persist_async, Store, StoreOpts};
+
+use crate::admin;
+use crate::http_resp::ResponseJson;
+use crate::http_server::{self, PostQuery};
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+/// Chunk is the temporary file of a chunk received, with its size
+type Chunk = (PathBuf, u64);
+
+/// Header carrying the key of an upload, which requests adding to it must send
+pub const UPLOAD_KEY_HEADER: &str = "x-upload-key";
+
+/// Largest number of chunks in one upload
+const MAX_CHUNKS: u64 = 10_000;
+
+/// Largest number of uploads in progress
+const MAX_UPLOADS: usize = 1024;
+
+/// Time after its last chunk that an unfinished upload is dropped
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Length of upload IDs, in hex characters
+const ID_LEN: usize = 32;
+
+/// Upload is an upload in progress
+struct Upload {
+    /// Digest of the key handed to the client that opened the upload (see `owner::digest`)
+    key: String,
+    /// Options the clipboard is stored with, whose owner is charged for the chunks
+    opts: StoreOpts,
+    /// Chunks received by chunk number
+    chunks: BTreeMap<u64, Chunk>,
+    touched: Instant,
+}
+
+impl Upload {
+    fn size(&self) -> u64 {
+        self.chunks.values().map(|(_, size)| size).sum()
+    }
+
+    /// missing returns the first chunk missing before the last chunk received, if any
+    fn missing(&self) -> Option<u64> {
+        (0..)
+            .zip(self.chunks.keys())
+            .find(|(n, received)| n != *received)
+            .map(|(n, _)| n)
+    }
+}
+
+/// Uploads are the uploads in progress, shared by every worker
+#[derive(Default)]
+pub struct Uploads {
+    uploads: Mutex<HashMap<String, Upload>>,
+}
+
+impl Uploads {
+    /// open adds a new upload of a clipboard to be stored with `opts`,
+    /// and returns its ID and the key that requests adding to it must send
+    fn open(&self, opts: StoreOpts) -> Result<(String, String), StoreError> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        if uploads.len() >= MAX_UPLOADS {
+            let err = StoreError::QuotaExceeded("too many uploads in progress".to_string());
+            return Err(err);
+        }
+
+        let (id, key) = (Alphabet::Hex.random(ID_LEN), owner::new_key());
+        let upload = Upload {
+            key: owner::digest(&key),
+            opts,
+            chunks: BTreeMap::new(),
+            touched: Instant::now(),
+        };
+        uploads.insert(id.clone(), upload);
+
+        Ok((id, key))
+    }
+
+    /// pending returns the bytes received for `upload` so far, and the options it was opened with
+    fn pending(&self, upload: &str, key: Option<&str>) -> Result<(u64, StoreOpts), StoreError> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        let upload = authorized(&mut uploads, upload, key)?;
+
+        Ok((upload.size(), upload.opts.clone()))
+    }
+
+    /// put records chunk `n` of `upload` in temporary file `tmp`, and returns the number
+    /// of chunks and bytes received, with the file and size of the chunk it replaced, if any
+    fn put(
+        &self,
+        upload: &str,
+        key: Option<&str>,
+        n: u64,
+        tmp: PathBuf,
+        size: u64,
+    ) -> Result<(usize, u64, Option<Chunk>), (PathBuf, StoreError)> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        let upload = match authorized(&mut uploads, upload, key) {
+            Ok(upload) => upload,
+            Err(err) => return Err((tmp, err)),
+        };
+
+        upload.touched = Instant::now();
+        let replaced = upload.chunks.insert(n, (tmp, size));
+
+        Ok((upload.chunks.len(), upload.size(), replaced))
+    }
+
+    /// chunks returns the chunk numbers received for `upload` and their total size
+    fn chunks(&self, upload: &str, key: Option<&str>) -> Result<(Vec<u64>, u64), StoreError> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        let upload = authorized(&mut uploads, upload, key)?;
+
+        Ok((upload.chunks.keys().copied().collect(), upload.size()))
+    }
+
+    /// finish removes `upload` and returns it. Uploads missing a chunk are kept,
+    /// so that the chunk can still be sent.
+    fn finish(&self, upload: &str, key: Option<&str>) -> Result<Upload, StoreError> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        if let Some(n) = authorized(&mut uploads, upload, key)?.missing() {
+            return Err(StoreError::MissingChunk(n));
+        }
+
+        Ok(uploads.remove(upload).expect("upload is there"))
+    }
+
+    /// expire removes and returns the uploads without a new chunk for `UPLOAD_TIMEOUT`
+    fn expire(&self) -> Vec<Upload> {
+        let mut uploads = self.uploads.lock().expect("uploads lock poisoned");
+        let expired: Vec<String> = uploads
+            .iter()
+            .filter(|(_, upload)| upload.touched.elapsed() >= UPLOAD_TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        expired.iter().filter_map(|id| uploads.remove(id)).collect()
+    }
+}
+
+/// authorized returns `upload` if `key` is its key. Uploads of other clients are not found,
+/// so that their IDs cannot be told from IDs that were never handed out.
+fn authorized<'a>(
+    uploads: &'a mut HashMap<String, Upload>,
+    upload: &str,
+    key: Option<&str>,
+) -> Result<&'a mut Upload, StoreError> {
+    match (uploads.get_mut(upload), key) {
+        (Some(upload), Some(key)) if upload.key == owner::digest(key) => Ok(upload),
+        _ => Err(StoreError::NoSuch),
+    }
+}
+
+/// routes returns the chunked upload resources, which must be mounted before the `/api` scope.
+/// They are resources rather than a scope, so that the other routes of `/api/drop/{id}`
+/// still reach the `/api` scope.
+pub fn routes() -> Vec<actix_web::Resource> {
+    vec![
+        web::resource("/api/uploads").route(web::post().to(open)),
+        web::resource("/api/drop/{upload}/chunks").route(web::get().to(get_chunks)),
+        web::resource("/api/drop/{upload}/chunks/{n}").route(web::post().to(post_chunk)),
+        web::resource("/api/drop/{upload}/finalize").route(web::post().to(finalize)),
+    ]
+}
+
+/// upload_key returns the upload key sent with `req`, if any
+fn upload_key(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(UPLOAD_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// rm_chunks removes the temporary files of chunks that are no longer needed
+async fn rm_chunks(chunks: impl IntoIterator<Item = PathBuf>) {
+    for tmp in chunks {
+        if let Err(err) = persist_async::rm_tmp_file(tmp).await {
+            eprintln!("error removing chunk file: {err}");
+        }
+    }
+}
+
+/// drop_uploads removes the chunks of `uploads` that are given up,
+/// and gives back what they reserved
+async fn drop_uploads(store: &Store, uploads: Vec<Upload>) {
+    for upload in uploads {
+        store.release_tmp(upload.opts.owner, upload.size());
+        rm_chunks(upload.chunks.into_values().map(|(tmp, _)| tmp)).await;
+    }
+}
+
+/// open opens a new upload of a clipboard stored with the options in the query,
+/// which are checked now, and responds with its ID and key
+async fn open(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    uploads: web::Data<Uploads>,
+    query: web::Query<PostQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    drop_uploads(&store, uploads.expire()).await;
+
+    let opts = match http_server::post_opts(query, &req, admin::is_admin(&req, &conf.load())) {
+        Ok(opts) => opts,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    match uploads.open(opts) {
+        Ok((upload, key)) => HttpResponse::Ok().json(json!({
+            "upload": upload,
+            "key": key,
+            "url": format!("/api/drop/{upload}/chunks"),
+        })),
+        Err(err) => http_server::store_error::<R>("", err),
+    }
+}
+
+/// post_chunk writes chunk `n` of an upload to a temporary file, and responds with
+/// the digest of the chunk, so that clients can check it arrived intact, and the number
+/// of chunks and bytes received so far
+async fn post_chunk(
+    store: web::Data<Store>,
+    hashing: web::Data<HashConfig>,
+    uploads: web::Data<Uploads>,
+    path: web::Path<(String, u64)>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> HttpResponse {
+    let (upload, n) = path.into_inner();
+    let key = upload_key(&req);
+
+    if n >= MAX_CHUNKS {
+        let err = StoreError::InvalidChunk(format!("chunks are numbered 0 to {}", MAX_CHUNKS - 1));
+        return http_server::store_error::<R>("", err);
+    }
+
+    drop_uploads(&store, uploads.expire()).await;
+
+    let (received, opts) = match uploads.pending(&upload, key) {
+        Ok(pending) => pending,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let (tmp, file) = match persist_async::create_tmp_file(&http_server::tmp_dir(&store)).await {
+        Ok(tmp) => tmp,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    // Chunks are reserved as they are written, so that concurrent uploads share the disk budget
+    let reserved = Cell::new(0);
+    let written = http_server::write_stream(file, payload, &hashing, |bytes| {
+        if let Some(max) = opts.max_size.filter(|max| received + bytes > *max) {
+            return Err(StoreError::TooLarge(max));
+        }
+
+        store.reserve_tmp(opts.owner, bytes - reserved.get())?;
+        reserved.set(bytes);
+
+        Ok(())
+    });
+
+    let (digest, size) = match written.await {
+        Ok(written) => written,
+        Err(err) => {
+            store.release_tmp(opts.owner, reserved.get());
+            rm_chunks([tmp]).await;
+            return http_server::store_error::<R>("", err);
+        }
+    };
+
+    let (chunks, bytes) = match uploads.put(&upload, key, n, tmp, size) {
+        Ok((chunks, bytes, replaced)) => {
+            if let Some((tmp, size)) = replaced {
+                store.release_tmp(opts.owner, size);
+                rm_chunks([tmp]).await;
+            }
+            (chunks, bytes)
+        }
+        Err((tmp, err)) => {
+            store.release_tmp(opts.owner, size);
+            rm_chunks([tmp]).await;
+            return http_server::store_error::<R>("", err);
+        }
+    };
+
+    HttpResponse::Ok().json(json!({
+        "upload": upload,
+        "chunk": n,
+        "digest": digest,
+        "chunks": chunks,
+        "size": bytes,
+    }))
+}
+
+/// get_chunks responds with the chunks received for an upload, and their total size
+async fn get_chunks(
+    uploads: web::Data<Uploads>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let upload = path.into_inner();
+
+    match uploads.chunks(&upload, upload_key(&req)) {
+        Ok((chunks, size)) => HttpResponse::Ok().json(json!({
+            "upload": upload,
+            "chunks": chunks,
+            "size": size,
+        })),
+        Err(err) => http_server::send_error::<R>("", err),
+    }
+}
+
+/// finalize joins the chunks of an upload, and stores them as a persisted clipboard
+/// like `POST /raw/drop` stores streamed uploads
+async fn finalize(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    hashing: web::Data<HashConfig>,
+    filters: Option<web::Data<Filters>>,
+    uploads: web::Data<Uploads>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let upload = match uploads.finish(&path, upload_key(&req)) {
+        Ok(upload) => upload,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let chunks: Vec<PathBuf> = upload.chunks.values().map(|(tmp, _)| tmp.clone()).collect();
+    let joined =
+        persist_async::join_tmp_files(&http_server::tmp_dir(&store), &chunks, hashing.hasher())
+            .await;
+
+    // The clipboard is charged for itself once it's stored
+    let opts = upload.opts.clone();
+    store.release_tmp(opts.owner, upload.size());
+
+    let (tmp, digest, size) = match joined {
+        Ok(joined) => joined,
+        Err(err) => {
+            rm_chunks(chunks).await;
+            return http_server::store_error::<R>("", err);
+        }
+    };
+
+    let written = Ok((digest, size));
+    http_server::store_upload::<R>(store, &conf, &hashing, filters, tmp, written, opts).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::{persist, Store, StoreConfig};
+
+    use super::UPLOAD_KEY_HEADER;
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    fn chunk(upload: &str, key: &str, n: u64, data: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/api/drop/{upload}/chunks/{n}"))
+            .insert_header((UPLOAD_KEY_HEADER, key))
+            .set_payload(data)
+    }
+
+    fn finalize(upload: &str, key: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/api/drop/{upload}/finalize"))
+            .insert_header((UPLOAD_KEY_HEADER, key))
+    }
+
+    fn open_req(uri: &str) -> test::TestRequest {
+        test::TestRequest::post().uri(uri)
+    }
+
+    /// ids returns the ID and key of an upload from the response opening it
+    fn ids(body: serde_json::Value) -> (String, String) {
+        let id = body["upload"].as_str().unwrap().to_string();
+        (id, body["key"].as_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_chunked_upload() {
+        let store = web::Data::new(Store::new());
+        let uploads = web::Data::new(super::Uploads::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(uploads.clone())
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let body = test::call_and_read_body_json(&app, open_req("/api/uploads").to_request()).await;
+        let (up, key) = ids(body);
+        assert_eq!(up.len(), super::ID_LEN);
+
+        // Chunks may arrive out of order, and be sent again
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, chunk(&up, &key, 1, "wor").to_request()).await;
+        assert_eq!(body["chunks"], 1);
+        assert_eq!(body["digest"], HashConfig::default().digest(b"wor"));
+        test::call_service(&app, chunk(&up, &key, 2, "ld").to_request()).await;
+
+        let resp = test::call_service(&app, finalize(&up, &key).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        test::call_service(&app, chunk(&up, &key, 0, "hi ").to_request()).await;
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, chunk(&up, &key, 0, "hello ").to_request()).await;
+        assert_eq!(body["chunks"], 3);
+        assert_eq!(body["size"], 11);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{up}/chunks"))
+            .insert_header((UPLOAD_KEY_HEADER, key.as_str()))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["chunks"], serde_json::json!([0, 1, 2]));
+
+        // Nothing is stored before the upload is finalized
+        assert!(store.index().is_empty());
+
+        let resp = test::call_service(&app, finalize(&up, &key).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let hash = HashConfig::default().key(&HashConfig::default().digest(b"hello world"));
+        assert_eq!(store.is_persisted(&hash), Some(true));
+        assert_eq!(&*store.get_clipboard(&hash).await.unwrap(), b"hello world");
+
+        // Finalized uploads are gone
+        let resp = test::call_service(&app, finalize(&up, &key).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Uploads are only opened by the server
+        let resp = test::call_service(&app, chunk("up-1", &key, 0, "x").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Options are checked when the upload is opened
+        let resp = test::call_service(&app, open_req("/api/uploads?pin=true").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_upload_hijacking() {
+        let store = web::Data::new(Store::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(web::Data::new(super::Uploads::default()))
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let body = test::call_and_read_body_json(&app, open_req("/api/uploads").to_request()).await;
+        let (up, key) = ids(body);
+        let body = test::call_and_read_body_json(&app, open_req("/api/uploads").to_request()).await;
+        let (_, other_key) = ids(body);
+
+        let resp = test::call_service(&app, chunk(&up, &key, 0, "mine").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Other clients cannot add to, list or finalize the upload, even with a key of their own
+        for key in [other_key.as_str(), ""] {
+            let resp = test::call_service(&app, chunk(&up, key, 0, "evil").to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+            let req = test::TestRequest::get()
+                .uri(&format!("/api/drop/{up}/chunks"))
+                .insert_header((UPLOAD_KEY_HEADER, key))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+            let resp = test::call_service(&app, finalize(&up, key).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/drop/{up}/chunks/0"))
+            .set_payload("evil")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = test::call_service(&app, finalize(&up, &key).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let hash = HashConfig::default().key(&HashConfig::default().digest(b"mine"));
+        assert_eq!(&*store.get_clipboard(&hash).await.unwrap(), b"mine");
+    }
+
+    #[actix_web::test]
+    async fn test_upload_disk_budget() {
+        persist::assert_dir(None);
+
+        let store = web::Data::new(Store::with_config(StoreConfig {
+            max_disk_bytes: Some(16),
+            ..StoreConfig::default()
+        }));
+        let uploads = web::Data::new(super::Uploads::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(uploads.clone())
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let body = test::call_and_read_body_json(&app, open_req("/api/uploads").to_request()).await;
+        let (first, first_key) = ids(body);
+        let body = test::call_and_read_body_json(&app, open_req("/api/uploads").to_request()).await;
+        let (second, second_key) = ids(body);
+
+        // Chunks in flight count against the disk budget shared by every upload
+        let resp = test::call_service(
+            &app,
+            chunk(&first, &first_key, 0, "0123456789").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(store.disk_bytes(), 10);
+
+        let resp = test::call_service(
+            &app,
+            chunk(&second, &second_key, 0, "0123456789").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 507);
+        assert_eq!(store.disk_bytes(), 10);
+
+        // Replaced chunks give back what they reserved
+        let resp =
+            test::call_service(&app, chunk(&first, &first_key, 0, "01234").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(store.disk_bytes(), 5);
+
+        let resp =
+            test::call_service(&app, chunk(&second, &second_key, 0, "56789").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(store.disk_bytes(), 10);
+
+        // Finalized clipboards are charged for themselves instead
+        let resp = test::call_service(&app, finalize(&first, &first_key).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(store.disk_bytes(), 10);
+
+        // Expired uploads give back their chunks
+        uploads
+            .uploads
+            .lock()
+            .unwrap()
+            .get_mut(&second)
+            .unwrap()
+            .touched -= super::UPLOAD_TIMEOUT;
+        super::drop_uploads(&store, uploads.expire()).await;
+        assert_eq!(store.disk_bytes(), 5);
+    }
+
+    #[actix_web::test]
+    async fn test_upload_token_required() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(web::Data::new(super::Uploads::default()))
+                .app_data(reload::shared(AppConfig {
+                    link_secret: Some("l1nks".to_string()),
+                    require_upload_token: Some(true),
+                    ..AppConfig::default()
+                }))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let resp = test::call_service(&app, open_req("/api/uploads").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
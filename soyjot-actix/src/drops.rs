@@ -0,0 +1,434 @@
+//! Listing of clipboards at `/api/drops`, optionally only those with a tag with `?tag=`,
+//! and batches of clipboards: uploads with `POST /api/drops`, and fetches with
+//! `GET /api/drops?ids=a1b2,c3d4`.
+//!
+//! Like `search`, admins list all global clipboards, and other clients only
+//! the clipboards of the owner key they send. Anyone may fetch clipboards by their IDs.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::filters::{FilterChain, Filters};
+use soyjot::hash::HashConfig;
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::error::StoreError;
+use soyjot::store::{index, Store, StoreOpts};
+use soyjot::tenant;
+
+use crate::admin;
+use crate::http_resp::{ClipboardResponse, ErrorResponse, ExpiresAt, ResponseJson};
+use crate::http_server::{self, PostQuery, ReqJson};
+use crate::middleware;
+use crate::reload::SharedConfig;
+
+const PATH: &str = "/api/drops";
+
+/// Maximum number of clipboards in one batch upload or fetch
+pub const MAX_BATCH: usize = 100;
+
+#[derive(Deserialize)]
+struct DropsQuery {
+    tag: Option<String>,
+    /// Comma-separated IDs of the clipboards to fetch, instead of listing clipboards
+    ids: Option<String>,
+}
+
+/// BatchDrop is a clipboard of a batch upload, e.g. `{"mem": "foo", "ttl": 60}`
+#[derive(Deserialize)]
+struct BatchDrop {
+    #[serde(flatten)]
+    clipboard: ReqJson,
+    /// Lifetime in seconds, like `?ttl=` for this clipboard only
+    ttl: Option<u64>,
+}
+
+/// routes returns the listing scope, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope(PATH)
+        .route("", web::get().to(list_drops))
+        .route("", web::post().to(add_drops))
+}
+
+/// list_drops lists the clipboards of the client, sorted by ID,
+/// or fetches the clipboards in `DropsQuery::ids`
+async fn list_drops(
+    req: HttpRequest,
+    query: web::Query<DropsQuery>,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    if let Some(ids) = query.ids.as_deref() {
+        return fetch_drops(ids, &store, &conf).await;
+    }
+
+    let Some(owner) = admin::owner_or_admin(&req, &conf) else {
+        return http_server::unauthorized::<ResponseJson>("admin token or owner key required");
+    };
+
+    let drops: Vec<_> = store
+        .drops(query.tag.as_deref(), owner.as_deref())
+        .iter()
+        .map(|drop| {
+            json!({
+                "clipboard": drop.hash,
+                "storage": drop.storage,
+                "size": drop.size,
+                "expires_at": drop.expires_at,
+                "pinned": drop.pinned,
+                "tags": drop.tags,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({ "clipboards": drops }))
+}
+
+/// fetch_drops responds with a JSON map of the comma-separated `ids` to the clipboards
+/// they are for, like `/api/v2/drop/{id}` would send them, or their errors.
+/// Each clipboard counts a view, and clipboards posted with a content type are sent
+/// base64-encoded like any other. Since batches cannot carry a signature for each ID,
+/// they are refused with `AppConfig::require_signed_links`.
+async fn fetch_drops(ids: &str, store: &Store, conf: &SharedConfig) -> HttpResponse {
+    type R = ResponseJson;
+
+    if conf.load().require_signed_links == Some(true) {
+        return http_server::send_error::<R>("", StoreError::InvalidSignature);
+    }
+
+    let mut ids: Vec<&str> = ids.split(',').map(str::trim).collect();
+    ids.retain(|id| !id.is_empty());
+    ids.sort_unstable();
+    ids.dedup();
+
+    if ids.len() > MAX_BATCH {
+        return http_server::send_error::<R>("", StoreError::TooMany(MAX_BATCH));
+    }
+
+    let mut drops = serde_json::Map::new();
+    let mut read = Vec::new();
+    for id in ids {
+        // Like global_ids does for the IDs of paths
+        if !tenant::is_global(id) {
+            drops.insert(
+                id.to_string(),
+                json!(ErrorResponse::new(id, StoreError::NoSuch)),
+            );
+            continue;
+        }
+
+        let expires_at = store.expires_at(id);
+        let drop = match store.get_clipboard(id).await {
+            Some(clipboard) => {
+                read.push(id.to_string());
+                let expires_at = expires_at.map(|at| ExpiresAt::Rfc3339(index::to_rfc3339(at)));
+                json!(ClipboardResponse::new(id, &clipboard, expires_at))
+            }
+            None => json!(ErrorResponse::new(id, store.not_found(id))),
+        };
+
+        drops.insert(id.to_string(), drop);
+    }
+
+    let mut resp = HttpResponse::Ok().json(json!({ "clipboards": drops }));
+    resp.extensions_mut().insert(middleware::Read(read));
+
+    resp
+}
+
+/// add_drops stores a JSON array of up to `MAX_BATCH` clipboards, each like one posted
+/// to `/api/drop` with the same query, and responds with the results in the same order.
+/// Clipboards are stored one after another, since the haystack is sharded and has no lock
+/// to hold for the whole batch, and each clipboard that fails to be stored gets its error
+/// without failing the others.
+async fn add_drops(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    hashing: web::Data<HashConfig>,
+    filters: Option<web::Data<Filters>>,
+    query: web::Query<PostQuery>,
+    req: HttpRequest,
+    web::Json(drops): web::Json<Vec<BatchDrop>>,
+) -> HttpResponse {
+    type R = ResponseJson;
+
+    if drops.len() > MAX_BATCH {
+        return http_server::store_error::<R>("", StoreError::TooMany(MAX_BATCH));
+    }
+
+    if query.upload_token.is_some() {
+        let err = "upload tokens are for one clipboard".to_string();
+        return http_server::store_error::<R>("", StoreError::Unauthorized(err));
+    }
+
+    let conf = conf.load();
+    let opts = match http_server::post_opts(query, &req, admin::is_admin(&req, &conf)) {
+        Ok(opts) => opts,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let (store, filters) = (store.into_inner(), http_server::filter_chain(filters));
+    let dur = conf.timeout_duration();
+
+    let mut results = Vec::with_capacity(drops.len());
+    for drop in drops {
+        let result = match add_drop(&store, &hashing, &filters, drop, opts.clone(), dur).await {
+            Ok((hash, storage, owner_key)) => {
+                let short = store.shortest_prefix(&hash);
+                json!({
+                    "clipboard": hash,
+                    "storage": storage,
+                    "short": short,
+                    "url": format!("/api/drop/{hash}"),
+                    "short_url": format!("/api/d/{short}"),
+                    "owner_key": owner_key,
+                    "expires_at": store.expires_at(&hash).map(index::to_rfc3339),
+                })
+            }
+
+            Err((hash, err)) => json!(ErrorResponse::new(&hash, err)),
+        };
+
+        results.push(result);
+    }
+
+    HttpResponse::Ok().json(json!({ "clipboards": results }))
+}
+
+/// add_drop stores `drop` of a batch like `http_server::add_clipboard` does,
+/// returning the key, storage and owner key of the clipboard,
+/// or the error with the hash of the clipboard, if it was hashed
+async fn add_drop(
+    store: &Arc<Store>,
+    hashing: &HashConfig,
+    filters: &FilterChain,
+    drop: BatchDrop,
+    mut opts: StoreOpts,
+    dur: Duration,
+) -> Result<(String, String, Option<String>), (String, StoreError)> {
+    let fail = |err| (String::new(), err);
+    let clipboard = Clipboard::try_from(drop.clipboard).map_err(fail)?;
+
+    clipboard.is_implemented().map_err(fail)?;
+    if clipboard.is_empty() {
+        return Err(fail(StoreError::Empty));
+    }
+
+    match drop.ttl {
+        Some(_) if opts.pin => {
+            let err = "pinned clipboards have no ttl or max_views".to_string();
+            return Err(fail(StoreError::InvalidTtl(err)));
+        }
+        Some(0) => return Err(fail(StoreError::InvalidTtl("must be positive".to_string()))),
+        Some(ttl) => opts.ttl = Some(Duration::from_secs(ttl)),
+        None => {}
+    }
+
+    let clipboard = filters.apply_clipboard(clipboard).map_err(fail)?;
+    let digest = hashing.digest(&clipboard);
+    let key_digest = http_server::key_digest(hashing, &clipboard, &digest);
+    let hash = http_server::new_key(store, hashing, &key_digest, None);
+    let clipboard = store.place(clipboard);
+
+    http_server::store_clipboard(store.clone(), &hash, &digest, clipboard, dur, opts)
+        .await
+        .map_err(|err| (hash, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::Store;
+
+    use crate::http_resp::ResponseJson;
+    use crate::http_server::{self, OWNER_KEY_HEADER};
+    use crate::reload;
+
+    #[actix_web::test]
+    async fn test_drops() {
+        let conf = reload::shared(AppConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..AppConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(conf)
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let mut keys = Vec::new();
+        for (content, tags) in [("tagged", "work,logs"), ("logs only", "logs,logs")] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/drop?tags={tags}"))
+                .set_json(serde_json::json!({ "mem": content }))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            keys.push(body["owner_key"].as_str().unwrap().to_string());
+        }
+
+        for tags in ["Work", "a,,b", &"a,".repeat(9)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/drop?tags={tags}"))
+                .set_json(serde_json::json!({ "mem": "bad tags" }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{tags}");
+        }
+
+        let list = |uri: &str, auth: (&str, &str)| {
+            test::TestRequest::get()
+                .uri(uri)
+                .insert_header(auth)
+                .to_request()
+        };
+
+        let admin = (header::AUTHORIZATION.as_str(), "Bearer s3cret");
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list("/api/drops?tag=logs", admin)).await;
+        assert_eq!(body["clipboards"].as_array().unwrap().len(), 2);
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list("/api/drops?tag=work", admin)).await;
+        assert_eq!(
+            body["clipboards"][0]["tags"],
+            serde_json::json!(["work", "logs"])
+        );
+
+        // Owners only list their own clipboards
+        let owner = (OWNER_KEY_HEADER, keys[1].as_str());
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list("/api/drops?tag=logs", owner)).await;
+        let drops = body["clipboards"].as_array().unwrap();
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0]["tags"], serde_json::json!(["logs"]));
+
+        let req = test::TestRequest::get().uri("/api/drops").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_upload() {
+        let store = web::Data::new(Store::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let batch = serde_json::json!([
+            { "mem": "first" },
+            { "mem": "" },
+            { "mem": "second", "ttl": 60 },
+            { "mem": "third", "ttl": 0 },
+        ]);
+        let req = test::TestRequest::post()
+            .uri("/api/drops?tags=batch")
+            .set_json(&batch)
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let results = body["clipboards"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+
+        // Failed clipboards do not fail the others
+        assert_eq!(results[1]["kind"], "Empty");
+        assert_eq!(results[3]["kind"], "InvalidTtl");
+
+        for (result, content) in [(&results[0], "first"), (&results[2], "second")] {
+            assert!(result["owner_key"].is_string());
+            assert!(result["expires_at"].is_string());
+
+            let req = test::TestRequest::get()
+                .uri(result["url"].as_str().unwrap())
+                .to_request();
+            assert_eq!(
+                test::call_and_read_body(&app, req).await,
+                content.as_bytes()
+            );
+        }
+
+        let meta = store
+            .meta(results[2]["clipboard"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(meta.tags, ["batch"]);
+
+        let too_many = vec![serde_json::json!({ "mem": "x" }); super::MAX_BATCH + 1];
+        let req = test::TestRequest::post()
+            .uri("/api/drops")
+            .set_json(too_many)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_fetch() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let batch = serde_json::json!([{ "mem": "text" }, { "mem": [0xff, 0x00] }]);
+        let req = test::TestRequest::post()
+            .uri("/api/drops?max_views=1")
+            .set_json(&batch)
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<&str> = body["clipboards"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|drop| drop["clipboard"].as_str().unwrap())
+            .collect();
+
+        let fetch = |ids: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/drops?ids={ids}"))
+                .to_request()
+        };
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, fetch(&format!("{},{},%20ffff,", ids[0], ids[1])))
+                .await;
+        let drops = body["clipboards"].as_object().unwrap();
+        assert_eq!(drops.len(), 3);
+        assert_eq!(drops[ids[0]]["data"], "text");
+        assert_eq!(drops[ids[0]]["encoding"], "utf8");
+        assert!(drops[ids[0]]["expires_at"].is_string());
+        assert_eq!(drops[ids[1]]["data"], "/wA=");
+        assert_eq!(drops[ids[1]]["encoding"], "base64");
+        assert_eq!(drops["ffff"]["kind"], "NoSuch");
+
+        // Fetches count views
+        let body: serde_json::Value = test::call_and_read_body_json(&app, fetch(ids[0])).await;
+        assert_eq!(body["clipboards"][ids[0]]["kind"], "Expired");
+
+        let too_many = (0..=super::MAX_BATCH)
+            .map(|i| format!("a{i}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let resp = test::call_service(&app, fetch(&too_many)).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
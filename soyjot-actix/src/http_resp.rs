@@ -1,11 +1,27 @@
+use std::string::FromUtf8Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::body::MessageBody;
+use actix_web::web::Bytes;
 use actix_web::{HttpResponse, HttpResponseBuilder};
+use base64::Engine;
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use utoipa::ToSchema;
 
+use soyjot::config::{AppConfig, Scope};
 use soyjot::html::{self, wrap_html};
 use soyjot::store::clipboard::{self, Clipboard};
 use soyjot::store::error::{public_error, StoreError};
+use soyjot::store::feed::PublicDrop;
+use soyjot::store::index::{self, IndexEntry};
+use soyjot::store::version::VersionInfo;
 use soyjot::{para, tag_html};
 
+use crate::admin::format_ttl;
+use crate::http_server::{self, OWNER_KEY_HEADER};
+
 /// DropResult represents clipboard or error from http_server
 /// The clipboard is wrapped in `Option` because when posting clipboard,
 /// the response contains to clipboard (None) but yet there's no error.
@@ -16,21 +32,87 @@ pub trait DropResponseHttp: From<(HttpResponseBuilder, DropResult)> {
     // HTTP header Content-Type
     const CONTENT_TYPE: &'static str;
 
+    /// Body of formatted errors, which is text for all but binary formats
+    type Body: MessageBody + 'static;
+
+    /// Whether clipboards posted with a content type are sent as-is (see `send_typed_clipboard`),
+    /// so that persisted ones can be streamed from file instead
+    const SENDS_TYPED_AS_IS: bool = false;
+
     /// landing_page is the default endpoint for R.
     /// It should return some kind of OK status and text,
-    /// and for HTML resposnes, it should offer some kind of user input.
-    fn landing_page() -> HttpResponse;
+    /// and for HTML resposnes, it should offer some kind of user input,
+    /// and describe the instance configured by `conf`.
+    fn landing_page(conf: &AppConfig) -> HttpResponse;
 
     /// format_err formats StoreError
-    fn format_err(hash: &str, err: StoreError) -> String;
+    fn format_err(hash: &str, err: StoreError) -> Self::Body;
 
     /// send_clipboard returns the response with the clipboard content
     /// self should be Ok(Some(_)), since we are sending the clipboard to clients.
     fn send_clipboard(self, hash: &str) -> HttpResponse;
 
+    /// send_clipboard_expiring is like send_clipboard for a clipboard expiring at `expires_at`,
+    /// as seconds since the UNIX epoch, for responses that describe the clipboard with its content.
+    fn send_clipboard_expiring(self, hash: &str, _expires_at: Option<u64>) -> HttpResponse {
+        self.send_clipboard(hash)
+    }
+
+    /// send_clipboard_base64 is like send_clipboard_expiring, for clients asking for the content
+    /// base64-encoded even if it's valid UTF-8. Only JSON responses encode clipboards.
+    fn send_clipboard_base64(self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        self.send_clipboard_expiring(hash, expires_at)
+    }
+
+    /// send_clipboard_lang is like send_clipboard_expiring for a text clipboard in language
+    /// or format `lang`, if known. Only HTML responses highlight clipboards.
+    fn send_clipboard_lang(
+        self,
+        hash: &str,
+        expires_at: Option<u64>,
+        _lang: Option<&str>,
+    ) -> HttpResponse {
+        self.send_clipboard_expiring(hash, expires_at)
+    }
+
+    /// send_typed_clipboard is like send_clipboard for clipboards posted with `content_type`,
+    /// e.g. images or JSON documents, which should be sent or rendered as that type.
+    fn send_typed_clipboard(self, hash: &str, content_type: &str) -> HttpResponse;
+
     /// post_clipboard returns the response when clipboard is posted to actix-drop
     /// self should be Ok(None), since we are not sending just the acknowledgement.
     fn post_clipboard(self, hash: &str) -> HttpResponse;
+
+    /// post_clipboard_stored is like post_clipboard, and also tells the client
+    /// which storage (`clipboard::MEM` or `clipboard::PERSIST`) the clipboard was stored in,
+    /// and its shortest unique prefix `short` (see `Store::shortest_prefix`).
+    /// `owner_key` is only given for new clipboards (see `Store::store_new_clipboard`),
+    /// `signed_url` for clipboards posted with `link_ttl`, and `expires_at`, as seconds since
+    /// the UNIX epoch, for clipboards that are not pinned.
+    #[allow(clippy::too_many_arguments)]
+    fn post_clipboard_stored(
+        self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> HttpResponse;
+
+    /// send_meta returns the response with clipboard metadata and access statistics
+    fn send_meta(self, meta: &IndexEntry) -> HttpResponse;
+
+    /// send_versions returns the response listing versions of clipboard `hash`
+    fn send_versions(self, hash: &str, versions: &[VersionInfo]) -> HttpResponse;
+
+    /// send_ambiguous returns the response when prefix `frag` matches several clipboards,
+    /// listed only by the lengths of their shortest unique prefixes (see `Store::resolve_prefix`).
+    fn send_ambiguous(self, frag: &str, lens: &[usize]) -> HttpResponse;
+
+    /// send_public returns the response listing recent public clipboards, newest first
+    /// (see `Store::public_drops`)
+    fn send_public(self, drops: &[PublicDrop]) -> HttpResponse;
 }
 
 /// ResponseHtml implements DropResponseHttp for HTML responses
@@ -39,6 +121,207 @@ pub struct ResponseHtml(HttpResponseBuilder, DropResult);
 pub struct ResponseText(HttpResponseBuilder, DropResult);
 /// ResponseHtml implements DropResponseHttp for JSON text responses
 pub struct ResponseJson(HttpResponseBuilder, DropResult);
+/// ResponseJsonVersioned implements DropResponseHttp for version `V` of the JSON API
+/// at `/api/v{V}` (see `http_server::routes_versioned`). Responses are like ResponseJson's,
+/// except that clipboards are sent as `ClipboardResponse`. Since v2, posted clipboards
+/// come with the URLs of their full ID and shortest unique prefix, and clipboards are sent
+/// with their expiry time in RFC 3339 instead of seconds since the UNIX epoch.
+pub struct ResponseJsonVersioned<const V: u8>(HttpResponseBuilder, DropResult);
+/// ResponseJsonV1 implements DropResponseHttp for the JSON responses at `/api/v1`
+pub type ResponseJsonV1 = ResponseJsonVersioned<1>;
+/// ResponseJsonV2 implements DropResponseHttp for the JSON responses at `/api/v2`
+pub type ResponseJsonV2 = ResponseJsonVersioned<2>;
+/// ResponseMsgpack implements DropResponseHttp for MessagePack responses at `/bin`,
+/// which have the same fields as the `/api/v1` JSON responses, with clipboards as binary data
+pub struct ResponseMsgpack(HttpResponseBuilder, DropResult);
+
+/// Encoding is how `ClipboardResponse::data` is encoded, which clients may pick with `?encoding=`
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Utf8,
+    Base64,
+}
+
+/// ExpiresAt is when a clipboard expires, as seconds since the UNIX epoch in `/api/v1`,
+/// and in RFC 3339 (e.g. `2024-02-29T12:30:00Z`) since `/api/v2`
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ExpiresAt {
+    Timestamp(u64),
+    Rfc3339(String),
+}
+
+/// ClipboardResponse is the JSON body of a clipboard sent by `/api/v1`
+#[derive(Serialize, ToSchema)]
+pub struct ClipboardResponse<'a> {
+    clipboard: &'a str,
+    /// Clipboard content, base64-encoded if it is not valid UTF-8
+    data: String,
+    encoding: Encoding,
+    /// Expiry time, none if the clipboard is pinned
+    expires_at: Option<ExpiresAt>,
+}
+
+impl<'a> ClipboardResponse<'a> {
+    /// new returns the response with the content of `clipboard`, base64-encoded if needed
+    pub(crate) fn new(hash: &'a str, clipboard: &Clipboard, expires_at: Option<ExpiresAt>) -> Self {
+        match std::str::from_utf8(clipboard) {
+            Ok(data) => Self {
+                clipboard: hash,
+                data: data.to_string(),
+                encoding: Encoding::Utf8,
+                expires_at,
+            },
+            Err(_) => Self::base64(hash, clipboard, expires_at),
+        }
+    }
+
+    /// base64 returns the response with the content of `clipboard` base64-encoded
+    pub(crate) fn base64(
+        hash: &'a str,
+        clipboard: &Clipboard,
+        expires_at: Option<ExpiresAt>,
+    ) -> Self {
+        Self {
+            clipboard: hash,
+            data: base64::engine::general_purpose::STANDARD.encode(clipboard),
+            encoding: Encoding::Base64,
+            expires_at,
+        }
+    }
+}
+
+/// PostResponse is the JSON body sent when a clipboard is posted or appended to
+#[derive(Serialize, ToSchema)]
+pub struct PostResponse<'a> {
+    /// Clipboard ID
+    clipboard: &'a str,
+    /// Storage the clipboard was stored in, either `mem` or `persist`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    storage: Option<&'a str>,
+    /// Clipboard ID, same as `clipboard`, sent along with `short`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_hash: Option<&'a str>,
+    /// Shortest unique prefix of the clipboard ID when it was posted, for `/api/d/{short}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short: Option<&'a str>,
+    /// Secret key to replace, append to or delete the clipboard with, only sent for new clipboards
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner_key: Option<&'a str>,
+    /// Link to the clipboard that expires after `link_ttl` seconds, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signed_url: Option<&'a str>,
+    /// Expiry time in RFC 3339, e.g. `2024-02-29T12:30:00Z`, unless the clipboard is pinned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    /// Path of the clipboard in the same API version, since `/api/v2`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Path of the clipboard's shortest unique prefix in the same API version, since `/api/v2`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_url: Option<String>,
+}
+
+/// ErrorResponse is the JSON body sent on errors, with the `StoreError` as `kind` and `detail`
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse<'a> {
+    /// Error message
+    error: String,
+    /// Clipboard ID, empty if the clipboard was not hashed yet
+    clipboard: &'a str,
+    #[serde(flatten)]
+    kind: StoreError,
+}
+
+impl<'a> ErrorResponse<'a> {
+    /// new returns the response to `err` for clipboard `hash`, with private errors
+    /// sent as `StoreError::Bug` (see `StoreError::is_public`)
+    pub(crate) fn new(hash: &'a str, err: StoreError) -> Self {
+        let kind =
+            public_error(err).unwrap_or_else(|| StoreError::Bug("private error".to_string()));
+
+        Self {
+            error: kind.to_string(),
+            clipboard: hash,
+            kind,
+        }
+    }
+}
+
+/// MetaResponse is the JSON body of clipboard metadata and access statistics
+#[derive(Serialize, ToSchema)]
+pub struct MetaResponse<'a> {
+    clipboard: &'a str,
+    storage: &'a str,
+    /// Expiry timestamp as seconds since the UNIX epoch, 0 if the clipboard is pinned
+    expires_at: u64,
+    /// Pinned clipboards never expire, and are only removed when deleted
+    pinned: bool,
+    /// Number of times the clipboard has been read
+    views: u64,
+    max_views: Option<u64>,
+    /// Timestamp of the last read as seconds since the UNIX epoch
+    last_access: Option<u64>,
+    content_type: Option<&'a str>,
+    /// Language or format of the clipboard, posted with it or guessed from its content
+    lang: Option<&'a str>,
+    /// Metadata of a clipboard encrypted by the client, whose content is ciphertext
+    encryption: Option<&'a str>,
+    tags: &'a [String],
+}
+
+impl<'a> From<&'a IndexEntry> for MetaResponse<'a> {
+    fn from(meta: &'a IndexEntry) -> Self {
+        Self {
+            clipboard: &meta.hash,
+            storage: &meta.storage,
+            expires_at: meta.expires_at,
+            pinned: meta.pinned,
+            views: meta.views,
+            max_views: meta.max_views,
+            last_access: meta.last_access,
+            content_type: meta.content_type.as_deref(),
+            lang: meta.lang.as_deref(),
+            encryption: meta.encryption.as_deref(),
+            tags: &meta.tags,
+        }
+    }
+}
+
+/// BinClipboardResponse is the MessagePack body of a clipboard sent by `/bin`,
+/// like `ClipboardResponse` without the need for an encoding
+#[derive(Serialize)]
+struct BinClipboardResponse<'a> {
+    clipboard: &'a str,
+    #[serde(with = "serde_bytes")]
+    data: &'a [u8],
+    expires_at: Option<u64>,
+}
+
+/// AmbiguousResponse is the JSON body sent when a prefix matches several clipboards
+#[derive(Serialize, ToSchema)]
+pub struct AmbiguousResponse<'a> {
+    prefix: &'a str,
+    /// Number of clipboards matching the prefix
+    candidates: usize,
+    /// Length of the shortest unique prefix of each candidate, in ascending order
+    prefix_lens: &'a [usize],
+}
+
+/// PublicResponse is the JSON body listing recent public clipboards
+#[derive(Serialize, ToSchema)]
+pub struct PublicResponse<'a> {
+    /// Newest first
+    clipboards: &'a [PublicDrop],
+}
+
+/// VersionsResponse is the JSON body listing versions of a clipboard
+#[derive(Serialize, ToSchema)]
+pub struct VersionsResponse<'a> {
+    clipboard: &'a str,
+    versions: &'a [VersionInfo],
+}
 
 macro_rules! impl_from_drop_result {
     ( $( $t: ident ),+ ) => {
@@ -53,43 +336,66 @@ macro_rules! impl_from_drop_result {
     }
 
 // Impl From<DropResult> for ResponseHtml, ResponsePlain, ResponseJson
-impl_from_drop_result!(ResponseHtml, ResponseText, ResponseJson);
+impl_from_drop_result!(ResponseHtml, ResponseText, ResponseJson, ResponseMsgpack);
+
+impl<const V: u8> From<(HttpResponseBuilder, DropResult)> for ResponseJsonVersioned<V> {
+    fn from(result: (HttpResponseBuilder, DropResult)) -> Self {
+        Self(result.0, result.1)
+    }
+}
 
 impl DropResponseHttp for ResponseHtml {
     const CONTENT_TYPE: &'static str = "text/html";
 
-    fn landing_page() -> HttpResponse {
+    type Body = String;
+
+    fn landing_page(conf: &AppConfig) -> HttpResponse {
         HttpResponse::Ok()
             .content_type("text/html")
-            .body(wrap_html(&format!(
-                r#"<form action="/app/drop" method="post">
-            <textarea id="textbox" name="data" rows="5" cols="32"></textarea><br>
-            <select id="selection box" name="store">
-                <option value="{}">In-memory database</option>
-                <option value="{}">Persist to file</option>
-            </select>
-            <button type="submit">Send</button>
-            </form>"#,
-                clipboard::MEM,
-                clipboard::PERSIST,
-            )))
+            .body(wrap_html(&landing_html(conf).into_string()))
     }
 
+    // Errors may quote what clients sent, so they are escaped like the hash
     fn format_err(hash: &str, err: StoreError) -> String {
         format!(
-            "<p>Error for clipboard {hash}: {}</p>",
-            extract_error_msg(err)
+            "<p>Error reading clipboard <code>{}</code>: {}</p>",
+            html::escape(hash),
+            html::escape(&extract_error_msg(err)),
         )
     }
 
-    fn send_clipboard(mut self, hash: &str) -> HttpResponse {
+    fn send_clipboard(self, hash: &str) -> HttpResponse {
+        self.send_clipboard_expiring(hash, None)
+    }
+
+    fn send_clipboard_expiring(self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        self.send_clipboard_lang(hash, expires_at, None)
+    }
+
+    fn send_clipboard_lang(
+        mut self,
+        hash: &str,
+        expires_at: Option<u64>,
+        lang: Option<&str>,
+    ) -> HttpResponse {
+        let expires = expires_html(expires_at);
+        let class = lang
+            .map(|lang| format!(r#" class="language-{lang}""#))
+            .unwrap_or_default();
+
         let body = match self.1 {
             Err(err) => Self::format_err(hash, err),
 
             Ok(Some(ref clipboard)) => match String::from_utf8(clipboard.to_vec()) {
                 Ok(clip_string) => format!(
                     r#"<p>Clipboard <code>{hash}</code>:</p>
-                    <pre><code>{clip_string}</code></pre>"#,
+                    <pre><code id="clipboard"{class}>{}</code></pre>{expires}
+                    <p><button type="button" data-copy="clipboard" hidden>Copy</button>
+                    <a href="/app/drop/{hash}/download">Download</a>
+                    <span data-share="{hash}"></span></p>
+                    <script src="/script.js"></script>"#,
+                    soyjot::lang::render(&clip_string, lang),
+                    hash = html::escape(hash),
                 ),
 
                 Err(err) => Self::format_err(hash, StoreError::InvalidUtf8(err)),
@@ -103,19 +409,48 @@ impl DropResponseHttp for ResponseHtml {
             .body(html::wrap_html(&body))
     }
 
+    fn send_typed_clipboard(mut self, hash: &str, content_type: &str) -> HttpResponse {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+        if self.1.is_err() || essence.starts_with("text/") {
+            return self.send_clipboard(hash);
+        }
+
+        let hash = html::escape(hash);
+        let essence = html::escape(essence);
+
+        let body = match essence.starts_with("image/") {
+            true => format!(
+                r#"<p>Clipboard <code>{hash}</code>:</p>
+                <p><img src="/raw/drop/{hash}" alt="clipboard {hash}"></p>"#
+            ),
+            false => format!(
+                r#"<p>Clipboard <code>{hash}</code> is <code>{essence}</code>:
+                <a href="/app/drop/{hash}/download">download</a></p>"#
+            ),
+        };
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+
     fn post_clipboard(mut self, hash: &str) -> HttpResponse {
         let body = match self.1 {
             Err(err) => {
                 format!(
-                    "<p>Error saving clipboard {hash}: {}</p>",
-                    extract_error_msg(err)
+                    "<p>Error saving clipboard <code>{}</code>: {}</p>",
+                    html::escape(hash),
+                    html::escape(&extract_error_msg(err)),
                 )
             }
 
             Ok(None) => {
                 format!(
                     r#"<p>Clipboard with hash <code>{hash}</code> created</p>
-                        <p>The clipboard is now available at path <a href="/app/drop/{hash}"><code>/app/drop/{hash}</code></a></p>"#
+                        <p>The clipboard is now available at path <a href="/app/drop/{hash}"><code>/app/drop/{hash}</code></a></p>
+                        <p><img src="/app/drop/{hash}/qr" alt="QR code for clipboard {hash}"></p>"#,
+                    hash = html::escape(hash),
                 )
             }
 
@@ -126,12 +461,215 @@ impl DropResponseHttp for ResponseHtml {
             .content_type(Self::CONTENT_TYPE)
             .body(html::wrap_html(&body))
     }
+
+    fn post_clipboard_stored(
+        mut self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> HttpResponse {
+        if self.1.is_err() {
+            return self.post_clipboard(hash);
+        }
+
+        let hash = html::escape(hash);
+        let storage = match storage {
+            clipboard::PERSIST => "persisted to file".to_string(),
+            clipboard::REDIRECT => format!(
+                r#"kept in memory, redirecting from <a href="/r/{hash}"><code>/r/{hash}</code></a>"#
+            ),
+            _ => "kept in memory".to_string(),
+        };
+
+        let owner_key = match owner_key {
+            Some(key) => format!(
+                r#"<p>Owner key: <code>{key}</code></p>
+                <p>Keep it to update, extend or delete this clipboard with the <code>{OWNER_KEY_HEADER}</code> header</p>"#
+            ),
+            None => String::new(),
+        };
+
+        // The QR code is only sent to signed links with require_signed_links
+        let qr_query = signed_url
+            .and_then(|url| url.split_once('?'))
+            .map_or(String::new(), |(_, query)| format!("?{query}"));
+
+        let signed_url = match signed_url {
+            Some(url) => format!(r#"<p>Expiring link: <a href="{url}"><code>{url}</code></a></p>"#),
+            None => String::new(),
+        };
+
+        let expires = match expires_at {
+            Some(_) => expires_html(expires_at),
+            None => "<p>The clipboard is pinned, and never expires</p>".to_string(),
+        };
+
+        let body = format!(
+            r#"<p>Clipboard with hash <code>{hash}</code> created and {storage}</p>
+                <p>The clipboard is now available at path <a href="/app/drop/{hash}"><code>/app/drop/{hash}</code></a></p>
+                <p>Short link: <a href="/app/d/{short}"><code>/app/d/{short}</code></a></p>
+                {expires}{owner_key}{signed_url}
+                <p><img src="/app/drop/{hash}/qr{qr_query}" alt="QR code for clipboard {hash}"></p>"#
+        );
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+
+    fn send_meta(mut self, meta: &IndexEntry) -> HttpResponse {
+        let body = format!(
+            r#"<p>Clipboard <a href="/app/drop/{0}"><code>{0}</code></a>:</p>
+            <ul>{1}</ul>"#,
+            meta.hash,
+            meta_fields(meta)
+                .into_iter()
+                .map(|(key, val)| format!("<li>{key}: <code>{val}</code></li>"))
+                .collect::<String>(),
+        );
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+
+    fn send_versions(mut self, hash: &str, versions: &[VersionInfo]) -> HttpResponse {
+        let hash = html::escape(hash);
+        let items = versions
+            .iter()
+            .map(|v| {
+                let current = if v.current { " (current)" } else { "" };
+                format!(
+                    r#"<li><a href="/app/drop/{hash}/v/{0}">version {0}</a>{current}: {1} bytes, created at <code>{2}</code></li>"#,
+                    v.version, v.size, v.created_at,
+                )
+            })
+            .collect::<String>();
+
+        let body = format!("<p>Versions of clipboard <code>{hash}</code>:</p><ul>{items}</ul>");
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+
+    fn send_ambiguous(mut self, frag: &str, lens: &[usize]) -> HttpResponse {
+        let body = format!(
+            "<p>Prefix <code>{}</code> matches {} clipboards, whose shortest unique prefixes are {} characters long</p>",
+            html::escape(frag),
+            lens.len(),
+            join_lens(lens),
+        );
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+
+    fn send_public(mut self, drops: &[PublicDrop]) -> HttpResponse {
+        // Anyone can post public clipboards, so their snippets are escaped
+        let items = drops
+            .iter()
+            .map(|drop| {
+                let snippet = match &drop.snippet {
+                    Some(snippet) => format!("<code>{}</code>", html::escape(snippet)),
+                    None => "<em>binary</em>".to_string(),
+                };
+
+                format!(
+                    r#"<li><a href="/app/drop/{0}"><code>{0}</code></a> {1} ago: {snippet}</li>"#,
+                    drop.hash,
+                    format_age(drop.age),
+                )
+            })
+            .collect::<String>();
+
+        let body = match drops.is_empty() {
+            true => "<p>No public clipboards yet</p>".to_string(),
+            false => format!("<p>Recent public clipboards:</p><ul>{items}</ul>"),
+        };
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(html::wrap_html(&body))
+    }
+}
+
+/// landing_html renders the HTML landing page, with forms to post and fetch clipboards,
+/// the limits of the instance, and the other mounted scopes
+fn landing_html(conf: &AppConfig) -> Markup {
+    let max_size = conf.filters.as_ref().and_then(|filters| filters.max_size);
+    let api_scopes: Vec<Scope> = conf
+        .scopes()
+        .into_iter()
+        .filter(|scope| *scope != Scope::App)
+        .collect();
+
+    html! {
+        p { img src="/logo.svg" alt="actix-drop" height="32"; }
+        form action="/app/drop" method="post" {
+            textarea #textbox name="data" rows="5" cols="32" {}
+            br;
+            select id="selection box" name="store" {
+                option value=(clipboard::MEM) { "In-memory database" }
+                option value=(clipboard::PERSIST) { "Persist to file" }
+                option value=(clipboard::REDIRECT) { "Short link to a URL" }
+            }
+            button type="submit" { "Send" }
+        }
+        p #limits {
+            "Clipboards expire after " (format_ttl(Some(conf.timeout_duration())))
+            @match max_size {
+                Some(max) => ", and may be up to " (max) " bytes.",
+                None => ".",
+            }
+        }
+        form action="/app/fetch" method="get" {
+            input name="id" placeholder="Clipboard ID or prefix" required;
+            button type="submit" { "Fetch" }
+        }
+        p { a href="/app/secure" { "Send an end-to-end encrypted clipboard" } }
+        p { a href="/app/public" { "Recent public clipboards" } }
+        @if !api_scopes.is_empty() {
+            p { "Clipboards can also be posted and fetched with:" }
+            ul {
+                @for scope in api_scopes {
+                    @let prefix = scope.prefix();
+                    li {
+                        @match scope {
+                            Scope::Api => {
+                                a href=(prefix) { code { (prefix) } } ": JSON API, described at "
+                                a href="/api/openapi.json" { code { "/api/openapi.json" } }
+                            }
+                            Scope::Txt => {
+                                a href=(prefix) { code { (prefix) } } ": plain text API"
+                            }
+                            Scope::Bin => {
+                                code { (prefix) } ": MessagePack API, with the fields of "
+                                code { "/api/v1" }
+                            }
+                            _ => {
+                                code { (prefix) "/drop" } ": raw bytes, e.g. "
+                                code { "curl --data-binary @file " (prefix) "/drop" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl DropResponseHttp for ResponseText {
     const CONTENT_TYPE: &'static str = "text/plain; charset=utf-8";
+    const SENDS_TYPED_AS_IS: bool = true;
 
-    fn landing_page() -> HttpResponse {
+    type Body = String;
+
+    fn landing_page(_conf: &AppConfig) -> HttpResponse {
         HttpResponse::Ok()
             .content_type(Self::CONTENT_TYPE)
             .body(para!("actix-drop: ok"))
@@ -143,10 +681,10 @@ impl DropResponseHttp for ResponseText {
 
     fn send_clipboard(mut self, hash: &str) -> HttpResponse {
         let body = match self.1 {
-            Err(err) => Self::format_err(hash, err),
-            Ok(Some(clipboard)) => match String::from_utf8(clipboard.to_vec()) {
-                Ok(clip_string) => clip_string,
-                Err(err) => Self::format_err(hash, StoreError::InvalidUtf8(err)),
+            Err(err) => Self::format_err(hash, err).into(),
+            Ok(Some(clipboard)) => match utf8(clipboard) {
+                Ok(bytes) => bytes,
+                Err(err) => Self::format_err(hash, StoreError::InvalidUtf8(err)).into(),
             },
 
             Ok(None) => panic!("Ok(None) in match arm"),
@@ -155,6 +693,10 @@ impl DropResponseHttp for ResponseText {
         self.0.content_type(Self::CONTENT_TYPE).body(body)
     }
 
+    fn send_typed_clipboard(self, hash: &str, content_type: &str) -> HttpResponse {
+        send_typed_clipboard::<Self>(self.0, self.1, hash, content_type)
+    }
+
     fn post_clipboard(mut self, hash: &str) -> HttpResponse {
         let body = match self.1 {
             Err(err) => Self::format_err(hash, err),
@@ -166,31 +708,98 @@ impl DropResponseHttp for ResponseText {
 
         self.0.content_type(Self::CONTENT_TYPE).body(body)
     }
+
+    fn post_clipboard_stored(
+        mut self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        _expires_at: Option<u64>,
+    ) -> HttpResponse {
+        if self.1.is_err() {
+            return self.post_clipboard(hash);
+        }
+
+        let owner_key = owner_key
+            .map(|key| format!(", owner key {key}"))
+            .unwrap_or_default();
+        let signed_url = signed_url
+            .map(|url| format!(", expiring link {url}"))
+            .unwrap_or_default();
+
+        self.0.content_type(Self::CONTENT_TYPE).body(format!(
+            "clipboard {hash} (short {short}{owner_key}{signed_url}) created in {storage} storage and available at /api/drop/{hash}"
+        ))
+    }
+
+    fn send_meta(mut self, meta: &IndexEntry) -> HttpResponse {
+        let body = meta_fields(meta)
+            .into_iter()
+            .map(|(key, val)| format!("{key}: {val}\n"))
+            .collect::<String>();
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(format!("clipboard: {}\n{body}", meta.hash))
+    }
+
+    fn send_versions(mut self, _hash: &str, versions: &[VersionInfo]) -> HttpResponse {
+        let body = versions
+            .iter()
+            .map(|v| {
+                let current = if v.current { " current" } else { "" };
+                format!("{} {} {}{current}\n", v.version, v.size, v.created_at)
+            })
+            .collect::<String>();
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_ambiguous(mut self, frag: &str, lens: &[usize]) -> HttpResponse {
+        self.0.content_type(Self::CONTENT_TYPE).body(format!(
+            "prefix {frag} matches {} clipboards, with shortest unique prefixes of {} characters",
+            lens.len(),
+            join_lens(lens),
+        ))
+    }
+
+    fn send_public(mut self, drops: &[PublicDrop]) -> HttpResponse {
+        let body = drops
+            .iter()
+            .map(|drop| {
+                let snippet = drop.snippet.as_deref().unwrap_or("(binary)");
+                format!("{} {} {snippet}\n", drop.hash, format_age(drop.age))
+            })
+            .collect::<String>();
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
 }
 
 impl DropResponseHttp for ResponseJson {
     const CONTENT_TYPE: &'static str = "application/json";
+    const SENDS_TYPED_AS_IS: bool = true;
 
-    fn landing_page() -> HttpResponse {
+    type Body = String;
+
+    fn landing_page(_conf: &AppConfig) -> HttpResponse {
         HttpResponse::Ok()
             .content_type(Self::CONTENT_TYPE)
             .body("actix-drop: ok")
     }
 
     fn format_err(hash: &str, err: StoreError) -> String {
-        json!({
-            "error": extract_error_msg(err),
-            "clipboard": hash,
-        })
-        .to_string()
+        serde_json::to_string(&ErrorResponse::new(hash, err)).expect("failed to serialize error")
     }
 
     fn send_clipboard(mut self, hash: &str) -> HttpResponse {
         let body = match self.1 {
-            Err(err) => Self::format_err(hash, err),
-            Ok(Some(clipboard)) => match String::from_utf8(clipboard.to_vec()) {
-                Ok(clip_string) => clip_string,
-                Err(err) => Self::format_err(hash, StoreError::InvalidUtf8(err)),
+            Err(err) => Self::format_err(hash, err).into(),
+            Ok(Some(clipboard)) => match utf8(clipboard) {
+                Ok(bytes) => bytes,
+                Err(err) => Self::format_err(hash, StoreError::InvalidUtf8(err)).into(),
             },
 
             Ok(None) => panic!("Ok(None) in match arm"),
@@ -199,11 +808,29 @@ impl DropResponseHttp for ResponseJson {
         self.0.content_type(Self::CONTENT_TYPE).body(body)
     }
 
+    /// Clipboards are sent raw unless they are asked for in base64,
+    /// and then they are sent like `/api/v2` sends them
+    fn send_clipboard_base64(self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        ResponseJsonV2::from((self.0, self.1)).send_clipboard_base64(hash, expires_at)
+    }
+
+    fn send_typed_clipboard(self, hash: &str, content_type: &str) -> HttpResponse {
+        send_typed_clipboard::<Self>(self.0, self.1, hash, content_type)
+    }
+
     fn post_clipboard(mut self, hash: &str) -> HttpResponse {
         let body = match self.1 {
             Err(err) => Self::format_err(hash, err),
-            Ok(None) => json!({
-                "clipboard": hash,
+            Ok(None) => json!(PostResponse {
+                clipboard: hash,
+                storage: None,
+                full_hash: None,
+                short: None,
+                owner_key: None,
+                signed_url: None,
+                expires_at: None,
+                url: None,
+                short_url: None,
             })
             .to_string(),
 
@@ -212,10 +839,403 @@ impl DropResponseHttp for ResponseJson {
 
         self.0.content_type(Self::CONTENT_TYPE).body(body)
     }
+
+    fn post_clipboard_stored(
+        mut self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> HttpResponse {
+        if self.1.is_err() {
+            return self.post_clipboard(hash);
+        }
+
+        let body = json!(PostResponse {
+            clipboard: hash,
+            storage: Some(storage),
+            full_hash: Some(hash),
+            short: Some(short),
+            owner_key,
+            signed_url,
+            expires_at: expires_at.map(index::to_rfc3339),
+            url: None,
+            short_url: None,
+        });
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+
+    fn send_meta(mut self, meta: &IndexEntry) -> HttpResponse {
+        let body = json!(MetaResponse::from(meta));
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+
+    fn send_versions(mut self, hash: &str, versions: &[VersionInfo]) -> HttpResponse {
+        let body = json!(VersionsResponse {
+            clipboard: hash,
+            versions,
+        });
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+
+    fn send_ambiguous(mut self, frag: &str, lens: &[usize]) -> HttpResponse {
+        let body = json!(AmbiguousResponse {
+            prefix: frag,
+            candidates: lens.len(),
+            prefix_lens: lens,
+        });
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+
+    fn send_public(mut self, drops: &[PublicDrop]) -> HttpResponse {
+        let body = json!(PublicResponse { clipboards: drops });
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+}
+
+impl<const V: u8> ResponseJsonVersioned<V> {
+    /// send_encoded sends the clipboard as the `ClipboardResponse` returned by `encode`
+    fn send_encoded<'a>(
+        mut self,
+        hash: &'a str,
+        expires_at: Option<u64>,
+        encode: fn(&'a str, &Clipboard, Option<ExpiresAt>) -> ClipboardResponse<'a>,
+    ) -> HttpResponse {
+        let body = match self.1 {
+            Err(err) => Self::format_err(hash, err),
+            Ok(Some(clipboard)) => {
+                let expires_at = expires_at.map(|expires_at| match V {
+                    1 => ExpiresAt::Timestamp(expires_at),
+                    _ => ExpiresAt::Rfc3339(index::to_rfc3339(expires_at)),
+                });
+
+                json!(encode(hash, &clipboard, expires_at)).to_string()
+            }
+
+            Ok(None) => panic!("Ok(None) in match arm"),
+        };
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+}
+
+impl<const V: u8> DropResponseHttp for ResponseJsonVersioned<V> {
+    const CONTENT_TYPE: &'static str = ResponseJson::CONTENT_TYPE;
+
+    type Body = String;
+
+    fn landing_page(conf: &AppConfig) -> HttpResponse {
+        ResponseJson::landing_page(conf)
+    }
+
+    fn format_err(hash: &str, err: StoreError) -> String {
+        ResponseJson::format_err(hash, err)
+    }
+
+    fn send_clipboard(self, hash: &str) -> HttpResponse {
+        self.send_clipboard_expiring(hash, None)
+    }
+
+    fn send_clipboard_expiring(self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        self.send_encoded(hash, expires_at, ClipboardResponse::new)
+    }
+
+    fn send_clipboard_base64(self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        self.send_encoded(hash, expires_at, ClipboardResponse::base64)
+    }
+
+    fn send_typed_clipboard(self, hash: &str, content_type: &str) -> HttpResponse {
+        send_typed_clipboard::<Self>(self.0, self.1, hash, content_type)
+    }
+
+    fn post_clipboard(self, hash: &str) -> HttpResponse {
+        ResponseJson(self.0, self.1).post_clipboard(hash)
+    }
+
+    fn post_clipboard_stored(
+        mut self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> HttpResponse {
+        if self.1.is_err() || V < 2 {
+            return ResponseJson(self.0, self.1)
+                .post_clipboard_stored(hash, storage, short, owner_key, signed_url, expires_at);
+        }
+
+        let body = json!(PostResponse {
+            clipboard: hash,
+            storage: Some(storage),
+            full_hash: Some(hash),
+            short: Some(short),
+            owner_key,
+            signed_url,
+            expires_at: expires_at.map(index::to_rfc3339),
+            url: Some(format!("/api/v{V}/drop/{hash}")),
+            short_url: Some(format!("/api/v{V}/d/{short}")),
+        });
+
+        self.0
+            .content_type(Self::CONTENT_TYPE)
+            .body(body.to_string())
+    }
+
+    fn send_meta(self, meta: &IndexEntry) -> HttpResponse {
+        ResponseJson(self.0, self.1).send_meta(meta)
+    }
+
+    fn send_versions(self, hash: &str, versions: &[VersionInfo]) -> HttpResponse {
+        ResponseJson(self.0, self.1).send_versions(hash, versions)
+    }
+
+    fn send_ambiguous(self, frag: &str, lens: &[usize]) -> HttpResponse {
+        ResponseJson(self.0, self.1).send_ambiguous(frag, lens)
+    }
+
+    fn send_public(self, drops: &[PublicDrop]) -> HttpResponse {
+        ResponseJson(self.0, self.1).send_public(drops)
+    }
+}
+
+impl DropResponseHttp for ResponseMsgpack {
+    const CONTENT_TYPE: &'static str = "application/msgpack";
+
+    type Body = Vec<u8>;
+
+    fn landing_page(_conf: &AppConfig) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type(Self::CONTENT_TYPE)
+            .body(msgpack(&"actix-drop: ok"))
+    }
+
+    fn format_err(hash: &str, err: StoreError) -> Vec<u8> {
+        msgpack(&ErrorResponse::new(hash, err))
+    }
+
+    fn send_clipboard(self, hash: &str) -> HttpResponse {
+        self.send_clipboard_expiring(hash, None)
+    }
+
+    fn send_clipboard_expiring(mut self, hash: &str, expires_at: Option<u64>) -> HttpResponse {
+        let body = match self.1 {
+            Err(err) => Self::format_err(hash, err),
+            Ok(Some(clipboard)) => msgpack(&BinClipboardResponse {
+                clipboard: hash,
+                data: clipboard.as_ref(),
+                expires_at,
+            }),
+
+            Ok(None) => panic!("Ok(None) in match arm"),
+        };
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_typed_clipboard(self, hash: &str, content_type: &str) -> HttpResponse {
+        send_typed_clipboard::<Self>(self.0, self.1, hash, content_type)
+    }
+
+    fn post_clipboard(mut self, hash: &str) -> HttpResponse {
+        let body = match self.1 {
+            Err(err) => Self::format_err(hash, err),
+            Ok(None) => msgpack(&PostResponse {
+                clipboard: hash,
+                storage: None,
+                full_hash: None,
+                short: None,
+                owner_key: None,
+                signed_url: None,
+                expires_at: None,
+                url: None,
+                short_url: None,
+            }),
+
+            Ok(Some(_)) => panic!("Ok(Some) in match arm"),
+        };
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn post_clipboard_stored(
+        mut self,
+        hash: &str,
+        storage: &str,
+        short: &str,
+        owner_key: Option<&str>,
+        signed_url: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> HttpResponse {
+        if self.1.is_err() {
+            return self.post_clipboard(hash);
+        }
+
+        let body = msgpack(&PostResponse {
+            clipboard: hash,
+            storage: Some(storage),
+            full_hash: Some(hash),
+            short: Some(short),
+            owner_key,
+            signed_url,
+            expires_at: expires_at.map(index::to_rfc3339),
+            url: None,
+            short_url: None,
+        });
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_meta(mut self, meta: &IndexEntry) -> HttpResponse {
+        let body = msgpack(&MetaResponse::from(meta));
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_versions(mut self, hash: &str, versions: &[VersionInfo]) -> HttpResponse {
+        let body = msgpack(&VersionsResponse {
+            clipboard: hash,
+            versions,
+        });
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_ambiguous(mut self, frag: &str, lens: &[usize]) -> HttpResponse {
+        let body = msgpack(&AmbiguousResponse {
+            prefix: frag,
+            candidates: lens.len(),
+            prefix_lens: lens,
+        });
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+
+    fn send_public(mut self, drops: &[PublicDrop]) -> HttpResponse {
+        let body = msgpack(&PublicResponse { clipboards: drops });
+
+        self.0.content_type(Self::CONTENT_TYPE).body(body)
+    }
+}
+
+/// utf8 returns the content of `clipboard` if it's valid UTF-8, sharing its bytes
+/// so that they are sent without being copied
+fn utf8(clipboard: Clipboard) -> Result<Bytes, FromUtf8Error> {
+    match std::str::from_utf8(&clipboard) {
+        Ok(_) => Ok(clipboard.into_bytes()),
+        Err(_) => String::from_utf8(clipboard.to_vec()).map(Bytes::from),
+    }
+}
+
+/// msgpack serializes `value` as MessagePack, with structs as maps keyed by field name
+fn msgpack<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    rmp_serde::to_vec_named(value).expect("failed to serialize MessagePack")
+}
+
+/// send_typed_clipboard sends the clipboard in `result` as-is with `content_type`,
+/// hardened with `http_server::untrusted`, or the error formatted by `R`.
+fn send_typed_clipboard<R: DropResponseHttp>(
+    mut resp: HttpResponseBuilder,
+    result: DropResult,
+    hash: &str,
+    content_type: &str,
+) -> HttpResponse {
+    match result {
+        Ok(Some(clipboard)) => http_server::untrusted(
+            resp.content_type(content_type).body(clipboard.into_bytes()),
+            content_type,
+        ),
+        Ok(None) => panic!("Ok(None) in match arm"),
+        Err(err) => resp
+            .content_type(R::CONTENT_TYPE)
+            .body(R::format_err(hash, err)),
+    }
+}
+
+/// join_lens lists prefix lengths for humans, e.g. `4, 4, 5`
+fn join_lens(lens: &[usize]) -> String {
+    lens.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// expires_html tells when a clipboard expiring at `expires_at`, as seconds since
+/// the UNIX epoch, expires, e.g. `expires in 14m 59s`, with the exact time as a tooltip
+fn expires_html(expires_at: Option<u64>) -> String {
+    let Some(expires_at) = expires_at else {
+        return String::new();
+    };
+
+    let deadline = UNIX_EPOCH + Duration::from_secs(expires_at);
+    let remaining = deadline.duration_since(SystemTime::now()).ok();
+
+    format!(
+        r#"<p>The clipboard <time datetime="{0}" title="{0}">expires in {1}</time></p>"#,
+        index::to_rfc3339(expires_at),
+        format_ttl(remaining),
+    )
+}
+
+/// format_age formats the age of a clipboard in its largest unit, e.g. `5m`
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
 }
 
-pub fn extract_error_msg(err: StoreError) -> String {
+fn extract_error_msg(err: StoreError) -> String {
     public_error(err)
         .unwrap_or_else(|| StoreError::Bug("private error".to_string()))
         .to_string()
 }
+
+/// meta_fields lists `IndexEntry` fields shown to clients, in display order
+fn meta_fields(meta: &IndexEntry) -> Vec<(&'static str, String)> {
+    let or_none = |val: Option<u64>| val.map_or("none".to_string(), |val| val.to_string());
+
+    vec![
+        ("storage", meta.storage.clone()),
+        ("expires_at", meta.expires_at.to_string()),
+        ("pinned", meta.pinned.to_string()),
+        ("views", meta.views.to_string()),
+        ("max_views", or_none(meta.max_views)),
+        ("last_access", or_none(meta.last_access)),
+        (
+            "content_type",
+            meta.content_type.clone().unwrap_or("none".to_string()),
+        ),
+        (
+            "encryption",
+            meta.encryption.clone().unwrap_or("none".to_string()),
+        ),
+        (
+            "tags",
+            match meta.tags.is_empty() {
+                true => "none".to_string(),
+                false => meta.tags.join(","),
+            },
+        ),
+    ]
+}
@@ -0,0 +1,57 @@
+//! The janitor removes clipboard files that no clipboard tracks every `AppConfig::janitor_interval`
+//! seconds while the server is running, so that files left behind (e.g. by failed removals)
+//! do not slowly fill the disk until the next restart. With `AppConfig::janitor_max_age`,
+//! persisted clipboards with older files are removed too (see `Store::sweep`).
+//! The max age is read from the shared config on every run, so it can be changed with a reload.
+
+use std::path::Path;
+use std::time::Duration;
+
+use actix_web::web;
+use colored::Colorize;
+
+use soyjot::store::{persist, Store};
+
+use crate::reload::SharedConfig;
+
+/// spawn spawns the janitor, which first runs one `interval` after startup
+pub fn spawn(store: web::Data<Store>, conf: web::Data<SharedConfig>, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+
+        loop {
+            ticks.tick().await;
+
+            let max_age = conf.load().janitor_max_age.map(Duration::from_secs);
+            if let Err(err) = sweep(&store, max_age).await {
+                eprintln!(
+                    "{} {err}",
+                    "janitor: error scanning storage directory:".red()
+                );
+            }
+        }
+    });
+}
+
+/// sweep runs the janitor once, logging every file it removed
+async fn sweep(store: &Store, max_age: Option<Duration>) -> Result<(), String> {
+    let Some(dir) = store.dir().map(Path::to_path_buf) else {
+        return Ok(());
+    };
+
+    let files = web::block(move || persist::list_dir(&dir))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+    let removed = store.sweep(files, max_age).await;
+    for name in &removed {
+        println!("{} {name}", "janitor: removed".yellow());
+    }
+
+    if !removed.is_empty() {
+        println!("{} {}", "janitor: files removed:".yellow(), removed.len());
+    }
+
+    Ok(())
+}
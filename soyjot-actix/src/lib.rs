@@ -0,0 +1,27 @@
+//! actix-web server of actix-drop. The `soyjot-actix` binary runs it from the config,
+//! and other applications can embed it with `server::DropServer`.
+
+mod admin;
+mod archive;
+mod assets;
+mod bundle;
+mod chunks;
+mod drops;
+mod http_resp;
+mod http_server;
+mod janitor;
+#[cfg(feature = "mailer")]
+mod mailer;
+mod middleware;
+mod openapi;
+mod reload;
+mod replication;
+mod search;
+mod secure;
+pub mod server;
+mod share;
+mod tenants;
+mod tls;
+mod upload_tokens;
+mod webhooks;
+mod ws;
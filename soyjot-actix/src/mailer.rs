@@ -0,0 +1,371 @@
+//! Emailing clipboards with `POST /api/drop/{id}/email`, in builds with the `mailer` feature.
+//!
+//! The JSON body has the address to email, and whether to send the content of the clipboard
+//! instead of a link to it, e.g. `{"to": "ops@example.com", "content": true}`. Clipboards
+//! may only be emailed to the domains in `MailerConfig::allowed_domains`, and each client
+//! may only send `MailerConfig::rate_limit` emails, so that the instance cannot be used
+//! to spam arbitrary addresses. Sending the content counts a view, like reading it.
+//!
+//! Emails are sent through the SMTP server of `AppConfig::mailer` with `lettre`,
+//! one message per connection, encoded in base64.
+
+use std::time::Duration;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use lettre::message::header::{ContentTransferEncoding, ContentType};
+use lettre::message::{Mailbox, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::config::{AppConfig, MailerConfig, SmtpTls};
+use soyjot::rate_limit::{RateLimitConfig, RateLimiter};
+use soyjot::store::error::StoreError;
+use soyjot::store::{index, Store};
+
+use crate::http_resp::ResponseJson;
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+/// Time sending an email may take before it fails
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest clipboard whose content may be emailed, in bytes
+const MAX_CONTENT: u64 = 1024 * 1024;
+
+/// Emails each client may send, unless configured with `MailerConfig::rate_limit`
+fn default_rate_limit() -> RateLimitConfig {
+    RateLimitConfig {
+        burst: 5,
+        per_sec: 1.0 / 60.0,
+    }
+}
+
+/// Mailer limits the emails sent by each client, shared by every worker
+pub struct Mailer {
+    limiter: RateLimiter,
+}
+
+impl Mailer {
+    /// new returns a mailer limiting clients to the rate limit of `conf`, if any
+    pub fn new(conf: Option<&MailerConfig>) -> Self {
+        let limits = conf.and_then(|conf| conf.rate_limit.clone());
+
+        Self {
+            limiter: RateLimiter::new(limits.unwrap_or_else(default_rate_limit)),
+        }
+    }
+}
+
+/// EmailRequest is the JSON body of `POST /api/drop/{id}/email`
+#[derive(Deserialize, Debug)]
+struct EmailRequest {
+    /// Address to email the clipboard to
+    to: String,
+    /// Send the content of the clipboard instead of a link to it
+    #[serde(default)]
+    content: bool,
+}
+
+/// routes returns the email route, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Resource {
+    web::resource("/api/drop/{id}/email")
+        .guard(http_server::global_ids())
+        .route(web::post().to(email_clipboard))
+}
+
+/// email_clipboard emails clipboard `id`, or a link to it, to the address in the body
+async fn email_clipboard(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    mailer: web::Data<Mailer>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    web::Json(email): web::Json<EmailRequest>,
+) -> HttpResponse {
+    let hash = path.into_inner();
+    let conf = conf.load();
+    let Some(mailer_conf) = conf.mailer.as_ref() else {
+        let err = StoreError::NotImplemented("emailing clipboards needs mailer".to_string());
+        return http_server::store_error::<R>(&hash, err);
+    };
+
+    if let Err(err) = http_server::check_link(&req, &hash) {
+        return http_server::store_error::<R>(&hash, err);
+    }
+
+    if !allowed(&email.to, &mailer_conf.allowed_domains) {
+        let err =
+            StoreError::InvalidRequest(format!("clipboards cannot be emailed to {}", email.to));
+        return http_server::store_error::<R>(&hash, err);
+    }
+
+    if let Some(Err(wait)) = http_server::client_ip(&req).map(|ip| mailer.limiter.check(ip)) {
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait.as_secs().max(1).to_string()))
+            .content_type("text/plain; charset=utf-8")
+            .body("rate limit exceeded");
+    }
+
+    let body = match email.content {
+        true => content(&store, &hash).await,
+        false => link(&store, &conf, &hash),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(err) => return http_server::send_error::<R>(&hash, err),
+    };
+
+    let subject = format!("Clipboard {hash}");
+    match send(mailer_conf, &email.to, &subject, &body).await {
+        Ok(()) => HttpResponse::Ok().json(json!({ "clipboard": hash, "to": email.to })),
+        Err(err) => {
+            eprintln!("mailer: failed to email {hash} to {}: {err}", email.to);
+            let err = StoreError::IoError(std::io::Error::other(err));
+            http_server::store_error::<R>(&hash, err)
+        }
+    }
+}
+
+/// allowed reports whether `to` is a plain email address, e.g. not `Name <a@b>`,
+/// in one of `domains`
+fn allowed(to: &str, domains: &[String]) -> bool {
+    let Some((local, domain)) = to.rsplit_once('@') else {
+        return false;
+    };
+
+    (1..=64).contains(&local.len())
+        && local
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-/=?^_`{|}~.".contains(&b))
+        && domains
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+}
+
+/// content returns the content of text clipboard `hash` to email, counting a view
+async fn content(store: &Store, hash: &str) -> Result<String, StoreError> {
+    let meta = store.meta(hash).ok_or_else(|| store.not_found(hash))?;
+    if meta.encryption.is_some() {
+        let err = "encrypted clipboards can only be emailed as links".to_string();
+        return Err(StoreError::InvalidRequest(err));
+    }
+
+    if meta.size > MAX_CONTENT {
+        return Err(StoreError::TooLarge(MAX_CONTENT));
+    }
+
+    let clipboard = store
+        .get_clipboard(hash)
+        .await
+        .ok_or_else(|| store.not_found(hash))?;
+
+    String::from_utf8(clipboard.to_vec()).map_err(StoreError::InvalidUtf8)
+}
+
+/// link returns the text of an email linking to the HTML view of clipboard `hash`,
+/// signed if `AppConfig::require_signed_links` is set (see `http_server::view_url`)
+fn link(store: &Store, conf: &AppConfig, hash: &str) -> Result<String, StoreError> {
+    let meta = store.meta(hash).ok_or_else(|| store.not_found(hash))?;
+    let url = http_server::view_url(conf, &meta);
+
+    let expires = match meta.pinned {
+        true => String::new(),
+        false => format!("\n\nIt expires at {}.", index::to_rfc3339(meta.expires_at)),
+    };
+
+    Ok(format!(
+        "A clipboard was shared with you:\n\n{url}{expires}\n"
+    ))
+}
+
+/// send emails `body` as plain text to `to` through the SMTP server of `conf`
+async fn send(conf: &MailerConfig, to: &str, subject: &str, body: &str) -> Result<(), String> {
+    let message = message(&conf.from, to, subject, body)?;
+    let transport = transport(conf)?;
+
+    match actix_web::rt::time::timeout(TIMEOUT, transport.send(message)).await {
+        Ok(sent) => sent.map(|_| ()).map_err(|err| err.to_string()),
+        Err(_) => Err(format!("timed out after {TIMEOUT:?}")),
+    }
+}
+
+/// transport returns the SMTP transport of `conf`. It has no connection pool, so each message
+/// is submitted in its own SMTP session. TLS certificates are verified against the web PKI roots.
+fn transport(conf: &MailerConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    type Transport = AsyncSmtpTransport<Tokio1Executor>;
+
+    let builder = match conf.tls {
+        SmtpTls::Implicit => Transport::relay(&conf.host),
+        SmtpTls::StartTls => Transport::starttls_relay(&conf.host),
+        SmtpTls::None => Ok(Transport::builder_dangerous(&conf.host)),
+    }
+    .map_err(|err| format!("bad host {}: {err}", conf.host))?
+    .port(conf.port())
+    .timeout(Some(TIMEOUT));
+
+    let builder = match &conf.username {
+        Some(username) => builder.credentials(Credentials::new(
+            username.clone(),
+            conf.password.clone().unwrap_or_default(),
+        )),
+        None => builder,
+    };
+
+    Ok(builder.build())
+}
+
+/// message returns the email of `body` from `from` to `to` with its headers,
+/// with the body encoded in base64
+fn message(from: &str, to: &str, subject: &str, body: &str) -> Result<Message, String> {
+    let mailbox = |addr: &str| {
+        addr.parse::<Mailbox>()
+            .map_err(|err| format!("bad address {addr}: {err}"))
+    };
+
+    let body = SinglePart::builder()
+        .header(ContentType::TEXT_PLAIN)
+        .header(ContentTransferEncoding::Base64)
+        .body(body.to_string());
+
+    Message::builder()
+        .from(mailbox(from)?)
+        .to(mailbox(to)?)
+        .subject(subject)
+        .singlepart(body)
+        .map_err(|err| format!("bad message: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use base64::Engine;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use soyjot::config::{AppConfig, MailerConfig, SmtpTls};
+    use soyjot::hash::HashConfig;
+    use soyjot::rate_limit::RateLimitConfig;
+    use soyjot::store::Store;
+
+    use super::Mailer;
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    /// serve accepts one SMTP session on localhost, and returns its port
+    /// and the task returning the body of the message it was sent, decoded
+    async fn serve() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let task = tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let mut conn = BufReader::new(conn);
+            conn.get_mut().write_all(b"220 ready\r\n").await.unwrap();
+
+            let (mut line, mut data, mut in_data) = (String::new(), Vec::new(), false);
+            loop {
+                line.clear();
+                if conn.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+
+                let reply: &[u8] = match line.trim_end() {
+                    "." if in_data => {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if in_data => {
+                        data.push(line.trim_end().to_string());
+                        continue;
+                    }
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go on\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    cmd if cmd.starts_with("EHLO") => b"250-hi\r\n250 SMTPUTF8\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                conn.get_mut().write_all(reply).await.unwrap();
+            }
+
+            // Headers end at the first empty line
+            let body: String = data
+                .iter()
+                .skip_while(|line| !line.is_empty())
+                .cloned()
+                .collect();
+            let body = base64::engine::general_purpose::STANDARD
+                .decode(body)
+                .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+
+        (port, task)
+    }
+
+    #[actix_web::test]
+    async fn test_email() {
+        let (port, session) = serve().await;
+        let mailer = MailerConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "drop@example.com".to_string(),
+            allowed_domains: vec!["example.com".to_string()],
+            rate_limit: Some(RateLimitConfig {
+                burst: 1,
+                per_sec: 0.0,
+            }),
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(web::Data::new(Mailer::new(Some(&mailer))))
+                .app_data(reload::shared(AppConfig {
+                    mailer: Some(mailer),
+                    ..AppConfig::default()
+                }))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/drop")
+            .set_json(serde_json::json!({ "mem": "mail me" }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let hash = body["clipboard"].as_str().unwrap();
+
+        let email = |to: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/drop/{hash}/email"))
+                .peer_addr("127.0.0.1:1234".parse().unwrap())
+                .set_json(serde_json::json!({ "to": to, "content": true }))
+                .to_request()
+        };
+
+        for to in ["ops@example.org", "Ops <ops@example.com>", "example.com"] {
+            let resp = test::call_service(&app, email(to)).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{to}");
+        }
+
+        let resp = test::call_service(&app, email("ops@EXAMPLE.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(session.await.unwrap(), "mail me");
+
+        let resp = test::call_service(&app, email("ops@example.com")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
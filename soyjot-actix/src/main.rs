@@ -1,57 +1,54 @@
-mod http_resp;
-mod http_server;
+use clap::Parser;
+use colored::Colorize;
+
+use soyjot::config::{AppConfig, ConfigArgs, ConfigError};
+use soyjot_actix::server::{self, DropServer};
+
+/// actix-drop server. Flags override config files and `DROP_` envs.
+#[derive(clap::Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+}
 
-#[cfg(unix)] // Our code currently uses UNIX file paths
 #[actix_web::main]
 async fn main() {
-    use std::time::Duration;
-
-    use actix_web::{middleware, web, App, HttpServer};
-    use colored::Colorize;
+    let args = Cli::parse();
+    let conf = match AppConfig::build_with(&args.config) {
+        Ok(conf) => conf,
+        Err(ConfigError::Invalid(problems)) => {
+            eprintln!("{}", "error: bad configuration".red());
+            for problem in problems {
+                eprintln!("  {problem}");
+            }
+
+            std::process::exit(1);
+        }
+
+        Err(err) => {
+            eprintln!("{} {err}", "error:".red());
+            std::process::exit(1);
+        }
+    };
 
-    use soyjot::config::AppConfig;
-    use soyjot::store::{self, Store};
-
-    let conf = AppConfig::init();
     println!(
         "\n{}\n{}\n",
         "Starting actix-drop: current configuration".yellow(),
-        serde_json::to_string(&conf).unwrap()
+        serde_json::to_string(&conf.app).unwrap()
     );
 
-    // Ensure that ./${DIR} is a directory
-    store::persist::assert_dir(conf.dir);
+    let clipboards = server::open_store(&conf);
 
-    let http_addr = format!(
-        "{}:{}",
-        conf.http_addr.expect(&"http_addr is None".red()),
-        conf.http_port.expect(&"http_port is None".red()),
-    );
-
-    println!(
-        "{} {}",
-        "Starting actix-web on".yellow(),
-        format!("http://{}", http_addr).cyan()
-    );
+    DropServer::new(conf)
+        .store(clipboards.clone())
+        .reload_on_sighup(args.config)
+        .run()
+        .unwrap_or_else(|err| panic!("{} {err}", "error starting server:".red()))
+        .await
+        .unwrap_or_else(|err| panic!("{}: {err}", "error running server".red()));
 
-    HttpServer::new(move || {
-        App::new()
-            .wrap(middleware::NormalizePath::new(
-                middleware::TrailingSlash::Trim,
-            ))
-            .app_data(web::Data::new(Duration::from_secs(
-                conf.timeout.expect("timeout is None"),
-            )))
-            .app_data(web::Data::new(String::from(http_server::CSS)))
-            .app_data(web::Data::new(Store::new()))
-            .service(web::resource("/style.css").route(web::get().to(http_server::serve_css)))
-            .service(http_server::routes::<http_resp::ResponseHtml>("/app"))
-            .service(http_server::routes::<http_resp::ResponseJson>("/api"))
-            .service(http_server::routes::<http_resp::ResponseText>("/txt"))
-    })
-    .bind(http_addr)
-    .expect(&"error binding server to address".red())
-    .run()
-    .await
-    .expect(&"error running server".red());
+    // The server has shut down gracefully (e.g. on SIGTERM), so the index of live clipboards
+    // is flushed to disk for the next startup.
+    server::save_index(&clipboards);
 }
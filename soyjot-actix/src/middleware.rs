@@ -0,0 +1,658 @@
+use std::sync::Arc;
+
+use actix_cors::Cors;
+use actix_web::body::{BodySize, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+
+use soyjot::audit::{Action, AuditLog, Record};
+use soyjot::rate_limit::RateLimiter;
+use soyjot::store::Store;
+
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+/// rate_limit rejects requests with 429 Too Many Requests once the client IP
+/// (see `http_server::client_ip`) runs out of tokens in the `RateLimiter` registered as app data.
+/// If no `RateLimiter` is registered, all requests are let through.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>();
+    let peer = http_server::client_ip(req.request());
+
+    if let (Some(limiter), Some(ip)) = (limiter, peer) {
+        if let Err(wait) = limiter.check(ip) {
+            let resp = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, wait.as_secs().max(1).to_string()))
+                .content_type("text/plain; charset=utf-8")
+                .body("rate limit exceeded");
+
+            return Ok(req.into_response(resp).map_into_right_body());
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// load_shed rejects requests posting new clipboards or appending to them with
+/// 503 Service Unavailable while the `Store` registered as app data is above
+/// the high-water marks of `AppConfig::load_shed`. Other requests, e.g. reads and deletes,
+/// are always let through, so that the instance keeps serving what it already has.
+pub async fn load_shed(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let shed = req
+        .app_data::<web::Data<SharedConfig>>()
+        .and_then(|conf| conf.load().load_shed.clone());
+    let store = req.app_data::<web::Data<Store>>();
+
+    if let (Some(shed), Some(store)) = (shed, store) {
+        if writes(&req) && shed.overloaded(store.mem_bytes(), store.disk_bytes()) {
+            let resp = HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, shed.retry_after.to_string()))
+                .content_type("text/plain; charset=utf-8")
+                .body("server is overloaded, try again later");
+
+            return Ok(req.into_response(resp).map_into_right_body());
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// writes reports whether `req` posts new clipboards or appends to one,
+/// i.e. `POST {prefix}/drop`, `POST {prefix}/drop/{id}/append` of any scope,
+/// a batch upload to `POST /api/drops`, a chunked upload (see `chunks`),
+/// a bundle posted to `POST /api/bundle` or an archive imported to `POST /api/admin/import`
+fn writes(req: &ServiceRequest) -> bool {
+    let path = req.path().trim_end_matches('/');
+    let suffixes = [
+        "/drop",
+        "/append",
+        "/drops",
+        "/finalize",
+        "/api/uploads",
+        "/api/bundle",
+        "/api/admin/import",
+    ];
+    let chunk = path.rsplit('/').nth(1) == Some("chunks");
+
+    req.method() == Method::POST && (chunk || suffixes.iter().any(|suffix| path.ends_with(suffix)))
+}
+
+/// Created marks the response to a request that stored the clipboard with this key,
+/// so that `audit` knows the key of new clipboards
+pub(crate) struct Created(pub String);
+
+/// Read marks the response to a request that read these clipboards, other than the one
+/// of its route, e.g. a batch fetch or an export, so that `audit` records a read of each
+pub(crate) struct Read(pub Vec<String>);
+
+/// audit appends a record to the `AuditLog` registered as app data for every request that
+/// creates, reads, appends to or deletes a clipboard, once it's handled, with the status
+/// of the response. Requests are told apart by the route they matched, in any scope,
+/// and requests reading other clipboards record a read of each clipboard marked with `Read`.
+/// If no `AuditLog` is registered, nothing is recorded.
+pub async fn audit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(log) = req.app_data::<web::Data<AuditLog>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let received = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok()?.parse::<u64>().ok());
+
+    let resp = next.call(req).await?;
+    let http_req = resp.request();
+    let client = http_server::client_ip(http_req);
+    let status = resp.status().as_u16();
+
+    let record = |record: Record| {
+        if let Err(err) = log.record(&record) {
+            eprintln!("error writing audit log: {err}");
+        }
+    };
+
+    if let Some(read) = resp.response().extensions().get::<Read>() {
+        for hash in &read.0 {
+            record(Record::new(Action::Read, Some(hash), client, status));
+        }
+    }
+
+    let Some(action) = http_req
+        .match_pattern()
+        .and_then(|pattern| audited(http_req.method(), &pattern))
+    else {
+        return Ok(resp);
+    };
+
+    let hash = match action {
+        Action::Create => resp
+            .response()
+            .extensions()
+            .get::<Created>()
+            .map(|created| created.0.clone()),
+        _ => http_req
+            .match_info()
+            .get("id")
+            .or_else(|| http_req.match_info().get("frag"))
+            .map(str::to_owned),
+    };
+
+    let size = match action {
+        Action::Create | Action::Append => received,
+        Action::Read => match resp.response().body().size() {
+            BodySize::Sized(size) => Some(size),
+            _ => None,
+        },
+        Action::Delete => None,
+    };
+
+    record(Record::new(action, hash.as_deref(), client, status).size(size));
+
+    Ok(resp)
+}
+
+/// audited returns the action of requests with `method` matching route `pattern`, if they're audited.
+/// Clipboards emailed or shared to a webhook are read on behalf of the client.
+fn audited(method: &Method, pattern: &str) -> Option<Action> {
+    let is_read = ["/drop/{id}", "/d/{frag}", "/drop/{id}/v/{n}", "/r/{id}"]
+        .iter()
+        .any(|path| pattern.ends_with(path));
+    let is_sent = ["/drop/{id}/email", "/drop/{id}/share"]
+        .iter()
+        .any(|path| pattern.ends_with(path));
+
+    match *method {
+        Method::POST if pattern.ends_with("/drop") => Some(Action::Create),
+        Method::POST if pattern.ends_with("/bundle") => Some(Action::Create),
+        Method::POST if pattern.ends_with("/drop/{upload}/finalize") => Some(Action::Create),
+        Method::POST if pattern.ends_with("/drop/{id}/append") => Some(Action::Append),
+        Method::POST if pattern.ends_with("/drop/{id}/delete") => Some(Action::Delete),
+        Method::DELETE if pattern.ends_with("/drop/{id}") => Some(Action::Delete),
+        Method::GET if is_read => Some(Action::Read),
+        Method::POST if is_sent => Some(Action::Read),
+        _ => None,
+    }
+}
+
+/// Hook is a request hook added with `DropServer::hook`
+pub type Hook = Arc<dyn Fn(&ServiceRequest) -> Option<HttpResponse> + Send + Sync>;
+
+/// Hooks are the request hooks registered as app data, run by `hooks`
+pub struct Hooks(Vec<Hook>);
+
+impl Hooks {
+    pub fn new(hooks: Vec<Hook>) -> Self {
+        Self(hooks)
+    }
+}
+
+/// hooks runs the `Hooks` registered as app data in order, and sends the response
+/// of the first hook that returns one instead of routing the request.
+/// If no `Hooks` are registered, all requests are let through.
+pub async fn hooks(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let resp = req
+        .app_data::<web::Data<Hooks>>()
+        .and_then(|hooks| hooks.0.iter().find_map(|hook| hook(&req)));
+
+    if let Some(resp) = resp {
+        return Ok(req.into_response(resp).map_into_right_body());
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// security_headers adds `AppConfig::security_headers` to HTML responses, and
+/// `X-Content-Type-Options` to all responses. Headers already set by handlers are kept.
+/// The headers are read from the `SharedConfig` registered as app data, so that they
+/// can be changed with a reload.
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let conf = req
+        .app_data::<web::Data<SharedConfig>>()
+        .and_then(|conf| conf.load().security_headers.clone())
+        .unwrap_or_default();
+
+    let mut resp = next.call(req).await?;
+
+    let is_html = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+
+    let headers = resp.headers_mut();
+    if conf.nosniff && !headers.contains_key(header::X_CONTENT_TYPE_OPTIONS) {
+        headers.insert(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+    }
+
+    if is_html {
+        for (name, value) in conf.headers() {
+            let name = HeaderName::from_static(name);
+            if headers.contains_key(&name) {
+                continue;
+            }
+
+            // Values are checked when the config is validated
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    Ok(resp)
+}
+
+/// cors allows cross-origin requests from `origins`, or from any origin if it contains `*`.
+/// Requests may send the headers used for posting, conditional or partial downloads,
+/// owner keys and bearer tokens, and responses expose the `ETag` and `Content-Range` headers
+/// and the keys of clipboards, so that tools can delete what they posted.
+pub fn cors(origins: &[String]) -> Cors {
+    let owner_key = HeaderName::from_static(http_server::OWNER_KEY_HEADER);
+    let cors = Cors::default()
+        .allowed_methods(["GET", "HEAD", "POST", "DELETE"])
+        .allowed_headers([
+            header::CONTENT_TYPE,
+            header::IF_NONE_MATCH,
+            header::RANGE,
+            header::AUTHORIZATION,
+            owner_key.clone(),
+        ])
+        .expose_headers([
+            header::ETAG,
+            header::CONTENT_RANGE,
+            owner_key,
+            HeaderName::from_static(http_server::DROP_KEY_HEADER),
+        ])
+        .max_age(3600);
+
+    match origins.iter().any(|origin| origin == "*") {
+        true => cors.allow_any_origin(),
+        false => origins
+            .iter()
+            .fold(cors, |cors, origin| cors.allowed_origin(origin)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, middleware, test, web, App, HttpResponse};
+
+    use soyjot::config::{AppConfig, SecurityHeaders, DEFAULT_CSP};
+
+    use crate::reload;
+
+    #[actix_web::test]
+    async fn test_security_headers() {
+        let html = || async {
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body("<p>")
+        };
+        let conf = reload::shared(AppConfig::default());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(conf.clone())
+                .wrap(middleware::from_fn(super::security_headers))
+                .route("/html", web::get().to(html))
+                .route(
+                    "/raw",
+                    web::get().to(|| async { HttpResponse::Ok().body("<p>") }),
+                ),
+        )
+        .await;
+
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let resp = test::call_service(&app, get("/html")).await;
+        let headers = resp.headers();
+        assert_eq!(
+            headers.get(header::CONTENT_SECURITY_POLICY).unwrap(),
+            DEFAULT_CSP
+        );
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+        assert_eq!(
+            headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+
+        // Non-HTML responses are only kept from being sniffed
+        let resp = test::call_service(&app, get("/raw")).await;
+        assert!(resp
+            .headers()
+            .get(header::CONTENT_SECURITY_POLICY)
+            .is_none());
+        assert_eq!(
+            resp.headers().get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+
+        // Headers can be relaxed or left out with a reload
+        conf.store(std::sync::Arc::new(AppConfig {
+            security_headers: Some(SecurityHeaders {
+                frame_options: "SAMEORIGIN".to_string(),
+                content_security_policy: String::new(),
+                ..SecurityHeaders::default()
+            }),
+            ..AppConfig::default()
+        }));
+        let resp = test::call_service(&app, get("/html")).await;
+        assert_eq!(
+            resp.headers().get(header::X_FRAME_OPTIONS).unwrap(),
+            "SAMEORIGIN"
+        );
+        assert!(resp
+            .headers()
+            .get(header::CONTENT_SECURITY_POLICY)
+            .is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_load_shed() {
+        use std::time::Duration;
+
+        use actix_web::http::StatusCode;
+
+        use soyjot::config::LoadShedConfig;
+        use soyjot::store::clipboard::Clipboard;
+        use soyjot::store::{Store, StoreOpts};
+
+        let store = web::Data::new(Store::new());
+        let conf = reload::shared(AppConfig {
+            load_shed: Some(LoadShedConfig {
+                mem_bytes: Some(4),
+                disk_bytes: None,
+                retry_after: 10,
+            }),
+            ..AppConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(conf)
+                .app_data(store.clone())
+                .wrap(middleware::from_fn(super::load_shed))
+                .route("/api/drop", web::post().to(HttpResponse::Ok))
+                .route("/api/drop/{id}", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/api/drop/{upload}/chunks/{n}",
+                    web::post().to(HttpResponse::Ok),
+                )
+                .route("/api/bundle", web::post().to(HttpResponse::Ok))
+                .route("/api/admin/import", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let post = || test::TestRequest::post().uri("/api/drop").to_request();
+        let chunk = || {
+            test::TestRequest::post()
+                .uri("/api/drop/up/chunks/0")
+                .to_request()
+        };
+        let get = || test::TestRequest::get().uri("/api/drop/shed").to_request();
+
+        let resp = test::call_service(&app, post()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Store::store_new_clipboard(
+            store.clone().into_inner(),
+            "shed",
+            "shed",
+            Clipboard::Mem("foobar".into()),
+            Duration::from_secs(90),
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        // New clipboards are shed above the high-water mark, but reads are still served
+        let resp = test::call_service(&app, post()).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "10");
+
+        let resp = test::call_service(&app, chunk()).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        for uri in ["/api/bundle", "/api/admin/import"] {
+            let req = test::TestRequest::post().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE, "{uri}");
+        }
+
+        let resp = test::call_service(&app, get()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        assert!(store.remove_clipboard("shed").await.unwrap());
+        let resp = test::call_service(&app, post()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_audit() {
+        use actix_web::http::StatusCode;
+
+        use soyjot::audit::{AuditConfig, AuditLog};
+        use soyjot::hash::HashConfig;
+        use soyjot::store::Store;
+
+        use crate::http_resp::ResponseJson;
+        use crate::http_server::{self, OWNER_KEY_HEADER};
+
+        let path = std::env::temp_dir().join(format!(
+            "soyjot-actix-test-audit-{}.jsonl",
+            std::process::id()
+        ));
+        let log = AuditLog::open(AuditConfig {
+            path: path.to_string_lossy().into_owned(),
+            max_bytes: None,
+            keep: 0,
+        })
+        .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(log))
+                .app_data(web::Data::new(Store::new()))
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .wrap(middleware::from_fn(super::audit))
+                .service(crate::drops::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let records = || -> Vec<serde_json::Value> {
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/api/drop")
+            .peer_addr("[2001:db8::1]:1234".parse().unwrap())
+            .set_json(serde_json::json!({ "mem": "audited" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let owner_key = resp.headers().get(OWNER_KEY_HEADER).unwrap().clone();
+
+        let created = &records()[0];
+        assert_eq!(created["action"], "create");
+        assert_eq!(created["client"], "2001:db8::1");
+        assert_eq!(created["status"], 200);
+        assert!(created["size"].as_u64().unwrap() > 0);
+        let hash = created["hash"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{hash}"))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        // Batches record a read of each clipboard they found
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drops?ids={hash},missing"))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        // Requests that fail are recorded too
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/drop/{hash}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/drop/{hash}"))
+            .insert_header((OWNER_KEY_HEADER, owner_key))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        // Other routes are not recorded
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{hash}/meta"))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let records = records();
+        let summary: Vec<_> = records[1..]
+            .iter()
+            .map(|r| {
+                (
+                    r["action"].as_str().unwrap(),
+                    r["hash"].as_str().unwrap(),
+                    r["status"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("read", hash.as_str(), 200),
+                ("read", hash.as_str(), 200),
+                ("delete", hash.as_str(), 403),
+                ("delete", hash.as_str(), 204),
+            ]
+        );
+        assert!(records[1]["size"].as_u64().unwrap() > 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_cors() {
+        let origins = vec!["https://tools.example.com".to_string()];
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/api")
+                    .wrap(super::cors(&origins))
+                    .route("/drop", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let preflight = |origin: &str| {
+            test::TestRequest::default()
+                .method(actix_web::http::Method::OPTIONS)
+                .uri("/api/drop")
+                .insert_header((header::ORIGIN, origin))
+                .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, preflight("https://tools.example.com")).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://tools.example.com"
+        );
+
+        let resp = test::call_service(&app, preflight("https://evil.example.com")).await;
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+
+        // Owners may delete what they posted with their owner key
+        let req = test::TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/api/drop")
+            .insert_header((header::ORIGIN, "https://tools.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "x-owner-key"))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/api/drop")
+            .insert_header((header::ORIGIN, "https://tools.example.com"))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        let exposed = resp
+            .headers()
+            .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(exposed.contains("x-owner-key"), "{exposed}");
+    }
+
+    #[actix_web::test]
+    async fn test_hooks() {
+        use std::sync::Arc;
+
+        use actix_web::http::StatusCode;
+
+        let teapot: super::Hook =
+            Arc::new(|req| (req.path() == "/tea").then(|| HttpResponse::ImATeapot().finish()));
+        let never: super::Hook = Arc::new(|_| panic!("hook after a response was run"));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(super::Hooks::new(vec![teapot, never])))
+                .wrap(middleware::from_fn(super::hooks))
+                .route(
+                    "/tea",
+                    web::get().to(|| async { HttpResponse::Ok().finish() }),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/tea").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+    }
+}
@@ -0,0 +1,86 @@
+//! OpenAPI spec of the JSON API at `/api`, generated from the `utoipa::path`
+//! annotations on the handlers in `http_server`.
+
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::error::StoreError;
+use soyjot::store::feed::PublicDrop;
+use soyjot::store::version::VersionInfo;
+
+use crate::http_resp::{
+    AmbiguousResponse, ClipboardResponse, Encoding, ErrorResponse, ExpiresAt, MetaResponse,
+    PostResponse, PublicResponse, VersionsResponse,
+};
+use crate::http_server::{self, ReqForm, ReqJson};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "actix-drop", description = "Text and data sharing app"),
+    paths(
+        http_server::add_clipboard,
+        http_server::get_clipboard,
+        http_server::get_clipboard_frag,
+        http_server::get_clipboard_meta,
+        http_server::has_content,
+        http_server::get_clipboard_qr,
+        http_server::download_clipboard,
+        http_server::append_clipboard,
+        http_server::delete_clipboard,
+        http_server::get_clipboard_versions,
+        http_server::get_clipboard_version,
+        http_server::get_public,
+    ),
+    components(schemas(
+        Clipboard,
+        ReqForm,
+        ReqJson,
+        StoreError,
+        VersionInfo,
+        PublicDrop,
+        PostResponse,
+        ErrorResponse,
+        MetaResponse,
+        VersionsResponse,
+        AmbiguousResponse,
+        PublicResponse,
+        ClipboardResponse,
+        Encoding,
+        ExpiresAt,
+    ))
+)]
+struct ApiDoc;
+
+/// openapi_json serves the OpenAPI spec, so API clients can be generated from it
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+
+    #[actix_web::test]
+    async fn test_openapi_json() {
+        let app = test::init_service(
+            App::new().route("/api/openapi.json", web::get().to(super::openapi_json)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/openapi.json")
+            .to_request();
+        let spec: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        for path in ["/api/drop", "/api/drop/{id}", "/api/drop/{id}/meta"] {
+            assert!(spec["paths"][path].is_object(), "missing path {path}");
+        }
+
+        // Public StoreError variants are documented, but IO errors are not
+        let variants = spec["components"]["schemas"]["StoreError"]["oneOf"].to_string();
+        assert!(variants.contains("QuotaExceeded"));
+        assert!(variants.contains("InvalidUtf8"));
+        assert!(!variants.contains("IoError"));
+    }
+}
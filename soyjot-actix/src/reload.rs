@@ -0,0 +1,211 @@
+//! Hot reloading of `AppConfig` on SIGHUP.
+//!
+//! Handlers read runtime-changeable settings (e.g. `AppConfig::timeout`) from the shared
+//! `SharedConfig` on every request, so a reload applies to the next request without a restart.
+//! The store, rate limiter and content filters are reconfigured in place. Listen addresses, TLS, the storage
+//! directory, `fsync`, the read cache, hashing, replication peers and the audit log are fixed at startup, and changing
+//! them still requires a restart.
+//!
+//! There is no SIGHUP on other platforms than UNIX, where the config is only read on startup.
+
+use std::sync::Arc;
+
+use actix_web::web;
+use arc_swap::ArcSwap;
+use colored::Colorize;
+
+use soyjot::config::{AppConfig, ConfigArgs};
+use soyjot::filters::Filters;
+use soyjot::rate_limit::RateLimiter;
+use soyjot::store::Store;
+
+/// SharedConfig is the current `AppConfig`, replaced whole on every reload
+pub type SharedConfig = ArcSwap<AppConfig>;
+
+/// shared wraps `conf` as app data for handlers
+pub fn shared(conf: AppConfig) -> web::Data<SharedConfig> {
+    web::Data::new(ArcSwap::from_pointee(conf))
+}
+
+/// spawn_reloader spawns a task that re-reads `AppConfig` with the command-line `args`
+/// on every SIGHUP, and applies it with `reload`. If the config cannot be read
+/// or is invalid, the current config is kept.
+#[cfg(unix)]
+pub fn spawn_reloader(
+    conf: web::Data<SharedConfig>,
+    args: ConfigArgs,
+    store: web::Data<Store>,
+    limiter: Option<web::Data<RateLimiter>>,
+    filters: web::Data<Filters>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            eprintln!("{} {err}", "error listening for SIGHUP:".red());
+            return;
+        }
+    };
+
+    actix_web::rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match AppConfig::build_with(&args) {
+                Ok(new) => {
+                    let limiter = limiter.as_ref().map(|limiter| limiter.get_ref());
+                    reload(&conf, new.app, &store, limiter, &filters);
+                    println!("{}", "Reloaded configuration".yellow());
+                }
+
+                Err(err) => eprintln!(
+                    "{} {err}",
+                    "error reloading config, keeping current config:".red()
+                ),
+            }
+        }
+    });
+}
+
+/// spawn_reloader does nothing on platforms without SIGHUP
+#[cfg(not(unix))]
+pub fn spawn_reloader(
+    _conf: web::Data<SharedConfig>,
+    _args: ConfigArgs,
+    _store: web::Data<Store>,
+    _limiter: Option<web::Data<RateLimiter>>,
+    _filters: web::Data<Filters>,
+) {
+    println!(
+        "{}",
+        "Config reload on SIGHUP is not supported on this platform".yellow()
+    );
+}
+
+/// reload applies `new` to the store, rate limiter and content filters,
+/// and replaces the shared config. Settings that only apply on startup are reported
+/// if they changed.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn reload(
+    conf: &SharedConfig,
+    new: AppConfig,
+    store: &Store,
+    limiter: Option<&RateLimiter>,
+    filters: &Filters,
+) {
+    let old = conf.load();
+
+    let restart_only = [
+        ("dir", old.dir != new.dir),
+        ("http_addr", old.http_addr != new.http_addr),
+        ("http_port", old.http_port != new.http_port),
+        ("hash_len", old.hash_len != new.hash_len),
+        ("hash_algo", old.hash_algo != new.hash_algo),
+        ("id_alphabet", old.id_alphabet != new.id_alphabet),
+        ("id_mode", old.id_mode != new.id_mode),
+        ("quota", old.quota != new.quota),
+        ("tenant quotas", old.tenant_quotas() != new.tenant_quotas()),
+        ("tls_cert", old.tls_cert != new.tls_cert),
+        ("tls_key", old.tls_key != new.tls_key),
+        ("cors_origins", old.cors_origins != new.cors_origins),
+        ("scopes", old.scopes != new.scopes),
+        (
+            "janitor_interval",
+            old.janitor_interval != new.janitor_interval,
+        ),
+        (
+            "read_cache_bytes",
+            old.read_cache_bytes != new.read_cache_bytes,
+        ),
+        ("audit_log", old.audit_log != new.audit_log),
+        (
+            "replication peers",
+            old.replication.as_ref().map(|r| &r.peers)
+                != new.replication.as_ref().map(|r| &r.peers),
+        ),
+        (
+            "mailer rate_limit",
+            old.mailer.as_ref().map(|m| &m.rate_limit)
+                != new.mailer.as_ref().map(|m| &m.rate_limit),
+        ),
+        (
+            "share rate_limit",
+            old.share.as_ref().map(|s| &s.rate_limit) != new.share.as_ref().map(|s| &s.rate_limit),
+        ),
+    ];
+
+    for (key, _) in restart_only.iter().filter(|(_, changed)| *changed) {
+        eprintln!(
+            "{} {key}",
+            "restart required to apply changed config:".red()
+        );
+    }
+
+    store.reconfigure(new.store_config());
+
+    match (limiter, &new.rate_limit) {
+        (Some(limiter), Some(limits)) => limiter.reconfigure(limits.clone()),
+        (None, None) => {}
+        _ => eprintln!(
+            "{}",
+            "restart required to enable or disable rate_limit".red()
+        ),
+    }
+
+    // `new` was validated, so its filters compile
+    match new.filter_chain() {
+        Ok(chain) => filters.reconfigure(chain),
+        Err(err) => eprintln!("{} {err}", "error reloading filters:".red()),
+    }
+
+    conf.store(Arc::new(new));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use soyjot::filters::{FilterChain, FilterConfig};
+    use soyjot::rate_limit::RateLimitConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_reload() {
+        let limits = RateLimitConfig {
+            burst: 1,
+            per_sec: 0.0,
+        };
+
+        let conf = shared(AppConfig {
+            rate_limit: Some(limits.clone()),
+            ..AppConfig::default()
+        });
+        let store = Store::new();
+        let limiter = RateLimiter::new(limits);
+        let filters = Filters::new(FilterChain::new());
+
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(limiter.check(ip).is_ok());
+        assert!(limiter.check(ip).is_err());
+
+        let new = AppConfig {
+            timeout: Some(60),
+            rate_limit: Some(RateLimitConfig {
+                burst: 2,
+                per_sec: 0.0,
+            }),
+            filters: Some(FilterConfig {
+                max_size: Some(4),
+                ..FilterConfig::default()
+            }),
+            ..AppConfig::default()
+        };
+        reload(&conf, new, &store, Some(&limiter), &filters);
+
+        assert_eq!(conf.load().timeout_duration(), Duration::from_secs(60));
+        // The client's bucket keeps its tokens, but may now hold more
+        assert!(limiter.check(ip).is_err());
+        assert!(filters.chain().apply(b"too long".to_vec()).is_err());
+    }
+}
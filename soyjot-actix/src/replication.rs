@@ -0,0 +1,494 @@
+//! Replication forwards every new and expired clipboard to the peers in
+//! `AppConfig::replication`, so that several instances can serve the same clipboards
+//! for simple high availability, e.g. behind a load balancer.
+//!
+//! Peers receive operations as JSON at `POST /api/replica`, authenticated with the shared token:
+//! `Op::Put` stores a clipboard with its content and metadata (see `IndexEntry`), keeping its
+//! expiry and owner, and `Op::Delete` removes it. Each peer has its own queue, whose operations
+//! are delivered in order. Failed deliveries (network errors and 5xx or 429 responses)
+//! are retried with exponential backoff up to `MAX_ATTEMPTS` times, and operations arriving
+//! while a queue is full are dropped.
+//!
+//! Operations applied from a peer are not replicated any further, so peers should replicate
+//! to each other directly. Views are counted by each instance on its own, and a clipboard
+//! removed after its last view on one instance is removed from its peers too.
+//! Peers and the token used to reach them are fixed at startup.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+
+use soyjot::config::ReplicationConfig;
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::error::StoreError;
+use soyjot::store::event::EventKind;
+use soyjot::store::index::IndexEntry;
+use soyjot::store::{Replica, Store, StoreOpts};
+use soyjot::tenant;
+
+use crate::admin::{credentials, tokens_eq};
+use crate::http_resp::ResponseJson;
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+const PATH: &str = "/api/replica";
+
+/// Number of times an operation is sent to a peer before it is dropped
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled after every failed attempt
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// Time each attempt may take before it fails, which is longer than for webhooks
+/// since operations carry the clipboard content
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of operations queued for each peer
+const QUEUE_LEN: usize = 1024;
+
+/// Largest operation accepted from a peer in bytes, with the content base64-encoded
+const MAX_OP_SIZE: usize = 64 << 20;
+
+/// Op is what happened to a clipboard on a peer
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Op {
+    /// Store clipboard `entry.hash` with base64-encoded `content`
+    Put {
+        entry: Box<IndexEntry>,
+        content: String,
+    },
+    /// Remove clipboard `hash`
+    Delete { hash: String },
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Put { entry, .. } => write!(f, "put of {}", entry.hash),
+            Self::Delete { hash } => write!(f, "delete of {hash}"),
+        }
+    }
+}
+
+/// Replicator remembers the operations being applied from peers,
+/// so that the events they cause are not replicated back
+#[derive(Default)]
+pub struct Replicator {
+    applying: Mutex<HashSet<(EventKind, String)>>,
+}
+
+impl Replicator {
+    /// apply records that an operation from a peer is about to cause event `kind` for `hash`
+    fn apply(&self, kind: EventKind, hash: &str) {
+        let mut applying = self.applying.lock().expect("replicator lock poisoned");
+        applying.insert((kind, hash.to_owned()));
+    }
+
+    /// applied forgets event `kind` for `hash`, and reports whether it was caused by a peer
+    fn applied(&self, kind: EventKind, hash: &str) -> bool {
+        let mut applying = self.applying.lock().expect("replicator lock poisoned");
+        applying.remove(&(kind, hash.to_owned()))
+    }
+}
+
+/// spawn spawns a task that replicates the clipboards of `store` to the peers in `conf`,
+/// and returns the `Replicator` that `routes` need as app data
+pub fn spawn(store: &web::Data<Store>, conf: &ReplicationConfig) -> web::Data<Replicator> {
+    let replicator = web::Data::new(Replicator::default());
+    let client = awc::Client::builder()
+        .timeout(TIMEOUT)
+        .bearer_auth(&conf.token)
+        .finish();
+
+    let queues: Vec<_> = conf
+        .peers
+        .iter()
+        .map(|peer| {
+            let (tx, rx) = mpsc::channel(QUEUE_LEN);
+            let url = format!("{}{PATH}", peer.trim_end_matches('/'));
+            actix_web::rt::spawn(deliver_queue(client.clone(), url, rx));
+
+            (peer.clone(), tx)
+        })
+        .collect();
+
+    let mut events = store.events();
+    let (store, task_replicator) = (store.clone(), replicator.clone());
+
+    actix_web::rt::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("{} {missed} events", "replication: missed".red());
+                    continue;
+                }
+
+                Err(RecvError::Closed) => break,
+            };
+
+            if task_replicator.applied(event.event, &event.hash) {
+                continue;
+            }
+
+            let op = match event.event {
+                // Extended clipboards are put again with their new expiry
+                EventKind::Created | EventKind::Extended => match put(&store, &event.hash).await {
+                    Some(op) => op,
+                    // Already gone, and its delete follows
+                    None => continue,
+                },
+                EventKind::Expired => Op::Delete { hash: event.hash },
+                // Replicas are held until the same time, with their own release timer
+                EventKind::Fetched | EventKind::Available => continue,
+            };
+
+            let op = Arc::new(op);
+            for (peer, queue) in &queues {
+                if queue.try_send(op.clone()).is_err() {
+                    eprintln!(
+                        "{} {op} for {peer}",
+                        "replication: queue full, dropped".red()
+                    );
+                }
+            }
+        }
+    });
+
+    replicator
+}
+
+/// put returns the operation that stores clipboard `hash` as it is in `store`,
+/// if it's still there. Held clipboards are put with their hold.
+async fn put(store: &Store, hash: &str) -> Option<Op> {
+    let entry = store.export_meta(hash)?;
+    let clipboard = store.export_clipboard(hash).await?;
+
+    Some(Op::Put {
+        entry: Box::new(entry),
+        content: STANDARD.encode(&*clipboard),
+    })
+}
+
+/// deliver_queue delivers the operations in `ops` to the replica endpoint `url` of a peer,
+/// one at a time so that they are applied in order
+async fn deliver_queue(client: awc::Client, url: String, mut ops: mpsc::Receiver<Arc<Op>>) {
+    while let Some(op) = ops.recv().await {
+        if let Err(err) = deliver(&client, &url, &op, BACKOFF).await {
+            eprintln!("{} {op} for {url}: {err}", "replication: dropped".red());
+        }
+    }
+}
+
+/// deliver POSTs `op` to `url`, retrying failed attempts after `backoff`, twice `backoff`,
+/// and so on. Other 4xx responses are not retried, since sending the same operation again
+/// would fail the same way.
+async fn deliver(
+    client: &awc::Client,
+    url: &str,
+    op: &Op,
+    backoff: Duration,
+) -> Result<(), String> {
+    let mut delay = backoff;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let (err, retry) = match client.post(url).send_json(op).await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => {
+                let status = resp.status();
+                let retry = status.is_server_error() || status.as_u16() == 429;
+
+                (format!("peer returned {status}"), retry)
+            }
+
+            Err(err) => (err.to_string(), true),
+        };
+
+        if !retry || attempt == MAX_ATTEMPTS {
+            return Err(format!("{err} (attempt {attempt})"));
+        }
+
+        actix_web::rt::time::sleep(delay).await;
+        delay *= 2;
+    }
+
+    unreachable!("MAX_ATTEMPTS is at least 1")
+}
+
+/// routes returns the route that peers replicate to, which must be mounted before the `/api` scope.
+/// It responds 404 unless replication is configured.
+pub fn routes() -> actix_web::Resource {
+    web::resource(PATH)
+        .app_data(web::JsonConfig::default().limit(MAX_OP_SIZE))
+        .route(web::post().to(post_replica))
+}
+
+/// unauthorized checks that replication is configured and that `req` carries its token,
+/// and returns the response to send instead if not
+fn unauthorized(req: &HttpRequest, conf: &SharedConfig) -> Option<HttpResponse> {
+    let conf = conf.load();
+    let Some(replication) = &conf.replication else {
+        return Some(HttpResponse::NotFound().json(json!({ "error": "replication disabled" })));
+    };
+
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(credentials);
+
+    match given {
+        Some(given) if tokens_eq(given.as_bytes(), replication.token.as_bytes()) => None,
+        _ => Some(http_server::unauthorized::<R>("replication token required")),
+    }
+}
+
+/// valid_key reports whether `key` could be the key of a clipboard (see `tenant::key`),
+/// since keys from peers end up in file paths
+pub(crate) fn valid_key(key: &str) -> bool {
+    let (tenant, hash) = tenant::split(key);
+
+    !hash.is_empty()
+        && hash.bytes().all(|b| b.is_ascii_alphanumeric())
+        && tenant.is_none_or(tenant::valid_name)
+}
+
+/// post_replica applies an operation replicated from a peer
+async fn post_replica(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    replicator: Option<web::Data<Replicator>>,
+    web::Json(op): web::Json<Op>,
+) -> HttpResponse {
+    if let Some(resp) = unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    let hash = match &op {
+        Op::Put { entry, .. } => &entry.hash,
+        Op::Delete { hash } => hash,
+    };
+    if !valid_key(hash) {
+        return http_server::store_error::<R>("", StoreError::NoSuch);
+    }
+
+    let replicator = replicator.as_ref().map(|replicator| replicator.get_ref());
+    match op {
+        Op::Put { entry, content } => {
+            put_replica(store.into_inner(), replicator, *entry, content).await
+        }
+        Op::Delete { hash } => {
+            if let Some(replicator) = replicator {
+                replicator.apply(EventKind::Expired, &hash);
+            }
+
+            let result = store.remove_clipboard(&hash).await;
+            if !matches!(result, Ok(true)) {
+                if let Some(replicator) = replicator {
+                    replicator.applied(EventKind::Expired, &hash);
+                }
+            }
+
+            match result {
+                // Clipboards that are already gone need no removal
+                Ok(_) => HttpResponse::NoContent().finish(),
+                Err(err) => http_server::store_error::<R>(&hash, err),
+            }
+        }
+    }
+}
+
+/// put_replica stores the clipboard of `entry` with base64-encoded `content`,
+/// replacing the clipboard there if any
+async fn put_replica(
+    store: Arc<Store>,
+    replicator: Option<&Replicator>,
+    entry: IndexEntry,
+    content: String,
+) -> HttpResponse {
+    let hash = entry.hash.clone();
+    let (Some(digest), Ok(content)) = (entry.digest.clone(), STANDARD.decode(content)) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "bad replica" }));
+    };
+
+    let clipboard = store.place(Clipboard::new_with_data(&entry.storage, content));
+
+    // Clipboards that expired on the way are dropped, and their delete follows
+    let Some((dur, opts)) = stored_as(entry) else {
+        return HttpResponse::NoContent().finish();
+    };
+
+    if let Some(replicator) = replicator {
+        replicator.apply(EventKind::Created, &hash);
+    }
+
+    let opts = StoreOpts {
+        force: true,
+        ..opts
+    };
+    match Store::store_new_clipboard(store, &hash, &digest, clipboard, dur, opts).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(err) => {
+            if let Some(replicator) = replicator {
+                replicator.applied(EventKind::Created, &hash);
+            }
+
+            http_server::store_error::<R>(&hash, err)
+        }
+    }
+}
+
+/// stored_as returns the time left before the clipboard of `entry` expires, and the options
+/// that store it again as it was, with its owner, creation time and release time.
+/// Clipboards that have already expired get `None`.
+pub(crate) fn stored_as(entry: IndexEntry) -> Option<(Duration, StoreOpts)> {
+    let dur = match entry.pinned {
+        true => Duration::ZERO,
+        false => entry.remaining()?,
+    };
+
+    let opts = StoreOpts {
+        max_views: entry.max_views,
+        content_type: entry.content_type,
+        filename: entry.filename,
+        lang: entry.lang,
+        encryption: entry.encryption,
+        tags: entry.tags,
+        pin: entry.pinned,
+        available_at: entry.available_at,
+        replica: Some(Replica {
+            owner: entry.owner,
+            created_at: entry.created_at,
+        }),
+        ..StoreOpts::default()
+    };
+
+    Some((dur, opts))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use actix_web::{web, App, HttpServer};
+
+    use soyjot::config::{AppConfig, ReplicationConfig};
+    use soyjot::store::clipboard::Clipboard;
+    use soyjot::store::{Store, StoreOpts};
+
+    use crate::reload;
+
+    /// wait polls `done` until it holds, for up to a few seconds
+    async fn wait(done: impl Fn() -> bool) -> bool {
+        for _ in 0..100 {
+            if done() {
+                return true;
+            }
+
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        false
+    }
+
+    #[actix_web::test]
+    async fn test_replication() {
+        let listeners: Vec<_> = (0..2)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let urls: Vec<_> = listeners
+            .iter()
+            .map(|listener| format!("http://{}", listener.local_addr().unwrap()))
+            .collect();
+
+        // Both instances replicate to each other
+        let mut stores = Vec::new();
+        for (i, listener) in listeners.into_iter().enumerate() {
+            let conf = ReplicationConfig {
+                peers: vec![urls[1 - i].clone()],
+                token: "secret".to_string(),
+            };
+
+            let store = web::Data::new(Store::new());
+            let replicator = super::spawn(&store, &conf);
+            let shared = reload::shared(AppConfig {
+                replication: Some(conf),
+                ..AppConfig::default()
+            });
+
+            let app_store = store.clone();
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(app_store.clone())
+                    .app_data(shared.clone())
+                    .app_data(replicator.clone())
+                    .service(super::routes())
+            })
+            .workers(1)
+            .listen(listener)
+            .unwrap();
+
+            actix_web::rt::spawn(server.run());
+            stores.push(store);
+        }
+
+        let (a, b) = (&stores[0], &stores[1]);
+        let opts = StoreOpts {
+            content_type: Some("text/plain".to_string()),
+            ..StoreOpts::default()
+        };
+        let owner_key = Store::store_new_clipboard(
+            a.clone().into_inner(),
+            "abcd",
+            "abcdef",
+            Clipboard::new_with_data("mem", "replicated"),
+            Duration::from_secs(60),
+            opts,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(
+            wait(|| b.meta("abcd").is_some()).await,
+            "put not replicated"
+        );
+        assert_eq!(b.content_type("abcd").as_deref(), Some("text/plain"));
+        assert_eq!(b.meta("abcd").unwrap().owner, a.meta("abcd").unwrap().owner);
+
+        // The owner can delete the replica with the owner key from the first instance
+        b.delete_clipboard("abcd", &owner_key).await.unwrap();
+        assert!(
+            wait(|| a.meta("abcd").is_none()).await,
+            "delete not replicated"
+        );
+
+        // Nothing is replicated back and forth
+        actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        assert!(a.meta("abcd").is_none() && b.meta("abcd").is_none());
+
+        // Peers must present the token
+        let resp = awc::Client::new()
+            .post(format!("{}/api/replica", urls[0]))
+            .send_json(&serde_json::json!({ "op": "delete", "hash": "abcd" }))
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
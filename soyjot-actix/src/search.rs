@@ -0,0 +1,148 @@
+//! Search within stored clipboards at `/api/search?q=`.
+//!
+//! Admins, with `AppConfig::admin_token` as a bearer token, search all global clipboards.
+//! Clients without it search only the clipboards of the owner key they send
+//! in `http_server::OWNER_KEY_HEADER`. Persisted clipboards are only read from file
+//! with `files=true`, since scanning them means reading every file.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::store::{search, Store};
+
+use crate::admin;
+use crate::http_resp::ResponseJson;
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+const PATH: &str = "/api/search";
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    /// Match `q` as a regular expression instead of a substring
+    #[serde(default)]
+    regex: bool,
+    /// Also search persisted clipboards
+    #[serde(default)]
+    files: bool,
+}
+
+/// routes returns the search scope, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope(PATH).route("", web::get().to(search))
+}
+
+/// search lists the clipboards matching the query, with the byte offsets of the matches
+async fn search(
+    req: HttpRequest,
+    query: web::Query<SearchQuery>,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    let Some(owner) = admin::owner_or_admin(&req, &conf) else {
+        return http_server::unauthorized::<ResponseJson>("admin token or owner key required");
+    };
+
+    let pattern = match search::pattern(&query.q, query.regex) {
+        Ok(pattern) => pattern,
+        Err(err) => return http_server::store_error::<ResponseJson>("", err),
+    };
+
+    let hits = store.search(&pattern, owner.as_deref(), query.files).await;
+
+    HttpResponse::Ok().json(json!({ "query": query.q, "clipboards": hits }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::Store;
+
+    use crate::http_resp::ResponseJson;
+    use crate::http_server::{self, OWNER_KEY_HEADER};
+    use crate::reload;
+
+    #[actix_web::test]
+    async fn test_search() {
+        let conf = reload::shared(AppConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..AppConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(conf)
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let mut posted = Vec::new();
+        for content in ["find me here", "me too, find me", "not this one"] {
+            let req = test::TestRequest::post()
+                .uri("/api/drop")
+                .set_json(serde_json::json!({ "mem": content }))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            posted.push((
+                body["clipboard"].as_str().unwrap().to_string(),
+                body["owner_key"].as_str().unwrap().to_string(),
+            ));
+        }
+
+        let search = |uri: &str, auth: Option<(&str, &str)>| {
+            let mut req = test::TestRequest::get().uri(uri);
+            if let Some(auth) = auth {
+                req = req.insert_header(auth);
+            }
+
+            req.to_request()
+        };
+
+        let resp = test::call_service(&app, search("/api/search?q=find", None)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer"
+        );
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["kind"], "Unauthorized");
+
+        // Admins search every clipboard
+        let admin = Some((header::AUTHORIZATION.as_str(), "Bearer s3cret"));
+        let req = search("/api/search?q=find%20me", admin);
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let mut hits: Vec<_> = body["clipboards"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| (hit["hash"].as_str().unwrap(), hit["offsets"].clone()))
+            .collect();
+        hits.sort_by_key(|(hash, _)| *hash == posted[1].0);
+        assert_eq!(
+            hits,
+            [
+                (posted[0].0.as_str(), serde_json::json!([0])),
+                (posted[1].0.as_str(), serde_json::json!([8])),
+            ]
+        );
+
+        // Owners only search their own clipboards
+        let owner = Some((OWNER_KEY_HEADER, posted[1].1.as_str()));
+        let req = search("/api/search?q=f.nd&regex=true", owner);
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["clipboards"].as_array().unwrap().len(), 1);
+        assert_eq!(body["clipboards"][0]["hash"], posted[1].0.as_str());
+
+        let resp = test::call_service(&app, search("/api/search?q=(&regex=true", admin)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,114 @@
+//! Client-side encrypted clipboards at `/app/secure`.
+//!
+//! The page's script encrypts text in the browser with AES-256-GCM, and posts only the
+//! ciphertext to `/api/drop`, with the cipher and IV as `encryption` metadata. The key is
+//! put in the fragment of the clipboard's secret link, which browsers never send to servers.
+//! Opening the link fetches the metadata and ciphertext from `/api` and decrypts them
+//! in the browser, so the page needs `Scope::Api` to be mounted too.
+
+use actix_web::{web, HttpResponse};
+
+use soyjot::html::wrap_html;
+
+/// Browser script of the page, loaded at compile time like `assets::SCRIPT`
+const SCRIPT: &str = include_str!("../../assets/secure.js");
+
+const PATH: &str = "/app/secure";
+
+/// routes returns the secure page scope, which must be mounted before the `/app` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope(PATH)
+        .route("", web::get().to(page))
+        .route("/secure.js", web::get().to(script))
+}
+
+async fn page() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(wrap_html(&format!(
+            r#"<p>Clipboards sent from this page are encrypted in your browser.
+        The server only stores the ciphertext, and the key is kept in the <code>#fragment</code>
+        of the secret link, which is never sent to the server.</p>
+        <div id="secure-new">
+        <textarea id="secure-text" rows="5" cols="32"></textarea><br>
+        <button id="secure-send" type="button">Encrypt and send</button>
+        </div>
+        <div id="secure-result" hidden>
+        <p>Secret link: <a id="secure-link"></a></p>
+        <p>Owner key: <code id="secure-owner"></code></p>
+        </div>
+        <pre id="secure-clipboard" hidden></pre>
+        <p id="secure-error" hidden></p>
+        <noscript><p>Encrypted clipboards need JavaScript</p></noscript>
+        <script src="{PATH}/secure.js"></script>"#
+        )))
+}
+
+async fn script() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript")
+        .body(SCRIPT)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::Store;
+
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    #[actix_web::test]
+    async fn test_secure() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(reload::shared(AppConfig::default()))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/app/secure").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"<script src="/app/secure/secure.js">"#));
+
+        let req = test::TestRequest::get()
+            .uri("/app/secure/secure.js")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/javascript"
+        );
+
+        // The server keeps the metadata of ciphertext as-is
+        let req = test::TestRequest::post()
+            .uri("/api/drop?encryption=aes-256-gcm:AAECAwQFBgcICQoL")
+            .set_json(serde_json::json!({ "mem": [1, 2, 3, 255] }))
+            .to_request();
+        let posted: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let hash = posted["clipboard"].as_str().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{hash}/meta"))
+            .to_request();
+        let meta: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(meta["encryption"], "aes-256-gcm:AAECAwQFBgcICQoL");
+
+        for bad in ["", "has%20space", &"a".repeat(257)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/drop?encryption={bad}"))
+                .set_json(serde_json::json!({ "mem": [1, 2, 3] }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{bad}");
+        }
+    }
+}
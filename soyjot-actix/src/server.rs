@@ -0,0 +1,553 @@
+//! Embeddable actix-drop server.
+//!
+//! `DropServer` builds the same server as the `soyjot-actix` binary from a `ValidatedConfig`,
+//! so that other applications can run actix-drop as a component, e.g. next to their own
+//! `HttpServer`. The storage backend, mounted scopes, extra routes and request hooks
+//! can be set on the builder, and everything else is read from the config.
+//!
+//! ```no_run
+//! use soyjot::config::AppConfig;
+//! use soyjot_actix::server::{self, DropServer};
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let conf = AppConfig::build().expect("bad config");
+//!     let store = server::open_store(&conf);
+//!
+//!     DropServer::new(conf).store(store.clone()).run()?.await?;
+//!     server::save_index(&store);
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::dev::{Server, ServiceRequest};
+use actix_web::{middleware as mw, web, App, HttpResponse, HttpServer};
+use colored::Colorize;
+
+use soyjot::audit::AuditLog;
+use soyjot::config::{ConfigArgs, Scope, ValidatedConfig};
+use soyjot::filters::Filters;
+use soyjot::hash::HashConfig;
+use soyjot::rate_limit::RateLimiter;
+use soyjot::store::{self, Store};
+
+use crate::chunks::Uploads;
+#[cfg(feature = "mailer")]
+use crate::mailer::{self, Mailer};
+use crate::middleware::{self, Hook, Hooks};
+use crate::reload::SharedConfig;
+use crate::share::{self, Sharing};
+use crate::upload_tokens::UsedTokens;
+use crate::{
+    admin, archive, assets, bundle, chunks, drops, http_resp, http_server, janitor, openapi,
+    reload, replication, search, secure, tenants, tls, upload_tokens, webhooks, ws,
+};
+
+/// Extra routes mounted with `DropServer::configure`
+type Configure = Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>;
+
+/// DropServer builds an actix-drop server, see the module docs
+pub struct DropServer {
+    conf: ValidatedConfig,
+    store: Option<web::Data<Store>>,
+    reload_args: Option<ConfigArgs>,
+    configure: Vec<Configure>,
+    hooks: Vec<Hook>,
+}
+
+impl DropServer {
+    /// new returns a builder for a server configured with `conf`
+    pub fn new(conf: ValidatedConfig) -> Self {
+        Self {
+            conf,
+            store: None,
+            reload_args: None,
+            configure: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// store makes the server use `store`, e.g. one shared with the embedding application.
+    /// Without it, a store is opened with `open_store`, whose index is not saved on shutdown.
+    pub fn store(mut self, store: web::Data<Store>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// scopes mounts `scopes` instead of `AppConfig::scopes`
+    pub fn scopes(mut self, scopes: Vec<Scope>) -> Self {
+        self.conf.app.scopes = Some(scopes);
+        self
+    }
+
+    /// reload_on_sighup reloads the config on SIGHUP, reading it again with `args`
+    /// like `AppConfig::build_with` (see `reload::spawn_reloader`)
+    pub fn reload_on_sighup(mut self, args: ConfigArgs) -> Self {
+        self.reload_args = Some(args);
+        self
+    }
+
+    /// configure mounts extra routes with `f`, after the actix-drop routes
+    pub fn configure<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut web::ServiceConfig) + Send + Sync + 'static,
+    {
+        self.configure.push(Arc::new(f));
+        self
+    }
+
+    /// hook runs `f` on every request before it's routed, e.g. to authenticate requests.
+    /// If `f` returns a response, it is sent instead, and later hooks are not run.
+    /// Hooks run in the order they were added, after rate limiting.
+    pub fn hook<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> Option<HttpResponse> + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(f));
+        self
+    }
+
+    /// run binds the server on every address in `ValidatedConfig::bind_addrs`, and starts
+    /// the background tasks enabled in the config, e.g. the janitor and webhooks.
+    /// It must be called from within an actix runtime, and the returned `Server`
+    /// must be awaited or spawned to serve requests.
+    pub fn run(self) -> std::io::Result<Server> {
+        let Self {
+            conf,
+            store,
+            reload_args,
+            configure,
+            hooks,
+        } = self;
+
+        let clipboards = store.unwrap_or_else(|| open_store(&conf));
+        let hashing = conf.hashing;
+
+        let tls_config = match &conf.tls {
+            Some((cert, key)) => {
+                Some(tls::server_config(cert, key).map_err(std::io::Error::other)?)
+            }
+            None => None,
+        };
+
+        let scheme = match tls_config {
+            Some(_) => "https",
+            None => "http",
+        };
+
+        // Every address in http_addr is bound, e.g. both 127.0.0.1 and [::1]
+        let bind_addrs = conf.bind_addrs.clone();
+        for addr in &bind_addrs {
+            println!(
+                "{} {}",
+                "Starting actix-web on".yellow(),
+                format!("{scheme}://{addr}").cyan()
+            );
+        }
+
+        if let (Some(_), Some(redirect_port)) = (&tls_config, conf.app.tls_redirect_port) {
+            let redirect_addrs: Vec<_> = bind_addrs
+                .iter()
+                .map(|addr| SocketAddr::new(addr.ip(), redirect_port))
+                .collect();
+
+            let redirect = tls::redirect_server(&redirect_addrs, conf.http_port)?;
+
+            for addr in &redirect_addrs {
+                println!(
+                    "{} {}",
+                    "Redirecting HTTP to HTTPS from".yellow(),
+                    format!("http://{addr}").cyan()
+                );
+            }
+
+            actix_web::rt::spawn(redirect);
+        }
+
+        // Rate limiter is shared by all workers
+        let rate_limiter = conf.app.rate_limit.clone().map(|limits| {
+            println!("{} {limits:?}", "Rate limiting enabled:".yellow());
+            web::Data::new(RateLimiter::new(limits))
+        });
+
+        // Content filters are shared by all workers, and replaced on reloads
+        let filters = conf
+            .app
+            .filter_chain()
+            .expect("validated config has bad filters");
+        if !filters.is_empty() {
+            println!(
+                "{} {:?}",
+                "Content filters enabled:".yellow(),
+                conf.app.filters
+            );
+        }
+        let filters = web::Data::new(Filters::new(filters));
+
+        // CORS is only enabled for the API scopes, if any origins are configured
+        if let Some(origins) = conf.app.cors_origins.as_ref().filter(|o| !o.is_empty()) {
+            println!("{} {origins:?}", "CORS enabled for:".yellow());
+        }
+
+        // Handlers read runtime settings from the shared config, which is replaced on SIGHUP
+        let shared_conf = reload::shared(conf.app);
+        if let Some(args) = reload_args {
+            reload::spawn_reloader(
+                shared_conf.clone(),
+                args,
+                clipboards.clone(),
+                rate_limiter.clone(),
+                filters.clone(),
+            );
+        }
+
+        // Webhooks are read from the shared config for every event, so they too can be reloaded
+        if let Some(urls) = &shared_conf.load().webhooks {
+            println!("{} {urls:?}", "Webhooks enabled for:".yellow());
+        }
+        webhooks::spawn(&clipboards, shared_conf.clone());
+
+        #[cfg(not(feature = "mailer"))]
+        if shared_conf.load().mailer.is_some() {
+            eprintln!(
+                "{}",
+                "mailer is ignored: soyjot-actix was built without the mailer feature".red()
+            );
+        }
+
+        // Peers are fixed at startup, since each of them has its own delivery queue
+        let replicator = shared_conf.load().replication.as_ref().map(|replication| {
+            println!("{} {:?}", "Replicating to:".yellow(), replication.peers);
+            replication::spawn(&clipboards, replication)
+        });
+
+        // The janitor cleans up clipboard files that are no longer tracked while the server runs
+        if let Some(secs) = shared_conf.load().janitor_interval {
+            println!("{} every {secs}s", "Janitor enabled:".yellow());
+            janitor::spawn(
+                clipboards.clone(),
+                shared_conf.clone(),
+                Duration::from_secs(secs),
+            );
+        }
+
+        // The audit log is shared by all workers, so that records are appended in order
+        let audit_log = match shared_conf.load().audit_log.clone() {
+            Some(audit) => {
+                println!("{} {}", "Audit log:".yellow(), audit.path);
+                let log = AuditLog::open(audit.clone()).map_err(|err| {
+                    std::io::Error::new(err.kind(), format!("{}: {err}", audit.path))
+                })?;
+
+                Some(web::Data::new(log))
+            }
+            None => None,
+        };
+
+        let opts = MountOpts {
+            scopes: shared_conf.load().scopes(),
+            #[cfg(feature = "mailer")]
+            mailer: web::Data::new(Mailer::new(shared_conf.load().mailer.as_ref())),
+            sharing: web::Data::new(Sharing::new(shared_conf.load().share.as_ref())),
+            conf: shared_conf,
+            store: clipboards,
+            hashing,
+            filters,
+            uploads: web::Data::new(Uploads::default()),
+            used_tokens: web::Data::new(UsedTokens::default()),
+        };
+        println!("{} {:?}", "Mounted scopes:".yellow(), opts.scopes);
+
+        let hooks = web::Data::new(Hooks::new(hooks));
+        let server = HttpServer::new(move || {
+            let mut app = App::new().app_data(hooks.clone());
+
+            if let Some(limiter) = rate_limiter.clone() {
+                app = app.app_data(limiter);
+            }
+
+            if let Some(replicator) = replicator.clone() {
+                app = app.app_data(replicator);
+            }
+
+            if let Some(log) = audit_log.clone() {
+                app = app.app_data(log);
+            }
+
+            // Responses are compressed according to Accept-Encoding, except for
+            // compressed clipboard files that are already served with a Content-Encoding.
+            // The audit log records the sizes of responses before they are compressed.
+            let app = app
+                .wrap(mw::from_fn(middleware::audit))
+                .wrap(mw::Compress::default())
+                .wrap(mw::NormalizePath::new(mw::TrailingSlash::Trim))
+                .wrap(mw::from_fn(middleware::hooks))
+                .wrap(mw::from_fn(middleware::load_shed))
+                .wrap(mw::from_fn(middleware::rate_limit))
+                .wrap(mw::from_fn(middleware::security_headers))
+                .configure(|cfg| mount(cfg, opts.clone()));
+
+            configure
+                .iter()
+                .fold(app, |app, f| app.configure(|cfg| f(cfg)))
+        });
+
+        let server = bind_addrs.iter().try_fold(server, |server, addr| {
+            let bound = match &tls_config {
+                Some(tls_config) => server.bind_rustls_0_23(addr, tls_config.clone()),
+                None => server.bind(addr),
+            };
+
+            bound.map_err(|err| std::io::Error::new(err.kind(), format!("{addr}: {err}")))
+        })?;
+
+        Ok(server.run())
+    }
+}
+
+/// MountOpts are the shared state of the routes mounted with `mount`.
+/// Clones share the same state, so that every worker of a server can mount with a clone.
+#[derive(Clone)]
+pub struct MountOpts {
+    conf: web::Data<SharedConfig>,
+    store: web::Data<Store>,
+    hashing: HashConfig,
+    filters: web::Data<Filters>,
+    uploads: web::Data<Uploads>,
+    used_tokens: web::Data<UsedTokens>,
+    #[cfg(feature = "mailer")]
+    mailer: web::Data<Mailer>,
+    sharing: web::Data<Sharing>,
+    scopes: Vec<Scope>,
+}
+
+impl MountOpts {
+    /// new returns the options for mounting the routes configured by `conf`,
+    /// storing clipboards in `store`
+    pub fn new(conf: ValidatedConfig, store: web::Data<Store>) -> Self {
+        let filters = conf
+            .app
+            .filter_chain()
+            .expect("validated config has bad filters");
+
+        Self {
+            scopes: conf.app.scopes(),
+            #[cfg(feature = "mailer")]
+            mailer: web::Data::new(Mailer::new(conf.app.mailer.as_ref())),
+            sharing: web::Data::new(Sharing::new(conf.app.share.as_ref())),
+            conf: reload::shared(conf.app),
+            store,
+            hashing: conf.hashing,
+            filters: web::Data::new(Filters::new(filters)),
+            uploads: web::Data::new(Uploads::default()),
+            used_tokens: web::Data::new(UsedTokens::default()),
+        }
+    }
+
+    /// scopes mounts `scopes` instead of `AppConfig::scopes`
+    pub fn scopes(mut self, scopes: Vec<Scope>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+}
+
+/// mount mounts the routes of the scopes in `opts` and `/ws` on `cfg`, and registers
+/// the app data they need, so that an existing actix-web application can serve clipboards,
+/// e.g. under its own prefix with `web::scope("/clipboards").configure(..)`.
+/// Only the routes are mounted: rate limiting, security headers and background tasks
+/// such as the janitor are left to `DropServer`. HTML pages link to their routes
+/// without the prefix, so prefixed mounts are best kept to the API scopes.
+pub fn mount(cfg: &mut web::ServiceConfig, opts: MountOpts) {
+    let cors_origins = opts.conf.load().cors_origins.clone().unwrap_or_default();
+
+    cfg.app_data(opts.conf)
+        .app_data(web::Data::new(opts.hashing))
+        .app_data(opts.store)
+        .app_data(opts.filters)
+        .app_data(opts.uploads)
+        .app_data(opts.used_tokens)
+        .app_data(opts.sharing);
+
+    #[cfg(feature = "mailer")]
+    cfg.app_data(opts.mailer);
+
+    configure_scopes(cfg, &opts.scopes, &cors_origins);
+    cfg.service(ws::routes("/ws"));
+}
+
+/// open_store creates the storage directory if needed, and opens a store configured
+/// with `conf`. The store is rebuilt from the index written on last shutdown
+/// (see `save_index`), and clipboard files not in the index are restored too.
+pub fn open_store(conf: &ValidatedConfig) -> web::Data<Store> {
+    store::persist::assert_dir(Some(&conf.dir));
+
+    // Files written before clipboard files were sharded are moved into their shards,
+    // before the index refers to them
+    match store::persist::migrate_flat_files(&conf.dir) {
+        Ok(0) => {}
+        Ok(moved) => println!("{} {moved}", "Migrated clipboard files:".yellow()),
+        Err(err) => eprintln!("{} {err}", "error migrating clipboard files:".red()),
+    }
+
+    // Store is shared by all workers, and keeps its files in the configured directory
+    let fsync = conf.app.fsync.unwrap_or_default();
+    let files = store::persist::DiskFs::new(&conf.dir)
+        .fsync(fsync)
+        .read_cache(conf.app.read_cache_bytes.unwrap_or_default());
+    let files = Arc::new(files);
+    let clipboards = web::Data::new(Store::with_persist(conf.app.store_config(), files));
+    match store::persist::read_index(&conf.dir) {
+        Ok(entries) => {
+            let restored = Store::restore_index(clipboards.clone().into_inner(), entries);
+            println!("{} {restored}", "Restored clipboards:".yellow());
+        }
+
+        Err(err) => eprintln!("{} {err}", "error reading clipboard index:".red()),
+    }
+
+    // Files not in the index (e.g. left behind by a crash) are restored with the default TTL
+    match store::persist::scan_dir(&conf.dir) {
+        Ok(files) => {
+            let (restored, removed) = Store::restore_files(
+                clipboards.clone().into_inner(),
+                files,
+                conf.timeout,
+                conf.app.orphan_max_age.map(Duration::from_secs),
+            );
+
+            println!(
+                "{} {restored} restored, {removed} removed",
+                "Orphaned clipboard files:".yellow()
+            );
+        }
+
+        Err(err) => eprintln!("{} {err}", "error scanning storage directory:".red()),
+    }
+
+    clipboards
+}
+
+/// save_index writes the index of live clipboards in `store` to disk, so that `open_store`
+/// restores them on the next startup. It should be called once the server has shut down.
+pub fn save_index(store: &Store) {
+    let Some(dir) = store.dir() else {
+        return;
+    };
+
+    let index = store.index();
+    match store::persist::write_index(dir, &index) {
+        Ok(()) => println!("{} {}", "Saved clipboard index:".yellow(), index.len()),
+        Err(err) => eprintln!("{} {err}", "error saving clipboard index:".red()),
+    }
+}
+
+/// configure_scopes mounts the routes of every scope in `scopes`, with CORS for
+/// the API scopes if `cors_origins` is not empty. The HTML UI's static assets are only
+/// served with `Scope::App`, and the OpenAPI spec with `Scope::Api`.
+/// `/drop/{id}` is mounted outside the scopes, responding like one of them
+/// depending on the `Accept` header, and so are the short links of redirects at `/r/{id}`.
+fn configure_scopes(cfg: &mut web::ServiceConfig, scopes: &[Scope], cors_origins: &[String]) {
+    let cors = || mw::Condition::new(!cors_origins.is_empty(), middleware::cors(cors_origins));
+
+    for scope in scopes {
+        let prefix = scope.prefix();
+
+        match scope {
+            Scope::App => {
+                cfg.configure(assets::register)
+                    .service(admin::routes())
+                    .service(secure::routes())
+                    .service(http_server::routes::<http_resp::ResponseHtml>(prefix));
+            }
+
+            Scope::Api => {
+                #[cfg(feature = "mailer")]
+                cfg.service(mailer::routes().wrap(cors()));
+
+                cfg.service(
+                    web::resource("/api/openapi.json")
+                        .route(web::get().to(openapi::openapi_json))
+                        .wrap(cors()),
+                )
+                .service(tenants::routes())
+                .service(replication::routes())
+                .service(upload_tokens::routes())
+                .service(bundle::routes().wrap(cors()))
+                .service(share::routes().wrap(cors()))
+                .service(archive::routes())
+                .service(chunks::routes())
+                .service(search::routes().wrap(cors()))
+                .service(drops::routes().wrap(cors()))
+                .service(
+                    http_server::routes_versioned(prefix)
+                        .into_iter()
+                        .map(|scope| scope.wrap(cors()))
+                        .collect::<Vec<_>>(),
+                )
+                .service(http_server::routes::<http_resp::ResponseJson>(prefix).wrap(cors()));
+            }
+
+            Scope::Txt => {
+                cfg.service(http_server::routes::<http_resp::ResponseText>(prefix).wrap(cors()));
+            }
+
+            Scope::Raw => {
+                cfg.service(http_server::routes_raw(prefix));
+            }
+
+            Scope::Bin => {
+                cfg.service(http_server::routes::<http_resp::ResponseMsgpack>(prefix).wrap(cors()));
+            }
+        }
+    }
+
+    cfg.service(http_server::routes_negotiated().wrap(cors()))
+        .service(http_server::routes_redirect());
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    use soyjot::config::{AppConfig, Scope};
+    use soyjot::store::Store;
+
+    use super::{mount, MountOpts};
+
+    #[actix_web::test]
+    async fn test_mount() {
+        let conf = AppConfig::default().validate().unwrap();
+        let opts = MountOpts::new(conf, web::Data::new(Store::new())).scopes(vec![Scope::Api]);
+
+        let app = test::init_service(
+            App::new().service(web::scope("/clipboards").configure(|cfg| mount(cfg, opts))),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/clipboards/api/drop")
+            .set_json(serde_json::json!({ "mem": "mounted" }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let hash = body["clipboard"].as_str().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/clipboards/api/drop/{hash}"))
+            .to_request();
+        assert_eq!(
+            test::call_and_read_body(&app, req).await,
+            "mounted".as_bytes()
+        );
+
+        // Only the given scopes are mounted
+        let req = test::TestRequest::get().uri("/clipboards/txt").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,335 @@
+//! Sharing clipboards to team chats with `POST /api/drop/{id}/share?target=slack`, which posts
+//! the link to the HTML view of the clipboard, and an excerpt of its text, to the webhook of
+//! the target in `AppConfig::share`. `GET /api/drop/{id}/share` lists the configured targets,
+//! so that the HTML view can show a share button for each of them.
+//!
+//! Targets are only the configured chats, but each client may only share
+//! `ShareConfig::rate_limit` clipboards, so that the instance cannot be used to flood them.
+//! Excerpts are taken without counting a view, so they are left out for encrypted,
+//! view-limited and held clipboards, whose content must not be posted to a whole channel.
+
+use std::time::Duration;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::config::{ShareConfig, ShareTarget};
+use soyjot::html;
+use soyjot::rate_limit::{RateLimitConfig, RateLimiter};
+use soyjot::store::error::StoreError;
+use soyjot::store::index::{self, IndexEntry};
+use soyjot::store::Store;
+
+use crate::http_resp::ResponseJson;
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+/// Time posting to a webhook may take before it fails
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Characters quoted from text clipboards, unless configured with `ShareConfig::excerpt`
+const EXCERPT: usize = 200;
+
+/// Largest clipboard that is quoted, in bytes
+const MAX_EXCERPT_SOURCE: u64 = 1024 * 1024;
+
+/// Shares each client may post, unless configured with `ShareConfig::rate_limit`
+fn default_rate_limit() -> RateLimitConfig {
+    RateLimitConfig {
+        burst: 5,
+        per_sec: 1.0 / 60.0,
+    }
+}
+
+/// Sharing limits the shares posted by each client, shared by every worker
+pub struct Sharing {
+    limiter: RateLimiter,
+}
+
+impl Sharing {
+    /// new returns the share limits of clients with the rate limit of `conf`, if any
+    pub fn new(conf: Option<&ShareConfig>) -> Self {
+        let limits = conf.and_then(|conf| conf.rate_limit.clone());
+
+        Self {
+            limiter: RateLimiter::new(limits.unwrap_or_else(default_rate_limit)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ShareQuery {
+    target: ShareTarget,
+}
+
+/// routes returns the share route, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Resource {
+    web::resource("/api/drop/{id}/share")
+        .guard(http_server::global_ids())
+        .route(web::get().to(targets))
+        .route(web::post().to(share_clipboard))
+}
+
+/// targets responds with the targets that clipboards can be shared to, none if unconfigured
+async fn targets(conf: web::Data<SharedConfig>) -> HttpResponse {
+    let conf = conf.load();
+    let targets = conf
+        .share
+        .as_ref()
+        .map(ShareConfig::targets)
+        .unwrap_or_default();
+
+    HttpResponse::Ok().json(json!({ "targets": targets }))
+}
+
+/// share_clipboard posts a link to clipboard `id`, and an excerpt of it, to the target chat
+async fn share_clipboard(
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    sharing: web::Data<Sharing>,
+    path: web::Path<String>,
+    query: web::Query<ShareQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let hash = path.into_inner();
+    let target = query.target;
+    let conf = conf.load();
+    let Some((share, url)) = conf
+        .share
+        .as_ref()
+        .and_then(|share| Some((share, share.webhook(target)?)))
+    else {
+        let err = StoreError::NotImplemented(format!("sharing to {target:?} is not configured"));
+        return http_server::store_error::<R>(&hash, err);
+    };
+
+    if let Err(err) = http_server::check_link(&req, &hash) {
+        return http_server::store_error::<R>(&hash, err);
+    }
+
+    let Some(meta) = store.meta(&hash) else {
+        return http_server::send_error::<R>(&hash, store.not_found(&hash));
+    };
+
+    if let Some(Err(wait)) = http_server::client_ip(&req).map(|ip| sharing.limiter.check(ip)) {
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait.as_secs().max(1).to_string()))
+            .content_type("text/plain; charset=utf-8")
+            .body("rate limit exceeded");
+    }
+
+    let link = http_server::view_url(&conf, &meta);
+    let excerpt = excerpt(&store, &meta, share.excerpt.unwrap_or(EXCERPT)).await;
+    let message = message(target, &meta, &link, excerpt.as_deref());
+
+    let client = awc::Client::builder().timeout(TIMEOUT).finish();
+    let err = match client.post(url).send_json(&message).await {
+        Ok(resp) if resp.status().is_success() => {
+            return HttpResponse::Ok().json(json!({ "clipboard": hash, "target": target }));
+        }
+
+        Ok(resp) => format!("webhook returned {}", resp.status()),
+        Err(err) => err.to_string(),
+    };
+
+    // The webhook URL holds its token, so only the target is logged
+    eprintln!("share: failed to share {hash} to {target:?}: {err}");
+    let err = StoreError::IoError(std::io::Error::other(err));
+    http_server::store_error::<R>(&hash, err)
+}
+
+/// excerpt returns up to `chars` characters of text clipboard `meta`, without counting a view,
+/// or None if it may not or cannot be quoted
+async fn excerpt(store: &Store, meta: &IndexEntry, chars: usize) -> Option<String> {
+    if chars == 0
+        || meta.encryption.is_some()
+        || meta.size > MAX_EXCERPT_SOURCE
+        || store.is_view_limited(&meta.hash)
+    {
+        return None;
+    }
+
+    let clipboard = store.peek_clipboard(&meta.hash).await?;
+    let text = std::str::from_utf8(clipboard.as_ref()).ok()?.trim_end();
+
+    match text.char_indices().nth(chars) {
+        Some((end, _)) => Some(format!("{}…", &text[..end])),
+        None if text.is_empty() => None,
+        None => Some(text.to_string()),
+    }
+}
+
+/// message returns the JSON body posted to the webhook of `target` to share clipboard `meta`,
+/// linked to at `link`: `text` in Slack's mrkdwn for Slack, and `text` and `html` for Matrix
+fn message(
+    target: ShareTarget,
+    meta: &IndexEntry,
+    link: &str,
+    excerpt: Option<&str>,
+) -> serde_json::Value {
+    let hash = &meta.hash;
+    let expires = match meta.pinned {
+        true => String::new(),
+        false => format!(", expiring at {}", index::to_rfc3339(meta.expires_at)),
+    };
+
+    match target {
+        ShareTarget::Slack => {
+            // Slack only needs &, < and > escaped in mrkdwn
+            let escape = |s: &str| {
+                s.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+            };
+            let quote = excerpt
+                .map(|excerpt| format!("\n```\n{}\n```", escape(excerpt)))
+                .unwrap_or_default();
+
+            json!({
+                "text": format!("Clipboard <{}|{hash}> was shared{expires}{quote}", escape(link)),
+            })
+        }
+
+        ShareTarget::Matrix => {
+            let (text, quote) = match excerpt {
+                Some(excerpt) => (
+                    format!("\n\n{excerpt}"),
+                    format!("<pre><code>{}</code></pre>", html::escape(excerpt)),
+                ),
+                None => Default::default(),
+            };
+
+            json!({
+                "text": format!("Clipboard {hash} was shared{expires}: {link}{text}"),
+                "html": format!(
+                    r#"<p>Clipboard <a href="{}"><code>{hash}</code></a> was shared{expires}</p>{quote}"#,
+                    html::escape(link),
+                ),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse, HttpServer};
+
+    use soyjot::config::{AppConfig, ShareConfig};
+    use soyjot::hash::HashConfig;
+    use soyjot::rate_limit::RateLimitConfig;
+    use soyjot::store::Store;
+
+    use super::Sharing;
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    /// serve starts a chat webhook, and returns its URL and the messages it received
+    fn serve() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let messages = received.clone();
+
+        let server = HttpServer::new(move || {
+            let messages = messages.clone();
+
+            App::new().route(
+                "/hook",
+                web::post().to(move |message: web::Json<serde_json::Value>| {
+                    messages.lock().unwrap().push(message.into_inner());
+                    async { HttpResponse::Ok().finish() }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        (format!("http://{addr}/hook"), received)
+    }
+
+    #[actix_web::test]
+    async fn test_share() {
+        let (url, messages) = serve();
+        let share = ShareConfig {
+            slack: Some(url),
+            matrix: None,
+            excerpt: Some(8),
+            rate_limit: Some(RateLimitConfig {
+                burst: 2,
+                per_sec: 0.0,
+            }),
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Store::new()))
+                .app_data(web::Data::new(Sharing::new(Some(&share))))
+                .app_data(reload::shared(AppConfig {
+                    share: Some(share),
+                    ..AppConfig::default()
+                }))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let mut ids = Vec::new();
+        for (content, query) in [("<b>bold</b> claims", ""), ("secret", "?max_views=1")] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/drop{query}"))
+                .set_json(serde_json::json!({ "mem": content }))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            ids.push(body["clipboard"].as_str().unwrap().to_string());
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{}/share", ids[0]))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["targets"], serde_json::json!(["slack"]));
+
+        let share = |id: &str, target: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/drop/{id}/share?target={target}"))
+                .peer_addr("127.0.0.1:1234".parse().unwrap())
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, share(&ids[0], "matrix")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = test::call_service(&app, share("f00d", "slack")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        for id in &ids {
+            let resp = test::call_service(&app, share(id, "slack")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let messages = messages.lock().unwrap().clone();
+        let text = messages[0]["text"].as_str().unwrap();
+        assert!(text.contains(&format!("/app/drop/{}|{}>", ids[0], ids[0])));
+        assert!(text.ends_with("```\n&lt;b&gt;bold&lt;…\n```"), "{text}");
+        let text = messages[1]["text"].as_str().unwrap();
+        assert!(!text.contains("secret"), "one-time clipboard was quoted");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/drop/{}", ids[1]))
+            .to_request();
+        let content = test::call_and_read_body(&app, req).await;
+        assert_eq!(content, "secret", "sharing counted a view");
+
+        let resp = test::call_service(&app, share(&ids[0], "slack")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,390 @@
+//! Tenant routes at `/api/t/{tenant}`, so that one server can serve several teams in isolation.
+//!
+//! Each tenant in `AppConfig::tenants` posts and reads clipboards in its own keyspace
+//! (see `soyjot::tenant`), so the same content gets the same ID in every tenant without
+//! the tenants seeing each other's clipboards, and tenant clipboards never show up
+//! on the global routes. Requests must carry the tenant's token as a bearer token.
+//! Tokens are read from the shared config, so they can be changed with a reload.
+//!
+//! Responses are JSON like `/api`, with clipboard IDs local to the tenant.
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use soyjot::filters::Filters;
+use soyjot::hash::HashConfig;
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::error::StoreError;
+use soyjot::store::{Resolved, Store};
+use soyjot::tenant;
+
+use crate::admin::{credentials, tokens_eq};
+use crate::http_resp::{DropResponseHttp, ResponseJson};
+use crate::http_server::{self, PostQuery};
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+const PATH: &str = "/api/t/{tenant}";
+
+/// routes returns the tenant scope, which must be mounted before the `/api` scope
+pub fn routes() -> actix_web::Scope {
+    web::scope(PATH)
+        .route("/drop", web::post().to(post_drop))
+        .route("/drop/{id}", web::get().to(get_drop))
+        .route("/drop/{id}", web::delete().to(delete_drop))
+        .route("/drop/{id}/meta", web::get().to(get_meta))
+        .route("/d/{frag}", web::get().to(get_frag))
+}
+
+/// unauthorized checks that `tenant` exists and that `req` carries its token,
+/// and returns the response to send instead if not
+fn unauthorized(req: &HttpRequest, conf: &SharedConfig, tenant: &str) -> Option<HttpResponse> {
+    let conf = conf.load();
+    let Some(config) = conf
+        .tenants
+        .as_ref()
+        .and_then(|tenants| tenants.get(tenant))
+    else {
+        return Some(HttpResponse::NotFound().json(json!({ "error": "no such tenant" })));
+    };
+
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(credentials);
+
+    match given {
+        Some(given) if tokens_eq(given.as_bytes(), config.token.as_bytes()) => None,
+        _ => Some(http_server::unauthorized::<R>("tenant token required")),
+    }
+}
+
+/// local returns the ID of clipboard `key` within its tenant
+fn local(key: &str) -> &str {
+    tenant::split(key).1
+}
+
+/// post_drop stores a clipboard posted as JSON like `POST /api/drop`,
+/// in the keyspace of the tenant
+#[allow(clippy::too_many_arguments)]
+async fn post_drop(
+    path: web::Path<String>,
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+    hashing: web::Data<HashConfig>,
+    filters: Option<web::Data<Filters>>,
+    query: web::Query<PostQuery>,
+    web::Json(clipboard): web::Json<Clipboard>,
+) -> HttpResponse {
+    let tenant = path.into_inner();
+    if let Some(resp) = unauthorized(&req, &conf, &tenant) {
+        return resp;
+    }
+
+    if let Err(err) = clipboard.is_implemented() {
+        return http_server::store_error::<R>("", err);
+    }
+
+    if clipboard.is_empty() {
+        return http_server::store_error::<R>("", StoreError::Empty);
+    }
+
+    let clipboard = match http_server::filter_chain(filters).apply_clipboard(clipboard) {
+        Ok(clipboard) => clipboard,
+        Err(err) => return http_server::store_error::<R>("", err),
+    };
+
+    let digest = hashing.digest(&clipboard);
+    let key_digest = http_server::key_digest(&hashing, &clipboard, &digest);
+    let hash = http_server::new_key(&store, &hashing, &key_digest, Some(&tenant));
+    let key = tenant::key(&tenant, &hash);
+
+    // Requests to tenants are authenticated with the tenant token, so they may pin
+    let opts = match http_server::post_opts(query, &req, true) {
+        Ok(opts) => opts,
+        Err(err) => return http_server::store_error::<R>(&hash, err),
+    };
+
+    let clipboard = store.place(clipboard);
+    let dur = conf.load().timeout_duration();
+    let store = store.into_inner();
+
+    match http_server::store_clipboard(store.clone(), &key, &digest, clipboard, dur, opts).await {
+        Ok((key, storage, owner_key)) => {
+            let short = store.shortest_prefix(&key);
+            let owner_key = owner_key.as_deref();
+            let resp = R::from((HttpResponse::Ok(), Ok(None))).post_clipboard_stored(
+                local(&key),
+                &storage,
+                local(&short),
+                owner_key,
+                None,
+                store.expires_at(&key),
+            );
+
+            http_server::created(resp, &key, owner_key)
+        }
+        Err(err) => http_server::store_error::<R>(&hash, err),
+    }
+}
+
+async fn get_drop(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    let (tenant, hash) = path.into_inner();
+    if let Some(resp) = unauthorized(&req, &conf, &tenant) {
+        return resp;
+    }
+
+    send(&req, &store, &tenant::key(&tenant, &hash)).await
+}
+
+/// get_frag is like `GET /api/d/{frag}`, resolving prefixes among the tenant's clipboards
+async fn get_frag(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    let (tenant, frag) = path.into_inner();
+    if let Some(resp) = unauthorized(&req, &conf, &tenant) {
+        return resp;
+    }
+
+    let prefix_len = tenant::key(&tenant, "").len();
+
+    match store.resolve_prefix(&tenant::key(&tenant, &frag)) {
+        Resolved::Unique(key) => send(&req, &store, &key).await,
+        Resolved::Ambiguous(lens) => {
+            let lens: Vec<usize> = lens.into_iter().map(|len| len - prefix_len).collect();
+            R::from((HttpResponse::MultipleChoices(), Ok(None))).send_ambiguous(&frag, &lens)
+        }
+        Resolved::None => http_server::send_error::<R>(&frag, StoreError::NoSuch),
+    }
+}
+
+/// send sends tenant clipboard `key`, counting the view
+async fn send(req: &HttpRequest, store: &Store, key: &str) -> HttpResponse {
+    let expires_at = store.expires_at(key);
+    match store.get_clipboard(key).await {
+        Some(clipboard) => {
+            let (content_type, lang) = (store.content_type(key), store.lang(key));
+            http_server::send_clipboard::<R>(
+                req,
+                local(key),
+                clipboard,
+                content_type,
+                lang,
+                expires_at,
+            )
+        }
+        None => http_server::send_error::<R>(local(key), store.not_found(key)),
+    }
+}
+
+async fn get_meta(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    let (tenant, hash) = path.into_inner();
+    if let Some(resp) = unauthorized(&req, &conf, &tenant) {
+        return resp;
+    }
+
+    match store.meta(&tenant::key(&tenant, &hash)) {
+        Some(mut meta) => {
+            meta.hash = hash;
+            R::from((HttpResponse::Ok(), Ok(None))).send_meta(&meta)
+        }
+        None => http_server::send_error::<R>(&hash, store.not_found(&tenant::key(&tenant, &hash))),
+    }
+}
+
+/// delete_drop removes a tenant clipboard on the request of its owner, like `DELETE /api/drop/{id}`
+async fn delete_drop(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    store: web::Data<Store>,
+    conf: web::Data<SharedConfig>,
+) -> HttpResponse {
+    let (tenant, hash) = path.into_inner();
+    if let Some(resp) = unauthorized(&req, &conf, &tenant) {
+        return resp;
+    }
+
+    let Some(owner_key) = http_server::owner_key(&req) else {
+        return http_server::store_error::<R>(&hash, StoreError::Forbidden);
+    };
+
+    match store
+        .delete_clipboard(&tenant::key(&tenant, &hash), &owner_key)
+        .await
+    {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => http_server::store_error::<R>(&hash, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::quota::QuotaConfig;
+    use soyjot::store::{Store, StoreConfig};
+    use soyjot::tenant::TenantConfig;
+
+    use crate::http_resp::ResponseJson;
+    use crate::{drops, http_server, reload};
+
+    #[actix_web::test]
+    async fn test_tenants() {
+        let tenants = BTreeMap::from([
+            (
+                "red".to_string(),
+                TenantConfig {
+                    token: "red-token".to_string(),
+                    quota: Some(QuotaConfig {
+                        max_clipboards: Some(1),
+                        max_bytes: None,
+                    }),
+                },
+            ),
+            (
+                "blue".to_string(),
+                TenantConfig {
+                    token: "blue-token".to_string(),
+                    quota: None,
+                },
+            ),
+        ]);
+        let conf = AppConfig {
+            tenants: Some(tenants),
+            ..AppConfig::default()
+        };
+
+        let store = Store::with_config(StoreConfig {
+            tenant_quotas: conf.tenant_quotas(),
+            ..StoreConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(store))
+                .app_data(reload::shared(conf))
+                .app_data(web::Data::new(HashConfig::default()))
+                .service(super::routes())
+                .service(drops::routes())
+                .service(http_server::routes_raw("/raw"))
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let post = |tenant: &str, token: &str, content: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/t/{tenant}/drop"))
+                .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+                .set_json(serde_json::json!({ "mem": content }))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, post("red", "blue-token", "shared")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, post("green", "red-token", "shared")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // The same content gets the same ID in both tenants
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, post("red", "red-token", "shared")).await;
+        let hash = body["clipboard"].as_str().unwrap().to_string();
+        assert_eq!(
+            body["short"],
+            hash.as_str(),
+            "short keys only resolve whole"
+        );
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, post("blue", "blue-token", "shared")).await;
+        assert_eq!(body["clipboard"], hash.as_str());
+
+        let resp = test::call_service(&app, post("red", "red-token", "other")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let get = |uri: String, token: &str| {
+            test::TestRequest::get()
+                .uri(&uri)
+                .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+                .to_request()
+        };
+
+        let uri = format!("/api/t/red/drop/{hash}");
+        let resp = test::call_service(&app, get(uri.clone(), "blue-token")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body = test::call_and_read_body(&app, get(uri, "red-token")).await;
+        assert_eq!(body, "shared".as_bytes());
+
+        let body =
+            test::call_and_read_body(&app, get(format!("/api/t/blue/d/{hash}"), "blue-token"))
+                .await;
+        assert_eq!(body, "shared".as_bytes());
+
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            get(format!("/api/t/blue/drop/{hash}/meta"), "blue-token"),
+        )
+        .await;
+        assert_eq!(body["clipboard"], hash.as_str());
+
+        // Tenant clipboards are not on the global routes
+        for uri in [format!("/api/drop/{hash}"), format!("/api/d/{hash}")] {
+            let resp = test::call_service(&app, get(uri.clone(), "red-token")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+
+        // Not even with the separator percent-encoded, which web::Path decodes
+        for uri in [
+            format!("/api/drop/red%2F{hash}"),
+            format!("/api/drop/red%2f{hash}/meta"),
+            format!("/api/d/red%2F{hash}"),
+            format!("/raw/drop/red%2F{hash}"),
+        ] {
+            let resp = test::call_service(&app, get(uri.clone(), "blue-token")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/drop/red%2F{hash}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            get(format!("/api/drops?ids=red/{hash}"), "blue-token"),
+        )
+        .await;
+        assert_eq!(body["clipboards"][format!("red/{hash}")]["kind"], "NoSuch");
+
+        let body =
+            test::call_and_read_body(&app, get(format!("/api/t/red/drop/{hash}"), "red-token"))
+                .await;
+        assert_eq!(
+            body,
+            "shared".as_bytes(),
+            "tenant clipboards must not be deleted"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix_web::dev::Server;
+use actix_web::{http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+
+/// server_config loads a PEM certificate chain and private key into a rustls `ServerConfig`.
+pub fn server_config(cert: &str, key: &str) -> Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("failed to read tls_cert {cert}: {err}"))?;
+
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|err| format!("failed to read tls_key {key}: {err}"))?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|err| format!("bad tls config: {err}"))
+}
+
+/// redirect_server binds a plain HTTP server on every address in `addrs` that permanently
+/// redirects every request to the same path on the HTTPS server listening on `https_port`.
+pub fn redirect_server(addrs: &[SocketAddr], https_port: u16) -> std::io::Result<Server> {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(https_port))
+            .default_service(web::to(redirect))
+    });
+
+    let server = addrs
+        .iter()
+        .try_fold(server, |server, addr| server.bind(addr))?
+        .run();
+
+    Ok(server)
+}
+
+async fn redirect(req: HttpRequest, https_port: web::Data<u16>) -> HttpResponse {
+    let conn = req.connection_info();
+    let host = conn.host();
+
+    // Strip the HTTP port, keeping bracketed IPv6 hosts intact
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    let location = match **https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    };
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test, web, App};
+
+    #[actix_web::test]
+    async fn test_redirect() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(8443_u16))
+                .default_service(web::to(super::redirect)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/app/drop/abcd?force=true")
+            .insert_header((header::HOST, "drop.example.com:8080"))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_redirection());
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "https://drop.example.com:8443/app/drop/abcd?force=true"
+        );
+    }
+}
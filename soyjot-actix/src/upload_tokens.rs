@@ -0,0 +1,234 @@
+//! One-time upload tokens, to collect clipboards (e.g. logs) from clients that are not
+//! otherwise allowed to post.
+//!
+//! `POST /api/admin/upload-tokens` mints a token signed with `AppConfig::link_secret`
+//! (see `signing::UploadGrant`), and responds with the URL to post the clipboard to,
+//! `/api/drop?upload_token=...`. Any post route but `/api/drops` takes the token.
+//! The token is used up by the first post that presents it, even if the clipboard
+//! is then rejected, and holds the clipboard to its `max_size` and `max_ttl`.
+//! With `AppConfig::require_upload_token`, clients may only post with an upload token,
+//! or the admin token.
+//!
+//! Used tokens are remembered in memory until they expire, so a token used before a restart
+//! can be used once more after it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use soyjot::id::Alphabet;
+use soyjot::signing::{self, UploadGrant};
+use soyjot::store::error::StoreError;
+use soyjot::store::index;
+
+use crate::archive;
+use crate::http_resp::ResponseJson;
+use crate::http_server;
+use crate::reload::SharedConfig;
+
+type R = ResponseJson;
+
+/// Seconds tokens can be used for, unless minted with a `ttl`
+const TOKEN_TTL: u64 = 3600;
+
+/// Length of token IDs, in hex characters
+const ID_LEN: usize = 32;
+
+/// UsedTokens are the IDs of the tokens used so far, with their expiry,
+/// shared by every worker
+#[derive(Default)]
+pub struct UsedTokens {
+    used: Mutex<HashMap<String, u64>>,
+}
+
+impl UsedTokens {
+    /// take records that the token of `grant` is used at `now`,
+    /// and returns false if it already was
+    fn take(&self, grant: &UploadGrant, now: u64) -> bool {
+        let mut used = self.used.lock().expect("upload tokens lock poisoned");
+        used.retain(|_, expires| *expires > now);
+
+        used.insert(grant.id.clone(), grant.expires).is_none()
+    }
+}
+
+/// MintRequest is the JSON body of `POST /api/admin/upload-tokens`
+#[derive(Deserialize, Debug, Default)]
+struct MintRequest {
+    /// Seconds the token can be used for
+    ttl: Option<u64>,
+    /// Largest clipboard that can be posted with the token, in bytes
+    max_size: Option<u64>,
+    /// Most seconds the clipboard posted with the token may live
+    max_ttl: Option<u64>,
+}
+
+/// routes returns the token routes, which must be mounted before the `/api` scope
+/// and the `/api/admin` scope of `archive`
+pub fn routes() -> actix_web::Resource {
+    web::resource("/api/admin/upload-tokens").route(web::post().to(mint))
+}
+
+/// mint responds with a new upload token, its expiry, and the URL to post the clipboard to
+async fn mint(
+    req: HttpRequest,
+    conf: web::Data<SharedConfig>,
+    web::Json(mint): web::Json<MintRequest>,
+) -> HttpResponse {
+    if let Some(resp) = archive::unauthorized(&req, &conf) {
+        return resp;
+    }
+
+    let conf = conf.load();
+    let Some(secret) = conf.link_secret.as_deref() else {
+        let err = StoreError::NotImplemented("upload tokens need link_secret".to_string());
+        return http_server::store_error::<R>("", err);
+    };
+
+    let ttl = mint.ttl.unwrap_or(TOKEN_TTL);
+    if ttl == 0 || mint.max_ttl == Some(0) {
+        let err = StoreError::InvalidTtl("ttl and max_ttl must be positive".to_string());
+        return http_server::store_error::<R>("", err);
+    }
+
+    let grant = UploadGrant {
+        id: Alphabet::Hex.random(ID_LEN),
+        expires: index::to_timestamp(SystemTime::now()) + ttl,
+        max_size: mint.max_size,
+        max_ttl: mint.max_ttl,
+    };
+    let token = signing::sign_upload(secret, &grant);
+
+    HttpResponse::Ok().json(json!({
+        "url": format!("{}/api/drop?upload_token={token}", conf.server_url()),
+        "token": token,
+        "expires_at": index::to_rfc3339(grant.expires),
+    }))
+}
+
+/// redeem checks upload token `token` sent with `req`, and uses it up,
+/// returning what the token allows
+pub(crate) fn redeem(req: &HttpRequest, token: &str) -> Result<UploadGrant, StoreError> {
+    let (Some(conf), Some(used)) = (
+        req.app_data::<web::Data<SharedConfig>>(),
+        req.app_data::<web::Data<UsedTokens>>(),
+    ) else {
+        return Err(StoreError::NotImplemented("upload tokens".to_string()));
+    };
+
+    let Some(secret) = conf.load().link_secret.clone() else {
+        return Err(StoreError::NotImplemented("upload tokens".to_string()));
+    };
+
+    let now = index::to_timestamp(SystemTime::now());
+    let grant = signing::verify_upload(&secret, token, now)?;
+
+    match used.take(&grant, now) {
+        true => Ok(grant),
+        false => Err(StoreError::Unauthorized(
+            "upload token already used".to_string(),
+        )),
+    }
+}
+
+/// required tells whether posts without an upload token are rejected
+/// (see `AppConfig::require_upload_token`)
+pub(crate) fn required(req: &HttpRequest) -> bool {
+    req.app_data::<web::Data<SharedConfig>>()
+        .is_some_and(|conf| conf.load().require_upload_token == Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use soyjot::config::AppConfig;
+    use soyjot::hash::HashConfig;
+    use soyjot::store::Store;
+
+    use super::UsedTokens;
+    use crate::http_resp::ResponseJson;
+    use crate::{http_server, reload};
+
+    #[actix_web::test]
+    async fn test_upload_tokens() {
+        let store = web::Data::new(Store::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(reload::shared(AppConfig {
+                    admin_token: Some("s3cret".to_string()),
+                    link_secret: Some("l1nks".to_string()),
+                    require_upload_token: Some(true),
+                    ..AppConfig::default()
+                }))
+                .app_data(store.clone())
+                .app_data(web::Data::new(HashConfig::default()))
+                .app_data(web::Data::new(UsedTokens::default()))
+                .service(super::routes())
+                .service(http_server::routes::<ResponseJson>("/api")),
+        )
+        .await;
+
+        let post = |uri: &str, data: &str| {
+            test::TestRequest::post()
+                .uri(uri)
+                .set_json(serde_json::json!({ "mem": data }))
+                .to_request()
+        };
+
+        // Posts need a token
+        let resp = test::call_service(&app, post("/api/drop", "foo")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let mint = |body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/api/admin/upload-tokens")
+                .insert_header((header::AUTHORIZATION, "Bearer s3cret"))
+                .set_json(body)
+                .to_request()
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/api/admin/upload-tokens")
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            mint(serde_json::json!({ "max_size": 3, "max_ttl": 60 })),
+        )
+        .await;
+        let token = body["token"].as_str().unwrap();
+        let url = format!("/api/drop?upload_token={token}");
+        assert!(body["url"].as_str().unwrap().ends_with(&url));
+
+        // Tokens hold clipboards to their limits, and only work once
+        let resp = test::call_service(&app, post(&format!("{url}&ttl=600"), "foo")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        let hash = body["clipboard"].as_str().unwrap();
+        let expires_at = store.meta(hash).unwrap().expires_at;
+        assert!(
+            expires_at <= soyjot::store::index::to_timestamp(std::time::SystemTime::now()) + 60
+        );
+
+        let resp = test::call_service(&app, post(&url, "bar")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, mint(serde_json::json!({ "max_size": 3 }))).await;
+        let url = format!("/api/drop?upload_token={}", body["token"].as_str().unwrap());
+        let resp = test::call_service(&app, post(&url, "toolong")).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = test::call_service(&app, post("/api/drop?upload_token=f00d.1", "foo")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,214 @@
+//! Webhooks POST every clipboard `Event` as JSON to the URLs in `AppConfig::webhooks`,
+//! so that external systems (e.g. chat bots or audit logs) can react to new, read and expired
+//! clipboards. The URLs are read from the shared config for every event, so they can be
+//! changed with a reload.
+//!
+//! Failed deliveries (network errors and 5xx or 429 responses) are retried with exponential
+//! backoff, up to `MAX_ATTEMPTS` times. Events are delivered concurrently, so they may arrive
+//! out of order, and their `at` timestamp should be used to order them.
+//!
+//! The first `fetched` event of clipboards posted with a `notify` URL (see `AppConfig::allow_notify`)
+//! is also delivered to that URL, so that creators learn that their clipboard was read.
+
+use std::time::Duration;
+
+use actix_web::web;
+use colored::Colorize;
+use tokio::sync::broadcast::error::RecvError;
+
+use soyjot::store::event::Event;
+use soyjot::store::Store;
+
+use crate::reload::SharedConfig;
+
+/// Number of times an event is sent to a URL before it is dropped
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled after every failed attempt
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// Time each attempt may take before it fails
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// spawn spawns a task that delivers every event of `store` to the webhooks in `conf`.
+pub fn spawn(store: &Store, conf: web::Data<SharedConfig>) {
+    let mut events = store.events();
+
+    actix_web::rt::spawn(async move {
+        let client = awc::Client::builder().timeout(TIMEOUT).finish();
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("{} {missed} events", "webhooks: missed".red());
+                    continue;
+                }
+
+                Err(RecvError::Closed) => break,
+            };
+
+            let webhooks = conf.load().webhooks.clone().unwrap_or_default();
+            for url in webhooks.into_iter().chain(event.notify.clone()) {
+                let (client, event) = (client.clone(), event.clone());
+
+                actix_web::rt::spawn(async move {
+                    if let Err(err) = deliver(&client, &url, &event, BACKOFF).await {
+                        eprintln!(
+                            "{} {:?} for {} to {url}: {err}",
+                            "webhooks: dropped event".red(),
+                            event.event,
+                            event.hash,
+                        );
+                    }
+                });
+            }
+        }
+    });
+}
+
+/// deliver POSTs `event` to `url`, retrying failed attempts after `backoff`, twice `backoff`,
+/// and so on. Other 4xx responses are not retried, since sending the same event again
+/// would fail the same way.
+async fn deliver(
+    client: &awc::Client,
+    url: &str,
+    event: &Event,
+    backoff: Duration,
+) -> Result<(), String> {
+    let mut delay = backoff;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let (err, retry) = match client.post(url).send_json(event).await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => {
+                let status = resp.status();
+                let retry = status.is_server_error() || status.as_u16() == 429;
+
+                (format!("server returned {status}"), retry)
+            }
+
+            Err(err) => (err.to_string(), true),
+        };
+
+        if !retry || attempt == MAX_ATTEMPTS {
+            return Err(format!("{err} (attempt {attempt})"));
+        }
+
+        actix_web::rt::time::sleep(delay).await;
+        delay *= 2;
+    }
+
+    unreachable!("MAX_ATTEMPTS is at least 1")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    use soyjot::config::AppConfig;
+    use soyjot::store::clipboard::Clipboard;
+    use soyjot::store::event::{Event, EventKind};
+    use soyjot::store::{Store, StoreOpts};
+
+    use crate::reload;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    /// serve starts a webhook receiver of `kind` events that fails the first `failures` requests
+    /// with `status`, and returns its URL and the number of requests received.
+    fn serve(failures: u32, status: u16, kind: &'static str) -> (String, Arc<AtomicU32>) {
+        let received = Arc::new(AtomicU32::new(0));
+        let counter = received.clone();
+
+        let server = HttpServer::new(move || {
+            let counter = counter.clone();
+
+            App::new().route(
+                "/hook",
+                web::post().to(move |event: web::Json<serde_json::Value>| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(event["event"], kind);
+
+                    async move {
+                        match n < failures {
+                            true => HttpResponse::build(status.try_into().unwrap()).finish(),
+                            false => HttpResponse::NoContent().finish(),
+                        }
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (url, received)
+    }
+
+    #[actix_web::test]
+    async fn test_deliver() {
+        let client = awc::Client::default();
+        let event = Event::new(EventKind::Created, "abcd");
+        let backoff = Duration::from_millis(10);
+
+        // 5xx responses are retried until the receiver accepts the event
+        let (url, received) = serve(2, 503, "created");
+        super::deliver(&client, &url, &event, backoff)
+            .await
+            .expect("event was not delivered");
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+
+        // Other 4xx responses are not
+        let (url, received) = serve(1, 400, "created");
+        assert!(super::deliver(&client, &url, &event, backoff)
+            .await
+            .is_err());
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+
+        // Receivers that are always down get MAX_ATTEMPTS attempts
+        let (url, received) = serve(u32::MAX, 500, "created");
+        assert!(super::deliver(&client, &url, &event, backoff)
+            .await
+            .is_err());
+        assert_eq!(received.load(Ordering::SeqCst), super::MAX_ATTEMPTS);
+    }
+
+    #[actix_web::test]
+    async fn test_notify() {
+        let (url, received) = serve(0, 200, "fetched");
+        let store = Arc::new(Store::new());
+        super::spawn(&store, reload::shared(AppConfig::default()));
+
+        let opts = StoreOpts {
+            notify: Some(url),
+            ..StoreOpts::default()
+        };
+        let clipboard = Clipboard::Mem("secret".into());
+        Store::store_new_clipboard(store.clone(), "abcd", "abcd", clipboard, TTL, opts)
+            .await
+            .unwrap();
+
+        // Only the first read is delivered to the notify URL
+        for _ in 0..2 {
+            store.get_clipboard("abcd").await.unwrap();
+        }
+
+        for _ in 0..100 {
+            if received.load(Ordering::SeqCst) > 0 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+    }
+}
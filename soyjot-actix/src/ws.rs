@@ -0,0 +1,112 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use futures_util::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+
+use soyjot::store::clipboard::Clipboard;
+use soyjot::store::Store;
+
+use crate::http_resp::ResponseText;
+use crate::http_server;
+
+/// routes returns a scope with the WebSocket clipboard channel at `{prefix}/drop/{id}`.
+/// Subscribers receive the clipboard right away, and then again every time
+/// a clipboard with the same hash is posted.
+pub fn routes(prefix: &str) -> actix_web::Scope {
+    web::scope(prefix)
+        .guard(http_server::global_ids())
+        .route("/drop/{id}", web::get().to(watch_clipboard))
+}
+
+async fn watch_clipboard(
+    store: web::Data<Store>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let hash = path.into_inner();
+    if let Err(err) = http_server::check_link(&req, &hash) {
+        return Ok(http_server::send_error::<ResponseText>(&hash, err));
+    }
+
+    // Subscribe before getting the clipboard, so that no update is missed
+    let rx = store.subscribe(&hash);
+
+    let Some(clipboard) = store.get_clipboard(&hash).await else {
+        store.unsubscribe(&hash, rx);
+
+        return Ok(http_server::send_error::<ResponseText>(
+            &hash,
+            store.not_found(&hash),
+        ));
+    };
+
+    let (resp, session, msgs) = match actix_ws::handle(&req, body) {
+        Ok(handled) => handled,
+        Err(err) => {
+            store.unsubscribe(&hash, rx);
+            return Err(err);
+        }
+    };
+
+    actix_web::rt::spawn(async move {
+        let store = store.into_inner();
+        let mut rx = rx;
+
+        if let Err(err) = serve(&store, &hash, &mut rx, session, msgs, clipboard).await {
+            eprintln!("ws: channel for {hash} closed: {err}");
+        }
+
+        store.unsubscribe(&hash, rx);
+    });
+
+    Ok(resp)
+}
+
+/// serve sends `clipboard` to the client, then pushes every new clipboard for `hash`
+/// until either the client goes away or the channel is closed.
+async fn serve(
+    store: &Store,
+    hash: &str,
+    rx: &mut tokio::sync::broadcast::Receiver<()>,
+    mut session: Session,
+    mut msgs: MessageStream,
+    clipboard: Clipboard,
+) -> Result<(), actix_ws::Closed> {
+    send_clipboard(&mut session, clipboard).await?;
+
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                // Lagging subscribers only need the latest clipboard
+                Ok(()) | Err(RecvError::Lagged(_)) => {
+                    if let Some(clipboard) = store.get_clipboard(hash).await {
+                        send_clipboard(&mut session, clipboard).await?;
+                    }
+                }
+
+                Err(RecvError::Closed) => break,
+            },
+
+            msg = msgs.next() => match msg {
+                Some(Ok(Message::Ping(bytes))) => session.pong(&bytes).await?,
+                Some(Ok(Message::Close(reason))) => return session.close(reason).await,
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+        }
+    }
+
+    session.close(None).await
+}
+
+/// send_clipboard sends UTF-8 clipboards as text messages, and others as binary messages.
+async fn send_clipboard(
+    session: &mut Session,
+    clipboard: Clipboard,
+) -> Result<(), actix_ws::Closed> {
+    match std::str::from_utf8(&clipboard) {
+        Ok(text) => session.text(text.to_owned()).await,
+        Err(_) => session.binary(clipboard.into_bytes()).await,
+    }
+}
@@ -0,0 +1,286 @@
+//! Store benchmarks, run with `cargo bench -p soyjot`.
+//!
+//! Inserts, gets and removals are measured under concurrency for each clipboard backend
+//! through `ClipboardStore`: `Store` with in-memory clipboards, `Store` with clipboards
+//! persisted to a temporary directory, and `PgStore` when built with the `postgres` feature
+//! and `DROP_BENCH_DATABASE_URL` is set. Prefix resolution is measured on a crowded store.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dashmap::DashMap;
+use tokio::runtime::Runtime;
+
+use soyjot::hash::HashConfig;
+use soyjot::store::backend::ClipboardStore;
+use soyjot::store::clipboard::{self, Clipboard};
+use soyjot::store::persist::DiskFs;
+use soyjot::store::{Store, StoreConfig, StoreOpts};
+
+const KEYS: usize = 256;
+const TASKS: usize = 8;
+const GETS_PER_TASK: usize = 256;
+
+/// Clipboards stored by each task per insert and expire iteration
+const OPS_PER_TASK: usize = 32;
+
+/// Live clipboards when resolving prefixes
+const PREFIX_KEYS: usize = 4096;
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("{i:04x}")).collect()
+}
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(TASKS)
+        .enable_all()
+        .build()
+        .expect("failed to build runtime")
+}
+
+/// concurrently runs `op` for every key, split over `TASKS` tasks
+async fn concurrently<S, F, Fut>(store: &Arc<S>, keys: &Arc<Vec<String>>, ops: usize, op: F)
+where
+    S: ClipboardStore + 'static,
+    F: Fn(Arc<S>, String) -> Fut + Clone + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send,
+{
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|t| {
+            let (store, keys, op) = (store.clone(), keys.clone(), op.clone());
+            tokio::spawn(async move {
+                for i in 0..ops {
+                    let key = keys[(t * ops + i) % keys.len()].clone();
+                    op(store.clone(), key).await;
+                }
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+async fn store(store: &impl ClipboardStore, key: &str, storage: &str) {
+    let clipboard = Clipboard::new_with_data(storage, key.repeat(256));
+    let dur = Duration::from_secs(3600);
+
+    store
+        .store_clipboard(key, key, clipboard, dur, StoreOpts::default())
+        .await
+        .expect("failed to store clipboard");
+}
+
+/// bench_backend measures concurrent inserts, gets and removals of `storage` clipboards
+/// through `store` on `rt`, as benchmark group `store_{name}`
+fn bench_backend<S>(
+    c: &mut Criterion,
+    rt: &Runtime,
+    name: &str,
+    store: Arc<S>,
+    storage: &'static str,
+) where
+    S: ClipboardStore + 'static,
+{
+    let keys = Arc::new(keys());
+    let mut group = c.benchmark_group(format!("store_{name}"));
+    if storage == clipboard::PERSIST {
+        group.sample_size(10);
+    }
+
+    let insert = || {
+        concurrently(&store, &keys, OPS_PER_TASK, move |s, key| async move {
+            self::store(&*s, &key, storage).await;
+        })
+    };
+    // Clipboards are only replaced by their owners, so each insert starts from an empty store
+    let clear = || {
+        concurrently(&store, &keys, OPS_PER_TASK, |s, key| async move {
+            s.remove_clipboard(&key).await.unwrap();
+        })
+    };
+
+    group.throughput(Throughput::Elements((TASKS * OPS_PER_TASK) as u64));
+    group.bench_function("insert", |b| {
+        b.to_async(rt).iter_custom(|iters| async move {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                let start = Instant::now();
+                insert().await;
+                elapsed += start.elapsed();
+
+                clear().await;
+            }
+
+            elapsed
+        })
+    });
+
+    group.bench_function("expire", |b| {
+        b.to_async(rt).iter(|| {
+            concurrently(&store, &keys, OPS_PER_TASK, move |s, key| async move {
+                self::store(&*s, &key, storage).await;
+                assert!(s.remove_clipboard(&key).await.unwrap());
+            })
+        })
+    });
+
+    rt.block_on(concurrently(
+        &store,
+        &keys,
+        KEYS / TASKS,
+        move |s, key| async move {
+            self::store(&*s, &key, storage).await;
+        },
+    ));
+
+    group.throughput(Throughput::Elements((TASKS * GETS_PER_TASK) as u64));
+    group.bench_function("get", |b| {
+        b.to_async(rt).iter(|| {
+            concurrently(&store, &keys, GETS_PER_TASK, |s, key| async move {
+                assert!(s.get_clipboard(&key).await.unwrap().is_some());
+            })
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_store_mem(c: &mut Criterion) {
+    let store = Arc::new(Arc::new(Store::new()));
+    bench_backend(c, &runtime(), "mem", store, clipboard::MEM);
+}
+
+fn bench_store_disk(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("soyjot-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create bench directory");
+
+    let files = Arc::new(DiskFs::new(PathBuf::from(&dir)));
+    let store = Store::with_persist(StoreConfig::default(), files);
+    bench_backend(
+        c,
+        &runtime(),
+        "disk",
+        Arc::new(Arc::new(store)),
+        clipboard::PERSIST,
+    );
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[cfg(feature = "postgres")]
+fn bench_store_postgres(c: &mut Criterion) {
+    use soyjot::store::postgres::PgStore;
+
+    let Ok(url) = std::env::var("DROP_BENCH_DATABASE_URL") else {
+        eprintln!("DROP_BENCH_DATABASE_URL is not set, skipping PgStore");
+        return;
+    };
+
+    // The pool's connections belong to the runtime it was created on
+    let rt = runtime();
+    let store = rt
+        .block_on(PgStore::connect(&url, StoreConfig::default()))
+        .expect("failed to connect to database");
+    bench_backend(c, &rt, "postgres", Arc::new(store), clipboard::PERSIST);
+}
+
+#[cfg(not(feature = "postgres"))]
+fn bench_store_postgres(_: &mut Criterion) {}
+
+/// Resolving prefixes and computing the shortest unique prefix of a key,
+/// which both scan every live key
+fn bench_prefix(c: &mut Criterion) {
+    let rt = runtime();
+    let store = Arc::new(Store::new());
+    let hashing = HashConfig::default();
+
+    // Long enough not to collide
+    let keys: Vec<String> = (0..PREFIX_KEYS)
+        .map(|i| hashing.digest(&i.to_le_bytes())[..16].to_string())
+        .collect();
+
+    rt.block_on(async {
+        for key in &keys {
+            self::store(&store, key, clipboard::MEM).await;
+        }
+    });
+
+    let mut group = c.benchmark_group("prefix");
+    group.bench_function(BenchmarkId::new("shortest_prefix", PREFIX_KEYS), |b| {
+        b.iter(|| store.shortest_prefix(&keys[0]))
+    });
+    group.bench_function(BenchmarkId::new("resolve_prefix", PREFIX_KEYS), |b| {
+        b.iter(|| store.resolve_prefix(&keys[0][..8]))
+    });
+    group.finish();
+}
+
+/// Compares the previous single `Mutex<HashMap>` haystack against the sharded `DashMap`
+/// under concurrent reads from OS threads
+fn bench_haystack_read(c: &mut Criterion) {
+    let keys = Arc::new(keys());
+    let mut group = c.benchmark_group("haystack_read");
+
+    let mutex: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(
+        keys.iter()
+            .map(|k| (k.clone(), k.repeat(64).into_bytes()))
+            .collect(),
+    ));
+
+    let sharded: Arc<DashMap<String, Vec<u8>>> = Arc::new(
+        keys.iter()
+            .map(|k| (k.clone(), k.repeat(64).into_bytes()))
+            .collect(),
+    );
+
+    group.bench_function(BenchmarkId::new("mutex_hashmap", TASKS), |b| {
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                for t in 0..TASKS {
+                    let (map, keys) = (mutex.clone(), keys.clone());
+                    scope.spawn(move || {
+                        for i in 0..GETS_PER_TASK {
+                            let key = &keys[(t * GETS_PER_TASK + i) % KEYS];
+                            let map = map.lock().unwrap();
+                            assert!(map.get(key).is_some_and(|v| !v.is_empty()));
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("dashmap", TASKS), |b| {
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                for t in 0..TASKS {
+                    let (map, keys) = (sharded.clone(), keys.clone());
+                    scope.spawn(move || {
+                        for i in 0..GETS_PER_TASK {
+                            let key = &keys[(t * GETS_PER_TASK + i) % KEYS];
+                            assert!(map.get(key).is_some_and(|v| !v.is_empty()));
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_store_mem,
+    bench_store_disk,
+    bench_store_postgres,
+    bench_prefix,
+    bench_haystack_read
+);
+criterion_main!(benches);
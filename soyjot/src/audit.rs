@@ -0,0 +1,212 @@
+//! Append-only audit log of what clients do with clipboards, written as JSON lines.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::index;
+
+/// AuditConfig sets where the audit log is written, and when it's rotated
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AuditConfig {
+    /// Path of the log file, which is created if needed and appended to
+    pub path: String,
+    /// The log is rotated before it grows over this many bytes. It's never rotated if unset.
+    pub max_bytes: Option<u64>,
+    /// Number of rotated logs kept as `{path}.1` (the newest) to `{path}.{keep}`.
+    /// With 0, the log is truncated when it's rotated.
+    #[serde(default)]
+    pub keep: usize,
+}
+
+/// Action is what a client did with a clipboard
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Read,
+    Append,
+    Delete,
+}
+
+/// Record is one line of the audit log
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct Record {
+    /// Unix timestamp in seconds
+    pub at: u64,
+    pub client: Option<IpAddr>,
+    pub action: Action,
+    /// Key of the clipboard, which is unknown for clipboards that failed to be created
+    pub hash: Option<String>,
+    /// Bytes received from or sent to the client, if known
+    pub size: Option<u64>,
+    /// HTTP status of the response
+    pub status: u16,
+}
+
+impl Record {
+    pub fn new(action: Action, hash: Option<&str>, client: Option<IpAddr>, status: u16) -> Self {
+        Self {
+            at: index::to_timestamp(SystemTime::now()),
+            client,
+            action,
+            hash: hash.map(str::to_owned),
+            size: None,
+            status,
+        }
+    }
+
+    pub fn size(self, size: Option<u64>) -> Self {
+        Self { size, ..self }
+    }
+}
+
+struct LogFile {
+    file: File,
+    len: u64,
+}
+
+/// AuditLog appends records to the file of its `AuditConfig`, rotating it when it grows
+/// too large. Records are written whole and unbuffered, so that no record is lost or torn
+/// if the server crashes.
+pub struct AuditLog {
+    conf: AuditConfig,
+    file: Mutex<LogFile>,
+}
+
+impl AuditLog {
+    /// open opens the log file of `conf` for appending, creating it if needed
+    pub fn open(conf: AuditConfig) -> io::Result<Self> {
+        let file = open(Path::new(&conf.path))?;
+
+        Ok(Self {
+            conf,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// record appends `record` to the log as one JSON line
+    pub fn record(&self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().expect("audit log lock poisoned");
+        let len = file.len + line.len() as u64;
+        if file.len > 0 && self.conf.max_bytes.is_some_and(|max| len > max) {
+            *file = self.rotate()?;
+        }
+
+        file.file.write_all(&line)?;
+        file.len += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// rotate shifts the rotated logs by one, drops the oldest,
+    /// and moves the current log to `{path}.1`, returning a new empty log
+    fn rotate(&self) -> io::Result<LogFile> {
+        let path = Path::new(&self.conf.path);
+
+        if self.conf.keep == 0 {
+            fs::remove_file(path)?;
+        } else {
+            for n in (1..self.conf.keep).rev() {
+                match fs::rename(rotated(path, n), rotated(path, n + 1)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+
+            fs::rename(path, rotated(path, 1))?;
+        }
+
+        open(path)
+    }
+}
+
+fn open(path: &Path) -> io::Result<LogFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let len = file.metadata()?.len();
+
+    Ok(LogFile { file, len })
+}
+
+/// rotated returns the path of the `n`th rotated log of `path`
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let dir = std::env::temp_dir().join(format!("soyjot-test-audit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+
+        let record = |hash: &str| {
+            Record::new(
+                Action::Create,
+                Some(hash),
+                Some("::1".parse().unwrap()),
+                200,
+            )
+            .size(Some(3))
+        };
+        let line_len = serde_json::to_vec(&record("abcd")).unwrap().len() as u64 + 1;
+
+        let log = AuditLog::open(AuditConfig {
+            path: path.to_string_lossy().into_owned(),
+            max_bytes: Some(line_len * 2),
+            keep: 2,
+        })
+        .unwrap();
+
+        for hash in ["aaaa", "bbbb", "cccc", "dddd", "eeee", "ffff", "0000"] {
+            log.record(&record(hash)).unwrap();
+        }
+
+        let hashes = |path: &Path| -> Vec<String> {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                    assert_eq!(record["action"], "create");
+                    assert_eq!(record["client"], "::1");
+                    assert_eq!(record["size"], 3);
+                    record["hash"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+
+        // The oldest rotated log was dropped
+        assert_eq!(hashes(&path), ["0000"]);
+        assert_eq!(hashes(&rotated(&path, 1)), ["eeee", "ffff"]);
+        assert_eq!(hashes(&rotated(&path, 2)), ["cccc", "dddd"]);
+        assert!(!rotated(&path, 3).exists());
+
+        // Logs are appended to when reopened
+        drop(log);
+        let log = AuditLog::open(AuditConfig {
+            path: path.to_string_lossy().into_owned(),
+            max_bytes: None,
+            keep: 0,
+        })
+        .unwrap();
+        log.record(&record("1111")).unwrap();
+        assert_eq!(hashes(&path), ["0000", "1111"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
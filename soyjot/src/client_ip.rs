@@ -0,0 +1,275 @@
+//! Client IP addresses of requests that reach the server through reverse proxies,
+//! e.g. nginx, which record the address they received a request from in the
+//! `Forwarded` (RFC 7239) or `X-Forwarded-For` headers. Only the header the proxies
+//! are configured to set is read (see `ProxyHeader`), since proxies pass the other one
+//! on as the client sent it.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Network is an IP network in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`.
+/// A bare address is the network of only that address.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// contains reports whether `ip` is in the network. IPv4-mapped IPv6 addresses are
+    /// matched as IPv4 (see `canonical`).
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map(canonical)
+            .map_err(|_| format!("bad network {s}: {addr} is not an IP address"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            None => max,
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("bad network {s}: prefix must be 0 to {max}"))?,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Network {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl From<Network> for String {
+    fn from(network: Network) -> Self {
+        network.to_string()
+    }
+}
+
+/// ProxyHeader is the header that trusted proxies record client addresses in
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyHeader {
+    /// `X-Forwarded-For`, e.g. nginx with `proxy_add_x_forwarded_for`
+    #[default]
+    XForwardedFor,
+    /// `Forwarded` (RFC 7239)
+    Forwarded,
+}
+
+impl ProxyHeader {
+    /// name returns the lowercase name of the header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::XForwardedFor => "x-forwarded-for",
+            Self::Forwarded => "forwarded",
+        }
+    }
+}
+
+/// canonical returns IPv4-mapped IPv6 addresses (e.g. `::ffff:192.0.2.1`, as seen on
+/// dual-stack sockets) as IPv4, so that a client has one address whichever way it connects
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    ip.to_canonical()
+}
+
+/// client_ip returns the IP address of the client that sent a request received from `peer`.
+///
+/// If `peer` is in `trusted`, the addresses recorded by proxies in the `values`
+/// of `header` are walked from the nearest hop, and the first address not in `trusted`
+/// is the client's. If a hop did not record an address (e.g. `for=unknown`),
+/// the last trusted proxy is the client.
+/// Otherwise, the headers may have been sent by the client itself, and `peer` is the client.
+pub fn client_ip<'a>(
+    trusted: &[Network],
+    peer: IpAddr,
+    header: ProxyHeader,
+    values: impl IntoIterator<Item = &'a str>,
+) -> IpAddr {
+    let peer = canonical(peer);
+    let trusts = |ip: IpAddr| trusted.iter().any(|network| network.contains(ip));
+    if !trusts(peer) {
+        return peer;
+    }
+
+    let elements = values.into_iter().flat_map(|value| value.split(','));
+    let hops: Vec<Option<IpAddr>> = match header {
+        ProxyHeader::XForwardedFor => elements.map(parse_node).collect(),
+        ProxyHeader::Forwarded => elements
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect(),
+    };
+
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        match hop {
+            Some(ip) if trusts(client) => client = ip,
+            _ => break,
+        }
+    }
+
+    client
+}
+
+/// parse_node parses an address recorded by a proxy, which may be quoted,
+/// have a port, and be bracketed if it's IPv6, e.g. `"[2001:db8::17]:4711"`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    let ip = match node.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => match node.strip_prefix('[') {
+            Some(rest) => rest.split_once(']')?.0.parse().ok()?,
+            None => node.parse::<SocketAddr>().ok()?.ip(),
+        },
+    };
+
+    Some(canonical(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_network() {
+        let net: Network = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(ip("10.1.2.3")));
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+        assert!(!net.contains(ip("11.0.0.1")));
+
+        let net: Network = "fd00::/8".parse().unwrap();
+        assert!(net.contains(ip("fd12::1")));
+        assert!(!net.contains(ip("fe80::1")));
+
+        let host: Network = "::1".parse().unwrap();
+        assert_eq!(host.to_string(), "::1/128");
+        assert!(host.contains(ip("::1")));
+        assert!(!host.contains(ip("::2")));
+
+        let any: Network = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("192.0.2.1")));
+
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("nginx".parse::<Network>().is_err());
+
+        let nets: Vec<Network> = serde_json::from_str(r#"["127.0.0.1", "fd00::/8"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&nets).unwrap(),
+            r#"["127.0.0.1/32","fd00::/8"]"#
+        );
+    }
+
+    #[test]
+    fn test_client_ip() {
+        use ProxyHeader::{Forwarded, XForwardedFor};
+
+        let trusted: Vec<Network> =
+            vec!["127.0.0.1".parse().unwrap(), "10.0.0.0/8".parse().unwrap()];
+        let none = || std::iter::empty::<&str>();
+
+        // Headers from untrusted peers are ignored
+        assert_eq!(
+            client_ip(&trusted, ip("192.0.2.1"), XForwardedFor, ["198.51.100.1"]),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            client_ip(&[], ip("::ffff:192.0.2.1"), XForwardedFor, none()),
+            ip("192.0.2.1")
+        );
+
+        // Trusted proxies are skipped from the nearest hop, so clients cannot spoof
+        // their address by sending the headers themselves
+        assert_eq!(
+            client_ip(
+                &trusted,
+                ip("127.0.0.1"),
+                XForwardedFor,
+                ["203.0.113.9, 198.51.100.1", "10.0.0.2"]
+            ),
+            ip("198.51.100.1")
+        );
+
+        // Forwarded may have IPv6 nodes with ports
+        assert_eq!(
+            client_ip(
+                &trusted,
+                ip("127.0.0.1"),
+                Forwarded,
+                [r#"for="[2001:db8:cafe::17]:4711";proto=https, For=10.0.0.2"#]
+            ),
+            ip("2001:db8:cafe::17")
+        );
+
+        // Hops that did not record an address stop the walk at the last trusted proxy
+        assert_eq!(
+            client_ip(
+                &trusted,
+                ip("127.0.0.1"),
+                Forwarded,
+                ["for=198.51.100.1, for=unknown"]
+            ),
+            ip("127.0.0.1")
+        );
+
+        // Requests only through trusted proxies come from the farthest one
+        assert_eq!(
+            client_ip(&trusted, ip("127.0.0.1"), XForwardedFor, ["10.0.0.3:8080"]),
+            ip("10.0.0.3")
+        );
+
+        let header: ProxyHeader = serde_json::from_str(r#""forwarded""#).unwrap();
+        assert_eq!(header, Forwarded);
+        assert_eq!(header.name(), "forwarded");
+        assert_eq!(ProxyHeader::default().name(), "x-forwarded-for");
+    }
+}
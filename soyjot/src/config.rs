@@ -1,32 +1,440 @@
-use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-const DIR: &'static str = "./drop";
-const HTTP_ADDR: &'static str = "127.0.0.1";
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::audit::AuditConfig;
+use crate::client_ip::{Network, ProxyHeader};
+use crate::filters::{FilterChain, FilterConfig};
+use crate::hash::{HashAlgo, HashConfig, HASH_LEN};
+use crate::id::{Alphabet, IdMode};
+use crate::quota::QuotaConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::store::compress::{CompressConfig, Compression};
+use crate::store::{Collision, Retention, StoreConfig};
+use crate::tenant::{self, TenantConfig};
+
+const DIR: &str = "./drop";
+const HTTP_ADDR: &str = "127.0.0.1";
 const HTTP_PORT: u16 = 8080;
 const TIMEOUT: u64 = 15;
 
+/// Content-Security-Policy sent with HTML responses by default. The UI loads its stylesheet
+/// from artnoi.com, and everything else from the server itself.
+pub const DEFAULT_CSP: &str = "default-src 'self'; style-src 'self' https://artnoi.com; \
+    img-src 'self' data:; object-src 'none'; base-uri 'none'; form-action 'self'; \
+    frame-ancestors 'none'";
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct AppConfig {
     pub dir: Option<String>,
-    pub http_addr: Option<String>,
+    /// Addresses to listen on, either a single address or a list, e.g. `["127.0.0.1", "[::1]"]`.
+    /// Addresses without a port use `http_port`.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub http_addr: Option<Vec<String>>,
     pub http_port: Option<u16>,
     pub timeout: Option<u64>,
+    /// Seconds in-memory clipboards live by default, instead of `timeout`
+    pub mem_ttl: Option<u64>,
+    /// Seconds persisted clipboards live by default, instead of `timeout`
+    pub persist_ttl: Option<u64>,
+    /// Most seconds in-memory clipboards may live, even if posted with a longer `ttl`
+    pub max_mem_ttl: Option<u64>,
+    /// Most seconds persisted clipboards may live, even if posted with a longer `ttl`
+    pub max_persist_ttl: Option<u64>,
+    /// Length of clipboard keys, in characters of `id_alphabet`
+    pub hash_len: Option<usize>,
+    pub hash_algo: Option<HashAlgo>,
+    /// Characters clipboard keys are made of (hex by default)
+    pub id_alphabet: Option<Alphabet>,
+    /// Whether clipboard keys are derived from the content (`hash`, the default) or random,
+    /// see `StoreConfig::exact_keys`
+    pub id_mode: Option<IdMode>,
+    /// What to do when a new clipboard's key is taken by a clipboard with different content
+    pub on_collision: Option<Collision>,
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Limits on live clipboards per client IP
+    pub quota: Option<QuotaConfig>,
+    /// Maximum size in bytes clipboards may grow to with appends
+    pub append_max_size: Option<u64>,
+    /// Number of previous versions kept when a clipboard is replaced with different content
+    pub max_versions: Option<usize>,
+    /// Least recently used in-memory clipboards are evicted while their total size
+    /// is over this many bytes
+    pub max_mem_bytes: Option<u64>,
+    /// Write evicted in-memory clipboards to file instead of removing them
+    pub evict_to_disk: Option<bool>,
+    /// In-memory clipboards posted with more than this many bytes are persisted instead
+    pub persist_threshold_bytes: Option<u64>,
+    /// New persisted clipboards are rejected once persisted clipboards total this many bytes
+    pub max_disk_bytes: Option<u64>,
+    /// High-water marks above which new clipboards are rejected, disabled if unset
+    pub load_shed: Option<LoadShedConfig>,
+    /// Posting content that's already live returns the live clipboard instead of storing a copy
+    pub dedupe: Option<bool>,
+    /// Sync persisted clipboard files to disk before responding, so that they survive power loss
+    pub fsync: Option<bool>,
+    /// Cache up to this many bytes of recently read persisted clipboards in memory,
+    /// so that popular ones are not read from disk on every view. Disabled if unset.
+    pub read_cache_bytes: Option<u64>,
+    /// Compression of persisted clipboard files
+    pub compress: Option<Compression>,
+    /// Persisted clipboards smaller than this many bytes are not compressed
+    pub compress_threshold: Option<u64>,
+    /// Clipboard files found on startup that are older than this many seconds are removed
+    pub orphan_max_age: Option<u64>,
+    /// Seconds between runs of the janitor, which removes untracked clipboard files
+    /// while the server is running. The janitor is disabled by default.
+    pub janitor_interval: Option<u64>,
+    /// The janitor also removes persisted clipboards whose files are older than this many seconds
+    pub janitor_max_age: Option<u64>,
+    /// Base URL of the server used by `drop-cli`, defaults to `http://{http_addr}:{http_port}`
+    pub server_url: Option<String>,
+    /// PEM certificate chain; HTTPS is served if both `tls_cert` and `tls_key` are set
+    pub tls_cert: Option<String>,
+    /// PEM private key for `tls_cert`
+    pub tls_key: Option<String>,
+    /// If set with TLS enabled, plain HTTP requests to this port are redirected to HTTPS
+    pub tls_redirect_port: Option<u16>,
+    /// Origins allowed to make cross-origin requests to `/api`, `/txt` and `/bin`,
+    /// or `*` for any origin.
+    /// CORS is disabled if unset.
+    pub cors_origins: Option<Vec<String>>,
+    /// Reverse proxies whose `proxy_header` is trusted to tell the client IP,
+    /// as addresses or CIDR networks (see `client_ip`).
+    /// Clients are identified by the addresses they connect from if unset.
+    pub trusted_proxies: Option<Vec<Network>>,
+    /// Header that `trusted_proxies` record client IPs in, `x-forwarded-for` if unset,
+    /// or `forwarded`. The other header is ignored, since proxies pass it on from clients.
+    pub proxy_header: Option<ProxyHeader>,
+    /// Scopes to mount, all of them by default. Leave out `app` for API-only deployments.
+    pub scopes: Option<Vec<Scope>>,
+    /// Security headers sent with HTML responses, `SecurityHeaders::default()` if unset
+    pub security_headers: Option<SecurityHeaders>,
+    /// URLs that clipboard events (created, fetched and expired) are POSTed to as JSON
+    pub webhooks: Option<Vec<String>>,
+    /// Let clipboards be posted with a `notify` URL, which is POSTed the `fetched` event
+    /// of their first read like a webhook. Off by default, since it has the server send
+    /// requests to URLs of the clients' choosing, though only to public addresses.
+    pub allow_notify: Option<bool>,
+    /// Token required for the admin dashboard at `/app/admin`, which is disabled if unset.
+    /// It is never serialized, so that it does not show up in logs.
+    #[serde(skip_serializing)]
+    pub admin_token: Option<String>,
+    /// Content filters that posted clipboards go through, none if unset
+    pub filters: Option<FilterConfig>,
+    /// Secret that links returned for clipboards posted with `link_ttl` are signed with
+    /// (see `signing`). It is never serialized, so that it does not show up in logs.
+    #[serde(skip_serializing)]
+    pub link_secret: Option<String>,
+    /// Only serve clipboard content to signed links
+    pub require_signed_links: Option<bool>,
+    /// Only accept posts with an upload token (see `signing::UploadGrant`) or the admin token
+    pub require_upload_token: Option<bool>,
+    /// Tenants served at `/api/t/{tenant}`, each in its own keyspace, by name
+    pub tenants: Option<BTreeMap<String, TenantConfig>>,
+    /// Peer instances that new and removed clipboards are replicated to, disabled if unset
+    pub replication: Option<ReplicationConfig>,
+    /// Audit log of clipboards created, read, appended to and deleted by clients,
+    /// disabled if unset
+    pub audit_log: Option<AuditConfig>,
+    /// SMTP server that clipboards are emailed through, in builds with the `mailer` feature
+    pub mailer: Option<MailerConfig>,
+    /// Chat webhooks that clipboards are shared to with `POST /api/drop/{id}/share`,
+    /// disabled if unset
+    pub share: Option<ShareConfig>,
+}
+
+/// Scope is a group of routes mounted under its own prefix
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// HTML UI at `/app`
+    App,
+    /// JSON API at `/api`
+    Api,
+    /// Plain text API at `/txt`
+    Txt,
+    /// Raw clipboard bytes at `/raw`
+    Raw,
+    /// MessagePack API at `/bin`
+    Bin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [Scope::App, Scope::Api, Scope::Txt, Scope::Raw, Scope::Bin];
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::App => "/app",
+            Self::Api => "/api",
+            Self::Txt => "/txt",
+            Self::Raw => "/raw",
+            Self::Bin => "/bin",
+        }
+    }
+}
+
+/// SecurityHeaders are the headers that harden HTML responses against XSS, clickjacking and
+/// content sniffing. Headers set to an empty string are not sent.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct SecurityHeaders {
+    pub content_security_policy: String,
+    pub frame_options: String,
+    pub referrer_policy: String,
+    /// Send `X-Content-Type-Options: nosniff` with every response, not only HTML ones,
+    /// so that browsers do not render raw clipboards as HTML
+    pub nosniff: bool,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            content_security_policy: DEFAULT_CSP.to_string(),
+            frame_options: "DENY".to_string(),
+            referrer_policy: "no-referrer".to_string(),
+            nosniff: true,
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// headers returns the names and values of the headers sent with HTML responses
+    pub fn headers(&self) -> Vec<(&'static str, &str)> {
+        [
+            (
+                "content-security-policy",
+                self.content_security_policy.as_str(),
+            ),
+            ("x-frame-options", self.frame_options.as_str()),
+            ("referrer-policy", self.referrer_policy.as_str()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+}
+
+/// ReplicationConfig lists the peers that clipboards are replicated to, so that several
+/// instances can serve the same clipboards
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReplicationConfig {
+    /// Base URLs of the peers, e.g. `https://drop2.example.com`
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Bearer token sent to the peers, and required from them at `/api/replica`,
+    /// so all peers share the same token.
+    /// It is never serialized, so that it does not show up in logs.
+    #[serde(skip_serializing, default)]
+    pub token: String,
+}
+
+/// MailerConfig sets the SMTP server that clipboards are emailed through
+/// with `POST /api/drop/{id}/email`, and who they may be emailed to
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MailerConfig {
+    /// Host name of the SMTP server, e.g. `smtp.example.com`
+    pub host: String,
+    /// Port of the SMTP server, see `MailerConfig::port`
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// User name to log in with, if the server needs a login
+    pub username: Option<String>,
+    /// Password to log in with. It is never serialized, so that it does not show up in logs.
+    #[serde(skip_serializing, default)]
+    pub password: Option<String>,
+    /// Sender address of the emails, e.g. `drop@example.com`
+    pub from: String,
+    /// Domains of the addresses clipboards may be emailed to, e.g. `example.com`
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// Emails each client may send, 5 at once and one a minute after that if unset
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// SmtpTls is how connections to the SMTP server are secured
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Connections are upgraded to TLS with STARTTLS
+    #[default]
+    StartTls,
+    /// Connections start with TLS, usually on port 465
+    Implicit,
+    /// Connections are not encrypted, e.g. to a relay on localhost
+    None,
+}
+
+impl MailerConfig {
+    /// port returns the configured port, or the usual port of `tls`:
+    /// 587 for STARTTLS, 465 for implicit TLS and 25 otherwise
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Implicit => 465,
+            SmtpTls::None => 25,
+        })
+    }
+}
+
+/// ShareConfig sets the chat webhooks that clipboards are shared to
+/// with `POST /api/drop/{id}/share?target=...`. The webhook URLs hold the tokens
+/// to post to them, so they are never serialized, so that they do not show up in logs.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ShareConfig {
+    /// Slack incoming webhook, e.g. `https://hooks.slack.com/services/...`
+    #[serde(skip_serializing, default)]
+    pub slack: Option<String>,
+    /// Matrix webhook taking `{"text": ..., "html": ...}`, e.g. a hookshot generic webhook
+    #[serde(skip_serializing, default)]
+    pub matrix: Option<String>,
+    /// Characters of text clipboards quoted in messages, 200 if unset. 0 sends links only.
+    pub excerpt: Option<usize>,
+    /// Shares each client may post, 5 at once and one a minute after that if unset
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// ShareTarget is a chat that clipboards can be shared to
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareTarget {
+    Slack,
+    Matrix,
+}
+
+impl ShareConfig {
+    /// webhook returns the webhook URL of `target`, if configured
+    pub fn webhook(&self, target: ShareTarget) -> Option<&str> {
+        match target {
+            ShareTarget::Slack => self.slack.as_deref(),
+            ShareTarget::Matrix => self.matrix.as_deref(),
+        }
+    }
+
+    /// targets returns the configured targets
+    pub fn targets(&self) -> Vec<ShareTarget> {
+        [ShareTarget::Slack, ShareTarget::Matrix]
+            .into_iter()
+            .filter(|target| self.webhook(*target).is_some())
+            .collect()
+    }
+}
+
+/// LoadShedConfig sets high-water marks on the bytes taken by live clipboards. While the store
+/// is above any of them, new clipboards and appends are rejected with 503 Service Unavailable,
+/// and existing clipboards are still served. Unset marks are not checked.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LoadShedConfig {
+    /// Total size of in-memory clipboards in bytes
+    pub mem_bytes: Option<u64>,
+    /// Total size of persisted clipboards in bytes
+    pub disk_bytes: Option<u64>,
+    /// Seconds that rejected clients are told to wait with `Retry-After`
+    #[serde(default = "LoadShedConfig::default_retry_after")]
+    pub retry_after: u64,
+}
+
+impl LoadShedConfig {
+    fn default_retry_after() -> u64 {
+        30
+    }
+
+    /// overloaded reports whether `mem_bytes` or `disk_bytes` are above their high-water marks
+    pub fn overloaded(&self, mem_bytes: u64, disk_bytes: u64) -> bool {
+        self.mem_bytes.is_some_and(|max| mem_bytes > max)
+            || self.disk_bytes.is_some_and(|max| disk_bytes > max)
+    }
+}
+
+/// ConfigArgs are command-line flags layered over config files and envs by `AppConfig::init_with`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct ConfigArgs {
+    /// Config file read after the default locations, overriding them
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub config: Option<PathBuf>,
+    /// Overrides http_port
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub port: Option<u16>,
+    /// Overrides dir
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub dir: Option<String>,
+    /// Overrides timeout, in seconds
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub timeout: Option<u64>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             dir: Some(DIR.to_string()),
-            http_addr: Some(HTTP_ADDR.to_string()),
+            http_addr: Some(vec![HTTP_ADDR.to_string()]),
             http_port: Some(HTTP_PORT),
             timeout: Some(TIMEOUT),
+            mem_ttl: None,
+            persist_ttl: None,
+            max_mem_ttl: None,
+            max_persist_ttl: None,
+            hash_len: Some(HASH_LEN),
+            hash_algo: Some(HashAlgo::default()),
+            id_alphabet: None,
+            id_mode: None,
+            on_collision: Some(Collision::default()),
+            rate_limit: None,
+            quota: None,
+            append_max_size: None,
+            max_versions: None,
+            max_mem_bytes: None,
+            evict_to_disk: None,
+            persist_threshold_bytes: None,
+            max_disk_bytes: None,
+            load_shed: None,
+            dedupe: None,
+            fsync: None,
+            read_cache_bytes: None,
+            compress: None,
+            compress_threshold: None,
+            orphan_max_age: None,
+            janitor_interval: None,
+            janitor_max_age: None,
+            server_url: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_redirect_port: None,
+            cors_origins: None,
+            trusted_proxies: None,
+            proxy_header: None,
+            scopes: None,
+            security_headers: None,
+            webhooks: None,
+            allow_notify: None,
+            admin_token: None,
+            filters: None,
+            link_secret: None,
+            require_signed_links: None,
+            require_upload_token: None,
+            tenants: None,
+            replication: None,
+            audit_log: None,
+            mailer: None,
+            share: None,
         }
     }
 }
 
 impl AppConfig {
     pub fn init() -> Self {
-        match init_config() {
+        Self::init_with(&ConfigArgs::default())
+    }
+
+    /// init_with reads the config like `init`, with `args` overriding files and envs
+    pub fn init_with(args: &ConfigArgs) -> Self {
+        match Self::load(args) {
             Ok(conf) => conf,
             Err(err) => {
                 eprintln!("error reading AppConfig, using default..: {err:?}");
@@ -34,18 +442,624 @@ impl AppConfig {
             }
         }
     }
+
+    /// load reads the config like `init_with`, but returns errors instead of falling back
+    /// to the default, e.g. when reloading a running server's config.
+    pub fn load(args: &ConfigArgs) -> Result<Self, config::ConfigError> {
+        init_config(args)
+    }
+
+    /// scopes returns the scopes to mount, without duplicates
+    pub fn scopes(&self) -> Vec<Scope> {
+        let scopes = self.scopes.as_deref().unwrap_or(&Scope::ALL);
+
+        Scope::ALL
+            .into_iter()
+            .filter(|scope| scopes.contains(scope))
+            .collect()
+    }
+
+    /// timeout_duration returns how long new clipboards live before they expire,
+    /// unless their storage class has its own lifetime (see `retention`)
+    pub fn timeout_duration(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(TIMEOUT))
+    }
+
+    pub fn hash_config(&self) -> HashConfig {
+        let default = HashConfig::default();
+
+        HashConfig {
+            algo: self.hash_algo.unwrap_or(default.algo),
+            len: self.hash_len.unwrap_or(default.len),
+            alphabet: self.id_alphabet.unwrap_or(default.alphabet),
+            mode: self.id_mode.unwrap_or(default.mode),
+        }
+    }
+
+    /// server_url returns the base URL of the server, without a trailing slash.
+    /// Without `server_url`, the first address in `bind_addrs` is used.
+    pub fn server_url(&self) -> String {
+        if let Some(url) = &self.server_url {
+            return url.trim_end_matches('/').to_string();
+        }
+
+        match self.bind_addrs().first() {
+            Some((host, port)) if host.contains(':') => format!("http://[{host}]:{port}"),
+            Some((host, port)) => format!("http://{host}:{port}"),
+            None => format!("http://{HTTP_ADDR}:{HTTP_PORT}"),
+        }
+    }
+
+    /// bind_addrs returns every host and port pair in `http_addr` to listen on.
+    /// IPv6 addresses may be bracketed, and must be if they have a port, e.g. `[::1]:8080`.
+    pub fn bind_addrs(&self) -> Vec<(String, u16)> {
+        let default = vec![HTTP_ADDR.to_string()];
+        let port = self.http_port.unwrap_or(HTTP_PORT);
+
+        self.http_addr
+            .as_ref()
+            .unwrap_or(&default)
+            .iter()
+            .map(|addr| bind_addr(addr, port))
+            .collect()
+    }
+
+    pub fn compress_config(&self) -> CompressConfig {
+        let default = CompressConfig::default();
+
+        CompressConfig {
+            algo: self.compress.unwrap_or(default.algo),
+            threshold: self.compress_threshold.unwrap_or(default.threshold),
+        }
+    }
+
+    pub fn store_config(&self) -> StoreConfig {
+        StoreConfig {
+            on_collision: self.on_collision.unwrap_or_default(),
+            quota: self.quota.clone(),
+            tenant_quotas: self.tenant_quotas(),
+            append_max_size: self.append_max_size,
+            max_versions: self.max_versions.unwrap_or_default(),
+            max_mem_bytes: self.max_mem_bytes,
+            evict_to_disk: self.evict_to_disk.unwrap_or_default(),
+            persist_threshold: self.persist_threshold_bytes,
+            max_disk_bytes: self.max_disk_bytes,
+            compress: self.compress_config(),
+            // Random keys must not be handed out again for the same content
+            dedupe: self.dedupe.unwrap_or_default() && self.id_mode != Some(IdMode::Random),
+            // Nor resolved from prefixes, which would let clients guess them
+            exact_keys: self.id_mode == Some(IdMode::Random),
+            retention: self.retention(),
+        }
+    }
+
+    /// retention returns the lifetimes of clipboards by storage class
+    pub fn retention(&self) -> Retention {
+        let secs = |secs: Option<u64>| secs.map(Duration::from_secs);
+
+        Retention {
+            mem_ttl: secs(self.mem_ttl),
+            persist_ttl: secs(self.persist_ttl),
+            max_mem_ttl: secs(self.max_mem_ttl),
+            max_persist_ttl: secs(self.max_persist_ttl),
+        }
+    }
+
+    /// tenant_quotas returns the quotas of the tenants that have one, by tenant name
+    pub fn tenant_quotas(&self) -> HashMap<String, QuotaConfig> {
+        self.tenants
+            .iter()
+            .flatten()
+            .filter_map(|(name, tenant)| Some((name.clone(), tenant.quota.clone()?)))
+            .collect()
+    }
+
+    /// filter_chain builds the chain of content filters, which fails if a denylist pattern
+    /// is not a valid regular expression
+    pub fn filter_chain(&self) -> Result<FilterChain, regex::Error> {
+        match &self.filters {
+            Some(filters) => FilterChain::from_config(filters),
+            None => Ok(FilterChain::new()),
+        }
+    }
+}
+
+/// ConfigProblem is a single problem found when validating `AppConfig`
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigProblem {
+    #[error("{0} is not set")]
+    Missing(&'static str),
+
+    #[error("{key}: port {port} is out of range")]
+    BadPort { key: &'static str, port: u16 },
+
+    #[error("http_addr: bad address {addr}: {reason}")]
+    BadAddr { addr: String, reason: String },
+
+    #[error("dir: {dir} {reason}")]
+    BadDir { dir: String, reason: String },
+
+    #[error("{key}: {reason}")]
+    Invalid { key: &'static str, reason: String },
 }
 
-fn init_config() -> Result<AppConfig, config::ConfigError> {
-    config::Config::builder()
+/// ConfigError is returned by `AppConfig::build`, with every problem found in the config
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config: {0}")]
+    Read(#[from] config::ConfigError),
+
+    #[error("bad config: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Invalid(Vec<ConfigProblem>),
+}
+
+/// ValidatedConfig is an `AppConfig` checked by `AppConfig::validate`,
+/// with the settings needed to start the server resolved.
+#[derive(Debug)]
+pub struct ValidatedConfig {
+    /// Storage directory, which exists or can be created
+    pub dir: PathBuf,
+    /// Every address in `http_addr`, with hostnames resolved
+    pub bind_addrs: Vec<SocketAddr>,
+    /// Default port of `bind_addrs`, which HTTP requests are redirected to with TLS
+    pub http_port: u16,
+    pub timeout: Duration,
+    pub hashing: HashConfig,
+    /// PEM certificate chain and private key, if HTTPS is enabled
+    pub tls: Option<(String, String)>,
+    /// The config as read, for settings that need no validation
+    pub app: AppConfig,
+}
+
+impl AppConfig {
+    /// build reads the config like `init`, and validates it
+    pub fn build() -> Result<ValidatedConfig, ConfigError> {
+        Self::build_with(&ConfigArgs::default())
+    }
+
+    /// build_with reads the config like `init_with`, and validates it
+    pub fn build_with(args: &ConfigArgs) -> Result<ValidatedConfig, ConfigError> {
+        Self::load(args)?.validate()
+    }
+
+    /// validate checks the config, and reports all problems at once
+    pub fn validate(self) -> Result<ValidatedConfig, ConfigError> {
+        let mut problems = Vec::new();
+
+        let dir = self.dir.clone().filter(|dir| !dir.is_empty());
+        let dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                problems.push(ConfigProblem::Missing("dir"));
+                PathBuf::new()
+            }
+        };
+
+        if let Err(reason) = check_dir(&dir) {
+            problems.push(ConfigProblem::BadDir {
+                dir: dir.display().to_string(),
+                reason,
+            });
+        }
+
+        for (key, port) in [
+            ("http_port", self.http_port),
+            ("tls_redirect_port", self.tls_redirect_port),
+        ] {
+            if port == Some(0) {
+                problems.push(ConfigProblem::BadPort { key, port: 0 });
+            }
+        }
+
+        let http_port = self.http_port.unwrap_or_else(|| {
+            problems.push(ConfigProblem::Missing("http_port"));
+            HTTP_PORT
+        });
+
+        if self.tls_redirect_port.is_some() && self.tls_redirect_port == self.http_port {
+            problems.push(ConfigProblem::Invalid {
+                key: "tls_redirect_port",
+                reason: "must differ from http_port".to_string(),
+            });
+        }
+
+        let mut bind_addrs = Vec::new();
+        for (host, port) in self.bind_addrs() {
+            match (host.as_str(), port).to_socket_addrs() {
+                Ok(addrs) => bind_addrs.extend(addrs),
+                Err(err) => problems.push(ConfigProblem::BadAddr {
+                    addr: host,
+                    reason: err.to_string(),
+                }),
+            }
+        }
+
+        if self.http_addr.as_ref().is_some_and(Vec::is_empty) {
+            problems.push(ConfigProblem::Missing("http_addr"));
+        }
+
+        let timeout = match self.timeout {
+            Some(0) => {
+                problems.push(ConfigProblem::Invalid {
+                    key: "timeout",
+                    reason: "must be at least 1 second".to_string(),
+                });
+                Duration::ZERO
+            }
+
+            Some(secs) => Duration::from_secs(secs),
+            None => {
+                problems.push(ConfigProblem::Missing("timeout"));
+                Duration::ZERO
+            }
+        };
+
+        let hashing = self.hash_config();
+        if !(1..=hashing.max_len()).contains(&hashing.len) {
+            problems.push(ConfigProblem::Invalid {
+                key: "hash_len",
+                reason: format!("{} is not between 1 and {}", hashing.len, hashing.max_len()),
+            });
+        }
+
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+            (None, None) => None,
+            _ => {
+                problems.push(ConfigProblem::Invalid {
+                    key: "tls_cert",
+                    reason: "tls_cert and tls_key must be set together".to_string(),
+                });
+                None
+            }
+        };
+
+        if self.scopes().is_empty() {
+            problems.push(ConfigProblem::Invalid {
+                key: "scopes",
+                reason: "no scopes to mount".to_string(),
+            });
+        }
+
+        for (name, value) in self
+            .security_headers
+            .iter()
+            .flat_map(SecurityHeaders::headers)
+        {
+            // Header values may only hold visible ASCII, spaces and tabs
+            if !value
+                .bytes()
+                .all(|b| b == b'\t' || (b' '..=b'~').contains(&b))
+            {
+                problems.push(ConfigProblem::Invalid {
+                    key: "security_headers",
+                    reason: format!("{name} is not a valid header value"),
+                });
+            }
+        }
+
+        for url in self.webhooks.iter().flatten() {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                problems.push(ConfigProblem::Invalid {
+                    key: "webhooks",
+                    reason: format!("{url} is not an HTTP URL"),
+                });
+            }
+        }
+
+        if let Some(replication) = &self.replication {
+            for url in &replication.peers {
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    problems.push(ConfigProblem::Invalid {
+                        key: "replication",
+                        reason: format!("peer {url} is not an HTTP URL"),
+                    });
+                }
+            }
+
+            if replication.token.is_empty() {
+                problems.push(ConfigProblem::Invalid {
+                    key: "replication",
+                    reason: "token must not be empty".to_string(),
+                });
+            }
+        }
+
+        if let Some(mailer) = &self.mailer {
+            let reasons = [
+                (mailer.host.is_empty(), "host must not be empty"),
+                (!mailer.from.contains('@'), "from must be an email address"),
+                (
+                    mailer.allowed_domains.is_empty(),
+                    "allowed_domains must list the domains clipboards may be emailed to",
+                ),
+                (
+                    mailer.password.is_some() && mailer.username.is_none(),
+                    "password needs a username",
+                ),
+            ];
+
+            for (_, reason) in reasons.iter().filter(|(bad, _)| *bad) {
+                problems.push(ConfigProblem::Invalid {
+                    key: "mailer",
+                    reason: reason.to_string(),
+                });
+            }
+        }
+
+        if let Some(share) = &self.share {
+            if share.targets().is_empty() {
+                problems.push(ConfigProblem::Invalid {
+                    key: "share",
+                    reason: "slack or matrix must be set".to_string(),
+                });
+            }
+
+            for url in [&share.slack, &share.matrix].into_iter().flatten() {
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    problems.push(ConfigProblem::Invalid {
+                        key: "share",
+                        // URLs hold tokens, so they are not in the reason
+                        reason: "webhooks must be HTTP URLs".to_string(),
+                    });
+                }
+            }
+        }
+
+        let ttls = [
+            ("mem_ttl", self.mem_ttl, self.max_mem_ttl),
+            ("persist_ttl", self.persist_ttl, self.max_persist_ttl),
+        ];
+        for (key, ttl, max) in ttls {
+            if ttl == Some(0) || max == Some(0) {
+                problems.push(ConfigProblem::Invalid {
+                    key,
+                    reason: "lifetimes must be positive".to_string(),
+                });
+            }
+
+            if let (Some(ttl), Some(max)) = (ttl, max) {
+                if ttl > max {
+                    problems.push(ConfigProblem::Invalid {
+                        key,
+                        reason: format!("{ttl}s is longer than the maximum of {max}s"),
+                    });
+                }
+            }
+        }
+
+        if let Some(audit) = &self.audit_log {
+            if audit.path.is_empty() || audit.max_bytes == Some(0) {
+                problems.push(ConfigProblem::Invalid {
+                    key: "audit_log",
+                    reason: "path must not be empty, and max_bytes must be positive".to_string(),
+                });
+            }
+        }
+
+        if let Some(shed) = &self.load_shed {
+            if shed.mem_bytes.is_none() && shed.disk_bytes.is_none() {
+                problems.push(ConfigProblem::Invalid {
+                    key: "load_shed",
+                    reason: "set mem_bytes, disk_bytes or both".to_string(),
+                });
+            }
+        }
+
+        if self.janitor_interval == Some(0) {
+            problems.push(ConfigProblem::Invalid {
+                key: "janitor_interval",
+                reason: "must be at least 1 second".to_string(),
+            });
+        }
+
+        if self.admin_token.as_deref() == Some("") {
+            problems.push(ConfigProblem::Invalid {
+                key: "admin_token",
+                reason: "must not be empty".to_string(),
+            });
+        }
+
+        match self.link_secret.as_deref() {
+            Some("") => problems.push(ConfigProblem::Invalid {
+                key: "link_secret",
+                reason: "must not be empty".to_string(),
+            }),
+            None if self.require_signed_links == Some(true) => {
+                problems.push(ConfigProblem::Invalid {
+                    key: "require_signed_links",
+                    reason: "links cannot be signed without link_secret".to_string(),
+                })
+            }
+            _ => {}
+        }
+
+        if self.link_secret.is_none() && self.require_upload_token == Some(true) {
+            problems.push(ConfigProblem::Invalid {
+                key: "require_upload_token",
+                reason: "upload tokens cannot be signed without link_secret".to_string(),
+            });
+        }
+
+        if let Some(limits) = &self.rate_limit {
+            if limits.burst == 0 || limits.per_sec < 0.0 || limits.per_sec.is_nan() {
+                problems.push(ConfigProblem::Invalid {
+                    key: "rate_limit",
+                    reason: "burst must be positive, and per_sec not negative".to_string(),
+                });
+            }
+        }
+
+        for (name, tenant) in self.tenants.iter().flatten() {
+            if !tenant::valid_name(name) {
+                problems.push(ConfigProblem::Invalid {
+                    key: "tenants",
+                    reason: format!(
+                        "bad tenant name {name:?}: use up to {} lowercase letters, digits, - and _",
+                        tenant::NAME_MAX_LEN
+                    ),
+                });
+            }
+
+            if tenant.token.is_empty() {
+                problems.push(ConfigProblem::Invalid {
+                    key: "tenants",
+                    reason: format!("tenant {name} has no token"),
+                });
+            }
+        }
+
+        if let Err(err) = self.filter_chain() {
+            problems.push(ConfigProblem::Invalid {
+                key: "filters",
+                reason: err.to_string(),
+            });
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        Ok(ValidatedConfig {
+            dir,
+            bind_addrs,
+            http_port,
+            timeout,
+            hashing,
+            tls,
+            app: self,
+        })
+    }
+}
+
+/// check_dir returns why `dir` cannot be used as the storage directory:
+/// it must either be a directory, or be creatable in an existing directory.
+fn check_dir(dir: &Path) -> Result<(), String> {
+    if dir.as_os_str().is_empty() {
+        return Ok(());
+    }
+
+    if dir.exists() {
+        return match dir.is_dir() {
+            true => Ok(()),
+            false => Err("is not a directory".to_string()),
+        };
+    }
+
+    let parent = match dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match parent.is_dir() {
+        true => Ok(()),
+        false => Err(format!(
+            "cannot be created, {} is not a directory",
+            parent.display()
+        )),
+    }
+}
+
+/// bind_addr splits `addr` into its host and port, using `port` if it has none
+fn bind_addr(addr: &str, port: u16) -> (String, u16) {
+    if let Ok(addr) = addr.parse::<std::net::SocketAddr>() {
+        return (addr.ip().to_string(), addr.port());
+    }
+
+    // Unbracketed IPv6 addresses have no port
+    if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+        return (addr.to_string(), port);
+    }
+
+    match addr.rsplit_once(':') {
+        Some((host, p)) if !p.contains(']') => match p.parse() {
+            Ok(p) => (host.trim_matches(['[', ']']).to_string(), p),
+            Err(_) => (addr.to_string(), port),
+        },
+        _ => (addr.trim_matches(['[', ']']).to_string(), port),
+    }
+}
+
+/// string_or_list deserializes either a single string or a list of strings into a list
+fn string_or_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    Ok(
+        Option::<StringOrList>::deserialize(deserializer)?.map(|addrs| match addrs {
+            StringOrList::String(addr) => vec![addr],
+            StringOrList::List(addrs) => addrs,
+        }),
+    )
+}
+
+/// config_files returns the optional config files, lowest priority first:
+/// `/etc/actix-drop/config` on UNIX, `actix-drop/config` in the platform's config directory
+/// (e.g. `~/.config` on Linux and `%APPDATA%` on Windows), and `~/.actix-drop/config`.
+/// Each may have any extension supported by `config`, e.g. `.yaml`.
+fn config_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    #[cfg(unix)]
+    files.push(PathBuf::from("/etc/actix-drop/config"));
+
+    if let Some(dir) = dirs::config_dir() {
+        files.push(dir.join("actix-drop").join("config"));
+    }
+
+    if let Some(home) = dirs::home_dir() {
+        files.push(home.join(".actix-drop").join("config"));
+    }
+
+    files
+}
+
+fn init_config(args: &ConfigArgs) -> Result<AppConfig, config::ConfigError> {
+    let mut builder = config::Config::builder()
         .set_default("dir", DIR)?
         .set_default("http_addr", HTTP_ADDR)?
         .set_default("http_port", HTTP_PORT)?
         .set_default("timeout", TIMEOUT.to_string())?
-        .add_source(config::File::with_name("/etc/actix-drop/config").required(false))
-        .add_source(config::File::with_name("$HOME/.config/actix-drop/config").required(false))
-        .add_source(config::File::with_name("$HOME/.actix-drop/config").required(false))
-        .add_source(config::Environment::with_prefix("DROP"))
+        .set_default("hash_len", HASH_LEN as u64)?
+        .set_default("hash_algo", "sha256")?;
+
+    for path in config_files() {
+        builder =
+            builder.add_source(config::File::with_name(&path.to_string_lossy()).required(false));
+    }
+
+    if let Some(path) = &args.config {
+        builder = builder.add_source(config::File::from(path.as_path()).required(true));
+    }
+
+    builder
+        // Nested keys use double underscores, e.g. DROP_RATE_LIMIT__BURST,
+        // and lists are comma-separated, e.g. DROP_CORS_ORIGINS=https://a.example,https://b.example
+        .add_source(
+            config::Environment::with_prefix("DROP")
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("cors_origins")
+                .with_list_parse_key("trusted_proxies")
+                .with_list_parse_key("http_addr")
+                .with_list_parse_key("scopes")
+                .with_list_parse_key("webhooks")
+                .with_list_parse_key("replication.peers")
+                .with_list_parse_key("mailer.allowed_domains"),
+        )
+        // Command-line flags override everything else
+        .set_override_option("http_port", args.port.map(u64::from))?
+        .set_override_option("dir", args.dir.clone())?
+        .set_override_option("timeout", args.timeout)?
         .build()?
         .try_deserialize::<AppConfig>()
 }
@@ -60,17 +1074,13 @@ mod tests {
     const TIMEOUT: u64 = 69;
 
     macro_rules! assert_eq_test_default {
-        ( $conf: expr ) => {
-            assert_eq!(
-                $conf,
-                AppConfig {
-                    dir: Some(DIR.to_string()),
-                    http_addr: Some(ADDR.to_string()),
-                    http_port: Some(PORT),
-                    timeout: Some(TIMEOUT),
-                }
-            )
-        };
+        ( $conf: expr ) => {{
+            let conf: AppConfig = $conf;
+            assert_eq!(conf.dir, Some(DIR.to_string()));
+            assert_eq!(conf.http_addr, Some(vec![ADDR.to_string()]));
+            assert_eq!(conf.http_port, Some(PORT));
+            assert_eq!(conf.timeout, Some(TIMEOUT));
+        }};
     }
 
     #[test]
@@ -88,7 +1098,366 @@ mod tests {
         assert_eq!(conf.dir, Some(DIR.to_string()));
         assert_eq!(conf.timeout, Some(TIMEOUT));
         assert_eq!(conf.http_port, None);
-        assert_eq!(conf.http_addr, Some(ADDR.to_string()));
+        assert_eq!(conf.http_addr, Some(vec![ADDR.to_string()]));
+
+        let j = json!({ "http_addr": [ADDR, "[::1]:8081"] }).to_string();
+        let conf = serde_json::from_str::<AppConfig>(&j).expect("failed to deserialize json");
+        assert_eq!(
+            conf.http_addr,
+            Some(vec![ADDR.to_string(), "[::1]:8081".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        use super::{ConfigError, ConfigProblem};
+
+        let conf = AppConfig::default()
+            .validate()
+            .expect("default config is invalid");
+        assert_eq!(conf.bind_addrs, vec!["127.0.0.1:8080".parse().unwrap()]);
+        assert_eq!(conf.timeout, std::time::Duration::from_secs(15));
+        assert!(conf.tls.is_none());
+
+        let conf = AppConfig {
+            dir: Some("/nonexistent/actix-drop".to_string()),
+            http_port: Some(0),
+            http_addr: Some(vec!["not an address".to_string()]),
+            timeout: None,
+            tls_key: Some("key.pem".to_string()),
+            ..AppConfig::default()
+        };
+
+        // All problems are reported at once
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("invalid config was validated");
+        };
+
+        assert_eq!(problems.len(), 5, "{problems:?}");
+        assert!(problems.contains(&ConfigProblem::Missing("timeout")));
+        assert!(problems.contains(&ConfigProblem::BadPort {
+            key: "http_port",
+            port: 0
+        }));
+        assert!(problems
+            .iter()
+            .any(|problem| matches!(problem, ConfigProblem::BadDir { .. })));
+        assert!(problems
+            .iter()
+            .any(|problem| matches!(problem, ConfigProblem::BadAddr { .. })));
+
+        let conf = AppConfig {
+            security_headers: Some(super::SecurityHeaders {
+                referrer_policy: "no-referrer\r\nSet-Cookie: x".to_string(),
+                ..Default::default()
+            }),
+            ..AppConfig::default()
+        };
+        assert!(conf.validate().is_err());
+    }
+
+    #[test]
+    fn test_scopes() {
+        use super::Scope;
+
+        assert_eq!(AppConfig::default().scopes(), Scope::ALL);
+
+        // Headless deployments leave out the HTML UI
+        let conf: AppConfig = serde_json::from_str(r#"{"scopes": ["raw", "api", "api"]}"#).unwrap();
+        assert_eq!(conf.scopes(), vec![Scope::Api, Scope::Raw]);
+
+        let conf = AppConfig {
+            scopes: Some(Vec::new()),
+            ..AppConfig::default()
+        };
+        assert!(conf.validate().is_err());
+    }
+
+    #[test]
+    fn test_webhooks() {
+        use super::{ConfigError, ConfigProblem};
+
+        let conf = AppConfig {
+            webhooks: Some(vec![
+                "https://hooks.example.com/drop".to_string(),
+                "ftp://hooks.example.com".to_string(),
+            ]),
+            ..AppConfig::default()
+        };
+
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("non-HTTP webhook was validated");
+        };
+        assert_eq!(
+            problems,
+            vec![ConfigProblem::Invalid {
+                key: "webhooks",
+                reason: "ftp://hooks.example.com is not an HTTP URL".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_replication() {
+        use super::{ConfigError, ConfigProblem, ReplicationConfig};
+
+        let conf = AppConfig {
+            replication: Some(ReplicationConfig {
+                peers: vec!["drop2.example.com".to_string()],
+                token: String::new(),
+            }),
+            ..AppConfig::default()
+        };
+
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("bad replication was validated");
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems.iter().all(|problem| matches!(
+            problem,
+            ConfigProblem::Invalid {
+                key: "replication",
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_mailer() {
+        use super::{ConfigError, ConfigProblem, MailerConfig, SmtpTls};
+
+        let mut mailer = MailerConfig {
+            host: "smtp.example.com".to_string(),
+            port: None,
+            tls: SmtpTls::Implicit,
+            username: None,
+            password: None,
+            from: "drop@example.com".to_string(),
+            allowed_domains: vec!["example.com".to_string()],
+            rate_limit: None,
+        };
+        assert_eq!(mailer.port(), 465);
+
+        let conf = AppConfig {
+            mailer: Some(mailer.clone()),
+            ..AppConfig::default()
+        };
+        assert!(conf.validate().is_ok());
+
+        mailer.from = "drop".to_string();
+        mailer.allowed_domains.clear();
+        let conf = AppConfig {
+            mailer: Some(mailer),
+            ..AppConfig::default()
+        };
+
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("bad mailer was validated");
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems
+            .iter()
+            .all(|problem| matches!(problem, ConfigProblem::Invalid { key: "mailer", .. })));
+
+        let tls: SmtpTls = serde_json::from_str(r#""starttls""#).unwrap();
+        assert_eq!(tls, SmtpTls::StartTls);
+    }
+
+    #[test]
+    fn test_share() {
+        use super::{ConfigError, ConfigProblem, ShareConfig, ShareTarget};
+
+        let mut share = ShareConfig {
+            slack: None,
+            matrix: Some("https://matrix.example.com/webhook/abc".to_string()),
+            excerpt: None,
+            rate_limit: None,
+        };
+        assert_eq!(share.targets(), vec![ShareTarget::Matrix]);
+
+        let conf = AppConfig {
+            share: Some(share.clone()),
+            ..AppConfig::default()
+        };
+        assert!(
+            !serde_json::to_string(&conf)
+                .unwrap()
+                .contains("webhook/abc"),
+            "webhook URL was serialized"
+        );
+        assert!(conf.validate().is_ok());
+
+        share.matrix = Some("matrix.example.com".to_string());
+        let conf = AppConfig {
+            share: Some(share.clone()),
+            ..AppConfig::default()
+        };
+        assert!(conf.validate().is_err());
+
+        share.matrix = None;
+        let conf = AppConfig {
+            share: Some(share),
+            ..AppConfig::default()
+        };
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("share without targets was validated");
+        };
+        assert!(matches!(
+            problems[..],
+            [ConfigProblem::Invalid { key: "share", .. }]
+        ));
+
+        let target: ShareTarget = serde_json::from_str(r#""slack""#).unwrap();
+        assert_eq!(target, ShareTarget::Slack);
+    }
+
+    #[test]
+    fn test_retention() {
+        use std::time::Duration;
+
+        use super::{ConfigError, ConfigProblem};
+
+        let conf = AppConfig {
+            mem_ttl: Some(60),
+            max_persist_ttl: Some(3600),
+            ..AppConfig::default()
+        };
+        let retention = conf.retention();
+        let secs = Duration::from_secs;
+
+        // Clipboards get the default of their storage class, or the global timeout
+        assert_eq!(retention.ttl(false, None, secs(15)), secs(60));
+        assert_eq!(retention.ttl(true, None, secs(15)), secs(15));
+        // Requested lifetimes are capped at the maximum of their storage class
+        assert_eq!(
+            retention.ttl(false, Some(secs(86400)), secs(15)),
+            secs(86400)
+        );
+        assert_eq!(retention.ttl(true, Some(secs(86400)), secs(15)), secs(3600));
+        assert!(conf.validate().is_ok());
+
+        let conf = AppConfig {
+            mem_ttl: Some(0),
+            persist_ttl: Some(7200),
+            max_persist_ttl: Some(3600),
+            ..AppConfig::default()
+        };
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("bad retention was validated");
+        };
+        let keys: Vec<_> = problems
+            .iter()
+            .map(|problem| match problem {
+                ConfigProblem::Invalid { key, .. } => *key,
+                _ => panic!("unexpected problem {problem:?}"),
+            })
+            .collect();
+        assert_eq!(keys, ["mem_ttl", "persist_ttl"]);
+    }
+
+    #[test]
+    fn test_load_shed() {
+        use super::{ConfigError, ConfigProblem, LoadShedConfig};
+
+        let conf: AppConfig = serde_json::from_str(r#"{"load_shed": {"mem_bytes": 1024}}"#)
+            .expect("failed to parse load_shed");
+        let shed = conf.load_shed.clone().unwrap();
+        assert_eq!(shed.retry_after, 30);
+        assert!(!shed.overloaded(1024, u64::MAX));
+        assert!(shed.overloaded(1025, 0));
+
+        let conf = AppConfig {
+            load_shed: Some(shed),
+            ..AppConfig::default()
+        };
+        assert!(conf.validate().is_ok());
+
+        let conf = AppConfig {
+            load_shed: Some(LoadShedConfig {
+                mem_bytes: None,
+                disk_bytes: None,
+                retry_after: 30,
+            }),
+            ..AppConfig::default()
+        };
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("load_shed without marks was validated");
+        };
+        assert!(matches!(
+            problems[..],
+            [ConfigProblem::Invalid {
+                key: "load_shed",
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_filters() {
+        use super::{ConfigError, ConfigProblem, FilterConfig};
+
+        let conf: AppConfig = serde_json::from_str(
+            r#"{"filters": {"max_size": 1024, "denylist": ["(?i)casino"], "secrets": "redact"}}"#,
+        )
+        .unwrap();
+        assert!(!conf.filter_chain().unwrap().is_empty());
+        assert!(AppConfig::default().filter_chain().unwrap().is_empty());
+
+        let conf = AppConfig {
+            filters: Some(FilterConfig {
+                denylist: vec!["[".to_string()],
+                ..FilterConfig::default()
+            }),
+            ..AppConfig::default()
+        };
+
+        let Err(ConfigError::Invalid(problems)) = conf.validate() else {
+            panic!("bad denylist pattern was validated");
+        };
+        assert!(matches!(
+            problems.as_slice(),
+            [ConfigProblem::Invalid { key: "filters", .. }]
+        ));
+    }
+
+    #[test]
+    fn test_bind_addrs() {
+        let addrs = ["127.0.0.1", "[::1]:8081", "::1", "localhost:9000", "[::]"];
+        let conf = AppConfig {
+            http_addr: Some(addrs.iter().map(|addr| addr.to_string()).collect()),
+            http_port: Some(PORT),
+            ..AppConfig::default()
+        };
+
+        let port = PORT;
+        assert_eq!(
+            conf.bind_addrs(),
+            vec![
+                ("127.0.0.1".to_string(), port),
+                ("::1".to_string(), 8081),
+                ("::1".to_string(), port),
+                ("localhost".to_string(), 9000),
+                ("::".to_string(), port),
+            ]
+        );
+
+        let conf = AppConfig {
+            http_addr: Some(vec!["::1".to_string()]),
+            ..AppConfig::default()
+        };
+        assert_eq!(conf.server_url(), "http://[::1]:8080");
+    }
+
+    #[test]
+    fn test_server_url() {
+        let conf = AppConfig::default();
+        assert_eq!(conf.server_url(), "http://127.0.0.1:8080");
+
+        let conf = AppConfig {
+            server_url: Some("https://drop.example.com/".to_string()),
+            ..AppConfig::default()
+        };
+        assert_eq!(conf.server_url(), "https://drop.example.com");
     }
 
     #[test]
@@ -110,19 +1479,61 @@ mod tests {
         assert_eq_test_default!(conf);
     }
 
+    #[test]
+    fn test_config_args() {
+        use super::{init_config, ConfigArgs};
+
+        let path = std::env::temp_dir().join("actix-drop-test-config.yaml");
+        std::fs::write(&path, "max_versions: 3\n").unwrap();
+
+        let args = ConfigArgs {
+            config: Some(path.clone()),
+            port: Some(7070),
+            dir: Some("./bar".to_string()),
+            timeout: Some(42),
+        };
+
+        // Flags override envs set by other tests
+        let conf = init_config(&args).expect("init_config failed");
+        assert_eq!(conf.http_port, Some(7070));
+        assert_eq!(conf.dir, Some("./bar".to_string()));
+        assert_eq!(conf.timeout, Some(42));
+        assert_eq!(conf.max_versions, Some(3));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(init_config(&args).is_err());
+    }
+
     #[test]
     fn test_init_config() {
-        use super::init_config;
+        use super::{init_config, ConfigArgs};
         use std::env;
 
         env::set_var("DROP_DIR", DIR);
         env::set_var("DROP_HTTP_ADDR", ADDR);
         env::set_var("DROP_HTTP_PORT", PORT.to_string());
         env::set_var("DROP_TIMEOUT", TIMEOUT.to_string());
+        env::set_var("DROP_CORS_ORIGINS", "https://a.example,https://b.example");
+        env::set_var("DROP_TRUSTED_PROXIES", "127.0.0.1,fd00::/8");
 
-        let conf = init_config().expect("init_config failed");
+        let conf = init_config(&ConfigArgs::default()).expect("init_config failed");
         println!("test_init_config: {conf:?}");
 
+        assert_eq!(conf.hash_config(), crate::hash::HashConfig::default());
+        assert_eq!(
+            conf.cors_origins,
+            Some(vec![
+                "https://a.example".to_string(),
+                "https://b.example".to_string()
+            ])
+        );
+        assert_eq!(
+            conf.trusted_proxies,
+            Some(vec![
+                "127.0.0.1".parse().unwrap(),
+                "fd00::/8".parse().unwrap()
+            ])
+        );
         assert_eq_test_default!(conf);
     }
 }
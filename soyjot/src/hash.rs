@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::id::{Alphabet, IdMode};
+
+/// Default length of clipboard keys, in characters
+pub const HASH_LEN: usize = 4;
+
+/// HashAlgo enumerates hash functions used to derive clipboard keys from clipboard content.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+/// Hasher incrementally hashes clipboard content with the configured `HashAlgo`.
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgo::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// finalize returns the hex-encoded digest
+    pub fn finalize(self) -> String {
+        match self {
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// HashConfig configures how clipboard keys are derived from clipboard content.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashConfig {
+    pub algo: HashAlgo,
+    /// Length of clipboard keys, in characters of `alphabet`
+    pub len: usize,
+    pub alphabet: Alphabet,
+    pub mode: IdMode,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            algo: HashAlgo::default(),
+            len: HASH_LEN,
+            alphabet: Alphabet::default(),
+            mode: IdMode::default(),
+        }
+    }
+}
+
+impl HashConfig {
+    pub fn hasher(&self) -> Hasher {
+        Hasher::new(self.algo)
+    }
+
+    /// digest returns the full hex-encoded digest of data
+    pub fn digest(&self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// key encodes a full hex-encoded digest in the key alphabet,
+    /// and truncates it into a clipboard key. With `IdMode::Random`,
+    /// key ignores the digest and returns a new random key.
+    pub fn key(&self, digest: &str) -> String {
+        if self.mode == IdMode::Random {
+            return self.alphabet.random(self.len.max(1));
+        }
+
+        let mut key = self.alphabet.encode(digest);
+        key.truncate(self.len.max(1));
+        key
+    }
+
+    /// max_len returns the length of keys made of whole digests
+    pub fn max_len(&self) -> usize {
+        // Both SHA-256 and BLAKE3 digests are 32 bytes
+        self.alphabet.encoded_len(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key() {
+        let sha256 = HashConfig::default();
+        let digest = sha256.digest(b"foo");
+
+        assert_eq!(
+            digest,
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+        assert_eq!(sha256.key(&digest), "2c26");
+
+        let blake3 = HashConfig {
+            algo: HashAlgo::Blake3,
+            len: 8,
+            ..HashConfig::default()
+        };
+        let digest = blake3.digest(b"foo");
+
+        assert_eq!(digest.len(), 64);
+        assert_eq!(blake3.key(&digest), digest[..8]);
+
+        // Incremental hashing matches one-shot hashing
+        let mut hasher = blake3.hasher();
+        hasher.update(b"f");
+        hasher.update(b"oo");
+        assert_eq!(hasher.finalize(), digest);
+
+        let crockford = HashConfig {
+            alphabet: Alphabet::Crockford,
+            ..HashConfig::default()
+        };
+        let key = crockford.key(&sha256.digest(b"foo"));
+        assert_eq!(key.len(), 4);
+        assert_eq!(key, Alphabet::Crockford.encode(&sha256.digest(b"foo"))[..4]);
+        assert_eq!(crockford.max_len(), 52);
+
+        // Random keys do not depend on the content
+        let random = HashConfig {
+            len: 32,
+            mode: IdMode::Random,
+            ..HashConfig::default()
+        };
+        assert_eq!(random.key(&digest).len(), 32);
+        assert_ne!(random.key(&digest), random.key(&digest));
+    }
+}
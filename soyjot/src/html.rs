@@ -1,4 +1,4 @@
-const HEADER: &str = r#"<!DOCTYPE html><html><head><meta name=viewport content="width=device-width, initial-scale=1.0"><meta name=keywords content="actix-drop"><meta name=author content=@artnoi><meta charset=UTF-8><link href=https://artnoi.com/style.css rel=stylesheet><title>actix-drop</title></head><body><h1><a href="/">actix-drop</a></h1>"#;
+const HEADER: &str = r#"<!DOCTYPE html><html><head><meta name=viewport content="width=device-width, initial-scale=1.0"><meta name=keywords content="actix-drop"><meta name=author content=@artnoi><meta charset=UTF-8><link href=/favicon.svg rel=icon type=image/svg+xml><link href=https://artnoi.com/style.css rel=stylesheet><title>actix-drop</title></head><body><h1><a href="/">actix-drop</a></h1>"#;
 const FOOTER: &str = r#"<footer><p><a href="https://github.com/soyart/actix-drop">Contribute on Github</a></p></footer></body></html>"#;
 
 #[macro_export]
@@ -26,11 +26,29 @@ pub fn wrap_html(s: &str) -> String {
     format!("{}{}{}", HEADER, s, FOOTER)
 }
 
+/// escape escapes `s` for use in HTML text and attribute values
+pub fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests_html {
     #[test]
     fn test_html() {
         assert_eq!(para!("foo"), "<p>foo</p>".to_string());
         assert_eq!(para!(code!("foo")), "<p><code>foo</code></p>".to_string());
+        assert_eq!(
+            super::escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
     }
 }
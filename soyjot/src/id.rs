@@ -0,0 +1,169 @@
+//! Alphabets of clipboard keys. Keys are prefixes of the clipboard digest encoded
+//! in the configured `Alphabet`, so denser alphabets give shorter keys for the same
+//! number of possible clipboards. With `IdMode::Random`, keys are random strings
+//! of the alphabet instead.
+
+use serde::{Deserialize, Serialize};
+
+const HEX: &[u8] = b"0123456789abcdef";
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// Crockford's base32, lowercased. It leaves out i, l, o and u, which are easily
+/// confused with 1, 0 and v.
+const CROCKFORD: &[u8] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// Alphabet enumerates the characters clipboard keys may be made of
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Alphabet {
+    /// Lowercase hex, 4 bits per character
+    #[default]
+    Hex,
+    /// Digits and both cases of ASCII letters, about 5.95 bits per character.
+    /// Keys are case-sensitive.
+    Base62,
+    /// Crockford's base32 in lowercase, 5 bits per character
+    #[serde(alias = "base32crockford")]
+    Crockford,
+}
+
+/// IdMode sets how clipboard keys are made
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdMode {
+    /// Keys are prefixes of the clipboard digest, so the same content gets the same key
+    #[default]
+    Hash,
+    /// Keys are drawn from the OS CSPRNG, so that keys reveal nothing about the content,
+    /// and clients cannot probe whether some content is stored by guessing its key
+    Random,
+}
+
+impl Alphabet {
+    fn chars(&self) -> &'static [u8] {
+        match self {
+            Self::Hex => HEX,
+            Self::Base62 => BASE62,
+            Self::Crockford => CROCKFORD,
+        }
+    }
+
+    /// encoded_len returns the length of `bytes` bytes encoded in the alphabet
+    pub fn encoded_len(&self, bytes: usize) -> usize {
+        let bits = (self.chars().len() as f64).log2();
+        (bytes as f64 * 8.0 / bits).ceil() as usize
+    }
+
+    /// encode encodes a hex-encoded digest in the alphabet, most significant digits first.
+    /// Encodings are padded with leading zeroes to `encoded_len`, so that all keys of
+    /// a digest size have the same length.
+    pub fn encode(&self, hex_digest: &str) -> String {
+        if *self == Self::Hex {
+            return hex_digest.to_string();
+        }
+
+        let mut bytes = from_hex(hex_digest);
+        let chars = self.chars();
+        let base = chars.len() as u32;
+        let len = self.encoded_len(bytes.len());
+
+        // Long division of the digest as a big-endian number by the base
+        let mut digits = Vec::with_capacity(len);
+        while digits.len() < len {
+            let mut rem = 0u32;
+            for byte in bytes.iter_mut() {
+                let acc = (rem << 8) | *byte as u32;
+                *byte = (acc / base) as u8;
+                rem = acc % base;
+            }
+
+            digits.push(chars[rem as usize]);
+        }
+
+        digits.iter().rev().map(|&c| c as char).collect()
+    }
+
+    /// random returns a random string of `len` characters of the alphabet
+    pub fn random(&self, len: usize) -> String {
+        let chars = self.chars();
+        // Bytes at or over the largest multiple of the base are rejected,
+        // so that every character is equally likely
+        let limit = 256 - 256 % chars.len();
+
+        let mut key = String::with_capacity(len);
+        let mut bytes = [0u8; 64];
+        while key.len() < len {
+            getrandom::getrandom(&mut bytes).expect("failed to get random bytes for key");
+
+            let accepted = bytes.iter().filter(|&&b| (b as usize) < limit);
+            for &b in accepted.take(len - key.len()) {
+                key.push(chars[b as usize % chars.len()] as char);
+            }
+        }
+
+        key
+    }
+}
+
+/// from_hex decodes hex digits into bytes, skipping characters that are not hex digits
+fn from_hex(hex: &str) -> Vec<u8> {
+    let nibbles: Vec<u8> = hex
+        .chars()
+        .filter_map(|c| c.to_digit(16))
+        .map(|n| n as u8)
+        .collect();
+
+    nibbles
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => hi << 4 | lo,
+            [hi] => hi << 4,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(Alphabet::Hex.encode("00ff"), "00ff");
+
+        // 0x00ff is 255, or 4 * 62 + 7 and 7 * 32 + 31
+        assert_eq!(Alphabet::Base62.encode("00ff"), "047");
+        assert_eq!(Alphabet::Crockford.encode("00ff"), "007z");
+        assert_eq!(Alphabet::Crockford.encode("ffff"), "1zzz");
+
+        let digest = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+        for (alphabet, len) in [
+            (Alphabet::Hex, 64),
+            (Alphabet::Base62, 43),
+            (Alphabet::Crockford, 52),
+        ] {
+            let encoded = alphabet.encode(digest);
+            assert_eq!(encoded.len(), len);
+            assert_eq!(alphabet.encoded_len(32), len);
+            assert!(encoded.bytes().all(|c| alphabet.chars().contains(&c)));
+        }
+
+        let crockford = Alphabet::Crockford.encode(digest);
+        assert!(!crockford.contains(['i', 'l', 'o', 'u']));
+
+        let alphabet: Alphabet = serde_json::from_str(r#""base32crockford""#).unwrap();
+        assert_eq!(alphabet, Alphabet::Crockford);
+    }
+
+    #[test]
+    fn test_random() {
+        for alphabet in [Alphabet::Hex, Alphabet::Base62, Alphabet::Crockford] {
+            let (a, b) = (alphabet.random(100), alphabet.random(100));
+
+            assert_eq!(a.len(), 100);
+            assert!(a.bytes().all(|c| alphabet.chars().contains(&c)));
+            assert_ne!(a, b);
+        }
+
+        assert_eq!(Alphabet::Base62.random(0), "");
+    }
+}
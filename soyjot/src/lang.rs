@@ -0,0 +1,530 @@
+//! Guesses of the language or format of text clipboards, and their rendering in the HTML UI.
+//!
+//! `detect` runs cheap heuristics over the start of a clipboard, which are good enough
+//! to pretty-print JSON and to highlight keywords, comments and strings with `render`,
+//! but are no parsers: clients that know better post clipboards with `lang`.
+
+use crate::html::escape;
+
+/// Bytes at the start of a clipboard that `detect` looks at
+const DETECT_LEN: usize = 64 * 1024;
+
+/// Markers that `detect` needs to find of a language before guessing it
+const MIN_SCORE: usize = 2;
+
+/// Longest language name clipboards may be posted with
+pub const LANG_MAX_LEN: usize = 32;
+
+/// Syntax is what `render` highlights in a language
+struct Syntax {
+    name: &'static str,
+    /// Substrings that are typical of the language, for `detect`
+    markers: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+/// Languages guessed from their markers, in order of precedence
+const LANGS: &[Syntax] = &[
+    Syntax {
+        name: "rust",
+        markers: &[
+            "fn ", "let mut ", "impl ", "pub fn", "use std", "println!", "&self", "#[derive",
+            "::new(",
+        ],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "else", "enum", "false",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+            "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+    },
+    Syntax {
+        name: "go",
+        markers: &[
+            "package ",
+            "func ",
+            ":= ",
+            "fmt.",
+            "import (",
+            "err != nil",
+            "go func",
+            "chan ",
+        ],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "for",
+            "func",
+            "go",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        name: "python",
+        markers: &[
+            "def ",
+            "import ",
+            "self.",
+            "elif ",
+            "print(",
+            "__init__",
+            "None",
+            " in range(",
+        ],
+        keywords: &[
+            "and", "as", "async", "await", "break", "class", "continue", "def", "elif", "else",
+            "except", "False", "finally", "for", "from", "if", "import", "in", "is", "lambda",
+            "None", "not", "or", "pass", "raise", "return", "True", "try", "while", "with",
+            "yield",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "javascript",
+        markers: &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "require(",
+            "export ",
+            "===",
+            "document.",
+        ],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "else",
+            "export",
+            "false",
+            "for",
+            "function",
+            "if",
+            "import",
+            "let",
+            "new",
+            "null",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typeof",
+            "undefined",
+            "var",
+            "while",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        name: "c",
+        markers: &[
+            "#include", "int main", "printf(", "void ", "malloc(", "NULL", "sizeof(",
+        ],
+        keywords: &[
+            "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+            "enum", "float", "for", "if", "int", "long", "return", "short", "sizeof", "static",
+            "struct", "switch", "typedef", "unsigned", "void", "while",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "shell",
+        markers: &[
+            "#!/bin/sh",
+            "#!/bin/bash",
+            "echo ",
+            "fi\n",
+            "; then",
+            "esac",
+            "$(",
+            "export ",
+        ],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "while",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "sql",
+        markers: &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "UPDATE ",
+            "JOIN ",
+        ],
+        keywords: &[
+            "AND", "AS", "BY", "CREATE", "DELETE", "FROM", "GROUP", "INSERT", "INTO", "JOIN",
+            "LIMIT", "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE",
+            "VALUES", "WHERE",
+        ],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+    },
+];
+
+/// Formats without markers, detected by their structure
+const FORMATS: &[Syntax] = &[
+    Syntax {
+        name: "json",
+        markers: &[],
+        keywords: &["false", "null", "true"],
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+    },
+    Syntax {
+        name: "yaml",
+        markers: &[],
+        keywords: &["false", "null", "true"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "xml",
+        markers: &[],
+        keywords: &[],
+        line_comments: &[],
+        block_comment: Some(("<!--", "-->")),
+        quotes: &[],
+    },
+    Syntax {
+        name: "html",
+        markers: &[],
+        keywords: &[],
+        line_comments: &[],
+        block_comment: Some(("<!--", "-->")),
+        quotes: &[],
+    },
+];
+
+/// valid reports whether `lang` may be given as the language of a clipboard:
+/// 1 to `LANG_MAX_LEN` lowercase ASCII letters, digits, `-`, `+` and `#`
+pub fn valid(lang: &str) -> bool {
+    (1..=LANG_MAX_LEN).contains(&lang.len())
+        && lang
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"-+#".contains(&b))
+}
+
+/// detect guesses the language or format of clipboard `content`, e.g. `json` or `rust`.
+/// Content that is not text, or that looks like nothing in particular, gets `None`.
+pub fn detect(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(DETECT_LEN)];
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // Only the cut at DETECT_LEN may split a character
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+
+    let start = text.trim_start();
+    if start.starts_with(['{', '[']) {
+        let complete = head.len() == content.len();
+        if (complete && serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok())
+            || (!complete && start[1..].trim_start().starts_with(json_value))
+        {
+            return Some("json");
+        }
+    }
+
+    let lower = start.get(..15).unwrap_or(start).to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some("html");
+    }
+    if start.starts_with("<?xml") || (start.starts_with('<') && start.contains("</")) {
+        return Some("xml");
+    }
+
+    if let Some(shebang) = start.lines().next().filter(|line| line.starts_with("#!")) {
+        for (interpreter, lang) in [
+            ("python", "python"),
+            ("node", "javascript"),
+            ("sh", "shell"),
+        ] {
+            if shebang.contains(interpreter) {
+                return Some(lang);
+            }
+        }
+    }
+
+    let (score, lang) = LANGS
+        .iter()
+        .rev()
+        .map(|lang| {
+            let found = lang.markers.iter().filter(|m| text.contains(*m)).count();
+            (found, lang.name)
+        })
+        .max_by_key(|(found, _)| *found)?;
+
+    match score >= MIN_SCORE {
+        true => Some(lang),
+        false => is_yaml(text).then_some("yaml"),
+    }
+}
+
+/// json_value reports whether `c` may start a JSON value other than a literal
+fn json_value(c: char) -> bool {
+    c.is_ascii_digit() || "\"{[-".contains(c)
+}
+
+/// is_yaml reports whether lines of `text` mostly look like YAML mappings and sequences
+fn is_yaml(text: &str) -> bool {
+    if text.starts_with("---\n") {
+        return true;
+    }
+
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let yaml_line = |line: &&str| {
+        let key = |key: &str| {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_-. ".contains(c))
+        };
+
+        line.starts_with("- ")
+            || line
+                .split_once(": ")
+                .map(|(k, _)| k)
+                .or_else(|| line.strip_suffix(':'))
+                .is_some_and(key)
+    };
+
+    lines.len() >= 2 && lines.iter().filter(|line| yaml_line(line)).count() * 5 >= lines.len() * 4
+}
+
+/// render returns clipboard `text` as HTML for a `<pre><code>` block in language `lang`,
+/// escaped and highlighted, with JSON pretty-printed. Keywords are wrapped in
+/// `<b class="kw">`, comments in `<i class="cm">`, and strings and numbers
+/// in `<span class="st">` and `<span class="nu">`, so they stand out even without styles.
+/// Unknown languages are only escaped.
+pub fn render(text: &str, lang: Option<&str>) -> String {
+    let Some(syntax) = LANGS
+        .iter()
+        .chain(FORMATS)
+        .find(|syntax| Some(syntax.name) == lang)
+    else {
+        return escape(text);
+    };
+
+    let pretty = match syntax.name {
+        "json" => serde_json::from_str::<serde_json::Value>(text)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .ok(),
+        _ => None,
+    };
+
+    highlight(pretty.as_deref().unwrap_or(text), syntax)
+}
+
+fn highlight(text: &str, syntax: &Syntax) -> String {
+    let mut html = String::with_capacity(text.len() * 2);
+    let mut rest = text;
+
+    let wrap = |html: &mut String, open: &str, close: &str, token: &str| {
+        html.push_str(open);
+        html.push_str(&escape(token));
+        html.push_str(close);
+    };
+
+    while let Some(c) = rest.chars().next() {
+        let token_len = if let Some((open, close)) = syntax
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open))
+        {
+            let len = rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+            wrap(&mut html, r#"<i class="cm">"#, "</i>", &rest[..len]);
+            len
+        } else if syntax.line_comments.iter().any(|c| rest.starts_with(c)) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            wrap(&mut html, r#"<i class="cm">"#, "</i>", &rest[..len]);
+            len
+        } else if syntax.quotes.contains(&c) {
+            let len = string_len(rest, c);
+            wrap(&mut html, r#"<span class="st">"#, "</span>", &rest[..len]);
+            len
+        } else if c.is_ascii_digit() {
+            let len = word_len(rest, true);
+            wrap(&mut html, r#"<span class="nu">"#, "</span>", &rest[..len]);
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_len(rest, false);
+            match syntax.keywords.contains(&&rest[..len]) {
+                true => wrap(&mut html, r#"<b class="kw">"#, "</b>", &rest[..len]),
+                false => html.push_str(&escape(&rest[..len])),
+            }
+            len
+        } else {
+            html.push_str(&escape(&rest[..c.len_utf8()]));
+            c.len_utf8()
+        };
+
+        rest = &rest[token_len..];
+    }
+
+    html
+}
+
+/// word_len returns the length of the identifier at the start of `text`,
+/// or of the number if it may have `dots`
+fn word_len(text: &str, dots: bool) -> usize {
+    text.find(|c: char| !(c.is_alphanumeric() || c == '_' || (dots && c == '.')))
+        .unwrap_or(text.len())
+}
+
+/// string_len returns the length of the string quoted with `quote` at the start of `text`,
+/// which ends at its closing quote, or at the end of the line if it's not closed.
+/// Backtick strings may span lines.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        for (content, lang) in [
+            (r#"{"foo": [1, 2]}"#, Some("json")),
+            ("[1, 2, 3]", Some("json")),
+            ("{ not json", None),
+            ("<?xml version=\"1.0\"?><a/>", Some("xml")),
+            ("<note><to>you</to></note>", Some("xml")),
+            ("<!DOCTYPE html><html></html>", Some("html")),
+            (
+                "fn main() {\n    let mut x = 1;\n    println!(\"{x}\");\n}",
+                Some("rust"),
+            ),
+            ("package main\n\nfunc main() {\n\tx := 1\n}", Some("go")),
+            (
+                "def f(x):\n    return [i for i in range(x)]\n\nprint(f(3))",
+                Some("python"),
+            ),
+            (
+                "const f = (x) => x + 1;\nconsole.log(f(1));",
+                Some("javascript"),
+            ),
+            (
+                "#include <stdio.h>\nint main() { printf(\"hi\"); }",
+                Some("c"),
+            ),
+            ("#!/usr/bin/env bash\nls", Some("shell")),
+            ("SELECT * FROM t WHERE id = 1;", Some("sql")),
+            ("name: foo\nversion: 1\ndeps:\n  - bar\n", Some("yaml")),
+            ("---\nfoo", Some("yaml")),
+            ("just some notes: nothing\nin particular", None),
+            ("hello, world", None),
+        ] {
+            assert_eq!(detect(content.as_bytes()), lang, "{content}");
+        }
+
+        assert_eq!(detect(b"\xff\x00{}"), None);
+
+        // Long clipboards are only looked at in part
+        let long = format!("[{}1]", "1, ".repeat(DETECT_LEN));
+        assert_eq!(detect(long.as_bytes()), Some("json"));
+        let long = format!("é{}", "fn main() { let mut x; } ".repeat(DETECT_LEN / 8));
+        assert_eq!(detect(&long.as_bytes()[1..]), None);
+        assert_eq!(detect(long.as_bytes()), Some("rust"));
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(r#"{"a":[1,true]}"#, Some("json")),
+            "{\n  <span class=\"st\">&quot;a&quot;</span>: [\n    <span class=\"nu\">1</span>,\n    \
+             <b class=\"kw\">true</b>\n  ]\n}"
+        );
+
+        assert_eq!(
+            render("let s = \"<b>\"; // done", Some("rust")),
+            "<b class=\"kw\">let</b> s = <span class=\"st\">&quot;&lt;b&gt;&quot;</span>; \
+             <i class=\"cm\">// done</i>"
+        );
+
+        // Unterminated strings and comments end with the line or the text
+        assert_eq!(
+            render("x = 'a\ny /* z", Some("python")),
+            "x = <span class=\"st\">&#39;a</span>\ny /* z"
+        );
+        assert_eq!(render("/* a", Some("c")), "<i class=\"cm\">/* a</i>");
+
+        assert_eq!(render("<i>", None), "&lt;i&gt;");
+        assert_eq!(render("<i>", Some("cobol")), "&lt;i&gt;");
+        assert!(valid("c++") && valid("c#") && !valid("Rust") && !valid(""));
+    }
+}
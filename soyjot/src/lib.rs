@@ -1,6 +1,17 @@
+pub mod audit;
+pub mod client_ip;
 pub mod config;
+pub mod filters;
+pub mod hash;
 pub mod html;
+pub mod id;
+pub mod lang;
+pub mod qr;
+pub mod quota;
+pub mod rate_limit;
+pub mod signing;
 pub mod store;
+pub mod tenant;
 
 pub use config::*;
 pub use html::*;
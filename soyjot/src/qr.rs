@@ -0,0 +1,31 @@
+use qrcode::render::svg;
+use qrcode::QrCode;
+
+use crate::store::error::StoreError;
+
+// Minimum width and height of rendered QR codes, in pixels
+const QR_SIZE: u32 = 200;
+
+/// qr_svg renders `data` (usually a clipboard retrieval URL) as an SVG QR code.
+pub fn qr_svg(data: &str) -> Result<String, StoreError> {
+    let code = QrCode::new(data.as_bytes())
+        .map_err(|err| StoreError::Bug(format!("failed to encode qr code: {err}")))?;
+
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(QR_SIZE, QR_SIZE)
+        .dark_color(svg::Color("#000000"))
+        .light_color(svg::Color("#ffffff"))
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_qr_svg() {
+        let svg = super::qr_svg("http://127.0.0.1:8080/app/drop/abcd").expect("qr_svg failed");
+
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg"));
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// QuotaConfig limits live clipboards per client IP, or per tenant (see `Client`).
+/// Unset limits are not enforced.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct QuotaConfig {
+    /// Maximum number of live clipboards per client
+    pub max_clipboards: Option<usize>,
+    /// Maximum total size of live clipboards per client, in bytes
+    pub max_bytes: Option<u64>,
+}
+
+/// Client is who a clipboard counts against: the IP address that posted it,
+/// or the tenant whose keyspace it was posted to (see `tenant`)
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    Tenant(Arc<str>),
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Tenant(tenant) => write!(f, "tenant {tenant}"),
+        }
+    }
+}
+
+/// Charge is the usage a single clipboard counts against its owner's quota.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Charge {
+    pub client: Client,
+    pub bytes: u64,
+}
+
+/// Usage is what a client currently has in live clipboards.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub clipboards: usize,
+    pub bytes: u64,
+}
+
+/// Quota tracks `Usage` for each client, and rejects charges beyond their `QuotaConfig` limits.
+/// Client IPs share one config, while each tenant has its own. Clients without limits
+/// are not tracked.
+pub struct Quota {
+    conf: Option<QuotaConfig>,
+    tenants: HashMap<String, QuotaConfig>,
+    usage: Mutex<HashMap<Client, Usage>>,
+}
+
+impl Quota {
+    pub fn new(conf: QuotaConfig) -> Self {
+        Self::with_tenants(Some(conf), HashMap::new())
+    }
+
+    /// with_tenants creates a quota limiting client IPs with `conf`, if any,
+    /// and the tenants in `tenants` with their own configs
+    pub fn with_tenants(conf: Option<QuotaConfig>, tenants: HashMap<String, QuotaConfig>) -> Self {
+        Self {
+            conf,
+            tenants,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limits(&self, client: &Client) -> Option<&QuotaConfig> {
+        match client {
+            Client::Ip(_) => self.conf.as_ref(),
+            Client::Tenant(tenant) => self.tenants.get(tenant.as_ref()),
+        }
+    }
+
+    /// charge adds `charge` to its client's usage. If `replacing` is a charge by
+    /// the same client that is about to be released, it does not count against the limits.
+    /// If the limits would be exceeded, usage is unchanged and the reason is returned.
+    pub fn charge(&self, charge: &Charge, replacing: Option<&Charge>) -> Result<(), String> {
+        let Some(limits) = self.limits(&charge.client) else {
+            return Ok(());
+        };
+
+        let mut usage = self.usage.lock().expect("failed to lock quota usage");
+        let mut current = usage.get(&charge.client).copied().unwrap_or_default();
+
+        if let Some(old) = replacing.filter(|old| old.client == charge.client) {
+            current.clipboards = current.clipboards.saturating_sub(1);
+            current.bytes = current.bytes.saturating_sub(old.bytes);
+        }
+
+        if let Some(max) = limits.max_clipboards {
+            if current.clipboards >= max {
+                return Err(format!(
+                    "{} already has {} live clipboards (max {max})",
+                    charge.client, current.clipboards
+                ));
+            }
+        }
+
+        if let Some(max) = limits.max_bytes {
+            if current.bytes + charge.bytes > max {
+                return Err(format!(
+                    "{} would have {} bytes in live clipboards (max {max})",
+                    charge.client,
+                    current.bytes + charge.bytes
+                ));
+            }
+        }
+
+        let usage = usage.entry(charge.client.clone()).or_default();
+        usage.clipboards += 1;
+        usage.bytes += charge.bytes;
+
+        Ok(())
+    }
+
+    /// grow adds `bytes` to an existing `charge`, e.g. when a clipboard is appended to.
+    /// Only `QuotaConfig::max_bytes` is checked, since no clipboard is added.
+    pub fn grow(&self, charge: &Charge, bytes: u64) -> Result<(), String> {
+        let Some(limits) = self.limits(&charge.client) else {
+            return Ok(());
+        };
+
+        let mut usage = self.usage.lock().expect("failed to lock quota usage");
+        let current = usage.get(&charge.client).copied().unwrap_or_default();
+
+        if let Some(max) = limits.max_bytes {
+            if current.bytes + bytes > max {
+                return Err(format!(
+                    "{} would have {} bytes in live clipboards (max {max})",
+                    charge.client,
+                    current.bytes + bytes
+                ));
+            }
+        }
+
+        usage.entry(charge.client.clone()).or_default().bytes += bytes;
+
+        Ok(())
+    }
+
+    /// shrink takes `bytes` added with `grow` back, e.g. if the append failed.
+    pub fn shrink(&self, charge: &Charge, bytes: u64) {
+        let mut usage = self.usage.lock().expect("failed to lock quota usage");
+
+        if let Some(current) = usage.get_mut(&charge.client) {
+            current.bytes = current.bytes.saturating_sub(bytes);
+
+            if *current == Usage::default() {
+                usage.remove(&charge.client);
+            }
+        }
+    }
+
+    /// release removes `charge` from its client's usage, e.g. when the clipboard expires.
+    pub fn release(&self, charge: &Charge) {
+        let mut usage = self.usage.lock().expect("failed to lock quota usage");
+
+        if let Some(current) = usage.get_mut(&charge.client) {
+            current.clipboards = current.clipboards.saturating_sub(1);
+            current.bytes = current.bytes.saturating_sub(charge.bytes);
+
+            // Bytes grown without a clipboard may be left, e.g. of uploads in progress
+            if *current == Usage::default() {
+                usage.remove(&charge.client);
+            }
+        }
+    }
+
+    pub fn usage(&self, client: &Client) -> Usage {
+        let usage = self.usage.lock().expect("failed to lock quota usage");
+        usage.get(client).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota() {
+        let quota = Quota::new(QuotaConfig {
+            max_clipboards: Some(2),
+            max_bytes: Some(100),
+        });
+
+        let ip = Client::Ip("127.0.0.1".parse().unwrap());
+        let other = Client::Ip("::1".parse().unwrap());
+        let charge = |client: &Client, bytes| Charge {
+            client: client.clone(),
+            bytes,
+        };
+        let small = charge(&ip, 10);
+
+        quota.charge(&small, None).unwrap();
+        quota.charge(&small, None).unwrap();
+        assert!(quota.charge(&small, None).is_err());
+        assert_eq!(
+            quota.usage(&ip),
+            Usage {
+                clipboards: 2,
+                bytes: 20
+            }
+        );
+
+        // Replacing a clipboard does not count against the limits
+        quota.charge(&small, Some(&small)).unwrap();
+        quota.release(&small);
+
+        // Other clients have their own quota
+        let big = charge(&other, 100);
+        quota.charge(&big, None).unwrap();
+        assert!(quota.charge(&big, None).is_err());
+
+        // With 1 clipboard left, 90 bytes are left for the first client
+        quota.release(&small);
+        assert!(quota.charge(&charge(&ip, 91), None).is_err());
+        quota.charge(&charge(&ip, 90), None).unwrap();
+
+        quota.release(&small);
+        quota.release(&charge(&ip, 90));
+        assert_eq!(quota.usage(&ip), Usage::default());
+    }
+
+    #[test]
+    fn test_tenant_quota() {
+        let tenants = HashMap::from([(
+            "team".to_string(),
+            QuotaConfig {
+                max_clipboards: Some(1),
+                max_bytes: None,
+            },
+        )]);
+        let quota = Quota::with_tenants(None, tenants);
+
+        let team = Charge {
+            client: Client::Tenant("team".into()),
+            bytes: 10,
+        };
+        quota.charge(&team, None).unwrap();
+        assert!(quota.charge(&team, None).is_err());
+
+        // Client IPs and other tenants are not limited, nor tracked
+        for client in [
+            Client::Ip("127.0.0.1".parse().unwrap()),
+            Client::Tenant("other".into()),
+        ] {
+            let charge = Charge { client, bytes: 10 };
+            quota.charge(&charge, None).unwrap();
+            quota.charge(&charge, None).unwrap();
+            assert_eq!(quota.usage(&charge.client), Usage::default());
+        }
+    }
+}
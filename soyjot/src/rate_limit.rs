@@ -0,0 +1,251 @@
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+// Number of tracked clients before full (idle) buckets are pruned.
+const PRUNE_THRESHOLD: usize = 4096;
+
+// Number of tracked clients before the least recently used buckets are evicted too,
+// down to `PRUNE_THRESHOLD`, e.g. when a client sprays requests from many addresses.
+const MAX_BUCKETS: usize = 4 * PRUNE_THRESHOLD;
+
+// Buckets are pruned at most this often, so that requests never pay for pruning one by one.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// RateLimitConfig configures the token buckets used by `RateLimiter`.
+/// Each client starts with `burst` tokens, and gets `per_sec` tokens back every second.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    pub burst: u32,
+    pub per_sec: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// RateLimiter is a token bucket rate limiter keyed on client IP address,
+/// or on the /64 prefix of IPv6 addresses (see `client_key`).
+/// Buckets are kept in a sharded map, so that clients only contend with those of the same shard.
+pub struct RateLimiter {
+    conf: ArcSwap<RateLimitConfig>,
+    buckets: DashMap<IpAddr, Bucket>,
+    /// When buckets were last pruned, see `PRUNE_INTERVAL`
+    pruned: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(conf: RateLimitConfig) -> Self {
+        Self {
+            conf: ArcSwap::from_pointee(conf),
+            buckets: DashMap::new(),
+            pruned: Mutex::new(None),
+        }
+    }
+
+    /// reconfigure replaces the limits, e.g. after the app config was reloaded.
+    /// Clients keep their current tokens, capped at the new `RateLimitConfig::burst`.
+    pub fn reconfigure(&self, conf: RateLimitConfig) {
+        self.conf.store(Arc::new(conf));
+    }
+
+    /// check takes a token from the bucket for `ip`.
+    /// If the bucket is empty, check returns `Err` with the duration
+    /// the client has to wait before its next token is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let conf = self.conf.load();
+        let burst = f64::from(conf.burst);
+
+        if self.buckets.len() >= PRUNE_THRESHOLD {
+            self.prune(now, conf.per_sec, burst);
+        }
+
+        let mut bucket = self.buckets.entry(client_key(ip)).or_insert(Bucket {
+            tokens: burst,
+            last: now,
+        });
+
+        if refill(&mut bucket, now, conf.per_sec, burst) >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if conf.per_sec <= 0.0 {
+            return Err(Duration::MAX);
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / conf.per_sec,
+        ))
+    }
+
+    /// prune removes the buckets that refilled to `burst`, which are the same as new ones,
+    /// and then the least recently used buckets beyond `MAX_BUCKETS`. Buckets are pruned
+    /// by one request at most every `PRUNE_INTERVAL`, and other requests do not wait for it.
+    fn prune(&self, now: Instant, per_sec: f64, burst: f64) {
+        {
+            let Ok(mut pruned) = self.pruned.try_lock() else {
+                return;
+            };
+
+            if pruned.is_some_and(|at| now.saturating_duration_since(at) < PRUNE_INTERVAL) {
+                return;
+            }
+            *pruned = Some(now);
+        }
+
+        self.buckets
+            .retain(|_, bucket| refill(bucket, now, per_sec, burst) < burst);
+
+        if self.buckets.len() <= MAX_BUCKETS {
+            return;
+        }
+
+        let mut used: Vec<(Instant, IpAddr)> = self
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.last, *bucket.key()))
+            .collect();
+        used.sort_unstable();
+
+        for (_, key) in &used[..used.len() - PRUNE_THRESHOLD] {
+            self.buckets.remove(key);
+        }
+    }
+}
+
+/// client_key returns the key of the bucket of `ip`. IPv6 clients are keyed by their /64 prefix,
+/// which usually holds every address of a single host or network, so that a client cannot get
+/// a new bucket for each of its addresses. IPv4-mapped addresses are keyed as IPv4.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+        },
+
+        ip => ip,
+    }
+}
+
+/// refill adds tokens accumulated since the bucket was last used, and returns the new token count.
+fn refill(bucket: &mut Bucket, now: Instant, per_sec: f64, burst: f64) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+
+    bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+    bucket.last = now;
+    bucket.tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            burst: 3,
+            per_sec: 2.0,
+        });
+
+        let foo: IpAddr = "127.0.0.1".parse().unwrap();
+        let bar: IpAddr = "::1".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(foo, now).is_ok());
+        }
+
+        let wait = limiter
+            .check_at(foo, now)
+            .expect_err("bucket should be empty");
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // Other clients have their own buckets
+        assert!(limiter.check_at(bar, now).is_ok());
+
+        // Half a second gives back 1 token
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_at(foo, later).is_ok());
+        assert!(limiter.check_at(foo, later).is_err());
+
+        // Buckets never grow beyond burst
+        let much_later = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check_at(foo, much_later).is_ok());
+        }
+        assert!(limiter.check_at(foo, much_later).is_err());
+
+        // Reconfigured limits apply to existing buckets
+        limiter.reconfigure(RateLimitConfig {
+            burst: 5,
+            per_sec: 2.0,
+        });
+
+        let reloaded = much_later + Duration::from_secs(60);
+        for _ in 0..5 {
+            assert!(limiter.check_at(foo, reloaded).is_ok());
+        }
+        assert!(limiter.check_at(foo, reloaded).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_keys() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            burst: 1,
+            per_sec: 0.0,
+        });
+        let now = Instant::now();
+
+        // Addresses of the same IPv6 /64 share their bucket
+        for (ip, allowed) in [
+            ("2001:db8::1", true),
+            ("2001:db8::2", false),
+            ("2001:db8:0:1::1", true),
+            ("::ffff:192.0.2.1", true),
+            ("192.0.2.1", false),
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(limiter.check_at(ip, now).is_ok(), allowed, "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_prune() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            burst: 2,
+            per_sec: 0.0,
+        });
+        let now = Instant::now();
+
+        // Clients spraying addresses never refill, so they are evicted by least recent use
+        for i in 0..=MAX_BUCKETS as u32 {
+            let ip = IpAddr::from(i.to_be_bytes());
+            limiter
+                .check_at(ip, now + Duration::from_micros(u64::from(i)))
+                .unwrap();
+        }
+        assert!(
+            limiter.buckets.len() > MAX_BUCKETS,
+            "pruned before the interval"
+        );
+
+        let later = now + PRUNE_INTERVAL + Duration::from_secs(1);
+        let recent = IpAddr::from((MAX_BUCKETS as u32).to_be_bytes());
+        assert!(limiter.check_at(recent, later).is_ok());
+        assert!(limiter.buckets.len() <= PRUNE_THRESHOLD + 1);
+        assert!(
+            limiter.check_at(recent, later).is_err(),
+            "recent bucket was evicted"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Signed links to clipboards, which expire independently of the clipboards themselves.
+//!
+//! A link to clipboard `hash` carries a Unix timestamp `expires` and a hex-encoded
+//! HMAC-SHA256 signature of both, keyed with `AppConfig::link_secret`, e.g.
+//! `/api/drop/{hash}?expires=1700000000&sig=...`. Links can only be signed by the server,
+//! so shared links stop working once they expire, even while the clipboard is still live.
+//!
+//! Upload tokens are signed the same way, and let clients post one clipboard within
+//! the limits of their `UploadGrant` (see `sign_upload`).
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::store::error::StoreError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// UploadGrant is what an upload token allows: posting one clipboard before `expires`,
+/// of at most `max_size` bytes, that lives at most `max_ttl` seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct UploadGrant {
+    /// Random ID of the token, so that it can be told apart from the others once it's used
+    pub id: String,
+    pub expires: u64,
+    pub max_size: Option<u64>,
+    pub max_ttl: Option<u64>,
+}
+
+impl UploadGrant {
+    /// fields returns the fields of the grant as they are written in tokens
+    fn fields(&self) -> [String; 4] {
+        let limit = |limit: Option<u64>| limit.map(|n| n.to_string()).unwrap_or_default();
+
+        [
+            self.id.clone(),
+            self.expires.to_string(),
+            limit(self.max_size),
+            limit(self.max_ttl),
+        ]
+    }
+}
+
+/// sign returns the signature of a link to clipboard `hash` expiring at `expires`
+pub fn sign(secret: &str, hash: &str, expires: u64) -> String {
+    mac(secret, &format!("{hash}\n{expires}"))
+}
+
+/// sign_upload returns the upload token of `grant`: its fields and their signature,
+/// separated by dots. Lines of the signed message never look like those of a link,
+/// so link signatures cannot be passed off as tokens.
+pub fn sign_upload(secret: &str, grant: &UploadGrant) -> String {
+    format!("{}.{}", grant.fields().join("."), upload_sig(secret, grant))
+}
+
+fn upload_sig(secret: &str, grant: &UploadGrant) -> String {
+    mac(secret, &format!("upload\n{}", grant.fields().join("\n")))
+}
+
+/// verify_upload checks the signature of upload token `token`, which must not have expired
+/// at `now`, and returns its grant
+pub fn verify_upload(secret: &str, token: &str, now: u64) -> Result<UploadGrant, StoreError> {
+    let limit = |field: &str| match field {
+        "" => Ok(None),
+        field => field.parse().map(Some),
+    };
+
+    let parts: Vec<&str> = token.split('.').collect();
+    let [id, expires, max_size, max_ttl, sig] = parts[..] else {
+        return Err(StoreError::InvalidSignature);
+    };
+
+    let (Ok(expires), Ok(max_size), Ok(max_ttl)) =
+        (expires.parse(), limit(max_size), limit(max_ttl))
+    else {
+        return Err(StoreError::InvalidSignature);
+    };
+
+    let grant = UploadGrant {
+        id: id.to_string(),
+        expires,
+        max_size,
+        max_ttl,
+    };
+
+    match (equal(&upload_sig(secret, &grant), sig), expires > now) {
+        (false, _) => Err(StoreError::InvalidSignature),
+        (true, false) => Err(StoreError::LinkExpired(expires)),
+        (true, true) => Ok(grant),
+    }
+}
+
+fn mac(secret: &str, message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(message.as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// equal compares hex-encoded signatures case-insensitively, in time independent
+/// of where they differ
+fn equal(expected: &str, sig: &str) -> bool {
+    let (expected, sig) = (expected.as_bytes(), sig.to_ascii_lowercase().into_bytes());
+
+    expected.len() == sig.len()
+        && expected
+            .iter()
+            .zip(&sig)
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// verify checks the signature `sig` of a link to clipboard `hash` expiring at `expires`,
+/// which must not have passed at `now`. Signatures are compared in time independent
+/// of where they differ.
+pub fn verify(
+    secret: &str,
+    hash: &str,
+    expires: u64,
+    sig: &str,
+    now: u64,
+) -> Result<(), StoreError> {
+    match (equal(&sign(secret, hash, expires), sig), expires > now) {
+        (false, _) => Err(StoreError::InvalidSignature),
+        (true, false) => Err(StoreError::LinkExpired(expires)),
+        (true, true) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing() {
+        let sig = sign("s3cret", "abcd", 1000);
+        assert_eq!(sig.len(), 64);
+
+        assert!(verify("s3cret", "abcd", 1000, &sig, 999).is_ok());
+        assert!(verify("s3cret", "abcd", 1000, &sig.to_uppercase(), 999).is_ok());
+        assert!(matches!(
+            verify("s3cret", "abcd", 1000, &sig, 1000),
+            Err(StoreError::LinkExpired(1000))
+        ));
+
+        // Signatures are bound to the secret, clipboard and expiry
+        for (secret, hash, expires) in [
+            ("other", "abcd", 1000),
+            ("s3cret", "abce", 1000),
+            ("s3cret", "abcd", 2000),
+        ] {
+            assert!(matches!(
+                verify(secret, hash, expires, &sig, 999),
+                Err(StoreError::InvalidSignature)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_upload_token() {
+        let grant = UploadGrant {
+            id: "f00d".to_string(),
+            expires: 1000,
+            max_size: Some(1024),
+            max_ttl: None,
+        };
+
+        let token = sign_upload("s3cret", &grant);
+        assert!(token.starts_with("f00d.1000.1024.."));
+        assert_eq!(verify_upload("s3cret", &token, 999).unwrap(), grant);
+        assert!(matches!(
+            verify_upload("s3cret", &token, 1000),
+            Err(StoreError::LinkExpired(1000))
+        ));
+
+        // Limits cannot be lifted without the secret
+        for token in [
+            sign_upload("other", &grant),
+            token.replace(".1024.", ".4096."),
+            token.replace(".1024.", ".."),
+            token.replace("f00d", "beef"),
+            "f00d.1000".to_string(),
+            format!("{token}.1"),
+        ] {
+            assert!(matches!(
+                verify_upload("s3cret", &token, 999),
+                Err(StoreError::InvalidSignature)
+            ));
+        }
+
+        // Link signatures are not tokens
+        let sig = sign("s3cret", "upload\nf00d", 1000);
+        assert!(verify_upload("s3cret", &format!("f00d.1000...{sig}"), 999).is_err());
+    }
+}
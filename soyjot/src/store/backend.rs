@@ -0,0 +1,140 @@
+//! `ClipboardStore` is the interface shared by clipboard backends: the in-memory and file
+//! `Store`, and `PgStore` with the `postgres` feature. Code that only needs to store, get
+//! and remove clipboards can be written once against it, while backend-specific features
+//! (e.g. versions, appends or subscriptions of `Store`) stay on the backends themselves.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::clipboard::Clipboard;
+use super::error::StoreError;
+use super::index::IndexEntry;
+use super::{Store, StoreOpts};
+
+pub trait ClipboardStore: Send + Sync {
+    /// store_clipboard stores `clipboard` at `hash`, expiring after `dur`.
+    /// `digest` is its full hex-encoded digest, used to detect collisions.
+    /// Backends that support owners return the owner key of new clipboards
+    /// (see `Store::store_new_clipboard`).
+    fn store_clipboard(
+        &self,
+        hash: &str,
+        digest: &str,
+        clipboard: Clipboard,
+        dur: Duration,
+        opts: StoreOpts,
+    ) -> impl Future<Output = Result<Option<String>, StoreError>> + Send;
+
+    /// get_clipboard gets live clipboard `hash`, counting the view
+    fn get_clipboard(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<Clipboard>, StoreError>> + Send;
+
+    /// remove_clipboard removes clipboard `hash` before it expires,
+    /// and reports whether there was such a clipboard.
+    fn remove_clipboard(&self, hash: &str)
+        -> impl Future<Output = Result<bool, StoreError>> + Send;
+
+    /// index lists all live clipboards
+    fn index(&self) -> impl Future<Output = Result<Vec<IndexEntry>, StoreError>> + Send;
+}
+
+/// `Store` expire timers refer back to the store, so it is used behind an `Arc`
+impl ClipboardStore for Arc<Store> {
+    async fn store_clipboard(
+        &self,
+        hash: &str,
+        digest: &str,
+        clipboard: Clipboard,
+        dur: Duration,
+        opts: StoreOpts,
+    ) -> Result<Option<String>, StoreError> {
+        let clipboard = self.place(clipboard);
+        Store::store_new_clipboard(self.clone(), hash, digest, clipboard, dur, opts).await
+    }
+
+    async fn get_clipboard(&self, hash: &str) -> Result<Option<Clipboard>, StoreError> {
+        Ok(Store::get_clipboard(self, hash).await)
+    }
+
+    async fn remove_clipboard(&self, hash: &str) -> Result<bool, StoreError> {
+        Store::remove_clipboard(self, hash).await
+    }
+
+    async fn index(&self) -> Result<Vec<IndexEntry>, StoreError> {
+        Ok(Store::index(self))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl ClipboardStore for super::postgres::PgStore {
+    async fn store_clipboard(
+        &self,
+        hash: &str,
+        digest: &str,
+        clipboard: Clipboard,
+        dur: Duration,
+        opts: StoreOpts,
+    ) -> Result<Option<String>, StoreError> {
+        self.store_new_clipboard(hash, digest, &clipboard, dur, opts)
+            .await
+            .map(|_| None)
+    }
+
+    async fn get_clipboard(&self, hash: &str) -> Result<Option<Clipboard>, StoreError> {
+        Self::get_clipboard(self, hash).await
+    }
+
+    async fn remove_clipboard(&self, hash: &str) -> Result<bool, StoreError> {
+        Self::remove_clipboard(self, hash).await
+    }
+
+    async fn index(&self) -> Result<Vec<IndexEntry>, StoreError> {
+        Self::index(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// roundtrip exercises `store` only through `ClipboardStore`
+    async fn roundtrip(store: &impl ClipboardStore) {
+        let clipboard = Clipboard::Mem("backend".into());
+        let dur = Duration::from_secs(5);
+
+        store
+            .store_clipboard("bk00", "bk00", clipboard, dur, StoreOpts::default())
+            .await
+            .unwrap();
+
+        let got = store.get_clipboard("bk00").await.unwrap().unwrap();
+        assert_eq!(got.as_ref() as &[u8], b"backend");
+        assert!(store
+            .index()
+            .await
+            .unwrap()
+            .iter()
+            .any(|entry| entry.hash == "bk00"));
+
+        assert!(store.remove_clipboard("bk00").await.unwrap());
+        assert!(!store.remove_clipboard("bk00").await.unwrap());
+        assert!(store.get_clipboard("bk00").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clipboard_store() {
+        roundtrip(&Arc::new(Store::new())).await;
+
+        #[cfg(feature = "postgres")]
+        if let Ok(url) = std::env::var("DROP_TEST_DATABASE_URL") {
+            let store = super::super::postgres::PgStore::connect(&url, Default::default())
+                .await
+                .unwrap();
+
+            roundtrip(&store).await;
+        }
+    }
+}
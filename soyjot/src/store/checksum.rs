@@ -0,0 +1,84 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::error::StoreError;
+
+/// Clipboard files have their checksum in a sidecar file named with this suffix,
+/// so that the files themselves can still be served as-is
+pub const SUFFIX: &str = ".sha256";
+
+/// sidecar returns the path of the checksum file of clipboard file `path`
+pub fn sidecar(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(SUFFIX);
+
+    PathBuf::from(name)
+}
+
+/// is_sidecar reports whether file `name` is a checksum file, not a clipboard file
+pub fn is_sidecar(name: &str) -> bool {
+    name.ends_with(SUFFIX)
+}
+
+/// Checksum computes the hex-encoded SHA-256 of the content of a clipboard,
+/// e.g. while it's read from a file in chunks
+#[derive(Default)]
+pub struct Checksum(Sha256);
+
+impl Checksum {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+/// checksum returns the hex-encoded SHA-256 of `content`
+pub fn checksum(content: &[u8]) -> String {
+    let mut sum = Checksum::default();
+    sum.update(content);
+
+    sum.finalize()
+}
+
+/// verify checks `content` against checksum `expected` read from a sidecar file.
+/// Files without a sidecar, e.g. those written before checksums, are not verified.
+pub fn verify(content: &[u8], expected: Option<&[u8]>) -> Result<(), StoreError> {
+    match expected {
+        Some(expected) if expected.trim_ascii() != checksum(content).as_bytes() => {
+            Err(StoreError::Corrupt)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let sum = checksum(b"foo");
+        assert_eq!(
+            sum,
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+
+        assert!(verify(b"foo", Some(sum.as_bytes())).is_ok());
+        assert!(verify(b"foo", None).is_ok());
+        assert!(matches!(
+            verify(b"fooo", Some(sum.as_bytes())),
+            Err(StoreError::Corrupt)
+        ));
+
+        assert_eq!(
+            sidecar(Path::new("drop/ab/cd/abcd")),
+            Path::new("drop/ab/cd/abcd.sha256")
+        );
+        assert!(is_sidecar("abcd.sha256"));
+    }
+}
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use serde::Deserialize;
 
 use super::data::Data;
@@ -5,14 +6,64 @@ use super::error::StoreError;
 
 pub const MEM: &str = "mem";
 pub const PERSIST: &str = "persist";
+pub const REDIRECT: &str = "redirect";
+
+/// Longest URL of a redirect, in bytes
+pub const URL_MAX_LEN: usize = 2048;
 
 /// Store enumerates over types of storage to use for a clipboard,
 /// with clipboard data as the value.
 #[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum Clipboard {
+    /// Clipboard kept in memory, as a string or an array of bytes
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     Mem(Data),
+    /// Clipboard persisted to file, as a string or an array of bytes
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     Persist(Data),
+    /// Clipboard kept in memory, as an HTTP URL that `/r/{id}` redirects to
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    Redirect(Url),
+}
+
+/// Url is the URL of a redirect clipboard. It is only checked to be an HTTP URL
+/// by `Clipboard::is_implemented`, so that clipboards restored from the index
+/// or a peer keep their storage.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Url(pub(crate) Data);
+
+impl Url {
+    /// as_str returns the URL, or an empty string if it is not UTF-8
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(self.0.as_ref()).unwrap_or_default()
+    }
+
+    /// check fails unless the URL is an absolute HTTP URL of at most `URL_MAX_LEN`
+    /// printable ASCII characters, so that it can be sent as a `Location` header
+    fn check(&self) -> Result<(), StoreError> {
+        let url = self.as_str();
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"));
+
+        match rest {
+            Some(rest)
+                if url.len() <= URL_MAX_LEN
+                    && !rest.is_empty()
+                    && !rest.starts_with('/')
+                    && url.bytes().all(|b| b.is_ascii_graphic()) =>
+            {
+                Ok(())
+            }
+
+            _ => Err(StoreError::InvalidRequest(format!(
+                "redirects must be to HTTP URLs of at most {URL_MAX_LEN} characters"
+            ))),
+        }
+    }
 }
 
 impl Clipboard {
@@ -20,6 +71,7 @@ impl Clipboard {
     pub fn new(t: &str) -> Self {
         match t {
             PERSIST => Self::Persist(Vec::new().into()),
+            REDIRECT => Self::Redirect(Url(Vec::new().into())),
             _ => Self::Mem(Vec::new().into()),
         }
     }
@@ -30,18 +82,32 @@ impl Clipboard {
     {
         match t {
             PERSIST => Self::Persist(data.into()),
+            REDIRECT => Self::Redirect(Url(data.into())),
             _ => Self::Mem(data.into()),
         }
     }
 
+    /// is_implemented fails for clipboards that cannot be stored, i.e. redirects
+    /// to anything but an HTTP URL
     pub fn is_implemented(&self) -> Result<(), StoreError> {
-        Ok(())
+        match self {
+            Self::Redirect(url) => url.check(),
+            _ => Ok(()),
+        }
     }
 
     pub fn key(&self) -> String {
         match self {
             Self::Mem(_) => MEM.to_string(),
             Self::Persist(_) => PERSIST.to_string(),
+            Self::Redirect(_) => REDIRECT.to_string(),
+        }
+    }
+
+    /// into_bytes returns the content, sharing the bytes instead of copying them
+    pub fn into_bytes(self) -> Bytes {
+        match self {
+            Self::Mem(data) | Self::Persist(data) | Self::Redirect(Url(data)) => data.0,
         }
     }
 }
@@ -49,10 +115,11 @@ impl Clipboard {
 impl std::ops::Deref for Clipboard {
     type Target = [u8];
 
-    fn deref(self: &Self) -> &Self::Target {
+    fn deref(&self) -> &Self::Target {
         match self {
-            Self::Mem(data) => return data.as_ref(),
-            Self::Persist(data) => return data.as_ref(),
+            Self::Mem(data) => data.as_ref(),
+            Self::Persist(data) => data.as_ref(),
+            Self::Redirect(url) => url.0.as_ref(),
         }
     }
 }
@@ -62,6 +129,7 @@ impl AsRef<Data> for Clipboard {
         match self {
             Self::Mem(data) => data,
             Self::Persist(data) => data,
+            Self::Redirect(url) => &url.0,
         }
     }
 }
@@ -69,14 +137,15 @@ impl AsRef<Data> for Clipboard {
 impl AsRef<[u8]> for Clipboard {
     fn as_ref(&self) -> &[u8] {
         match self {
-            Self::Mem(data) => return data.as_ref(),
-            Self::Persist(data) => return data.as_ref(),
+            Self::Mem(data) => data.as_ref(),
+            Self::Persist(data) => data.as_ref(),
+            Self::Redirect(url) => url.0.as_ref(),
         }
     }
 }
 
 impl std::fmt::Debug for Clipboard {
-    fn fmt(self: &Self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let bytes: &[u8] = self.as_ref();
 
         if let Ok(string) = std::str::from_utf8(bytes) {
@@ -96,11 +165,42 @@ mod tests {
         let mem_str = Clipboard::Mem("foo".into());
         assert_eq!(r#""mem":"foo""#, format!("{:?}", mem_str));
 
-        let persist_bin = Clipboard::Persist(Data(vec![14, 16, 200]));
+        let persist_bin = Clipboard::Persist(Data(vec![14, 16, 200].into()));
         assert_eq!(r#""persist":"[14, 16, 200]"#, format!("{:?}", persist_bin));
 
         // Valid UTF-8 byte array should be formatted as string
         let mem_str_vec = Clipboard::Mem("bar".into());
         assert_eq!(r#""mem":"bar""#, format!("{:?}", mem_str_vec));
     }
+
+    #[test]
+    fn test_into_bytes() {
+        let data = Data::from(vec![1, 2, 3]);
+        let clipboard = Clipboard::Mem(data.clone());
+
+        // Clones share the content, which is never copied on the way out
+        assert_eq!(clipboard.into_bytes().as_ptr(), data.0.as_ptr());
+    }
+
+    #[test]
+    fn test_redirect() {
+        use super::{Clipboard, REDIRECT, URL_MAX_LEN};
+
+        let redirect: Clipboard =
+            serde_json::from_str(r#"{"redirect": "https://example.com/a?b=c"}"#).unwrap();
+        assert!(redirect.is_implemented().is_ok());
+        assert_eq!(redirect.key(), REDIRECT);
+
+        let long = format!("https://example.com/{}", "a".repeat(URL_MAX_LEN));
+        for url in [
+            "ftp://example.com",
+            "https://",
+            "https:///path",
+            "https://a b",
+            long.as_str(),
+        ] {
+            let redirect = Clipboard::new_with_data(REDIRECT, url.to_string());
+            assert!(redirect.is_implemented().is_err(), "{url}");
+        }
+    }
 }
@@ -0,0 +1,102 @@
+//! Time source of the expiry logic of a `Store`. Stores use `SystemClock`, and tests
+//! use `MockClock` to expire clipboards by advancing time instead of sleeping.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::watch;
+
+/// Sleep is the future returned by `Clock::sleep_until`
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Clock tells the time and sleeps for the reaper of a `Store` (see `Store::with_clock`)
+pub trait Clock: Send + Sync {
+    /// now returns the current time
+    fn now(&self) -> SystemTime;
+
+    /// sleep_until returns a future that completes once `now` has reached `deadline`
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep;
+}
+
+/// SystemClock is the system's wall clock, and sleeps with Tokio timers,
+/// so that it follows Tokio's time when it's paused
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep {
+        let dur = deadline.duration_since(self.now()).unwrap_or_default();
+        Box::pin(tokio::time::sleep(dur))
+    }
+}
+
+/// MockClock only moves when it's advanced, waking the sleepers whose deadlines it reaches
+#[derive(Debug)]
+pub struct MockClock {
+    now: watch::Sender<SystemTime>,
+}
+
+impl MockClock {
+    /// new returns a clock stopped at `now`
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: watch::Sender::new(now),
+        }
+    }
+
+    /// advance moves the clock forward by `dur`
+    pub fn advance(&self, dur: Duration) {
+        self.now.send_modify(|now| *now += dur);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: SystemTime) -> Sleep {
+        let mut now = self.now.subscribe();
+
+        Box::pin(async move {
+            // A dropped clock never reaches the deadline
+            if now.wait_for(|now| *now >= deadline).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let start = SystemTime::now();
+        let clock = MockClock::new(start);
+
+        let sleep = tokio::spawn(clock.sleep_until(start + Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(59));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        sleep.await.unwrap();
+        assert_eq!(clock.now(), start + Duration::from_secs(60));
+
+        // Deadlines that already passed do not wait
+        clock.sleep_until(start).await;
+    }
+}
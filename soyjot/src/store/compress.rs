@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::StoreError;
+
+/// Default size in bytes from which clipboard files are compressed
+pub const COMPRESS_THRESHOLD: u64 = 4096;
+
+/// Compressed clipboard files start with `MAGIC` followed by a `Compression` byte.
+/// Files without it are read as-is, so files written before compression was enabled still read.
+const MAGIC: &[u8; 4] = b"\0drz";
+
+/// Length of the header of compressed clipboard files
+pub const HEADER_LEN: usize = MAGIC.len() + 1;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression is the algorithm persisted clipboards are compressed with
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Clipboard files are written as-is
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Gzip => 1,
+            Self::Zstd => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::None),
+            1 => Some(Self::Gzip),
+            2 => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressConfig {
+    pub algo: Compression,
+    /// Clipboards smaller than this many bytes are written as-is
+    pub threshold: u64,
+}
+
+impl Default for CompressConfig {
+    fn default() -> Self {
+        Self {
+            algo: Compression::None,
+            threshold: COMPRESS_THRESHOLD,
+        }
+    }
+}
+
+/// header returns the `Compression` of a clipboard file starting with `data`,
+/// or `None` if the file is not compressed.
+pub fn header(data: &[u8]) -> Option<Compression> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return None;
+    }
+
+    Compression::from_byte(data[MAGIC.len()]).filter(|algo| *algo != Compression::None)
+}
+
+/// encode returns `content` as it should be written to file: compressed with a header
+/// if `conf` enables compression and `content` reaches `CompressConfig::threshold`,
+/// or unchanged otherwise.
+pub fn encode<'a>(content: &'a [u8], conf: &CompressConfig) -> Result<Cow<'a, [u8]>, StoreError> {
+    if conf.algo == Compression::None || (content.len() as u64) < conf.threshold {
+        return Ok(Cow::Borrowed(content));
+    }
+
+    let mut data = Vec::with_capacity(HEADER_LEN + content.len() / 2);
+    data.extend_from_slice(MAGIC);
+    data.push(conf.algo.to_byte());
+    data.extend_from_slice(&compress(content, conf.algo)?);
+
+    Ok(Cow::Owned(data))
+}
+
+/// compress compresses `content` with `algo` without a header
+fn compress(content: &[u8], algo: Compression) -> Result<Vec<u8>, StoreError> {
+    match algo {
+        Compression::None => Ok(content.to_vec()),
+
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(content)?;
+
+            Ok(encoder.finish()?)
+        }
+
+        Compression::Zstd => Ok(zstd::encode_all(content, ZSTD_LEVEL)?),
+    }
+}
+
+/// decode returns the content of a clipboard file read whole into `data`,
+/// decompressing it if it has a header (see `encode`).
+pub fn decode(data: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+    let Some(algo) = header(&data) else {
+        return Ok(data);
+    };
+
+    decompress(&data[HEADER_LEN..], algo)
+}
+
+/// decompress decompresses `compressed` content without a header, compressed with `algo`
+pub fn decompress(compressed: &[u8], algo: Compression) -> Result<Vec<u8>, StoreError> {
+    let mut content = Vec::with_capacity(compressed.len() * 2);
+
+    match algo {
+        Compression::None => content.extend_from_slice(compressed),
+        Compression::Gzip => {
+            flate2::read::MultiGzDecoder::new(compressed).read_to_end(&mut content)?;
+        }
+        Compression::Zstd => {
+            zstd::stream::read::Decoder::new(compressed)?.read_to_end(&mut content)?;
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let text = "actix-drop ".repeat(1000);
+
+        for algo in [Compression::Gzip, Compression::Zstd] {
+            let conf = CompressConfig {
+                algo,
+                threshold: 100,
+            };
+
+            let data = encode(text.as_bytes(), &conf).unwrap().into_owned();
+            assert_eq!(header(&data), Some(algo));
+            assert!(data.len() < text.len());
+            assert_eq!(decode(data).unwrap(), text.as_bytes());
+        }
+
+        // Small clipboards and old uncompressed files are read as-is
+        let conf = CompressConfig {
+            algo: Compression::Zstd,
+            threshold: 100,
+        };
+        let small = encode(b"foo", &conf).unwrap();
+        assert!(matches!(small, Cow::Borrowed(b"foo")));
+        assert_eq!(header(&small), None);
+        assert_eq!(decode(text.clone().into_bytes()).unwrap(), text.as_bytes());
+    }
+}
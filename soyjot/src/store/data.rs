@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use serde::{
     de::{self, SeqAccess, Visitor},
     Deserialize, Deserializer,
@@ -5,8 +6,10 @@ use serde::{
 
 /// Data represents clipboard data as bytes.
 /// Valid strings (&str and String) can be deserialized into Data.
+/// The bytes are reference-counted, so clones share them, e.g. when a clipboard
+/// kept in memory is sent as a response body.
 #[derive(Clone, Deserialize)]
-pub struct Data(#[serde(deserialize_with = "string_or_bytes")] pub Vec<u8>);
+pub struct Data(#[serde(deserialize_with = "string_or_bytes")] pub Bytes);
 
 impl AsRef<[u8]> for Data {
     fn as_ref(&self) -> &[u8] {
@@ -14,9 +17,9 @@ impl AsRef<[u8]> for Data {
     }
 }
 
-impl<'a, T> From<T> for Data
+impl<T> From<T> for Data
 where
-    T: Into<Vec<u8>>,
+    T: Into<Bytes>,
 {
     fn from(value: T) -> Self {
         Self(value.into())
@@ -26,11 +29,11 @@ where
 impl TryInto<String> for Data {
     type Error = std::string::FromUtf8Error;
     fn try_into(self) -> Result<String, Self::Error> {
-        String::from_utf8(self.0)
+        String::from_utf8(self.0.into())
     }
 }
 
-fn string_or_bytes<'de, D>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error>
+fn string_or_bytes<'de, D>(deserializer: D) -> std::result::Result<Bytes, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -64,5 +67,7 @@ where
         }
     }
 
-    deserializer.deserialize_any(StringOrBytes(std::marker::PhantomData))
+    deserializer
+        .deserialize_any(StringOrBytes(std::marker::PhantomData))
+        .map(Bytes::from)
 }
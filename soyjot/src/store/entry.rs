@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use super::clipboard::Clipboard;
+use super::index;
+use super::owner;
+use super::version::Version;
+use crate::quota::Charge;
+
+pub(super) enum Storage {
+    Memory(Clipboard),
+    Persistent,
+}
+
+/// State tracks file IO done on a persisted entry outside of the haystack lock.
+/// In-memory entries are always `Live`.
+///
+/// ```text
+/// Live <-> Reading(n)      get_clipboard reads the file
+/// Live <-> Appending       append_clipboard appends to the file
+/// Live  -> Removing        the entry expired, and its file is being removed
+/// Live  -> Demoting -> Live an in-memory entry was evicted, and is being written to file
+/// ```
+///
+/// Besides its expire timer, an entry posted with `StoreOpts::available_at` has a release
+/// timer, and is not served until it fires (see `Entry::available_at`).
+///
+/// Entries are only replaced or removed while `Live`, so a file is never
+/// overwritten or removed while it's being read, and a new clipboard file
+/// is never written while the old one is still being removed.
+/// Files are not read while being appended to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum State {
+    Live,
+    Reading(usize),
+    Appending,
+    Demoting,
+    Removing,
+}
+
+/// Meta holds the optional properties of a new `Entry`
+#[derive(Default)]
+pub(super) struct Meta {
+    pub(super) digest: Option<String>,
+    pub(super) charge: Option<Charge>,
+    pub(super) max_views: Option<u64>,
+    pub(super) content_type: Option<String>,
+    pub(super) filename: Option<String>,
+    pub(super) lang: Option<String>,
+    pub(super) notify: Option<String>,
+    pub(super) available_at: Option<SystemTime>,
+    /// Digest of the owner key, see `Entry::owner`
+    pub(super) owner: Option<String>,
+    pub(super) encryption: Option<String>,
+    pub(super) tags: Vec<String>,
+    /// Size of the clipboard in bytes
+    pub(super) size: u64,
+    /// Version number of the clipboard, 0 is the same as 1 for new clipboards
+    pub(super) version: u64,
+    /// Previous versions of the clipboard, oldest first
+    pub(super) history: VecDeque<Version>,
+    /// When the clipboard was created, if not now, e.g. for restored clipboards
+    pub(super) created_at: Option<SystemTime>,
+    /// See `Entry::pinned`
+    pub(super) pinned: bool,
+}
+
+/// Replaced is what's kept of an entry taken out of the haystack to be replaced
+pub(super) struct Replaced {
+    pub(super) storage: Storage,
+    pub(super) digest: Option<String>,
+    pub(super) owner: Option<String>,
+    pub(super) version: u64,
+    pub(super) created_at: SystemTime,
+    pub(super) history: VecDeque<Version>,
+}
+
+pub(super) struct Entry {
+    /// Unique for every entry ever inserted into a `Store`, so that delayed
+    /// state transitions never apply to a newer entry with the same hash
+    pub(super) id: u64,
+    pub(super) state: State,
+    pub(super) storage: Storage,
+    /// Meaningless for pinned entries, which never expire
+    pub(super) expires_at: SystemTime,
+    pub(super) created_at: SystemTime,
+    /// Full hex-encoded digest of the clipboard content, used to tell hash collisions
+    /// from the same content being posted again. Unknown for files restored from disk.
+    pub(super) digest: Option<String>,
+    /// Usage counted against the owner's quota, released when the entry is removed
+    pub(super) charge: Option<Charge>,
+    /// The entry is removed once it has been read this many times
+    pub(super) max_views: Option<u64>,
+    /// Content type the clipboard was posted with, see `StoreOpts::content_type`
+    pub(super) content_type: Option<String>,
+    /// Filename the clipboard was posted with, see `StoreOpts::filename`
+    pub(super) filename: Option<String>,
+    /// Language or format of the clipboard, see `StoreOpts::lang`
+    pub(super) lang: Option<String>,
+    /// URL notified of the first read, see `StoreOpts::notify`
+    pub(super) notify: Option<String>,
+    /// Whether `notify` was taken by a read, updated without locking the entry for writing
+    pub(super) notified: AtomicBool,
+    /// The entry is not served before this time, see `StoreOpts::available_at`.
+    /// It is cleared by the release timer of the entry.
+    pub(super) available_at: Option<SystemTime>,
+    /// Digest of the key given to the clipboard's creator (see `owner::digest`),
+    /// which is kept when the clipboard is replaced. Unknown for files restored without an index.
+    pub(super) owner: Option<String>,
+    /// Encryption metadata of client-side encrypted clipboards, see `StoreOpts::encryption`
+    pub(super) encryption: Option<String>,
+    /// Tags the clipboard was posted with, see `StoreOpts::tags`
+    pub(super) tags: Vec<String>,
+    /// Number of times the entry has been read, updated without locking the entry for writing
+    pub(super) views: AtomicU64,
+    /// Timestamp of the last read as seconds since the UNIX epoch, or 0 if never read
+    pub(super) last_access: AtomicU64,
+    /// Tick of `Store::tick` when the entry was inserted or last read, for LRU eviction
+    pub(super) touched: AtomicU64,
+    /// Size of the clipboard in bytes
+    pub(super) size: u64,
+    pub(super) version: u64,
+    /// Previous versions of the clipboard, oldest first, see `StoreConfig::max_versions`
+    pub(super) history: VecDeque<Version>,
+    /// Pinned entries have no timer and no `max_views`, and are only removed explicitly
+    /// (see `StoreOpts::pin`)
+    pub(super) pinned: bool,
+}
+
+impl Entry {
+    /// new returns an entry created at `now`, which expires after `dur`
+    pub(super) fn new(
+        id: u64,
+        storage: Storage,
+        now: SystemTime,
+        dur: Duration,
+        meta: Meta,
+    ) -> Self {
+        Self {
+            id,
+            state: State::Live,
+            storage,
+            expires_at: now + dur,
+            created_at: meta.created_at.unwrap_or(now),
+            digest: meta.digest,
+            charge: meta.charge,
+            max_views: meta.max_views,
+            content_type: meta.content_type,
+            filename: meta.filename,
+            lang: meta.lang,
+            notify: meta.notify,
+            notified: AtomicBool::new(false),
+            available_at: meta.available_at,
+            owner: meta.owner,
+            encryption: meta.encryption,
+            tags: meta.tags,
+            views: AtomicU64::new(0),
+            last_access: AtomicU64::new(0),
+            touched: AtomicU64::new(0),
+            size: meta.size,
+            version: meta.version.max(1),
+            history: meta.history,
+            pinned: meta.pinned,
+        }
+    }
+
+    pub(super) fn is_persisted(&self) -> bool {
+        matches!(self.storage, Storage::Persistent)
+    }
+
+    pub(super) fn is_live(&self) -> bool {
+        self.state == State::Live
+    }
+
+    /// is_available reports whether the entry may be served at `now` (see `available_at`)
+    pub(super) fn is_available(&self, now: SystemTime) -> bool {
+        self.available_at.is_none_or(|at| at <= now)
+    }
+
+    /// owned_by reports whether `key` may replace or append to the entry.
+    /// Entries without an owner may be changed by anyone.
+    pub(super) fn owned_by(&self, key: Option<&str>) -> bool {
+        match &self.owner {
+            None => true,
+            Some(owner) => key.is_some_and(|key| owner::digest(key) == *owner),
+        }
+    }
+
+    /// mem_size returns the bytes the entry counts against `StoreConfig::max_mem_bytes`
+    pub(super) fn mem_size(&self) -> u64 {
+        match self.storage {
+            Storage::Memory(_) => self.size,
+            Storage::Persistent => 0,
+        }
+    }
+
+    /// disk_size returns the bytes the entry counts against `StoreConfig::max_disk_bytes`
+    pub(super) fn disk_size(&self) -> u64 {
+        match self.storage {
+            Storage::Memory(_) => 0,
+            Storage::Persistent => self.size,
+        }
+    }
+
+    /// appended records that `bytes` were appended to the entry's clipboard,
+    /// and returns the digest it no longer matches
+    pub(super) fn appended(&mut self, bytes: u64) -> Option<String> {
+        self.size += bytes;
+
+        if let Some(charge) = self.charge.as_mut() {
+            charge.bytes += bytes;
+        }
+
+        self.digest.take()
+    }
+
+    /// replace returns what's needed to replace the entry
+    pub(super) fn replace(self) -> Replaced {
+        let Self {
+            storage,
+            digest,
+            owner,
+            version,
+            created_at,
+            history,
+            ..
+        } = self;
+
+        Replaced {
+            storage,
+            digest,
+            owner,
+            version,
+            created_at,
+            history,
+        }
+    }
+
+    /// view counts a read of the entry, and records its time as `last_access`. view returns `None` if the entry has no views left,
+    /// or whether this was the last view allowed by `max_views`.
+    pub(super) fn view(&self) -> Option<bool> {
+        let max_views = self.max_views;
+        let views = self
+            .views
+            .fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |views| match max_views {
+                    Some(max) if views >= max => None,
+                    _ => Some(views + 1),
+                },
+            )
+            .ok()?;
+
+        let now = index::to_timestamp(SystemTime::now());
+        self.last_access.store(now, Ordering::Relaxed);
+
+        Some(max_views == Some(views + 1))
+    }
+}
+
+impl Entry {
+    /// take_notify returns the URL to notify of a read of the entry, if it has one,
+    /// for the first read that takes it only
+    pub(super) fn take_notify(&self) -> Option<String> {
+        self.notify
+            .as_ref()
+            .filter(|_| !self.notified.swap(true, Ordering::Relaxed))
+            .cloned()
+    }
+}
+
+impl From<Clipboard> for Storage {
+    fn from(clip: Clipboard) -> Self {
+        match clip {
+            clip @ (Clipboard::Mem(_) | Clipboard::Redirect(_)) => Self::Memory(clip),
+            Clipboard::Persist(_) => Self::Persistent,
+        }
+    }
+}
@@ -1,7 +1,14 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use thiserror::Error;
 
-#[derive(Error, Debug, Serialize, Deserialize)]
+/// StoreError is serialized with its variant name as `kind`, and its value as `detail`,
+/// e.g. `{"kind": "TooLarge", "detail": 1048576}`.
+/// Errors are either public, and sent to clients as-is, or private, like IO and database
+/// errors whose details could leak server internals (see `StoreError::is_public`).
+/// Private variants are never serialized.
+#[derive(Error, Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "kind", content = "detail")]
 #[error("clipboard store error")]
 pub enum StoreError {
     #[error("not implemented")]
@@ -10,25 +17,203 @@ pub enum StoreError {
     #[error("no such clipboard")]
     NoSuch,
 
+    #[error("clipboard expired at {0}")]
+    Expired(u64),
+
+    #[error("clipboard is not available until {0}")]
+    TooEarly(u64),
+
     #[error("actix-drop bug")]
     Bug(String),
 
     #[error("empty clipboard sent")]
     Empty,
 
+    #[error("hash collision with another clipboard")]
+    Conflict,
+
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("clipboard would exceed the maximum size of {0} bytes")]
+    TooLarge(u64),
+
+    #[error("bad content type {0}")]
+    InvalidContentType(String),
+
+    #[error("missing or wrong owner key")]
+    Forbidden,
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("bad encryption metadata")]
+    InvalidEncryption,
+
+    #[error("rejected by content filters: {0}")]
+    Rejected(String),
+
+    #[error("missing or bad link signature")]
+    InvalidSignature,
+
+    #[error("link expired at {0}")]
+    LinkExpired(u64),
+
+    #[error("bad search query: {0}")]
+    InvalidQuery(String),
+
+    #[error("bad request: {0}")]
+    InvalidRequest(String),
+
+    #[error("bad tag {0}")]
+    InvalidTag(String),
+
+    #[error("bad filename {0}")]
+    InvalidFilename(String),
+
+    #[error("bad ttl: {0}")]
+    InvalidTtl(String),
+
+    #[error("batches may have at most {0} clipboards")]
+    TooMany(usize),
+
+    #[error("bad archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("bad chunk: {0}")]
+    InvalidChunk(String),
+
+    #[error("bad base64: {0}")]
+    InvalidBase64(String),
+
+    #[error("chunk {0} of the upload is missing")]
+    MissingChunk(u64),
+
+    #[error("persisted clipboards would exceed the disk limit of {0} bytes")]
+    DiskFull(u64),
+
+    #[error("clipboard file failed checksum verification and was removed")]
+    Corrupt,
+
     #[serde(skip)]
     #[error("io error")]
     IoError(#[from] std::io::Error),
 
-    #[serde(skip)]
+    #[serde(serialize_with = "serialize_display")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     #[error("bad utf-8")]
     InvalidUtf8(#[from] std::string::FromUtf8Error),
+
+    #[serde(serialize_with = "serialize_display")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    #[error("bad json")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[cfg(feature = "postgres")]
+    #[serde(skip)]
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
 }
 
-// Do not send IO error to clients
+impl StoreError {
+    /// status_code returns the HTTP status code of responses failing with the error,
+    /// so that every response format sends the same code for the same error.
+    /// Requests for features the server was not set up for are bad requests.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::NoSuch => 404,
+            Self::Unauthorized(_) => 401,
+            Self::Conflict | Self::MissingChunk(_) => 409,
+            Self::TooLarge(_) | Self::TooMany(_) => 413,
+            Self::QuotaExceeded(_) => 429,
+            Self::Forbidden | Self::InvalidSignature => 403,
+            Self::Expired(_) | Self::LinkExpired(_) => 410,
+            Self::TooEarly(_) => 425,
+            Self::Rejected(_) => 422,
+            Self::DiskFull(_) => 507,
+            Self::NotImplemented(_)
+            | Self::Empty
+            | Self::InvalidContentType(_)
+            | Self::InvalidEncryption
+            | Self::InvalidQuery(_)
+            | Self::InvalidRequest(_)
+            | Self::InvalidTag(_)
+            | Self::InvalidFilename(_)
+            | Self::InvalidTtl(_)
+            | Self::InvalidArchive(_)
+            | Self::InvalidChunk(_)
+            | Self::InvalidBase64(_)
+            | Self::InvalidUtf8(_)
+            | Self::InvalidJson(_) => 400,
+            Self::Bug(_) | Self::Corrupt | Self::IoError(_) => 500,
+            #[cfg(feature = "postgres")]
+            Self::Database(_) => 500,
+        }
+    }
+
+    /// is_public reports whether the error may be sent to clients. Private errors
+    /// are sent as `StoreError::Bug` instead, and logged by the server.
+    pub fn is_public(&self) -> bool {
+        match self {
+            Self::IoError(_) => false,
+            #[cfg(feature = "postgres")]
+            Self::Database(_) => false,
+            _ => true,
+        }
+    }
+}
+
+fn serialize_display<T, S>(err: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: std::fmt::Display,
+    S: serde::Serializer,
+{
+    serializer.collect_str(err)
+}
+
+/// public_error returns `err` if it may be sent to clients (see `StoreError::is_public`)
 pub fn public_error(err: StoreError) -> Option<StoreError> {
-    match err {
-        StoreError::IoError(_) => None,
-        _ => Some(err),
+    err.is_public().then_some(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(StoreError::NoSuch.status_code(), 404);
+        assert_eq!(StoreError::Expired(1).status_code(), 410);
+        assert_eq!(StoreError::TooEarly(1).status_code(), 425);
+        assert_eq!(StoreError::Unauthorized("x".into()).status_code(), 401);
+        assert_eq!(StoreError::Conflict.status_code(), 409);
+        assert_eq!(StoreError::MissingChunk(1).status_code(), 409);
+        assert_eq!(StoreError::TooLarge(1).status_code(), 413);
+        assert_eq!(StoreError::TooMany(1).status_code(), 413);
+        assert_eq!(StoreError::QuotaExceeded("x".into()).status_code(), 429);
+        assert_eq!(StoreError::Empty.status_code(), 400);
+        assert_eq!(StoreError::InvalidRequest("x".into()).status_code(), 400);
+        assert_eq!(StoreError::InvalidArchive("x".into()).status_code(), 400);
+        assert_eq!(StoreError::Bug("x".into()).status_code(), 500);
+
+        let io = std::io::Error::other("disk on fire");
+        assert_eq!(StoreError::IoError(io).status_code(), 500);
+    }
+
+    #[test]
+    fn test_public_error() {
+        let io = StoreError::IoError(std::io::Error::other("/secret/path"));
+        assert!(!io.is_public());
+        assert!(public_error(io).is_none());
+
+        let err = public_error(StoreError::Expired(1700000000)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({ "kind": "Expired", "detail": 1700000000 })
+        );
+        assert_eq!(
+            serde_json::to_value(StoreError::NoSuch).unwrap(),
+            serde_json::json!({ "kind": "NoSuch" })
+        );
     }
 }
@@ -0,0 +1,47 @@
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use super::index;
+
+/// EventKind is what happened to a clipboard
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// The clipboard was stored
+    Created,
+    /// The timer of the clipboard was extended by its owner posting the same content again
+    /// (see `Store::extend_duplicate`)
+    Extended,
+    /// The clipboard was read
+    Fetched,
+    /// The clipboard became available at its `StoreOpts::available_at` time
+    Available,
+    /// The clipboard timed out, or was removed after its last view or by `Store::remove_clipboard`
+    Expired,
+}
+
+/// Event is sent to `Store::events` subscribers whenever a clipboard changes
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct Event {
+    pub event: EventKind,
+    pub hash: String,
+    /// Unix timestamp in seconds
+    pub at: u64,
+    /// URL the creator of the clipboard asked to be notified at of its first read,
+    /// on the first `EventKind::Fetched` event only (see `StoreOpts::notify`).
+    /// It is left out of the event sent to webhooks.
+    #[serde(skip)]
+    pub notify: Option<String>,
+}
+
+impl Event {
+    pub fn new(event: EventKind, hash: &str) -> Self {
+        Self {
+            event,
+            hash: hash.to_owned(),
+            at: index::to_timestamp(SystemTime::now()),
+            notify: None,
+        }
+    }
+}
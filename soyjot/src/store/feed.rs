@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use super::index::to_timestamp;
+
+/// Number of recent public clipboards kept in the feed, older ones are dropped
+pub const FEED_LEN: usize = 50;
+
+/// Maximum number of characters in the snippet of a public clipboard
+pub const SNIPPET_LEN: usize = 80;
+
+/// PublicDrop describes a clipboard posted with `StoreOpts::public`, as listed by `Store::public_drops`.
+#[derive(Serialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PublicDrop {
+    pub hash: String,
+    /// Beginning of the clipboard text, `None` for binary or encrypted clipboards
+    pub snippet: Option<String>,
+    /// Creation timestamp as seconds since the UNIX epoch
+    pub created_at: u64,
+    /// Seconds since the clipboard was posted
+    pub age: u64,
+}
+
+/// Recent is a public clipboard in the feed, which only lists it
+/// as long as entry `id` is the one live at `hash`
+struct Recent {
+    id: u64,
+    hash: String,
+    snippet: Option<String>,
+    created_at: SystemTime,
+}
+
+/// Feed is a ring buffer of the last `FEED_LEN` public clipboards, newest last
+#[derive(Default)]
+pub(super) struct Feed {
+    recent: Mutex<VecDeque<Recent>>,
+}
+
+impl Feed {
+    /// push adds entry `id` at `hash` to the feed, replacing older posts of the same hash
+    pub(super) fn push(&self, id: u64, hash: &str, snippet: Option<String>) {
+        let mut recent = self.recent.lock().expect("feed lock poisoned");
+
+        recent.retain(|r| r.hash != hash);
+        if recent.len() == FEED_LEN {
+            recent.pop_front();
+        }
+
+        recent.push_back(Recent {
+            id,
+            hash: hash.to_owned(),
+            snippet,
+            created_at: SystemTime::now(),
+        });
+    }
+
+    /// list returns the clipboards in the feed for which `live(hash, id)` holds, newest first
+    pub(super) fn list(&self, live: impl Fn(&str, u64) -> bool) -> Vec<PublicDrop> {
+        let recent = self.recent.lock().expect("feed lock poisoned");
+        let now = SystemTime::now();
+
+        recent
+            .iter()
+            .rev()
+            .filter(|r| live(&r.hash, r.id))
+            .map(|r| PublicDrop {
+                hash: r.hash.clone(),
+                snippet: r.snippet.clone(),
+                created_at: to_timestamp(r.created_at),
+                age: now
+                    .duration_since(r.created_at)
+                    .map(|age| age.as_secs())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+/// snippet returns up to `SNIPPET_LEN` characters from the beginning of `content`,
+/// with whitespace collapsed, or `None` if it's not text
+pub(super) fn snippet(content: &[u8]) -> Option<String> {
+    // A character is at most 4 bytes long
+    let prefix = &content[..content.len().min(SNIPPET_LEN * 4)];
+
+    let text = match std::str::from_utf8(prefix) {
+        Ok(text) => text,
+        // The prefix may end in the middle of a character
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&prefix[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+
+    if text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        return None;
+    }
+
+    let snippet = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SNIPPET_LEN)
+        .collect();
+
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed() {
+        let feed = Feed::default();
+        for id in 0..FEED_LEN as u64 + 2 {
+            feed.push(id, &format!("h{id}"), None);
+        }
+
+        let drops = feed.list(|_, _| true);
+        assert_eq!(drops.len(), FEED_LEN);
+        assert_eq!(drops[0].hash, format!("h{}", FEED_LEN + 1));
+        assert_eq!(drops.last().unwrap().hash, "h2");
+
+        // Re-posted clipboards move to the front, and replaced entries are not listed
+        feed.push(100, "h5", Some("again".to_string()));
+        let drops = feed.list(|hash, id| hash != "h5" || id == 100);
+        assert_eq!(drops.len(), FEED_LEN);
+        assert_eq!(drops[0].snippet.as_deref(), Some("again"));
+        assert!(feed.list(|_, id| id != 100).iter().all(|d| d.hash != "h5"));
+
+        assert_eq!(snippet(b"hello\n\n  world").as_deref(), Some("hello world"));
+        assert_eq!(snippet(&[0xff, 0xfe]), None);
+        assert_eq!(snippet(b"\x00\x01ELF"), None);
+
+        let long = "\u{e9}".repeat(SNIPPET_LEN * 3);
+        assert_eq!(
+            snippet(long.as_bytes()).unwrap().chars().count(),
+            SNIPPET_LEN
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Persisted clipboards streamed from file with `Store::open_clipboard`, instead of being
+//! read into memory first. The entry of a streamed clipboard stays in `State::Reading`
+//! until its `ClipboardFile` is dropped, e.g. when the response body is done or aborted,
+//! so that the file is not replaced, appended to or removed while it's streamed.
+
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+use super::compress::Compression;
+use super::Store;
+
+/// ClipboardFile is the open file of a persisted clipboard, read with `AsyncRead`.
+/// Compressed files are read as-is from after their header.
+pub struct ClipboardFile {
+    file: fs::File,
+    /// How the file is compressed, if it is
+    pub compression: Option<Compression>,
+    /// Length of the file in bytes
+    pub len: u64,
+    /// Hex-encoded SHA-256 of the content (see `checksum`), if the file has a sidecar
+    pub checksum: Option<String>,
+    _reading: Reading,
+}
+
+impl ClipboardFile {
+    pub(super) fn new(
+        file: fs::File,
+        compression: Option<Compression>,
+        len: u64,
+        checksum: Option<String>,
+        reading: Reading,
+    ) -> Self {
+        Self {
+            file,
+            compression,
+            len,
+            checksum,
+            _reading: reading,
+        }
+    }
+}
+
+impl AsyncRead for ClipboardFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for ClipboardFile {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
+
+/// Reading keeps entry `id` of clipboard `hash` in `State::Reading` until it's dropped
+pub(super) struct Reading {
+    pub(super) store: Arc<Store>,
+    pub(super) hash: String,
+    pub(super) id: u64,
+}
+
+impl Drop for Reading {
+    fn drop(&mut self) {
+        self.store.end_read(&self.hash, self.id);
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Filename of the clipboard index inside the storage directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// Persisted clipboards have their `IndexEntry` in a sidecar file named with this suffix,
+/// see `persist::Persist::write_meta`
+pub const META_SUFFIX: &str = ".meta.json";
+
+/// IndexEntry describes a live clipboard in `Store`, and is written to `INDEX_FILE`
+/// on shutdown so that the store can be rebuilt on the next startup.
+/// Persisted clipboards also keep theirs next to their file while they are live,
+/// so that they can be restored as they were even without an index, e.g. after a crash.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub hash: String,
+    /// Storage kind, one of `clipboard::MEM`, `clipboard::PERSIST` or `clipboard::REDIRECT`
+    pub storage: String,
+    /// Expiry timestamp as seconds since the UNIX epoch, 0 if the clipboard is pinned
+    pub expires_at: u64,
+    /// Creation timestamp as seconds since the UNIX epoch, if known
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    /// Size of the clipboard content in bytes
+    #[serde(default)]
+    pub size: u64,
+    /// Full hex-encoded digest of the clipboard content, if known
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub max_views: Option<u64>,
+    /// Content type the clipboard was posted with, if any
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Filename the clipboard is downloaded as, if it was posted with one
+    #[serde(default)]
+    pub filename: Option<String>,
+    /// Language or format of the clipboard, posted with it or guessed (see `lang::detect`)
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// URL notified when the clipboard is first read, until it is (see `StoreOpts::notify`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<String>,
+    /// Timestamp the clipboard is not served before, as seconds since the UNIX epoch
+    /// (see `StoreOpts::available_at`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_at: Option<u64>,
+    /// Digest of the clipboard's owner key (see `owner::digest`), never the key itself
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Encryption metadata, if the clipboard was encrypted by the client
+    #[serde(default)]
+    pub encryption: Option<String>,
+    /// Tags the clipboard was posted with
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Number of times the clipboard has been read
+    #[serde(default)]
+    pub views: u64,
+    /// Timestamp of the last read as seconds since the UNIX epoch, if the clipboard was read
+    #[serde(default)]
+    pub last_access: Option<u64>,
+    /// Pinned clipboards never expire, see `StoreOpts::pin`
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl IndexEntry {
+    /// remaining returns the time left before the entry expires,
+    /// or `None` if it has already expired. Pinned entries have no time left,
+    /// so callers must check `IndexEntry::pinned` first.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(SystemTime::now())
+    }
+
+    /// remaining_at is like `remaining`, at time `now`
+    pub fn remaining_at(&self, now: SystemTime) -> Option<Duration> {
+        let deadline = UNIX_EPOCH + Duration::from_secs(self.expires_at);
+
+        deadline
+            .duration_since(now)
+            .ok()
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+pub fn to_timestamp(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// to_rfc3339 formats `timestamp`, as seconds since the UNIX epoch, as an RFC 3339
+/// date and time in UTC, e.g. `2024-02-29T12:30:00Z`
+pub fn to_rfc3339(timestamp: u64) -> String {
+    let (days, secs) = (timestamp / 86400, timestamp % 86400);
+
+    // Civil date from days since the epoch, counted in 400-year eras from 0000-03-01
+    // (see http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rfc3339() {
+        assert_eq!(to_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(to_rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(to_rfc3339(1709209800), "2024-02-29T12:30:00Z");
+        assert_eq!(to_rfc3339(1735689599), "2024-12-31T23:59:59Z");
+        assert_eq!(to_rfc3339(4107542400), "2100-03-01T00:00:00Z");
+    }
+}
@@ -1,313 +1,3752 @@
+pub mod backend;
+pub mod checksum;
 pub mod clipboard;
+pub mod clock;
+pub mod compress;
 pub mod data;
+mod entry;
 pub mod error;
+pub mod event;
+pub mod feed;
+pub mod file;
+pub mod index;
+pub mod owner;
 pub mod persist;
 pub mod persist_async;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod reaper;
+pub mod search;
+mod tombstone;
+pub mod version;
 
-use tokio::sync::oneshot;
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use futures_util::stream::{self, StreamExt};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Notify};
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use clipboard::Clipboard;
+use clock::{Clock, SystemClock};
+use compress::CompressConfig;
+use entry::{Entry, Meta, Replaced, State, Storage};
 use error::StoreError;
+use event::{Event, EventKind};
+use feed::{Feed, PublicDrop};
+use file::{ClipboardFile, Reading};
+use index::IndexEntry;
+use search::SearchHit;
+use tombstone::Tombstones;
+use version::{Version, VersionInfo};
 
-enum Storage {
-    Memory(Clipboard),
-    Persistent,
+use crate::lang;
+use crate::quota::{Charge, Client, Quota, QuotaConfig};
+use crate::tenant;
+
+/// Default maximum size of clipboards grown with `Store::append_clipboard`, in bytes
+pub const APPEND_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Shortest prefix of a clipboard key, not counting its tenant, that `Store::resolve_prefix`
+/// matches other keys with. Shorter prefixes only resolve whole keys, so clients can't
+/// enumerate clipboards a character at a time.
+pub const MIN_PREFIX_LEN: usize = 6;
+
+/// Number of pending notifications kept for each subscriber before older ones are dropped
+const WATCH_CAPACITY: usize = 16;
+
+/// Number of pending events kept for each `Store::events` subscriber
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Untracked clipboard files modified more recently than this may belong to clipboards
+/// still being stored, so `Store::sweep` leaves them alone
+pub const ORPHAN_GRACE: Duration = Duration::from_secs(60);
+
+/// Collision chooses what happens when a new clipboard's key is already taken
+/// by a clipboard with different content. Posting the same content again
+/// is never a collision, and simply resets the clipboard timer.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Collision {
+    /// The new clipboard replaces the old one
+    #[default]
+    Overwrite,
+    /// The new clipboard is rejected with `StoreError::Conflict`
+    Reject,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StoreConfig {
+    pub on_collision: Collision,
+    /// Per-client limits, enforced for clipboards posted with `StoreOpts::owner`
+    pub quota: Option<QuotaConfig>,
+    /// Limits of tenants, enforced for all clipboards in their keyspaces (see `tenant`)
+    pub tenant_quotas: HashMap<String, QuotaConfig>,
+    /// Appends that would grow a clipboard beyond this many bytes are rejected,
+    /// defaults to `APPEND_MAX_SIZE`
+    pub append_max_size: Option<u64>,
+    /// Number of previous versions kept when a clipboard is replaced with different content
+    pub max_versions: usize,
+    /// Least recently used in-memory clipboards are evicted while their total size
+    /// is over this many bytes
+    pub max_mem_bytes: Option<u64>,
+    /// Evicted in-memory clipboards are written to file instead of being removed
+    pub evict_to_disk: bool,
+    /// In-memory clipboards larger than this many bytes are persisted instead, see `Store::place`
+    pub persist_threshold: Option<u64>,
+    /// New persisted clipboards, and appends to them, are rejected with `StoreError::DiskFull`
+    /// if the total size of persisted clipboards would go over this many bytes
+    pub max_disk_bytes: Option<u64>,
+    /// How persisted clipboards are compressed on disk
+    pub compress: CompressConfig,
+    /// Clipboards whose content is already live under any key in the same keyspace
+    /// are not stored again, and extend the timer of the live clipboard if they are posted
+    /// with its owner key, see `Store::extend_duplicate`
+    pub dedupe: bool,
+    /// `Store::resolve_prefix` only resolves whole keys, e.g. with random keys,
+    /// which are only as private as they are unguessable
+    pub exact_keys: bool,
+    /// Lifetimes of clipboards by storage class
+    pub retention: Retention,
 }
 
-struct Entry {
-    storage: Storage,
-    abort_tx: oneshot::Sender<()>,
+/// Retention sets the default and maximum lifetimes of in-memory and persisted clipboards.
+/// Unset defaults fall back to the lifetime clipboards are stored with,
+/// and unset maximums are not enforced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Retention {
+    pub mem_ttl: Option<Duration>,
+    pub persist_ttl: Option<Duration>,
+    pub max_mem_ttl: Option<Duration>,
+    pub max_persist_ttl: Option<Duration>,
 }
 
-impl Entry {
-    fn is_persisted(&self) -> bool {
-        matches!(self.storage, Storage::Persistent)
+impl Retention {
+    /// ttl returns the lifetime of a clipboard that is `persisted` or in memory:
+    /// the `requested` lifetime, or else the default of its storage class, or else `dur`,
+    /// capped at the maximum of its storage class
+    pub fn ttl(&self, persisted: bool, requested: Option<Duration>, dur: Duration) -> Duration {
+        let (default, max) = match persisted {
+            true => (self.persist_ttl, self.max_persist_ttl),
+            false => (self.mem_ttl, self.max_mem_ttl),
+        };
+
+        let ttl = requested.or(default).unwrap_or(dur);
+        max.map_or(ttl, |max| ttl.min(max))
     }
 }
 
+/// StoreOpts are per-clipboard options for `Store::store_new_clipboard`
+#[derive(Clone, Debug, Default)]
+pub struct StoreOpts {
+    /// Replace a colliding clipboard even if `StoreConfig::on_collision` is `Collision::Reject`
+    pub force: bool,
+    /// Client that posted the clipboard, whose quota the clipboard counts against
+    pub owner: Option<IpAddr>,
+    /// Remove the clipboard once it has been read this many times
+    pub max_views: Option<u64>,
+    /// Content type the clipboard is sent with, e.g. `image/png`
+    pub content_type: Option<String>,
+    /// Filename the clipboard is downloaded as, instead of its key
+    pub filename: Option<String>,
+    /// Language or format of the clipboard for the HTML UI, e.g. `rust` or `json`.
+    /// `store_new_clipboard` guesses it from the content if unset (see `lang::detect`).
+    pub lang: Option<String>,
+    /// URL POSTed a `EventKind::Fetched` event when the clipboard is first read,
+    /// which the webhook subscriber of `Store::events` sends
+    pub notify: Option<String>,
+    /// Owner key returned when the clipboard was first stored,
+    /// required to replace a clipboard that has an owner
+    pub owner_key: Option<String>,
+    /// Opaque metadata (e.g. the cipher and IV) of a clipboard that was encrypted
+    /// by the client, which the store keeps without ever seeing the key
+    pub encryption: Option<String>,
+    /// List the clipboard in the public feed (see `Store::public_drops`).
+    /// Clipboards of tenants are never listed.
+    pub public: bool,
+    /// Tags to find the clipboard by, see `Store::drops`
+    pub tags: Vec<String>,
+    /// Lifetime requested for the clipboard, instead of the default of its storage class
+    /// (see `Retention`)
+    pub ttl: Option<Duration>,
+    /// Keep the clipboard until it's removed with `Store::remove_clipboard`, ignoring its
+    /// lifetime and `max_views`. Pinned clipboards are always persisted, so that they
+    /// survive restarts.
+    pub pin: bool,
+    /// Reject clipboards larger than this many bytes, e.g. those posted with an upload token
+    pub max_size: Option<u64>,
+    /// Hold the clipboard until this time, as seconds since the UNIX epoch, e.g. for an
+    /// embargoed release. Until then, it's stored but not served, and lookups fail with
+    /// `StoreError::TooEarly`. It must be before the clipboard expires.
+    pub available_at: Option<u64>,
+    /// Set for clipboards replicated from a peer instance. Replicas replace the clipboard
+    /// regardless of its owner, and keep the owner they have on the peer.
+    pub replica: Option<Replica>,
+}
+
+/// Replica is what a clipboard replicated from a peer instance keeps from the peer
+#[derive(Clone, Debug, Default)]
+pub struct Replica {
+    /// Digest of the owner key (see `IndexEntry::owner`), so that the owner can remove
+    /// the clipboard from any instance
+    pub owner: Option<String>,
+    /// Creation timestamp as seconds since the UNIX epoch
+    pub created_at: Option<u64>,
+}
+
+/// Resolved is what a prefix of clipboard keys resolves to, see `Store::resolve_prefix`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolved {
+    /// The key of the only clipboard matching the prefix
+    Unique(String),
+    /// The prefix matches several clipboards, whose keys are not disclosed.
+    /// Each candidate is listed by the length of its shortest unique prefix, in ascending order.
+    Ambiguous(Vec<usize>),
+    /// No clipboard matches the prefix
+    None,
+}
+
 /// Store is used to store in-memory actix-drop clipboard
 pub struct Store {
     /// If a clipboard is `Clipboard::Mem`, its hash gets inserted as map key with value `Some(_)`
     /// If a clipboard is `Clipboard::Persist`, its hash gets inserted as map key with value `None`
-    /// The one-shot sender is for aborting the timeout timer
-    haystack: Mutex<HashMap<String, Entry>>,
+    /// The map is sharded, so concurrent access to different clipboards does not contend
+    /// on a single lock. Shard guards must never be held across `.await` points.
+    haystack: DashMap<String, Entry>,
+    /// Notified whenever an entry leaves `State::Reading`, `State::Appending` or `State::Removing`
+    settled: Notify,
+    next_id: AtomicU64,
+    /// Subscribers waiting for clipboards to be re-posted (see `Store::subscribe`)
+    watchers: DashMap<String, broadcast::Sender<()>>,
+    /// Lifecycle events of all clipboards (see `Store::events`)
+    events: broadcast::Sender<Event>,
+    quota: Option<Quota>,
+    /// Total size of in-memory clipboards, see `StoreConfig::max_mem_bytes`
+    mem_bytes: AtomicU64,
+    /// Total size of persisted clipboards, see `StoreConfig::max_disk_bytes`
+    disk: persist::DiskUsage,
+    /// Where persisted clipboards are kept, see `Store::with_persist`
+    files: Arc<dyn persist::Persist>,
+    /// Incremented on every insert and read, so that entries can be ordered by last use
+    clock: AtomicU64,
+    /// Time source of expiry timers and lifetimes, see `Store::with_clock`
+    time: Arc<dyn Clock>,
+    /// Replaced on config reloads, see `Store::reconfigure`
+    conf: ArcSwap<StoreConfig>,
+    /// Expiry timers of all entries, see `reaper`
+    timers: reaper::Timers,
+    /// Recent clipboards posted with `StoreOpts::public`
+    feed: Feed,
+    /// Recently expired clipboards, see `Store::not_found`
+    tombstones: Tombstones,
+    /// Keys of the clipboards with each tag, see `StoreOpts::tags`
+    tags: DashMap<String, HashSet<String>>,
+    /// Key of the clipboard with each digest, by `digest_key`, see `StoreConfig::dedupe`
+    digests: DashMap<String, String>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Store {
     pub fn new() -> Self {
+        Self::with_config(StoreConfig::default())
+    }
+
+    /// with_config returns a store that keeps persisted clipboards in the default
+    /// storage directory `persist::DIR`
+    pub fn with_config(conf: StoreConfig) -> Self {
+        Self::with_persist(conf, Arc::new(persist::DiskFs::default()))
+    }
+
+    /// with_persist returns a store that keeps persisted clipboards in `files`,
+    /// e.g. a `persist::DiskFs` in the configured directory, or `persist::InMemoryFs` in tests.
+    /// Servers stream clipboard files straight from `Store::dir` if there is one.
+    pub fn with_persist(conf: StoreConfig, files: Arc<dyn persist::Persist>) -> Self {
+        Self::with_clock(conf, files, Arc::new(SystemClock))
+    }
+
+    /// with_clock is like `with_persist`, with clipboards expiring on `clock` instead of
+    /// the system's clock, e.g. a `clock::MockClock` in tests
+    pub fn with_clock(
+        conf: StoreConfig,
+        files: Arc<dyn persist::Persist>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         Self {
-            haystack: Mutex::new(HashMap::new()),
+            haystack: DashMap::new(),
+            settled: Notify::new(),
+            next_id: AtomicU64::new(0),
+            watchers: DashMap::new(),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            quota: match (&conf.quota, conf.tenant_quotas.is_empty()) {
+                (None, true) => None,
+                (quota, _) => Some(Quota::with_tenants(
+                    quota.clone(),
+                    conf.tenant_quotas.clone(),
+                )),
+            },
+            mem_bytes: AtomicU64::new(0),
+            disk: persist::DiskUsage::default(),
+            files,
+            clock: AtomicU64::new(0),
+            time: clock,
+            conf: ArcSwap::from_pointee(conf),
+            timers: reaper::Timers::new(),
+            feed: Feed::default(),
+            tombstones: Tombstones::default(),
+            tags: DashMap::new(),
+            digests: DashMap::new(),
+        }
+    }
+
+    /// dir returns the storage directory of persisted clipboards,
+    /// or `None` if they are not kept on disk (see `persist::Persist::dir`)
+    pub fn dir(&self) -> Option<&Path> {
+        self.files.dir()
+    }
+
+    /// reconfigure replaces the store's config, e.g. after the app config was reloaded.
+    /// The new config applies to clipboards stored or appended to from now on,
+    /// except for `StoreConfig::quota` and `StoreConfig::tenant_quotas`,
+    /// which are fixed when the store is created.
+    pub fn reconfigure(&self, conf: StoreConfig) {
+        self.conf.store(Arc::new(conf));
+    }
+
+    /// place returns `clipboard` as it should be stored: `Clipboard::Mem` clipboards larger
+    /// than `StoreConfig::persist_threshold` become `Clipboard::Persist`,
+    /// so that large pastes never sit in memory.
+    pub fn place(&self, clipboard: Clipboard) -> Clipboard {
+        let threshold = self.conf.load().persist_threshold;
+
+        match clipboard {
+            Clipboard::Mem(data) if threshold.is_some_and(|max| data.0.len() as u64 > max) => {
+                Clipboard::Persist(data)
+            }
+
+            clipboard => clipboard,
         }
     }
 
     /// store_new_clipboard stores new clipboard in Store.
-    /// With each clipboard, a timer task will be dispatched
-    /// to the background to expire it (see `async fn expire_timer`).
+    /// Each clipboard gets a timer in the store's reaper task, which expires it.
     /// If a new clipboard comes in with identical 4-byte hash,
-    /// the previous clipboard timer thread is forced to return,
-    /// and a the new clipboard with its own timer takes its place.
-    pub fn store_new_clipboard(
+    /// the previous clipboard's timer is stopped,
+    /// and the new clipboard with its own timer takes its place.
+    /// Persisted clipboards are written with `tokio::fs`, and the haystack
+    /// is never locked while the file is being written.
+    /// `digest` is the full hex-encoded digest of the clipboard, and is used to detect
+    /// hash collisions, which are then handled according to `StoreConfig::on_collision`
+    /// unless `StoreOpts::force` is set.
+    /// New clipboards get an owner key, which store_new_clipboard returns. Clipboards with
+    /// an owner can only be replaced, or have their timer reset, with `StoreOpts::owner_key`.
+    pub async fn store_new_clipboard(
         store: Arc<Self>,
         hash: &str,
+        digest: &str,
         clipboard: Clipboard,
         dur: Duration,
-    ) -> Result<(), StoreError> {
-        // Drop the old timer for the hash key
-        if let Some(entry) = store.remove_entry(&hash) {
-            // Recevier might have been dropped
-            if let Err(_) = entry.abort_tx.send(()) {
-                eprintln!("store_new_clipboard: failed to remove old timer for {hash}");
-            }
+        opts: StoreOpts,
+    ) -> Result<Option<String>, StoreError> {
+        let clipboard = match clipboard {
+            Clipboard::Mem(data) if opts.pin => Clipboard::Persist(data),
+            clipboard => clipboard,
+        };
+
+        let size = clipboard.len() as u64;
+        check_size(size, &opts)?;
+
+        let persisted = matches!(clipboard, Clipboard::Persist(_));
+        if persisted {
+            store.check_disk(hash, size)?;
         }
 
-        let to_save = match clipboard.clone() {
+        let dur = store.ttl(persisted, &opts, dur);
+        let available_at = release_at(&opts, store.time.now(), dur)?;
+
+        let charge = store.charge(hash, opts.owner, size)?;
+        // Encrypted clipboards are listed without their ciphertext
+        let snippet = match (opts.public, &opts.encryption) {
+            (true, None) => feed::snippet(&clipboard),
+            _ => None,
+        };
+        let lang = match (opts.lang.clone(), &opts.encryption) {
+            (None, None) => lang::detect(clipboard.as_ref()).map(str::to_owned),
+            (lang, _) => lang,
+        };
+        let old = match store.take_entry(hash, digest, &opts).await {
+            Ok(old) => old,
+            Err(err) => {
+                store.release(charge.as_ref());
+                return Err(err);
+            }
+        };
+
+        let old_persisted = old
+            .as_ref()
+            .is_some_and(|old| matches!(old.storage, Storage::Persistent));
+        let (owner, owner_key) = claim(old.as_ref(), opts.replica.as_ref());
+        let (version, history) = store.next_version(hash, digest, old).await;
+
+        let saved = match clipboard {
             // Clipboard::Mem(data) => data will have to live in haystack
-            clip @ Clipboard::Mem(_) => Storage::Memory(clip),
+            clip @ (Clipboard::Mem(_) | Clipboard::Redirect(_)) => {
+                // The old clipboard file would otherwise be left dangling
+                if old_persisted {
+                    store
+                        .files
+                        .remove(hash)
+                        .await
+                        .map(|_| Storage::Memory(clip))
+                } else {
+                    Ok(Storage::Memory(clip))
+                }
+            }
 
             // Clipboard::Persist(data) => data does not have to live in haystack
             Clipboard::Persist(data) => {
-                persist::write_clipboard_file(hash, data.as_ref())?;
-                Storage::Persistent
+                let compress = store.conf.load().compress.clone();
+
+                store
+                    .files
+                    .write(hash, data.as_ref(), &compress)
+                    .await
+                    .map(|_| Storage::Persistent)
             }
         };
 
-        // Store will remember tx_abort to abort the timer in expire_timer.
-        let (tx_abort, rx_abort) = oneshot::channel();
-        tokio::task::spawn(cleanup(
-            store.clone(),
-            hash.to_owned(),
-            dur.clone(),
-            rx_abort,
-        ));
+        let to_save = match saved {
+            Ok(to_save) => to_save,
+            Err(err) => {
+                store.release(charge.as_ref());
+                return Err(err);
+            }
+        };
 
-        store
+        let meta = Meta {
+            digest: Some(digest.to_owned()),
+            charge,
+            max_views: opts.max_views.filter(|_| !opts.pin),
+            content_type: opts.content_type,
+            filename: opts.filename,
+            lang,
+            notify: opts.notify,
+            available_at,
+            owner,
+            encryption: opts.encryption,
+            tags: opts.tags,
+            size,
+            version,
+            history,
+            created_at: created_at(opts.replica.as_ref()),
+            pinned: opts.pin,
+        };
+
+        let id = Self::insert_entry(store.clone(), hash, to_save, dur, meta);
+        if opts.public {
+            store.list_public(hash, id, snippet);
+        }
+
+        store.save_meta(hash).await;
+        store.publish(hash);
+        store.emit(EventKind::Created, hash);
+        store.evict().await;
+
+        Ok(owner_key)
+    }
+
+    /// extend_duplicate looks for a live clipboard with content `digest` in the keyspace of `hash`
+    /// (see `tenant`), which may be stored under another key, e.g. one restored with a different
+    /// `hash_len`. If there is one and `StoreConfig::dedupe` is set, its key is returned,
+    /// so that the same content is never stored twice. If `owner_key` is the owner key of
+    /// the live clipboard, or it has no owner, its timer is also extended to the lifetime
+    /// a new clipboard of its storage class would get with `dur` and the `requested` lifetime
+    /// (see `Retention::ttl`) unless it's already due later, with an `EventKind::Extended` event.
+    /// Others posting the same content cannot keep it alive. The live clipboard keeps
+    /// its options and owner.
+    pub fn extend_duplicate(
+        store: &Arc<Self>,
+        hash: &str,
+        digest: &str,
+        dur: Duration,
+        requested: Option<Duration>,
+        owner_key: Option<&str>,
+    ) -> Option<String> {
+        if !store.conf.load().dedupe {
+            return None;
+        }
+
+        let key = store.digests.get(&digest_key(hash, digest))?.clone();
+        let mut entry = store
             .haystack
-            .lock()
-            .expect("failed to lock haystack")
-            .insert(
-                hash.to_owned(),
-                Entry {
-                    storage: to_save,
-                    abort_tx: tx_abort,
-                },
-            );
+            .get_mut(&key)
+            .filter(|entry| entry.is_live() && entry.digest.as_deref() == Some(digest))?;
+
+        let persisted = matches!(entry.storage, Storage::Persistent);
+        let dur = store.conf.load().retention.ttl(persisted, requested, dur);
+        let expires_at = store.time.now() + dur;
+        // Held clipboards are not disclosed, nor released early, to those posting the same content
+        if !entry.is_available(store.time.now()) {
+            return None;
+        }
+
+        let extends = !entry.pinned && expires_at > entry.expires_at && entry.owned_by(owner_key);
+        let extended = extends.then(|| {
+            entry.expires_at = expires_at;
+            entry.id
+        });
+
+        drop(entry);
+        // Starting the timer again replaces its deadline
+        if let Some(id) = extended {
+            store.timers.start(store, &key, id, expires_at);
+
+            // The new expiry is saved in the background, since extend_duplicate is not async
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let (store, key) = (store.clone(), key.clone());
+                runtime.spawn(async move { store.save_meta(&key).await });
+            }
+
+            store.emit(EventKind::Extended, &key);
+        }
+
+        Some(key)
+    }
+
+    /// check_upload checks that `bytes` written so far of an upload stored with `opts` can
+    /// still be stored, so that uploads written to file as they arrive are aborted as soon as
+    /// they grow over `StoreOpts::max_size` or `StoreConfig::max_disk_bytes`,
+    /// rather than once they were written whole. `store_tmp_clipboard` checks them again.
+    pub fn check_upload(&self, bytes: u64, opts: &StoreOpts) -> Result<(), StoreError> {
+        check_size(bytes, opts)?;
+
+        match self.conf.load().max_disk_bytes {
+            Some(max) if !self.disk.fits(bytes, 0, Some(max)) => Err(StoreError::DiskFull(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// reserve_tmp charges `bytes` more of temporary files written for `client`, e.g. chunks
+    /// of an upload that is not a clipboard yet, to `StoreConfig::max_disk_bytes` and to
+    /// the quota of `client`, so that uploads in progress cannot fill the disk.
+    /// Reserved bytes are given back with `release_tmp`.
+    pub fn reserve_tmp(&self, client: Option<IpAddr>, bytes: u64) -> Result<(), StoreError> {
+        let max_disk = self.conf.load().max_disk_bytes;
+        if !self.disk.fits(bytes, 0, max_disk) {
+            return Err(StoreError::DiskFull(max_disk.unwrap_or_default()));
+        }
+
+        self.grow(tmp_charge(client).as_ref(), bytes)?;
+        self.disk.add(bytes);
 
         Ok(())
     }
 
-    pub async fn store_new_clipboard_async(
+    /// release_tmp gives back `bytes` reserved for `client` with `reserve_tmp`
+    pub fn release_tmp(&self, client: Option<IpAddr>, bytes: u64) {
+        self.disk.sub(bytes);
+
+        if let (Some(quota), Some(charge)) = (&self.quota, tmp_charge(client)) {
+            quota.shrink(&charge, bytes);
+        }
+    }
+
+    /// store_tmp_clipboard moves a clipboard file that was already written by the caller
+    /// (e.g. streamed in chunks, see `persist_async::create_tmp_file`) into place,
+    /// and starts its expire timer just like `store_new_clipboard`, returning the same owner key.
+    /// `size` is the length of the file in bytes. The file is moved as-is,
+    /// so streamed clipboards are never compressed.
+    /// If the clipboard is rejected, the temporary file is removed.
+    pub async fn store_tmp_clipboard(
         store: Arc<Self>,
         hash: &str,
-        clipboard: Clipboard,
+        digest: &str,
+        tmp: PathBuf,
+        size: u64,
         dur: Duration,
-    ) -> Result<(), StoreError> {
-        // Drop the old timer for the hash key
-        if let Some(entry) = store.remove_entry(&hash) {
-            // Recevier might have been dropped
-            if let Err(_) = entry.abort_tx.send(()) {
-                eprintln!("store_new_clipboard: failed to remove old timer for {hash}");
-            }
-        }
+        opts: StoreOpts,
+    ) -> Result<Option<String>, StoreError> {
+        let dur = store.ttl(true, &opts, dur);
+        let taken = match check_size(size, &opts)
+            .and_then(|_| release_at(&opts, store.time.now(), dur))
+            .and_then(|available_at| store.check_disk(hash, size).map(|_| available_at))
+            .and_then(|available_at| {
+                let charge = store.charge(hash, opts.owner, size)?;
+                Ok((available_at, charge))
+            }) {
+            Ok((available_at, charge)) => store
+                .take_entry(hash, digest, &opts)
+                .await
+                .inspect_err(|_| store.release(charge.as_ref()))
+                .map(|old| (available_at, charge, old)),
 
-        let to_save = match clipboard.clone() {
-            // Clipboard::Mem(data) => data will have to live in haystack
-            clip @ Clipboard::Mem(_) => Storage::Memory(clip),
+            Err(err) => Err(err),
+        };
 
-            // Clipboard::Persist(data) => data does not have to live in haystack
-            Clipboard::Persist(data) => {
-                persist_async::write_clipboard_file(hash, data.as_ref()).await?;
-                Storage::Persistent
+        let (available_at, charge, old) = match taken {
+            Ok(taken) => taken,
+            Err(err) => {
+                persist_async::rm_tmp_file(tmp).await?;
+                return Err(err);
             }
         };
 
-        // Store will remember tx_abort to abort the timer in expire_timer.
-        let (tx_abort, rx_abort) = oneshot::channel();
-        tokio::task::spawn(cleanup(
-            store.clone(),
-            hash.to_owned(),
-            dur.clone(),
-            rx_abort,
-        ));
+        // The old file is read into history before it's replaced
+        let (owner, owner_key) = claim(old.as_ref(), opts.replica.as_ref());
+        let (version, history) = store.next_version(hash, digest, old).await;
 
-        store
-            .haystack
-            .lock()
-            .expect("failed to lock haystack")
-            .insert(
-                hash.to_owned(),
-                Entry {
-                    storage: to_save,
-                    abort_tx: tx_abort,
-                },
-            );
+        if let Err(err) = store.files.adopt(tmp, hash).await {
+            store.release(charge.as_ref());
+            return Err(err);
+        }
 
-        Ok(())
+        let meta = Meta {
+            digest: Some(digest.to_owned()),
+            charge,
+            max_views: opts.max_views.filter(|_| !opts.pin),
+            content_type: opts.content_type,
+            filename: opts.filename,
+            lang: opts.lang,
+            notify: opts.notify,
+            available_at,
+            owner,
+            encryption: opts.encryption,
+            tags: opts.tags,
+            size,
+            version,
+            history,
+            created_at: created_at(opts.replica.as_ref()),
+            pinned: opts.pin,
+        };
+
+        let id = Self::insert_entry(store.clone(), hash, Storage::Persistent, dur, meta);
+        if opts.public {
+            store.list_public(hash, id, None);
+        }
+
+        store.save_meta(hash).await;
+        store.publish(hash);
+        store.emit(EventKind::Created, hash);
+
+        Ok(owner_key)
     }
 
     /// get_clipboard gets a clipboard whose entry key matches `hash`.
-    /// Calling get_clipboard does not move the value out of haystack
-    pub fn get_clipboard(&self, hash: &str) -> Option<Clipboard> {
-        let mut haystack = self.haystack.lock().expect("failed to lock haystack");
+    /// Calling get_clipboard does not move the value out of haystack.
+    /// The haystack lock is released before persisted clipboards are read from file,
+    /// and the entry stays in `State::Reading` until the read is done.
+    /// Clipboards with `StoreOpts::max_views` are removed after their last view.
+    /// Clipboards held with `StoreOpts::available_at` are not found until they are available.
+    pub async fn get_clipboard(&self, hash: &str) -> Option<Clipboard> {
+        {
+            let now = self.time.now();
+            let entry = self
+                .haystack
+                .get(hash)
+                .filter(|entry| entry.is_available(now))?;
 
-        match haystack.get(hash) {
-            None => None,
+            if let Storage::Memory(clipboard) = &entry.storage {
+                let last = entry.view()?;
+                entry.touched.store(self.tick(), Ordering::Relaxed);
+                let (clipboard, id, notify) = (clipboard.to_owned(), entry.id, entry.take_notify());
 
-            Some(entry) => match &entry.storage {
-                Storage::Persistent => match persist::read_clipboard_file(hash) {
-                    Err(err) => {
-                        eprintln!("error reading file {hash}: {}", err.to_string());
+                drop(entry);
+                self.emit_fetched(hash, notify);
+                if last {
+                    self.remove_entry(hash, id);
+                    self.expired(hash);
+                }
 
-                        // Clear dangling persisted clipboard from haystack
-                        haystack.remove(hash);
-                        None
+                return Some(clipboard);
+            }
+        }
+
+        let (id, last, result) = self.read_file(hash, true).await?;
+
+        match result {
+            Err(err) => {
+                eprintln!("error reading file {hash}: {err}");
+
+                // Clear dangling persisted clipboard from haystack
+                let removed = self
+                    .haystack
+                    .remove_if(hash, |_, entry| entry.id == id && entry.is_live());
+
+                if let Some((_, entry)) = removed {
+                    self.forget(hash, &entry);
+                }
+
+                None
+            }
+
+            Ok(data) => {
+                let notify = self
+                    .haystack
+                    .get(hash)
+                    .and_then(|entry| entry.take_notify());
+                self.emit_fetched(hash, notify);
+                match last {
+                    true => {
+                        if let Err(err) = self.expire(hash, id).await {
+                            eprintln!("error removing viewed clipboard {hash}: {err}");
+                        }
                     }
 
-                    Ok(data) => Some(Clipboard::Persist(data.into())),
-                },
+                    // Views are kept, so that view limits still hold after a crash
+                    false => self.save_meta(hash).await,
+                }
 
-                Storage::Memory(clipboard) => Some(clipboard.to_owned()),
-            },
+                Some(Clipboard::Persist(data.into()))
+            }
         }
     }
 
-    fn remove_entry(&self, hash: &str) -> Option<Entry> {
+    /// peek_clipboard gets clipboard `hash` like `get_clipboard`, but without counting a view,
+    /// e.g. to excerpt it. Held clipboards are not found either.
+    pub async fn peek_clipboard(&self, hash: &str) -> Option<Clipboard> {
+        let now = self.time.now();
         self.haystack
-            .lock()
-            .expect("failed to lock haystack")
-            .remove(&hash.to_owned())
+            .get(hash)
+            .filter(|entry| entry.is_available(now))?;
+
+        self.export_clipboard(hash).await
     }
-}
 
-/// Spawns async task with timer to remove clipboard once it expires.
-///
-/// cleanup waits on 2 futures:
-/// 1. the timer
-/// 2. the abort signal
-/// If the timer finishes first, expire_timer removes the entry from `Store.haystack`.
-/// If the abort signal comes first, expire_timer simply returns `Ok(())`.
-async fn cleanup(
-    store: Arc<Store>,
-    hash: String,
-    dur: Duration,
-    abort: oneshot::Receiver<()>,
-) -> Result<(), StoreError> {
-    tokio::select! {
-        // Set a timer to remove clipboard once it expires
-        _ = tokio::time::sleep(dur) => {
-            if let Some((_, entry)) = store.haystack
-                    .lock()
-                    .expect("failed to lock haystack")
-                    .remove_entry(&hash)
-            {
-                if entry.is_persisted() {
-                    persist::rm_clipboard_file(hash)?;
-                }
+    /// export_clipboard is like `peek_clipboard`, but also gets held clipboards,
+    /// e.g. to copy them elsewhere along with `Store::export_meta`
+    pub async fn export_clipboard(&self, hash: &str) -> Option<Clipboard> {
+        let entry = self
+            .haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing)?;
+
+        if let Storage::Memory(clipboard) = &entry.storage {
+            return Some(clipboard.to_owned());
+        }
+        drop(entry);
+
+        match self.read_file(hash, false).await? {
+            (_, _, Ok(data)) => Some(Clipboard::Persist(data.into())),
+            (_, _, Err(err)) => {
+                eprintln!("error reading file {hash}: {err}");
+                None
             }
         }
+    }
+
+    /// open_clipboard opens the file of persisted clipboard `hash` to be streamed, instead of
+    /// reading it into memory like `get_clipboard`. Opening the file counts as a read just
+    /// the same: it counts a view, notifies the creator of the first read, and the file is
+    /// verified against its checksum first (see `persist_async::verify_clipboard_file`).
+    /// The entry stays in `State::Reading` until the returned file is dropped, and clipboards
+    /// viewed for the last time are removed once it is.
+    /// open_clipboard returns `None` if there's no such persisted clipboard on disk,
+    /// e.g. for in-memory clipboards, which are read with `get_clipboard` instead.
+    pub async fn open_clipboard(
+        self: &Arc<Self>,
+        hash: &str,
+    ) -> Option<Result<ClipboardFile, StoreError>> {
+        let dir = self.files.dir()?.to_path_buf();
+        let now = self.time.now();
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.is_persisted() && entry.is_available(now))?;
+
+        let (id, last) = self.begin_read(hash, true).await?;
+        let reading = Reading {
+            store: self.clone(),
+            hash: hash.to_owned(),
+            id,
+        };
+
+        let opened = async {
+            let checksum = persist_async::verify_clipboard_file(&dir, hash).await?;
+            let (compression, file) = persist_async::open_compressed_file(&dir, hash).await?;
+            let len = file.metadata().await?.len();
+
+            Ok::<_, StoreError>(ClipboardFile::new(
+                file,
+                compression,
+                len,
+                checksum,
+                reading,
+            ))
+        }
+        .await;
+
+        let file = match opened {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("error opening file {hash}: {err}");
+
+                // Clear dangling persisted clipboard from haystack, once it's no longer read
+                let removed = self
+                    .haystack
+                    .remove_if(hash, |_, entry| entry.id == id && entry.is_live());
+
+                if let Some((_, entry)) = removed {
+                    self.forget(hash, &entry);
+                }
+
+                return Some(Err(err));
+            }
+        };
+
+        let notify = self
+            .haystack
+            .get(hash)
+            .and_then(|entry| entry.take_notify());
+        self.emit_fetched(hash, notify);
+        match last {
+            // The clipboard is removed once it's been streamed, see `expire`
+            true => {
+                let (store, hash) = (self.clone(), hash.to_owned());
+                tokio::spawn(async move {
+                    if let Err(err) = store.expire(&hash, id).await {
+                        eprintln!("error removing viewed clipboard {hash}: {err}");
+                    }
+                });
+            }
 
-        // If we get cancellation signal, return from this function
-        _ = abort => {
-            println!("expire_timer: timer for {hash} extended for {dur:?}");
+            false => self.save_meta(hash).await,
         }
+
+        Some(Ok(file))
     }
 
-    Ok(())
-}
+    /// read_file reads the file of persisted clipboard `hash`, keeping its entry
+    /// in `State::Reading` so that the file is not replaced or removed meanwhile.
+    /// It returns the id of the entry, and with `view`, counts the read as a view
+    /// and reports whether it was the last one.
+    async fn read_file(
+        &self,
+        hash: &str,
+        view: bool,
+    ) -> Option<(u64, bool, Result<Vec<u8>, StoreError>)> {
+        let (id, last) = self.begin_read(hash, view).await?;
+        let result = self.files.read(hash).await;
+        self.end_read(hash, id);
 
-impl From<(Storage, oneshot::Sender<()>)> for Entry {
-    fn from(value: (Storage, oneshot::Sender<()>)) -> Self {
-        Self {
-            storage: value.0,
-            abort_tx: value.1,
+        Some((id, last, result))
+    }
+
+    /// begin_read puts the entry of persisted clipboard `hash` in `State::Reading`, once its file
+    /// is fully written, and returns its id. With `view`, it counts the read as a view
+    /// and reports whether it was the last one. Every read must be ended with `end_read`.
+    async fn begin_read(&self, hash: &str, view: bool) -> Option<(u64, bool)> {
+        loop {
+            let settled = self.settled.notified();
+
+            {
+                let mut entry = self
+                    .haystack
+                    .get_mut(hash)
+                    .filter(|entry| entry.is_persisted())?;
+                let state = match entry.state {
+                    State::Live => Some(State::Reading(1)),
+                    State::Reading(n) => Some(State::Reading(n + 1)),
+                    // Wait for the file to be fully written
+                    State::Appending | State::Demoting => None,
+                    State::Removing => return None,
+                };
+
+                if let Some(state) = state {
+                    let last = match view {
+                        true => entry.view()?,
+                        false => false,
+                    };
+                    entry.state = state;
+
+                    return Some((entry.id, last));
+                }
+            }
+
+            settled.await;
         }
     }
-}
 
-impl From<Clipboard> for Storage {
-    fn from(clip: Clipboard) -> Self {
-        match clip {
-            clip @ Clipboard::Mem(_) => Self::Memory(clip),
-            Clipboard::Persist(_) => Self::Persistent,
+    /// end_read ends a read of entry `id` of clipboard `hash` started with `begin_read`
+    fn end_read(&self, hash: &str, id: u64) {
+        if let Some(mut entry) = self.haystack.get_mut(hash).filter(|entry| entry.id == id) {
+            if let State::Reading(n) = entry.state {
+                entry.state = if n > 1 {
+                    State::Reading(n - 1)
+                } else {
+                    State::Live
+                };
+            }
         }
+
+        self.settled.notify_waiters();
     }
-}
 
-#[cfg(test)]
-#[allow(dead_code)] // Bad tests - actix/tokio runtime conflict, will come back later
-mod tests {
-    use super::*;
+    /// append_clipboard appends `data` to the end of clipboard `hash`, keeping its key and timer.
+    /// Clipboards may not grow beyond `StoreConfig::append_max_size`, and the appended bytes
+    /// count against the owner's quota. Persisted clipboards stay in `State::Appending`
+    /// while the file is appended to.
+    /// Appended clipboards no longer match their digest, so they never collide with new clipboards.
+    /// Clipboards with an owner can only be appended to with their owner key `key`.
+    pub async fn append_clipboard(
+        &self,
+        hash: &str,
+        data: &[u8],
+        key: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let max_size = self.conf.load().append_max_size.unwrap_or(APPEND_MAX_SIZE);
+        let bytes = data.len() as u64;
 
-    #[test]
-    fn test_store_get() {
-        // We should be able to get multiple times
-        let foo = "foo";
-        let clip = Clipboard::Mem("eiei".into());
-        let (tx, _) = oneshot::channel();
-        let entry = Entry {
-            storage: clip.into(),
-            abort_tx: tx,
+        let (id, size, charge) = loop {
+            let settled = self.settled.notified();
+
+            {
+                let mut entry = self
+                    .haystack
+                    .get_mut(hash)
+                    .filter(|entry| entry.state != State::Removing)
+                    .ok_or(StoreError::NoSuch)?;
+
+                if !entry.owned_by(key) {
+                    return Err(StoreError::Forbidden);
+                }
+
+                if entry.is_live() {
+                    let charge = entry.charge.clone();
+
+                    if let Storage::Memory(clipboard) = &mut entry.storage {
+                        if let Clipboard::Redirect(_) = clipboard {
+                            let err = "redirects cannot be appended to".to_string();
+                            return Err(StoreError::InvalidRequest(err));
+                        }
+
+                        if clipboard.len() as u64 + bytes > max_size {
+                            return Err(StoreError::TooLarge(max_size));
+                        }
+
+                        self.grow(charge.as_ref(), bytes)?;
+                        match clipboard {
+                            Clipboard::Mem(old)
+                            | Clipboard::Persist(old)
+                            | Clipboard::Redirect(clipboard::Url(old)) => {
+                                // Bytes that are not shared keep their allocation
+                                let mut content = Vec::from(std::mem::take(&mut old.0));
+                                content.extend_from_slice(data);
+                                old.0 = content.into();
+                            }
+                        }
+
+                        let digest = entry.appended(bytes);
+                        drop(entry);
+                        self.unindex_digest(hash, digest.as_deref());
+                        self.mem_bytes.fetch_add(bytes, Ordering::Relaxed);
+                        self.publish(hash);
+                        self.evict().await;
+
+                        return Ok(());
+                    }
+
+                    entry.state = State::Appending;
+                    break (entry.id, entry.size, charge);
+                }
+            }
+
+            settled.await;
         };
 
-        let store = Store::new();
-        store
+        let result = self
+            .append_file(hash, data, size, max_size, charge.as_ref())
+            .await;
+
+        let appended = self
             .haystack
-            .lock()
-            .expect("failed to lock haystack")
-            .insert(foo.to_owned(), entry);
+            .get_mut(hash)
+            .filter(|entry| entry.id == id)
+            .and_then(|mut entry| {
+                entry.state = State::Live;
+                result.as_ref().ok().and_then(|_| entry.appended(bytes))
+            });
+
+        self.unindex_digest(hash, appended.as_deref());
+
+        self.settled.notify_waiters();
+
+        if result.is_ok() {
+            self.save_meta(hash).await;
+            self.publish(hash);
+        }
 
-        assert!(store.get_clipboard(foo).is_some());
-        assert!(store.get_clipboard(foo).is_some());
-        assert!(store.get_clipboard(foo).is_some());
+        result
     }
 
-    #[tokio::test]
-    async fn test_store_expire() {
-        let store = Arc::new(Store::new());
-        let key = "keyfoo";
-        let dur100 = Duration::from_millis(100);
-        let dur200 = Duration::from_millis(200);
-        let dur300 = Duration::from_millis(300);
+    /// remove_clipboard removes clipboard `hash` before it expires, e.g. on an admin's request,
+    /// and reports whether there was such a clipboard.
+    pub async fn remove_clipboard(&self, hash: &str) -> Result<bool, StoreError> {
+        let Some(id) = self
+            .haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing)
+            .map(|entry| entry.id)
+        else {
+            return Ok(false);
+        };
 
-        // Store and launch the expire timer
-        Store::store_new_clipboard(store.clone(), key, Clipboard::Mem("foo".into()), dur300)
-            .expect("failed to store new clipboard");
+        let result = self.expire(hash, id).await;
+        self.tombstones.exhume(hash);
+        result?;
+
+        Ok(true)
+    }
 
-        // Sleep for less than exp time => should have some after thread wake up
-        tokio::spawn(tokio::time::sleep(dur100)).await.unwrap();
-        assert!(store.get_clipboard(key).is_some());
+    /// delete_clipboard removes clipboard `hash` on the request of its owner, who must present
+    /// the owner key `key`. Clipboards without an owner can only be removed with `remove_clipboard`.
+    pub async fn delete_clipboard(&self, hash: &str, key: &str) -> Result<(), StoreError> {
+        let id = {
+            let entry = self
+                .haystack
+                .get(hash)
+                .filter(|entry| entry.state != State::Removing)
+                .ok_or(StoreError::NoSuch)?;
 
-        // Clipboard with `key` should have been expired
-        tokio::spawn(tokio::time::sleep(dur200)).await.unwrap();
-        assert!(store.get_clipboard(key).is_none());
+            if entry.owner.is_none() || !entry.owned_by(Some(key)) {
+                return Err(StoreError::Forbidden);
+            }
+
+            entry.id
+        };
+
+        // Removed clipboards did not expire, so they are not found at all
+        let result = self.expire(hash, id).await;
+        self.tombstones.exhume(hash);
+
+        result
     }
 
-    #[tokio::test]
-    async fn test_reset_timer() {
-        let hash = "keyfoo";
-        let store = Arc::new(Store::new());
+    /// versions lists the versions of clipboard `hash`, oldest first and ending with the current one.
+    /// Held clipboards have none until they're available, like `meta`.
+    pub fn versions(&self, hash: &str) -> Option<Vec<VersionInfo>> {
+        let now = self.time.now();
+        let entry = self
+            .haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing && entry.is_available(now))?;
 
-        let clipboard = Clipboard::Mem(vec![1u8, 2, 3].into());
-        let dur200 = Duration::from_millis(200);
-        let dur400 = Duration::from_millis(400);
+        let mut versions: Vec<_> = entry
+            .history
+            .iter()
+            .map(|version| VersionInfo {
+                version: version.number,
+                size: version.clipboard.len() as u64,
+                created_at: index::to_timestamp(version.created_at),
+                current: false,
+            })
+            .collect();
+
+        versions.push(VersionInfo {
+            version: entry.version,
+            size: entry.size,
+            created_at: index::to_timestamp(entry.created_at),
+            current: true,
+        });
 
-        Store::store_new_clipboard(store.clone(), hash, clipboard.clone(), dur400)
-            .expect("failed to store to Store");
+        Some(versions)
+    }
 
-        tokio::spawn(tokio::time::sleep(dur200)).await.unwrap();
+    /// get_version gets version `version` of clipboard `hash` (see `Store::versions`).
+    /// Getting the current version is the same as `get_clipboard`.
+    pub async fn get_version(&self, hash: &str, version: u64) -> Option<Clipboard> {
+        {
+            let now = self.time.now();
+            let entry = self
+                .haystack
+                .get(hash)
+                .filter(|entry| entry.state != State::Removing && entry.is_available(now))?;
 
-        Store::store_new_clipboard(store.clone(), hash, clipboard, dur400)
-            .expect("failed to re-write to Store");
+            if entry.version != version {
+                return entry
+                    .history
+                    .iter()
+                    .find(|old| old.number == version)
+                    .map(|old| old.clipboard.clone());
+            }
+        }
+
+        self.get_clipboard(hash).await
+    }
+
+    /// resolve_prefix finds the live clipboard whose key starts with `frag`.
+    /// A key equal to `frag` is always a unique match, even if longer keys also start with it.
+    /// Only keys in the keyspace of `frag` match, so global prefixes never resolve to clipboards
+    /// of tenants. Fragments shorter than `MIN_PREFIX_LEN`, or any fragment with
+    /// `StoreConfig::exact_keys`, only match the key equal to them.
+    pub fn resolve_prefix(&self, frag: &str) -> Resolved {
+        if self.conf.load().exact_keys || tenant::split(frag).1.chars().count() < MIN_PREFIX_LEN {
+            return match self.haystack.get(frag) {
+                Some(entry) if entry.state != State::Removing => Resolved::Unique(frag.to_owned()),
+                _ => Resolved::None,
+            };
+        }
 
-        tokio::spawn(tokio::time::sleep(dur200)).await.unwrap();
+        let mut candidates: Vec<String> = self
+            .haystack
+            .iter()
+            .filter(|entry| entry.state != State::Removing && entry.key().starts_with(frag))
+            .filter(|entry| tenant::same_keyspace(entry.key(), frag))
+            .map(|entry| entry.key().to_owned())
+            .collect();
+
+        if candidates.iter().any(|key| key == frag) {
+            return Resolved::Unique(frag.to_owned());
+        }
+
+        match candidates.len() {
+            0 => Resolved::None,
+            1 => Resolved::Unique(candidates.remove(0)),
+            _ => {
+                candidates.sort();
+                let mut lens = unique_prefix_lens(&candidates);
+                lens.sort();
+
+                Resolved::Ambiguous(lens)
+            }
+        }
+    }
 
-        assert!(store.get_clipboard(hash).is_some());
+    /// shortest_prefix returns the shortest prefix of `hash` that no other live clipboard key
+    /// starts with, e.g. for short URLs resolved by `Store::resolve_prefix`.
+    /// Later clipboards may share the prefix, so it's only unique when returned.
+    /// Prefixes of tenant clipboards always include their tenant, and are at least
+    /// `MIN_PREFIX_LEN` characters longer. With `StoreConfig::exact_keys`, it's the whole key.
+    pub fn shortest_prefix(&self, hash: &str) -> String {
+        if self.conf.load().exact_keys {
+            return hash.to_owned();
+        }
+
+        let tenant_len = hash.chars().count() - tenant::split(hash).1.chars().count();
+        let common = self
+            .haystack
+            .iter()
+            .filter(|entry| entry.state != State::Removing && entry.key() != hash)
+            .filter(|entry| tenant::same_keyspace(entry.key(), hash))
+            .map(|entry| {
+                entry
+                    .key()
+                    .chars()
+                    .zip(hash.chars())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .max()
+            .unwrap_or_default();
+
+        hash.chars()
+            .take((common + 1).max(tenant_len + MIN_PREFIX_LEN))
+            .collect()
+    }
+
+    /// is_persisted reports whether the clipboard `hash` is persisted to file,
+    /// or `None` if there's no such clipboard.
+    pub fn is_persisted(&self, hash: &str) -> Option<bool> {
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing)
+            .map(|entry| entry.is_persisted())
+    }
+
+    /// content_type returns the content type clipboard `hash` was posted with, if any
+    pub fn content_type(&self, hash: &str) -> Option<String> {
+        self.haystack
+            .get(hash)
+            .and_then(|entry| entry.content_type.clone())
+    }
+
+    /// expires_at returns when clipboard `hash` expires, as seconds since the UNIX epoch,
+    /// or `None` if there's no such clipboard or it's pinned
+    pub fn expires_at(&self, hash: &str) -> Option<u64> {
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing && !entry.pinned)
+            .map(|entry| index::to_timestamp(entry.expires_at))
+    }
+
+    /// available_at returns when clipboard `hash` becomes available, as seconds since
+    /// the UNIX epoch, or `None` if there's no such clipboard or it's already available
+    /// (see `StoreOpts::available_at`)
+    pub fn available_at(&self, hash: &str) -> Option<u64> {
+        let now = self.time.now();
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing && !entry.is_available(now))
+            .and_then(|entry| entry.available_at)
+            .map(index::to_timestamp)
+    }
+
+    /// not_found returns the error for a lookup of clipboard `hash` that found nothing:
+    /// `StoreError::TooEarly` if it's held until later, `StoreError::Expired` if it's one of
+    /// the last `tombstone::TOMBSTONES_LEN` clipboards to expire, and `StoreError::NoSuch` otherwise
+    pub fn not_found(&self, hash: &str) -> StoreError {
+        if let Some(available_at) = self.available_at(hash) {
+            return StoreError::TooEarly(available_at);
+        }
+
+        match self.tombstones.expired_at(hash) {
+            Some(expired_at) => StoreError::Expired(expired_at),
+            None => StoreError::NoSuch,
+        }
+    }
+
+    /// lang returns the language or format of clipboard `hash`, if known (see `StoreOpts::lang`)
+    pub fn lang(&self, hash: &str) -> Option<String> {
+        self.haystack.get(hash).and_then(|entry| entry.lang.clone())
+    }
+
+    /// filename returns the filename clipboard `hash` was posted with, if any
+    pub fn filename(&self, hash: &str) -> Option<String> {
+        self.haystack
+            .get(hash)
+            .and_then(|entry| entry.filename.clone())
+    }
+
+    /// is_view_limited reports whether the clipboard `hash` has `StoreOpts::max_views` set,
+    /// in which case it must be read with `get_clipboard` so that its views are counted.
+    pub fn is_view_limited(&self, hash: &str) -> bool {
+        self.haystack
+            .get(hash)
+            .is_some_and(|entry| entry.max_views.is_some())
+    }
+
+    /// index lists all live clipboards in the store with their expiry timestamps
+    /// and access statistics.
+    pub fn index(&self) -> Vec<IndexEntry> {
+        self.haystack
+            .iter()
+            .filter(|entry| entry.state != State::Removing)
+            .map(|entry| index_entry(entry.key(), &entry))
+            .collect()
+    }
+
+    /// meta returns the `IndexEntry` of clipboard `hash` without reading it,
+    /// so it does not count as a view. Held clipboards have none until they're available.
+    pub fn meta(&self, hash: &str) -> Option<IndexEntry> {
+        let now = self.time.now();
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing && entry.is_available(now))
+            .map(|entry| index_entry(hash, &entry))
+    }
+
+    /// export_meta is like `meta`, but also of held clipboards, like `Store::index`
+    pub fn export_meta(&self, hash: &str) -> Option<IndexEntry> {
+        self.haystack
+            .get(hash)
+            .filter(|entry| entry.state != State::Removing)
+            .map(|entry| index_entry(hash, &entry))
+    }
+
+    /// find_digest returns the key of the live clipboard outside of tenants
+    /// with full hex-encoded digest `digest`, if any. Held clipboards are not found
+    /// until they're available, like `meta`.
+    pub fn find_digest(&self, digest: &str) -> Option<String> {
+        let now = self.time.now();
+        let key = self.digests.get(digest)?.clone();
+        self.haystack.get(&key).filter(|entry| {
+            entry.is_live() && entry.is_available(now) && entry.digest.as_deref() == Some(digest)
+        })?;
+
+        Some(key)
+    }
+
+    /// public_drops lists the live clipboards among the last `feed::FEED_LEN` clipboards
+    /// posted with `StoreOpts::public`, newest first. Clipboards replaced by a post
+    /// that was not public are no longer listed, and held clipboards are not listed yet.
+    pub fn public_drops(&self) -> Vec<PublicDrop> {
+        let now = self.time.now();
+        self.feed.list(|hash, id| {
+            self.haystack.get(hash).is_some_and(|entry| {
+                entry.id == id && entry.state != State::Removing && entry.is_available(now)
+            })
+        })
+    }
+
+    /// search finds the live clipboards whose content matches `pattern` (see `search::pattern`),
+    /// sorted by hash. If `owner` is given, only clipboards whose owner key has that digest
+    /// are searched, and persisted clipboards are only read from file with `files`.
+    /// Clipboards of tenants, encrypted clipboards and held clipboards are never searched.
+    /// Searching does not count as a view. At most `search::CONCURRENCY` clipboards
+    /// are scanned at a time, on blocking threads so that large clipboards do not stall the runtime.
+    pub async fn search(
+        &self,
+        pattern: &Regex,
+        owner: Option<&str>,
+        files: bool,
+    ) -> Vec<SearchHit> {
+        let now = self.time.now();
+        let candidates: Vec<_> = self
+            .haystack
+            .iter()
+            .filter(|entry| entry.state != State::Removing && entry.encryption.is_none())
+            .filter(|entry| entry.is_available(now))
+            .filter(|entry| tenant::split(entry.key()).0.is_none())
+            .filter(|entry| owner.is_none_or(|owner| entry.owner.as_deref() == Some(owner)))
+            .filter_map(|entry| match &entry.storage {
+                Storage::Memory(clipboard) => Some((entry.key().clone(), Some(clipboard.clone()))),
+                Storage::Persistent if files => Some((entry.key().clone(), None)),
+                Storage::Persistent => None,
+            })
+            .collect();
+
+        let mut hits: Vec<SearchHit> = stream::iter(candidates)
+            .map(|(hash, clipboard)| async move {
+                let content = match clipboard {
+                    Some(clipboard) => clipboard.into_bytes(),
+                    None => match self.read_file(&hash, false).await? {
+                        (_, _, Ok(data)) => data.into(),
+                        (_, _, Err(err)) => {
+                            eprintln!("error reading file {hash} for search: {err}");
+                            return None;
+                        }
+                    },
+                };
+
+                let pattern = pattern.clone();
+                let offsets = tokio::task::spawn_blocking(move || search::scan(&pattern, &content))
+                    .await
+                    .ok()?;
+
+                (!offsets.is_empty()).then_some(SearchHit { hash, offsets })
+            })
+            .buffer_unordered(search::CONCURRENCY)
+            .filter_map(|hit| async move { hit })
+            .collect()
+            .await;
+
+        hits.sort_by(|a, b| a.hash.cmp(&b.hash));
+        hits
+    }
+
+    /// drops lists the live clipboards with `tag`, or all of them, sorted by hash.
+    /// If `owner` is given, only clipboards whose owner key has that digest are listed.
+    /// Clipboards of tenants are never listed.
+    pub fn drops(&self, tag: Option<&str>, owner: Option<&str>) -> Vec<IndexEntry> {
+        let hashes: Vec<String> = match tag {
+            Some(tag) => self
+                .tags
+                .get(tag)
+                .map(|hashes| hashes.iter().cloned().collect())
+                .unwrap_or_default(),
+            None => self
+                .haystack
+                .iter()
+                .map(|entry| entry.key().clone())
+                .collect(),
+        };
+
+        let mut drops: Vec<_> = hashes
+            .iter()
+            .filter(|hash| tenant::split(hash).0.is_none())
+            .filter_map(|hash| self.meta(hash))
+            .filter(|meta| tag.is_none_or(|tag| meta.tags.iter().any(|t| t == tag)))
+            .filter(|meta| owner.is_none_or(|owner| meta.owner.as_deref() == Some(owner)))
+            .collect();
+
+        drops.sort_by(|a, b| a.hash.cmp(&b.hash));
+        drops
+    }
+
+    /// subscribe returns a receiver that gets notified every time a clipboard
+    /// is stored with key `hash`. Subscribers should call `get_clipboard` for the new content,
+    /// and should hand the receiver back with `unsubscribe` when done.
+    pub fn subscribe(&self, hash: &str) -> broadcast::Receiver<()> {
+        self.watchers
+            .entry(hash.to_owned())
+            .or_insert_with(|| broadcast::channel(WATCH_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// events returns a receiver of the `Event`s of every clipboard, e.g. for webhooks.
+    /// Subscribers that fall more than `EVENTS_CAPACITY` events behind miss the oldest ones.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// unsubscribe drops `rx`, and forgets `hash` if it was the last subscriber.
+    pub fn unsubscribe(&self, hash: &str, rx: broadcast::Receiver<()>) {
+        drop(rx);
+        self.watchers
+            .remove_if(hash, |_, tx| tx.receiver_count() == 0);
+    }
+
+    /// restore_index re-registers persisted clipboards from `entries` (see `Store::index`),
+    /// re-arming their expire timers with the time they had left.
+    /// Files of clipboards that expired in the meantime are removed,
+    /// and in-memory clipboards are skipped since their data did not survive.
+    /// restore_index returns the number of clipboards restored.
+    pub fn restore_index(store: Arc<Self>, entries: Vec<IndexEntry>) -> usize {
+        let mut restored = 0;
+
+        for entry in entries {
+            if entry.storage != clipboard::PERSIST {
+                eprintln!("restore_index: skipping in-memory clipboard {}", entry.hash);
+                continue;
+            }
+
+            if !store.files.exists(&entry.hash) {
+                eprintln!("restore_index: missing file for clipboard {}", entry.hash);
+                continue;
+            }
+
+            if Self::restore_entry(&store, entry) {
+                restored += 1;
+            }
+        }
+
+        restored
+    }
+
+    /// restore_entry re-registers persisted clipboard `entry` with the time it had left,
+    /// or removes its file if it expired, and reports whether it was restored.
+    /// Pinned clipboards are always restored.
+    fn restore_entry(store: &Arc<Self>, entry: IndexEntry) -> bool {
+        let remaining = match entry.pinned {
+            true => Some(Duration::ZERO),
+            false => entry.remaining_at(store.time.now()),
+        };
+
+        let Some(dur) = remaining else {
+            if let Err(err) = store.files.remove_blocking(&entry.hash) {
+                eprintln!("restore: failed to remove {}: {err}", entry.hash);
+            }
+
+            return false;
+        };
+
+        let meta = Meta {
+            digest: entry.digest,
+            max_views: entry.max_views,
+            content_type: entry.content_type,
+            filename: entry.filename,
+            lang: entry.lang,
+            notify: entry.notify,
+            available_at: entry
+                .available_at
+                .map(|at| SystemTime::UNIX_EPOCH + Duration::from_secs(at)),
+            owner: entry.owner,
+            encryption: entry.encryption,
+            tags: entry.tags,
+            size: store.files.size(&entry.hash).unwrap_or_default(),
+            created_at: entry
+                .created_at
+                .map(|at| SystemTime::UNIX_EPOCH + Duration::from_secs(at)),
+            pinned: entry.pinned,
+            ..Meta::default()
+        };
+
+        Self::insert_entry(store.clone(), &entry.hash, Storage::Persistent, dur, meta);
+        if let Some(restored) = store.haystack.get(&entry.hash) {
+            let last_access = entry.last_access.unwrap_or_default();
+            restored.views.store(entry.views, Ordering::Relaxed);
+            restored.last_access.store(last_access, Ordering::Relaxed);
+        }
+
+        true
+    }
+
+    /// restore_files re-registers clipboard files found by `persist::scan_dir`
+    /// that are not already tracked (e.g. files left behind by a crash).
+    /// Files with metadata (see `Persist::write_meta`) are restored like with `restore_index`,
+    /// and files without it get TTL `dur` and default metadata.
+    /// If `max_age` is given, files last modified longer ago than `max_age` are removed instead,
+    /// unless they are pinned. restore_files returns the number of files restored and removed,
+    /// including those that expired in the meantime.
+    pub fn restore_files(
+        store: Arc<Self>,
+        files: Vec<(String, SystemTime)>,
+        dur: Duration,
+        max_age: Option<Duration>,
+    ) -> (usize, usize) {
+        let (mut restored, mut removed) = (0, 0);
+        let now = store.time.now();
+
+        for (hash, modified) in files {
+            if store.is_persisted(&hash).is_some() {
+                continue;
+            }
+
+            let meta = store.files.read_meta(&hash);
+            let pinned = matches!(&meta, Ok(Some(entry)) if entry.pinned);
+
+            let age = now.duration_since(modified).unwrap_or_default();
+            if !pinned && max_age.is_some_and(|max_age| age > max_age) {
+                match store.files.remove_blocking(&hash) {
+                    Ok(()) => removed += 1,
+                    Err(err) => eprintln!("restore_files: failed to remove {hash}: {err}"),
+                }
+
+                continue;
+            }
+
+            match meta {
+                Ok(Some(entry)) if entry.hash == hash => {
+                    match Self::restore_entry(&store, entry) {
+                        true => restored += 1,
+                        false => removed += 1,
+                    }
+
+                    continue;
+                }
+
+                Ok(_) => {}
+                Err(err) => eprintln!("restore_files: bad metadata of {hash}: {err}"),
+            }
+
+            let meta = Meta {
+                size: store.files.size(&hash).unwrap_or_default(),
+                ..Meta::default()
+            };
+
+            Self::insert_entry(store.clone(), &hash, Storage::Persistent, dur, meta);
+            restored += 1;
+        }
+
+        (restored, removed)
+    }
+
+    /// sweep removes the clipboard files in `files` (see `persist::list_dir`) that no entry
+    /// tracks, e.g. files left behind by failed removals, unless they were modified within
+    /// `ORPHAN_GRACE`. If `max_age` is given, persisted clipboards whose files were last modified
+    /// longer ago than `max_age` are removed too, like with `remove_clipboard`, unless pinned.
+    /// sweep returns the names of the files removed.
+    pub async fn sweep(
+        &self,
+        files: Vec<(String, SystemTime)>,
+        max_age: Option<Duration>,
+    ) -> Vec<String> {
+        let now = self.time.now();
+        let mut removed = Vec::new();
+
+        for (hash, modified) in files {
+            let age = now.duration_since(modified).unwrap_or_default();
+            let (tracked, pinned) = self
+                .haystack
+                .get(&hash)
+                .filter(|entry| entry.is_persisted())
+                .map_or((false, false), |entry| (true, entry.pinned));
+
+            let result = match tracked {
+                false if age <= ORPHAN_GRACE => continue,
+                false => self.files.remove(&hash).await.map(|_| true),
+                true if !pinned && max_age.is_some_and(|max_age| age > max_age) => {
+                    self.remove_clipboard(&hash).await
+                }
+                true => continue,
+            };
+
+            match result {
+                Ok(true) => removed.push(hash),
+                Ok(false) => {}
+                Err(err) => eprintln!("sweep: failed to remove {hash}: {err}"),
+            }
+        }
+
+        removed
+    }
+
+    /// take_entry removes the entry for `hash` once it's `Live`, stops its timer,
+    /// and returns its storage. If the entry is being read or removed,
+    /// take_entry waits for that to finish first.
+    /// If the entry has content other than `digest` and collisions are rejected (and not forced),
+    /// the entry is kept and `StoreError::Conflict` is returned.
+    /// If the entry has an owner other than the one with `StoreOpts::owner_key`
+    /// and `opts` is not a replica, the entry is kept and `StoreError::Forbidden` is returned.
+    async fn take_entry(
+        &self,
+        hash: &str,
+        digest: &str,
+        opts: &StoreOpts,
+    ) -> Result<Option<Replaced>, StoreError> {
+        let collides = |entry: &Entry| {
+            !opts.force
+                && self.conf.load().on_collision == Collision::Reject
+                && entry.digest.as_deref().is_some_and(|d| d != digest)
+        };
+        // Replicas replace the clipboard on the peer's authority
+        let owned =
+            |entry: &Entry| opts.replica.is_some() || entry.owned_by(opts.owner_key.as_deref());
+
+        loop {
+            // Created before checking the entry, so that no notification is missed
+            let settled = self.settled.notified();
+
+            let taken = self.haystack.remove_if(hash, |_, entry| {
+                entry.is_live() && owned(entry) && !collides(entry)
+            });
+
+            if let Some((_, entry)) = taken {
+                self.forget(hash, &entry);
+
+                return Ok(Some(entry.replace()));
+            }
+
+            match self.haystack.get(hash) {
+                None => return Ok(None),
+                Some(entry) if !owned(&entry) => return Err(StoreError::Forbidden),
+                Some(entry) if collides(&entry) => return Err(StoreError::Conflict),
+                Some(_) => {}
+            }
+
+            settled.await;
+        }
+    }
+
+    /// next_version returns the version number and history of a clipboard replacing `old`.
+    /// If `old` has different content, it's kept in the history,
+    /// which holds at most `StoreConfig::max_versions` clipboards.
+    async fn next_version(
+        &self,
+        hash: &str,
+        digest: &str,
+        old: Option<Replaced>,
+    ) -> (u64, VecDeque<Version>) {
+        let Some(old) = old else {
+            return (1, VecDeque::new());
+        };
+
+        // Posting the same content again only resets the timer
+        if old.digest.as_deref() == Some(digest) {
+            return (old.version, old.history);
+        }
+
+        let max_versions = self.conf.load().max_versions;
+        let mut history = old.history;
+
+        if max_versions > 0 {
+            let clipboard = match old.storage {
+                Storage::Memory(clipboard) => Some(clipboard),
+                Storage::Persistent => match self.files.read(hash).await {
+                    Ok(data) => Some(Clipboard::Persist(data.into())),
+                    Err(err) => {
+                        eprintln!("failed to keep version {} of {hash}: {err}", old.version);
+                        None
+                    }
+                },
+            };
+
+            if let Some(clipboard) = clipboard {
+                history.push_back(Version {
+                    number: old.version,
+                    clipboard,
+                    created_at: old.created_at,
+                });
+            }
+        }
+
+        while history.len() > max_versions {
+            history.pop_front();
+        }
+
+        (old.version + 1, history)
+    }
+
+    /// insert_entry inserts a new entry for `hash`, and starts its timer in the reaper
+    /// unless it's pinned, and its release timer if it's held. It returns the id of the new entry.
+    fn insert_entry(
+        store: Arc<Self>,
+        hash: &str,
+        storage: Storage,
+        dur: Duration,
+        meta: Meta,
+    ) -> u64 {
+        let id = store.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = Entry::new(id, storage, store.time.now(), dur, meta);
+        let (pinned, deadline, release) = (entry.pinned, entry.expires_at, entry.available_at);
+
+        for tag in &entry.tags {
+            store
+                .tags
+                .entry(tag.clone())
+                .or_default()
+                .insert(hash.to_owned());
+        }
+
+        if let Some(digest) = &entry.digest {
+            store
+                .digests
+                .insert(digest_key(hash, digest), hash.to_owned());
+        }
+
+        entry.touched.store(store.tick(), Ordering::Relaxed);
+        store
+            .mem_bytes
+            .fetch_add(entry.mem_size(), Ordering::Relaxed);
+        store.disk.add(entry.disk_size());
+        store.tombstones.exhume(hash);
+        store.haystack.insert(hash.to_owned(), entry);
+        if !pinned {
+            store.timers.start(&store, hash, id, deadline);
+        }
+        if let Some(release) = release {
+            store.timers.release(&store, hash, id, release);
+        }
+
+        id
+    }
+
+    /// make_available releases entry `id` for `hash` held with `StoreOpts::available_at`,
+    /// once its release timer fires, and notifies its watchers and `Store::events` subscribers
+    fn make_available(&self, hash: &str, id: u64) {
+        let released = self
+            .haystack
+            .get_mut(hash)
+            .filter(|entry| entry.id == id)
+            .and_then(|mut entry| entry.available_at.take())
+            .is_some();
+
+        if released {
+            self.publish(hash);
+            self.emit(EventKind::Available, hash);
+        }
+    }
+
+    /// list_public adds entry `id` at `hash` to the public feed, unless it belongs to a tenant
+    fn list_public(&self, hash: &str, id: u64, snippet: Option<String>) {
+        if tenant::split(hash).0.is_none() {
+            self.feed.push(id, hash, snippet);
+        }
+    }
+
+    /// remove_entry removes in-memory entry `id` for `hash` before it expires,
+    /// and aborts its timer.
+    fn remove_entry(&self, hash: &str, id: u64) {
+        if let Some((_, entry)) = self.haystack.remove_if(hash, |_, entry| entry.id == id) {
+            self.forget(hash, &entry);
+        }
+    }
+
+    /// charge charges `bytes` for clipboard `hash` to the quota of `owner`, if quotas are enabled.
+    /// Clipboards of tenants are charged to their tenant instead.
+    /// The clipboard currently at `hash` is about to be replaced, so it does not count.
+    fn charge(
+        &self,
+        hash: &str,
+        owner: Option<IpAddr>,
+        bytes: u64,
+    ) -> Result<Option<Charge>, StoreError> {
+        let Some(quota) = &self.quota else {
+            return Ok(None);
+        };
+
+        let client = match (tenant::split(hash).0, owner) {
+            (Some(tenant), _) => Client::Tenant(tenant.into()),
+            (None, Some(ip)) => Client::Ip(ip),
+            (None, None) => return Ok(None),
+        };
+
+        let charge = Charge { client, bytes };
+        let replacing = self
+            .haystack
+            .get(hash)
+            .and_then(|entry| entry.charge.clone());
+
+        quota
+            .charge(&charge, replacing.as_ref())
+            .map_err(StoreError::QuotaExceeded)?;
+
+        Ok(Some(charge))
+    }
+
+    /// grow adds `bytes` to `charge`, if quotas are enabled
+    fn grow(&self, charge: Option<&Charge>, bytes: u64) -> Result<(), StoreError> {
+        match (&self.quota, charge) {
+            (Some(quota), Some(charge)) => {
+                quota.grow(charge, bytes).map_err(StoreError::QuotaExceeded)
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// append_file appends `data` to the file of clipboard `hash` holding `size` bytes,
+    /// charging `charge` for it. `size` is tracked by the entry, since compressed files
+    /// are smaller than their content.
+    async fn append_file(
+        &self,
+        hash: &str,
+        data: &[u8],
+        size: u64,
+        max_size: u64,
+        charge: Option<&Charge>,
+    ) -> Result<(), StoreError> {
+        let bytes = data.len() as u64;
+
+        if size + bytes > max_size {
+            return Err(StoreError::TooLarge(max_size));
+        }
+
+        let max_disk = self.conf.load().max_disk_bytes;
+        if !self.disk.fits(bytes, 0, max_disk) {
+            return Err(StoreError::DiskFull(max_disk.unwrap_or_default()));
+        }
+
+        self.grow(charge, bytes)?;
+
+        let result = self.files.append(hash, data).await;
+        match (&result, &self.quota, charge) {
+            (Ok(()), _, _) => self.disk.add(bytes),
+            (Err(_), Some(quota), Some(charge)) => quota.shrink(charge, bytes),
+            _ => {}
+        }
+
+        result
+    }
+
+    /// ttl returns the lifetime of a new clipboard that is `persisted` or in memory, stored with
+    /// `opts` and `dur` (see `Retention::ttl`). Replicas keep `dur`, which is what's left
+    /// of the lifetime they got on the instance they were replicated from.
+    fn ttl(&self, persisted: bool, opts: &StoreOpts, dur: Duration) -> Duration {
+        match opts.replica {
+            Some(_) => dur,
+            None => self.conf.load().retention.ttl(persisted, opts.ttl, dur),
+        }
+    }
+
+    /// check_disk checks that a new persisted clipboard of `bytes` fits within
+    /// `StoreConfig::max_disk_bytes`. The clipboard currently at `hash` is about to be replaced,
+    /// so it does not count.
+    fn check_disk(&self, hash: &str, bytes: u64) -> Result<(), StoreError> {
+        let Some(max) = self.conf.load().max_disk_bytes else {
+            return Ok(());
+        };
+
+        let replacing = self.haystack.get(hash).map_or(0, |entry| entry.disk_size());
+
+        match self.disk.fits(bytes, replacing, Some(max)) {
+            true => Ok(()),
+            false => Err(StoreError::DiskFull(max)),
+        }
+    }
+
+    /// release gives `charge` back to its owner's quota
+    fn release(&self, charge: Option<&Charge>) {
+        if let (Some(quota), Some(charge)) = (&self.quota, charge) {
+            quota.release(charge);
+        }
+    }
+
+    /// forget gives back what removed entry `hash` counted against:
+    /// its owner's quota and the memory budget, stops its timer, and untags it.
+    fn forget(&self, hash: &str, entry: &Entry) {
+        for tag in &entry.tags {
+            if let Some(mut hashes) = self.tags.get_mut(tag) {
+                hashes.remove(hash);
+            }
+
+            self.tags.remove_if(tag, |_, hashes| hashes.is_empty());
+        }
+
+        self.unindex_digest(hash, entry.digest.as_deref());
+
+        self.timers.stop(entry.id);
+        self.release(entry.charge.as_ref());
+        self.mem_bytes
+            .fetch_sub(entry.mem_size(), Ordering::Relaxed);
+        self.disk.sub(entry.disk_size());
+    }
+
+    /// unindex_digest removes `digest` from the digests of live clipboards, if it's still `hash`'s
+    fn unindex_digest(&self, hash: &str, digest: Option<&str>) {
+        if let Some(digest) = digest {
+            self.digests
+                .remove_if(&digest_key(hash, digest), |_, key| key == hash);
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// evict removes the least recently used in-memory clipboards while their total size is
+    /// over `StoreConfig::max_mem_bytes`. With `StoreConfig::evict_to_disk`, they are
+    /// written to file instead, and stay in `State::Demoting` while the file is written.
+    async fn evict(&self) {
+        let (max, evict_to_disk) = {
+            let conf = self.conf.load();
+            (conf.max_mem_bytes, conf.evict_to_disk)
+        };
+
+        let Some(max) = max else {
+            return;
+        };
+
+        while self.mem_bytes.load(Ordering::Relaxed) > max {
+            let lru = self
+                .haystack
+                .iter()
+                .filter(|entry| entry.is_live() && !entry.is_persisted())
+                .min_by_key(|entry| entry.touched.load(Ordering::Relaxed))
+                .map(|entry| (entry.key().to_owned(), entry.id));
+
+            let Some((hash, id)) = lru else {
+                return;
+            };
+
+            if !evict_to_disk {
+                self.remove_entry(&hash, id);
+                continue;
+            }
+
+            if let Err(err) = self.demote(&hash, id).await {
+                eprintln!("error demoting evicted clipboard {hash}: {err}");
+                self.remove_entry(&hash, id);
+            }
+        }
+    }
+
+    /// demote writes in-memory entry `id` for `hash` to file, keeping its timer.
+    /// Entries that do not fit within `StoreConfig::max_disk_bytes` are left in memory,
+    /// and `StoreError::DiskFull` is returned.
+    async fn demote(&self, hash: &str, id: u64) -> Result<(), StoreError> {
+        let (compress, max_disk) = {
+            let conf = self.conf.load();
+            (conf.compress.clone(), conf.max_disk_bytes)
+        };
+
+        let clipboard = {
+            let Some(mut entry) = self
+                .haystack
+                .get_mut(hash)
+                .filter(|entry| entry.id == id && entry.is_live())
+            else {
+                return Ok(());
+            };
+
+            if entry.is_persisted() {
+                return Ok(());
+            }
+
+            if !self.disk.fits(entry.size, 0, max_disk) {
+                return Err(StoreError::DiskFull(max_disk.unwrap_or_default()));
+            }
+
+            let Storage::Memory(clipboard) =
+                std::mem::replace(&mut entry.storage, Storage::Persistent)
+            else {
+                return Ok(());
+            };
+
+            entry.state = State::Demoting;
+            self.mem_bytes.fetch_sub(entry.size, Ordering::Relaxed);
+            self.disk.add(entry.size);
+
+            clipboard
+        };
+
+        let result = self.files.write(hash, clipboard.as_ref(), &compress).await;
+
+        if let Some(mut entry) = self.haystack.get_mut(hash).filter(|entry| entry.id == id) {
+            entry.state = State::Live;
+        }
+
+        self.settled.notify_waiters();
+
+        if result.is_ok() {
+            self.save_meta(hash).await;
+        }
+
+        result
+    }
+
+    /// save_meta keeps the `IndexEntry` of persisted clipboard `hash` with its file
+    /// (see `Persist::write_meta`), so that `restore_files` can restore it as it is now,
+    /// e.g. after a crash. It's called whenever the entry changes in a way worth restoring.
+    async fn save_meta(&self, hash: &str) {
+        let Some(meta) = self
+            .haystack
+            .get(hash)
+            .filter(|entry| entry.is_persisted() && entry.state != State::Removing)
+            .map(|entry| index_entry(hash, &entry))
+        else {
+            return;
+        };
+
+        if let Err(err) = self.files.write_meta(hash, &meta).await {
+            eprintln!("failed to save metadata of {hash}: {err}");
+        }
+    }
+
+    /// mem_bytes returns the total size of in-memory clipboards in bytes
+    pub fn mem_bytes(&self) -> u64 {
+        self.mem_bytes.load(Ordering::Relaxed)
+    }
+
+    /// disk_bytes returns the total size of persisted clipboards, and of temporary files
+    /// reserved with `reserve_tmp`, in bytes
+    pub fn disk_bytes(&self) -> u64 {
+        self.disk.bytes()
+    }
+
+    /// publish notifies subscribers of `hash` that a new clipboard was stored.
+    fn publish(&self, hash: &str) {
+        if let Some(tx) = self.watchers.get(hash) {
+            // All receivers might have been dropped
+            let _ = tx.send(());
+        }
+    }
+
+    /// expired leaves a tombstone for clipboard `hash`, which just expired or was viewed
+    /// for the last time, and notifies `Store::events` subscribers
+    fn expired(&self, hash: &str) {
+        self.tombstones
+            .bury(hash, index::to_timestamp(self.time.now()));
+        self.emit(EventKind::Expired, hash);
+    }
+
+    /// emit sends an `Event` to `Store::events` subscribers, if there are any.
+    fn emit(&self, kind: EventKind, hash: &str) {
+        if self.events.receiver_count() > 0 {
+            // All receivers might have been dropped since
+            let _ = self.events.send(Event::new(kind, hash));
+        }
+    }
+
+    /// emit_fetched emits an `EventKind::Fetched` event for a read of `hash`,
+    /// with the URL to notify of the read, if any
+    fn emit_fetched(&self, hash: &str, notify: Option<String>) {
+        if self.events.receiver_count() > 0 {
+            let event = Event {
+                notify,
+                ..Event::new(EventKind::Fetched, hash)
+            };
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// expire removes entry `id` for `hash`. Persisted entries first wait for
+    /// in-flight reads, and then stay in `State::Removing` while their file is removed.
+    async fn expire(&self, hash: &str, id: u64) -> Result<(), StoreError> {
+        loop {
+            let settled = self.settled.notified();
+
+            {
+                let mut entry = match self.haystack.get_mut(hash) {
+                    Some(entry) if entry.id == id => entry,
+                    _ => return Ok(()),
+                };
+
+                match entry.state {
+                    State::Live if entry.is_persisted() => {
+                        entry.state = State::Removing;
+                        break;
+                    }
+
+                    State::Live => {
+                        drop(entry);
+                        if let Some((_, entry)) =
+                            self.haystack.remove_if(hash, |_, entry| entry.id == id)
+                        {
+                            self.forget(hash, &entry);
+                            self.expired(hash);
+                        }
+
+                        return Ok(());
+                    }
+
+                    State::Reading(_) | State::Appending | State::Demoting => {}
+                    State::Removing => return Ok(()),
+                }
+            }
+
+            settled.await;
+        }
+
+        let result = self.files.remove(hash).await;
+
+        if let Some((_, entry)) = self.haystack.remove_if(hash, |_, entry| entry.id == id) {
+            self.forget(hash, &entry);
+            self.expired(hash);
+        }
+        self.settled.notify_waiters();
+
+        result
+    }
+}
+
+/// unique_prefix_lens returns the length of the shortest prefix of each of the sorted `keys`
+/// that no other key starts with. Keys that are prefixes of others need all of their characters.
+fn unique_prefix_lens(keys: &[String]) -> Vec<usize> {
+    let common = |a: &str, b: &str| a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count();
+
+    // In sorted keys, the longest common prefix of a key with any other is with a neighbor
+    (0..keys.len())
+        .map(|i| {
+            let prev = i.checked_sub(1).map_or(0, |j| common(&keys[i], &keys[j]));
+            let next = keys.get(i + 1).map_or(0, |next| common(&keys[i], next));
+
+            (prev.max(next) + 1).min(keys[i].chars().count())
+        })
+        .collect()
+}
+
+/// claim returns the owner of a clipboard replacing `old`, which keeps the owner of `old`.
+/// New clipboards get a new owner key, which is returned along with its digest,
+/// and replicas keep the owner they have on their peer.
+fn claim(old: Option<&Replaced>, replica: Option<&Replica>) -> (Option<String>, Option<String>) {
+    match (replica, old) {
+        (Some(replica), _) => (replica.owner.clone(), None),
+        (None, Some(old)) => (old.owner.clone(), None),
+        (None, None) => {
+            let key = owner::new_key();
+            (Some(owner::digest(&key)), Some(key))
+        }
+    }
+}
+
+/// created_at returns the creation time of a clipboard being stored,
+/// which is only known in advance for replicas
+fn created_at(replica: Option<&Replica>) -> Option<SystemTime> {
+    replica
+        .and_then(|replica| replica.created_at)
+        .map(|at| SystemTime::UNIX_EPOCH + Duration::from_secs(at))
+}
+
+/// release_at returns when a clipboard stored at `now` with `opts` and lifetime `dur`
+/// becomes available, or `None` if it's available right away.
+/// Clipboards must be available before they expire, unless they are pinned.
+fn release_at(
+    opts: &StoreOpts,
+    now: SystemTime,
+    dur: Duration,
+) -> Result<Option<SystemTime>, StoreError> {
+    let Some(at) = opts.available_at else {
+        return Ok(None);
+    };
+
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(at);
+    if at <= now {
+        return Ok(None);
+    }
+
+    if !opts.pin && at >= now + dur {
+        return Err(StoreError::InvalidTtl(format!(
+            "clipboard would expire before it is available at {}",
+            index::to_rfc3339(index::to_timestamp(at)),
+        )));
+    }
+
+    Ok(Some(at))
+}
+
+/// tmp_charge returns the charge that temporary files written for `client` grow,
+/// which counts no clipboard (see `Store::reserve_tmp`)
+fn tmp_charge(client: Option<IpAddr>) -> Option<Charge> {
+    client.map(|ip| Charge {
+        client: Client::Ip(ip),
+        bytes: 0,
+    })
+}
+
+/// check_size rejects clipboards of `size` bytes over `StoreOpts::max_size`
+fn check_size(size: u64, opts: &StoreOpts) -> Result<(), StoreError> {
+    match opts.max_size {
+        Some(max) if size > max => Err(StoreError::TooLarge(max)),
+        _ => Ok(()),
+    }
+}
+
+/// digest_key returns the key of clipboards with content `digest` in `Store::digests`,
+/// which is kept in the keyspace of clipboard `hash`
+fn digest_key(hash: &str, digest: &str) -> String {
+    match tenant::split(hash).0 {
+        Some(tenant) => tenant::key(tenant, digest),
+        None => digest.to_owned(),
+    }
+}
+
+/// index_entry describes `entry` for clipboard `hash`
+fn index_entry(hash: &str, entry: &Entry) -> IndexEntry {
+    let last_access = entry.last_access.load(Ordering::Relaxed);
+
+    IndexEntry {
+        hash: hash.to_owned(),
+        storage: match entry.storage {
+            Storage::Memory(ref clipboard) => clipboard.key(),
+            Storage::Persistent => clipboard::PERSIST.to_string(),
+        },
+        expires_at: match entry.pinned {
+            true => 0,
+            false => index::to_timestamp(entry.expires_at),
+        },
+        created_at: Some(index::to_timestamp(entry.created_at)),
+        size: entry.size,
+        digest: entry.digest.clone(),
+        max_views: entry.max_views,
+        content_type: entry.content_type.clone(),
+        filename: entry.filename.clone(),
+        lang: entry.lang.clone(),
+        notify: entry
+            .notify
+            .clone()
+            .filter(|_| !entry.notified.load(Ordering::Relaxed)),
+        owner: entry.owner.clone(),
+        encryption: entry.encryption.clone(),
+        tags: entry.tags.clone(),
+        views: entry.views.load(Ordering::Relaxed),
+        last_access: (last_access != 0).then_some(last_access),
+        pinned: entry.pinned,
+        available_at: entry.available_at.map(index::to_timestamp),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+    use persist::{InMemoryFs, Persist};
+
+    /// mock_store returns a store whose clipboards expire on the returned clock
+    fn mock_store(conf: StoreConfig) -> (Arc<Store>, Arc<InMemoryFs>, Arc<MockClock>) {
+        let (files, clock) = (
+            Arc::new(InMemoryFs::default()),
+            Arc::new(MockClock::default()),
+        );
+        let store = Store::with_clock(conf, files.clone(), clock.clone());
+
+        (Arc::new(store), files, clock)
+    }
+
+    /// expired waits for the reaper to expire clipboard `hash`
+    async fn expired(events: &mut broadcast::Receiver<Event>, hash: &str) {
+        loop {
+            let event = events.recv().await.expect("store was dropped");
+            if event.event == EventKind::Expired && event.hash == hash {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_get() {
+        // We should be able to get multiple times
+        let foo = "foo";
+        let clip = Clipboard::Mem("eiei".into());
+        let now = SystemTime::now();
+        let entry = Entry::new(0, clip.into(), now, Duration::from_secs(1), Meta::default());
+
+        let store = Store::new();
+        store.haystack.insert(foo.to_owned(), entry);
+
+        assert!(store.get_clipboard(foo).await.is_some());
+        assert!(store.get_clipboard(foo).await.is_some());
+        assert!(store.get_clipboard(foo).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_store_expire() {
+        // Far from the system time, so tombstones must be dated by the store clock
+        let clock = Arc::new(MockClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(86400),
+        ));
+        let store = Arc::new(Store::with_clock(
+            StoreConfig::default(),
+            Arc::new(InMemoryFs::default()),
+            clock.clone(),
+        ));
+        let mut events = store.events();
+        let key = "keyfoo";
+        let dur100 = Duration::from_millis(100);
+        let dur200 = Duration::from_millis(200);
+        let dur300 = Duration::from_millis(300);
+
+        // Store and launch the expire timer
+        Store::store_new_clipboard(
+            store.clone(),
+            key,
+            key,
+            Clipboard::Mem("foo".into()),
+            dur300,
+            StoreOpts::default(),
+        )
+        .await
+        .expect("failed to store new clipboard");
+
+        // Less than the lifetime has passed, so the clipboard is still there
+        clock.advance(dur100);
+        tokio::task::yield_now().await;
+        assert!(store.get_clipboard(key).await.is_some());
+
+        // Clipboard with `key` should have been expired
+        clock.advance(dur200);
+        expired(&mut events, key).await;
+        assert!(store.get_clipboard(key).await.is_none());
+        assert!(matches!(store.not_found(key), StoreError::Expired(86400)));
+    }
+
+    #[tokio::test]
+    async fn test_reaper_drop() {
+        let store = Arc::new(Store::new());
+
+        for hash in ["rp00", "rp01"] {
+            Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                Clipboard::Mem("foo".into()),
+                Duration::from_secs(60),
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Pending timers must not keep the store alive
+        let weak = Arc::downgrade(&store);
+        drop(store);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reset_timer() {
+        let hash = "keyfoo";
+        let (store, _, clock) = mock_store(StoreConfig::default());
+        let mut events = store.events();
+
+        let clipboard = Clipboard::Mem(vec![1u8, 2, 3].into());
+        let dur200 = Duration::from_millis(200);
+        let dur400 = Duration::from_millis(400);
+
+        let owner_key = Store::store_new_clipboard(
+            store.clone(),
+            hash,
+            hash,
+            clipboard.clone(),
+            dur400,
+            StoreOpts::default(),
+        )
+        .await
+        .expect("failed to store to Store");
+
+        clock.advance(dur200);
+
+        // Only the owner may reset the timer
+        let result = Store::store_new_clipboard(
+            store.clone(),
+            hash,
+            hash,
+            clipboard.clone(),
+            dur400,
+            StoreOpts::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(StoreError::Forbidden)));
+
+        let opts = StoreOpts {
+            owner_key,
+            ..StoreOpts::default()
+        };
+        Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur400, opts)
+            .await
+            .expect("failed to re-write to Store");
+
+        // The first timer was stopped
+        clock.advance(dur200);
+        tokio::task::yield_now().await;
+        assert!(store.get_clipboard(hash).await.is_some());
+
+        clock.advance(dur200);
+        expired(&mut events, hash).await;
+        assert!(store.get_clipboard(hash).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_restore_index() {
+        let fs = Arc::new(InMemoryFs::default());
+
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let dur = Duration::from_secs(60);
+
+        let mem = Clipboard::Mem("index-mem".into());
+        let persisted = Clipboard::Persist("index-persist".into());
+
+        Store::store_new_clipboard(
+            store.clone(),
+            "idx0",
+            "idx0",
+            mem,
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+        Store::store_new_clipboard(
+            store.clone(),
+            "idx1",
+            "idx1",
+            persisted,
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut entries = store.index();
+        entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].storage, clipboard::MEM);
+        assert_eq!(entries[1].storage, clipboard::PERSIST);
+        assert!(entries[1].remaining().is_some());
+
+        // Expired persisted clipboards are removed instead of restored
+        fs.insert("idx2", b"expired".to_vec());
+        entries.push(IndexEntry {
+            hash: "idx2".to_string(),
+            storage: clipboard::PERSIST.to_string(),
+            expires_at: 0,
+            created_at: None,
+            size: 7,
+            digest: None,
+            max_views: None,
+            content_type: None,
+            filename: None,
+            lang: None,
+            notify: None,
+            owner: None,
+            encryption: None,
+            tags: Vec::new(),
+            views: 0,
+            last_access: None,
+            pinned: false,
+            available_at: None,
+        });
+
+        let restored = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        assert_eq!(Store::restore_index(restored.clone(), entries), 1);
+
+        assert!(restored.get_clipboard("idx0").await.is_none());
+        assert!(restored.get_clipboard("idx1").await.is_some());
+        assert!(!fs.exists("idx2"));
+    }
+
+    #[tokio::test]
+    async fn test_restore_files() {
+        let fs = Arc::new(InMemoryFs::default());
+
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let dur = Duration::from_secs(60);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        Store::store_new_clipboard(
+            store.clone(),
+            "orp0",
+            "orp0",
+            Clipboard::Persist("tracked".into()),
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        fs.insert("orp1", b"orphan".to_vec());
+        fs.insert("orp2", b"old orphan".to_vec());
+
+        let files = vec![
+            ("orp0".to_string(), SystemTime::now()),
+            ("orp1".to_string(), SystemTime::now()),
+            ("orp2".to_string(), SystemTime::now() - 2 * day),
+        ];
+
+        let result = Store::restore_files(store.clone(), files, dur, Some(day));
+        assert_eq!(result, (1, 1));
+
+        assert!(store.get_clipboard("orp1").await.is_some());
+        assert!(store.get_clipboard("orp2").await.is_none());
+        assert!(!fs.exists("orp2"));
+    }
+
+    #[tokio::test]
+    async fn test_restore_meta() {
+        let fs = Arc::new(InMemoryFs::default());
+
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let hour = Duration::from_secs(60 * 60);
+        let opts = StoreOpts {
+            max_views: Some(3),
+            content_type: Some("text/csv".to_string()),
+            filename: Some("table.csv".to_string()),
+            ..StoreOpts::default()
+        };
+
+        let clipboard = Clipboard::Persist("a,b".into());
+        Store::store_new_clipboard(store.clone(), "meta0", "meta0", clipboard, hour, opts)
+            .await
+            .unwrap();
+        store.get_clipboard("meta0").await.unwrap();
+        let before = store.meta("meta0").unwrap();
+
+        // The store crashes without writing its index
+        drop(store);
+        let restored = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let files = vec![("meta0".to_string(), SystemTime::now())];
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(
+            Store::restore_files(restored.clone(), files, day, None),
+            (1, 0)
+        );
+
+        let after = restored.meta("meta0").unwrap();
+        assert_eq!(after.expires_at, before.expires_at);
+        assert_eq!(after.created_at, before.created_at);
+        assert_eq!(after.content_type.as_deref(), Some("text/csv"));
+        assert_eq!(after.filename.as_deref(), Some("table.csv"));
+        assert_eq!(after.views, 1);
+
+        // Two views are left of the three
+        assert!(restored.get_clipboard("meta0").await.is_some());
+        assert!(restored.get_clipboard("meta0").await.is_some());
+        assert!(restored.get_clipboard("meta0").await.is_none());
+        assert!(fs.read_meta("meta0").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sweep() {
+        let fs = Arc::new(InMemoryFs::default());
+
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let dur = Duration::from_secs(60);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        for hash in ["swp0", "swp1"] {
+            let clipboard = Clipboard::Persist("tracked".into());
+            Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        fs.insert("swp2", b"new orphan".to_vec());
+        fs.insert("swp3", b"orphan".to_vec());
+
+        let old = SystemTime::now() - 2 * day;
+        let files = vec![
+            ("swp0".to_string(), SystemTime::now()),
+            ("swp1".to_string(), old),
+            ("swp2".to_string(), SystemTime::now()),
+            ("swp3".to_string(), old),
+        ];
+
+        // Tracked clipboards are kept regardless of age without max_age
+        let removed = store.sweep(files.clone(), None).await;
+        assert_eq!(removed, ["swp3"]);
+        assert!(!fs.exists("swp3"));
+        assert!(fs.exists("swp2"));
+
+        let removed = store.sweep(files, Some(day)).await;
+        assert_eq!(removed, ["swp1"]);
+        assert!(store.meta("swp1").is_none());
+        assert!(!fs.exists("swp1"));
+        assert!(store.meta("swp0").is_some());
+
+        fs.remove_blocking("swp2").unwrap();
+        store.remove_clipboard("swp0").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_clipboard() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = Path::new(persist::DIR).join("open");
+        persist::assert_dir(None);
+        persist::assert_dir(Some(&dir));
+
+        let files = Arc::new(persist::DiskFs::new(&dir));
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), files));
+        let mut events = store.events();
+        let hash = "open0";
+        let opts = StoreOpts {
+            max_views: Some(1),
+            ..StoreOpts::default()
+        };
+
+        let clipboard = Clipboard::Persist("streamed".into());
+        Store::store_new_clipboard(
+            store.clone(),
+            hash,
+            hash,
+            clipboard,
+            Duration::from_secs(60),
+            opts,
+        )
+        .await
+        .unwrap();
+
+        let mut file = store.open_clipboard(hash).await.unwrap().unwrap();
+        assert_eq!(
+            file.checksum.as_deref(),
+            Some(checksum::checksum(b"streamed").as_str())
+        );
+
+        // The last view is counted, but the file is kept until it's been streamed
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(store.is_persisted(hash).is_some());
+        assert!(store.get_clipboard(hash).await.is_none());
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "streamed");
+
+        drop(file);
+        expired(&mut events, hash).await;
+        assert!(store.is_persisted(hash).is_none());
+        assert!(!persist::clipboard_file_exists(&dir, hash));
+    }
+
+    #[tokio::test]
+    async fn test_expire_waits_for_readers() {
+        let fs = Arc::new(InMemoryFs::default());
+
+        let hash = "rdr0";
+        let store = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        let clipboard = Clipboard::Persist("being read".into());
+
+        Store::store_new_clipboard(
+            store.clone(),
+            hash,
+            hash,
+            clipboard,
+            Duration::from_secs(60),
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        let id = {
+            let mut entry = store.haystack.get_mut(hash).unwrap();
+            entry.state = State::Reading(1);
+            entry.id
+        };
+
+        let expiring = tokio::spawn({
+            let store = store.clone();
+            async move { store.expire(hash, id).await }
+        });
+
+        // The file must not be removed while it's being read
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(fs.exists(hash));
+        assert!(store.is_persisted(hash).is_some());
+
+        store.haystack.get_mut(hash).unwrap().state = State::Live;
+        store.settled.notify_waiters();
+
+        expiring.await.unwrap().expect("expire failed");
+        assert!(!fs.exists(hash));
+        assert!(store.is_persisted(hash).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let store = Arc::new(Store::with_config(StoreConfig {
+            retention: Retention {
+                mem_ttl: Some(Duration::from_secs(60)),
+                max_mem_ttl: Some(Duration::from_secs(120)),
+                ..Retention::default()
+            },
+            ..StoreConfig::default()
+        }));
+
+        let store_with = |hash: &'static str, opts: StoreOpts| {
+            let store = store.clone();
+            async move {
+                let clipboard = Clipboard::Mem(hash.to_owned().into());
+                let dur = Duration::from_secs(15);
+                Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts)
+                    .await
+                    .unwrap();
+
+                let now = index::to_timestamp(SystemTime::now());
+                store.expires_at(hash).unwrap() - now
+            }
+        };
+
+        let ttl = store_with("ret0", StoreOpts::default()).await;
+        assert!((59..=60).contains(&ttl), "{ttl}");
+
+        let long = StoreOpts {
+            ttl: Some(Duration::from_secs(3600)),
+            ..StoreOpts::default()
+        };
+        let ttl = store_with("ret1", long).await;
+        assert!((119..=120).contains(&ttl), "{ttl}");
+
+        // Replicas keep the lifetime they have left on their peer
+        let replica = StoreOpts {
+            replica: Some(Replica::default()),
+            ..StoreOpts::default()
+        };
+        let ttl = store_with("ret2", replica).await;
+        assert!((14..=15).contains(&ttl), "{ttl}");
+    }
+
+    #[tokio::test]
+    async fn test_pin() {
+        let (store, fs, clock) = mock_store(StoreConfig {
+            dedupe: true,
+            ..StoreConfig::default()
+        });
+
+        let pin = StoreOpts {
+            pin: true,
+            max_views: Some(1),
+            ..StoreOpts::default()
+        };
+        let clipboard = Clipboard::Mem("pinned".into());
+        let dur = Duration::from_millis(10);
+        Store::store_new_clipboard(store.clone(), "pin0", "pin0", clipboard, dur, pin)
+            .await
+            .unwrap();
+
+        // Pinned clipboards are persisted, have no timer and ignore max_views
+        assert!(fs.exists("pin0"));
+        assert_eq!(store.expires_at("pin0"), None);
+        clock.advance(Duration::from_secs(60));
+        tokio::task::yield_now().await;
+        assert!(store.get_clipboard("pin0").await.is_some());
+        assert!(store.get_clipboard("pin0").await.is_some());
+
+        // Duplicates of pinned clipboards are deduplicated, and stay pinned
+        let key = Store::extend_duplicate(&store, "pin0", "pin0", dur, None, None);
+        assert_eq!(key.as_deref(), Some("pin0"));
+        assert_eq!(store.expires_at("pin0"), None);
+
+        let entries = store.index();
+        assert!(entries[0].pinned);
+        assert_eq!(entries[0].expires_at, 0);
+
+        let restored = Arc::new(Store::with_persist(StoreConfig::default(), fs.clone()));
+        assert_eq!(Store::restore_index(restored.clone(), entries), 1);
+        assert!(restored.get_clipboard("pin0").await.is_some());
+
+        assert!(restored.remove_clipboard("pin0").await.unwrap());
+        assert!(!fs.exists("pin0"));
+    }
+
+    #[tokio::test]
+    async fn test_collision() {
+        let hash = "col0";
+        let dur = Duration::from_secs(60);
+        let foo = Clipboard::Mem("foo".into());
+        let bar = Clipboard::Mem("bar".into());
+
+        let overwrite = Arc::new(Store::new());
+        let owner_key = Store::store_new_clipboard(
+            overwrite.clone(),
+            hash,
+            "digest-foo",
+            foo.clone(),
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+        let owned = StoreOpts {
+            owner_key,
+            ..StoreOpts::default()
+        };
+        Store::store_new_clipboard(
+            overwrite.clone(),
+            hash,
+            "digest-bar",
+            bar.clone(),
+            dur,
+            owned,
+        )
+        .await
+        .expect("collisions should overwrite by default");
+
+        let clipboard = overwrite.get_clipboard(hash).await.unwrap();
+        assert_eq!(clipboard.as_ref() as &[u8], b"bar");
+
+        let reject = Arc::new(Store::with_config(StoreConfig {
+            on_collision: Collision::Reject,
+            ..StoreConfig::default()
+        }));
+        let owner_key = Store::store_new_clipboard(
+            reject.clone(),
+            hash,
+            "digest-foo",
+            foo.clone(),
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+        let owned = StoreOpts {
+            owner_key,
+            ..StoreOpts::default()
+        };
+
+        let result =
+            Store::store_new_clipboard(reject.clone(), hash, "digest-bar", bar, dur, owned.clone())
+                .await;
+        assert!(matches!(result, Err(StoreError::Conflict)));
+
+        // Same content is not a collision
+        Store::store_new_clipboard(reject.clone(), hash, "digest-foo", foo, dur, owned.clone())
+            .await
+            .expect("same content should not collide");
+
+        let clipboard = reject.get_clipboard(hash).await.unwrap();
+        assert_eq!(clipboard.as_ref() as &[u8], b"foo");
+
+        // Forced collisions overwrite
+        let force = StoreOpts {
+            force: true,
+            ..owned
+        };
+        let bar = Clipboard::Mem("bar".into());
+        Store::store_new_clipboard(reject.clone(), hash, "digest-bar", bar, dur, force)
+            .await
+            .expect("forced collision should overwrite");
+
+        let clipboard = reject.get_clipboard(hash).await.unwrap();
+        assert_eq!(clipboard.as_ref() as &[u8], b"bar");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_prefix() {
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(5);
+
+        for hash in ["abcdef01", "abcdef02", "abcdeg03", "ffffffff", "abc"] {
+            let clipboard = Clipboard::Mem(hash.into());
+            Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            store.resolve_prefix("ffffff"),
+            Resolved::Unique("ffffffff".into())
+        );
+        assert_eq!(
+            store.resolve_prefix("abcdeg"),
+            Resolved::Unique("abcdeg03".into())
+        );
+        assert_eq!(
+            store.resolve_prefix("abcdef02"),
+            Resolved::Unique("abcdef02".into())
+        );
+        assert_eq!(
+            store.resolve_prefix("abcdef"),
+            Resolved::Ambiguous(vec![8, 8])
+        );
+        assert_eq!(
+            store.resolve_prefix("abcde"),
+            Resolved::None,
+            "short prefixes must not match"
+        );
+        assert_eq!(store.resolve_prefix("f"), Resolved::None);
+        assert_eq!(store.resolve_prefix("abc"), Resolved::Unique("abc".into()));
+        assert_eq!(store.resolve_prefix("000000"), Resolved::None);
+
+        assert_eq!(store.shortest_prefix("ffffffff"), "ffffff");
+        assert_eq!(store.shortest_prefix("abcdeg03"), "abcdeg");
+        assert_eq!(store.shortest_prefix("abcdef01"), "abcdef01");
+        assert_eq!(store.shortest_prefix("abc"), "abc");
+
+        store.reconfigure(StoreConfig {
+            exact_keys: true,
+            ..StoreConfig::default()
+        });
+        assert_eq!(store.resolve_prefix("abcdeg"), Resolved::None);
+        assert_eq!(
+            store.resolve_prefix("abcdeg03"),
+            Resolved::Unique("abcdeg03".into())
+        );
+        assert_eq!(store.shortest_prefix("ffffffff"), "ffffffff");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(5);
+        let hash = "sub1";
+
+        let mut rx = store.subscribe(hash);
+        assert!(rx.try_recv().is_err());
+
+        let mut owner_key = None;
+        for data in ["foo", "bar"] {
+            let clipboard = Clipboard::Mem(data.into());
+            let opts = StoreOpts {
+                owner_key: owner_key.clone(),
+                ..StoreOpts::default()
+            };
+            let key = Store::store_new_clipboard(store.clone(), hash, data, clipboard, dur, opts)
+                .await
+                .unwrap();
+            owner_key = owner_key.or(key);
+
+            rx.recv().await.expect("no notification for new clipboard");
+
+            let clipboard = store.get_clipboard(hash).await.unwrap();
+            assert_eq!(clipboard.as_ref() as &[u8], data.as_bytes());
+        }
+
+        store.unsubscribe(hash, rx);
+        assert!(store.watchers.get(hash).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let (store, _, clock) = mock_store(StoreConfig::default());
+        let (mut events, mut reaped) = (store.events(), store.events());
+        let hash = "event1";
+
+        let mut opts = StoreOpts {
+            max_views: Some(2),
+            notify: Some("http://localhost/notify".to_string()),
+            ..StoreOpts::default()
+        };
+        let clipboard = Clipboard::Mem("foo".into());
+        let dur = Duration::from_millis(100);
+
+        for _ in 0..2 {
+            let owner_key = Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                clipboard.clone(),
+                dur,
+                opts.clone(),
+            )
+            .await
+            .unwrap();
+            opts.owner_key = opts.owner_key.or(owner_key);
+        }
+
+        // The second post replaces the first, so 2 views use it up
+        store.get_clipboard(hash).await.unwrap();
+        store.get_clipboard(hash).await.unwrap();
+
+        // Timers expire clipboards just the same
+        Store::store_new_clipboard(
+            store.clone(),
+            "event2",
+            "event2",
+            clipboard,
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+        clock.advance(dur);
+        expired(&mut reaped, "event2").await;
+
+        let (mut got, mut notified) = (Vec::new(), Vec::new());
+        while let Ok(event) = events.try_recv() {
+            notified.extend(event.notify);
+            got.push((event.event, event.hash));
+        }
+
+        // Only the first read notifies the creator
+        assert_eq!(notified, vec!["http://localhost/notify".to_string()]);
+
+        let hash = hash.to_string();
+        assert_eq!(
+            got,
+            vec![
+                (EventKind::Created, hash.clone()),
+                (EventKind::Created, hash.clone()),
+                (EventKind::Fetched, hash.clone()),
+                (EventKind::Fetched, hash.clone()),
+                (EventKind::Expired, hash),
+                (EventKind::Created, "event2".to_string()),
+                (EventKind::Expired, "event2".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        let (store, _, clock) = mock_store(StoreConfig {
+            quota: Some(QuotaConfig {
+                max_clipboards: Some(1),
+                max_bytes: None,
+            }),
+            ..StoreConfig::default()
+        });
+        let mut events = store.events();
+
+        let dur = Duration::from_millis(100);
+        let mut opts = StoreOpts {
+            owner: Some("127.0.0.1".parse().unwrap()),
+            ..StoreOpts::default()
+        };
+
+        for (hash, data) in [("quo1", "foo"), ("quo1", "foo")] {
+            let clipboard = Clipboard::Mem(data.into());
+            let owner_key =
+                Store::store_new_clipboard(store.clone(), hash, data, clipboard, dur, opts.clone())
+                    .await
+                    .expect("replacing own clipboard should not exceed quota");
+            opts.owner_key = opts.owner_key.or(owner_key);
+        }
+
+        let clipboard = Clipboard::Mem("bar".into());
+        let result =
+            Store::store_new_clipboard(store.clone(), "quo2", "bar", clipboard, dur, opts.clone())
+                .await;
+        assert!(matches!(result, Err(StoreError::QuotaExceeded(_))));
+
+        // Clipboards without owners are not limited
+        let clipboard = Clipboard::Mem("bar".into());
+        let anon = StoreOpts::default();
+        Store::store_new_clipboard(store.clone(), "quo2", "bar", clipboard, dur, anon)
+            .await
+            .unwrap();
+
+        // Expired clipboards are released
+        clock.advance(dur);
+        expired(&mut events, "quo1").await;
+
+        let clipboard = Clipboard::Mem("baz".into());
+        Store::store_new_clipboard(store.clone(), "quo3", "baz", clipboard, dur, opts)
+            .await
+            .expect("expired clipboard should be released");
+    }
+
+    #[tokio::test]
+    async fn test_store_dirs() {
+        let dirs = ["dirs-a", "dirs-b"].map(|name| Path::new(persist::DIR).join(name));
+        let dur = Duration::from_secs(60);
+
+        for (dir, text) in dirs.iter().zip(["first", "second"]) {
+            persist::assert_dir(None);
+            persist::assert_dir(Some(dir));
+
+            let files = Arc::new(persist::DiskFs::new(dir));
+            let store = Arc::new(Store::with_persist(StoreConfig::default(), files));
+            assert_eq!(store.dir(), Some(dir.as_path()));
+
+            let clipboard = Clipboard::Persist(text.into());
+            Store::store_new_clipboard(
+                store,
+                "dirs0",
+                "dirs0",
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // The same clipboard in each store is kept in its own directory
+        for (dir, text) in dirs.iter().zip(["first", "second"]) {
+            assert_eq!(
+                persist::read_clipboard_file(dir, "dirs0").unwrap(),
+                text.as_bytes()
+            );
+        }
+
+        assert!(
+            Store::with_persist(StoreConfig::default(), Arc::new(InMemoryFs::default()))
+                .dir()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_views() {
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(60);
+        let opts = StoreOpts {
+            max_views: Some(2),
+            ..StoreOpts::default()
+        };
+
+        let clipboards = [
+            ("view0", Clipboard::Mem("viewed mem".into())),
+            ("view1", Clipboard::Persist("viewed persist".into())),
+        ];
+
+        for (hash, clipboard) in clipboards {
+            Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts.clone())
+                .await
+                .unwrap();
+
+            assert!(store.is_view_limited(hash));
+            assert!(store.get_clipboard(hash).await.is_some());
+            assert!(store.get_clipboard(hash).await.is_some());
+            assert!(store.get_clipboard(hash).await.is_none());
+            assert!(store.is_persisted(hash).is_none());
+        }
+
+        assert!(!persist::clipboard_file_exists(
+            Path::new(persist::DIR),
+            "view1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_access_stats() {
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(60);
+        let hash = "stat";
+
+        let clipboard = Clipboard::Mem("stats".into());
+        Store::store_new_clipboard(
+            store.clone(),
+            hash,
+            hash,
+            clipboard,
+            dur,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+
+        let meta = store.meta(hash).unwrap();
+        assert_eq!(meta.views, 0);
+        assert_eq!(meta.last_access, None);
+
+        store.get_clipboard(hash).await.unwrap();
+        store.get_clipboard(hash).await.unwrap();
+
+        let meta = store.meta(hash).unwrap();
+        assert_eq!(meta.views, 2);
+        assert!(meta.last_access.is_some());
+
+        assert!(store.meta("nope").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_append() {
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::with_config(StoreConfig {
+            append_max_size: Some(16),
+            ..StoreConfig::default()
+        }));
+        let dur = Duration::from_secs(60);
+
+        let clipboards = [
+            ("app0", Clipboard::Mem("foo".into())),
+            ("app1", Clipboard::Persist("foo".into())),
+        ];
+
+        for (hash, clipboard) in clipboards {
+            let key = Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+            let key = key.as_deref();
+
+            store.append_clipboard(hash, b"bar", key).await.unwrap();
+            store.append_clipboard(hash, b"baz", key).await.unwrap();
+
+            // Only the owner may append
+            for other in [None, Some("other")] {
+                let result = store.append_clipboard(hash, b"qux", other).await;
+                assert!(matches!(result, Err(StoreError::Forbidden)));
+            }
+
+            let clipboard = store.get_clipboard(hash).await.unwrap();
+            assert_eq!(clipboard.as_ref() as &[u8], b"foobarbaz");
+
+            let result = store.append_clipboard(hash, b"too much data", key).await;
+            assert!(matches!(result, Err(StoreError::TooLarge(16))));
+
+            let clipboard = store.get_clipboard(hash).await.unwrap();
+            assert_eq!(clipboard.as_ref() as &[u8], b"foobarbaz");
+        }
+
+        let result = store.append_clipboard("nope", b"foo", None).await;
+        assert!(matches!(result, Err(StoreError::NoSuch)));
+    }
+
+    #[tokio::test]
+    async fn test_compress() {
+        use compress::Compression;
+
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::with_config(StoreConfig {
+            compress: CompressConfig {
+                algo: Compression::Gzip,
+                threshold: 64,
+            },
+            ..StoreConfig::default()
+        }));
+        let dur = Duration::from_secs(60);
+        let dir = Path::new(persist::DIR);
+        let text = "compressed ".repeat(100);
+
+        let clipboards = [("cmp0", text.as_str()), ("cmp1", "small")];
+
+        for (hash, text) in clipboards {
+            let clipboard = Clipboard::Persist(text.to_owned().into());
+            let key = Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+
+            store
+                .append_clipboard(hash, b"!", key.as_deref())
+                .await
+                .unwrap();
+
+            let clipboard = store.get_clipboard(hash).await.unwrap();
+            assert_eq!(clipboard.as_ref() as &[u8], format!("{text}!").as_bytes());
+            assert_eq!(
+                persist::clipboard_size(dir, hash).unwrap(),
+                text.len() as u64 + 1
+            );
+        }
+
+        let compressed = persist_async::clipboard_file_compression(dir, "cmp0")
+            .await
+            .unwrap();
+        assert_eq!(compressed, Some(Compression::Gzip));
+        assert!(persist::clipboard_file_len(dir, "cmp0").unwrap() < text.len() as u64);
+
+        let compressed = persist_async::clipboard_file_compression(dir, "cmp1")
+            .await
+            .unwrap();
+        assert_eq!(compressed, None);
+    }
+
+    #[tokio::test]
+    async fn test_evict() {
+        persist::assert_dir(None);
+
+        let dur = Duration::from_secs(60);
+        for evict_to_disk in [false, true] {
+            let store = Arc::new(Store::with_config(StoreConfig {
+                max_mem_bytes: Some(8),
+                evict_to_disk,
+                ..StoreConfig::default()
+            }));
+
+            for hash in ["lru0", "lru1"] {
+                let clipboard = Clipboard::Mem("abcd".into());
+                Store::store_new_clipboard(
+                    store.clone(),
+                    hash,
+                    hash,
+                    clipboard,
+                    dur,
+                    StoreOpts::default(),
+                )
+                .await
+                .unwrap();
+            }
+            assert_eq!(store.mem_bytes(), 8);
+
+            // lru0 was used more recently than lru1, so lru1 is evicted
+            store.get_clipboard("lru0").await.unwrap();
+            let clipboard = Clipboard::Mem("efgh".into());
+            Store::store_new_clipboard(
+                store.clone(),
+                "lru2",
+                "lru2",
+                clipboard,
+                dur,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(store.mem_bytes(), 8);
+            assert_eq!(store.is_persisted("lru0"), Some(false));
+            assert_eq!(store.is_persisted("lru2"), Some(false));
+
+            match evict_to_disk {
+                false => assert!(store.get_clipboard("lru1").await.is_none()),
+                true => {
+                    assert_eq!(store.is_persisted("lru1"), Some(true));
+                    let clipboard = store.get_clipboard("lru1").await.unwrap();
+                    assert_eq!(clipboard.as_ref() as &[u8], b"abcd");
+                    // Remove the demoted file
+                    let id = store.haystack.get("lru1").unwrap().id;
+                    store.expire("lru1", id).await.unwrap();
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::with_config(StoreConfig {
+            max_versions: 2,
+            ..StoreConfig::default()
+        }));
+        let dur = Duration::from_secs(60);
+        let hash = "ver0";
+
+        let clipboards = [
+            ("v1", Clipboard::Mem("v1".into())),
+            ("v2", Clipboard::Persist("v2".into())),
+            // Same content is not a new version
+            ("v2", Clipboard::Persist("v2".into())),
+            ("v3", Clipboard::Mem("v3".into())),
+            ("v4", Clipboard::Persist("v4".into())),
+        ];
+
+        let mut opts = StoreOpts::default();
+        for (digest, clipboard) in clipboards {
+            let owner_key = Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                digest,
+                clipboard,
+                dur,
+                opts.clone(),
+            )
+            .await
+            .unwrap();
+            opts.owner_key = opts.owner_key.or(owner_key);
+        }
+
+        let versions = store.versions(hash).unwrap();
+        let numbers: Vec<_> = versions.iter().map(|v| (v.version, v.current)).collect();
+        assert_eq!(numbers, [(2, false), (3, false), (4, true)]);
+
+        for (version, content) in [(2, "v2"), (3, "v3"), (4, "v4")] {
+            let clipboard = store.get_version(hash, version).await.unwrap();
+            assert_eq!(clipboard.as_ref() as &[u8], content.as_bytes());
+        }
+
+        // Version 1 was dropped from history
+        assert!(store.get_version(hash, 1).await.is_none());
+        assert!(store.versions("nope").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_public_drops() {
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(60);
+        let public = StoreOpts {
+            public: true,
+            ..StoreOpts::default()
+        };
+
+        let clipboards = [
+            ("pub0", "pub0", public.clone()),
+            ("pub1", "pub1", StoreOpts::default()),
+            ("team/pub2", "pub2", public.clone()),
+            (
+                "pub3",
+                "pub3",
+                StoreOpts {
+                    encryption: Some("aes-256-gcm:iv".to_string()),
+                    ..public.clone()
+                },
+            ),
+            ("pub4", "pub4", public.clone()),
+        ];
+
+        let mut keys = HashMap::new();
+        for (hash, content, opts) in clipboards {
+            let clipboard = Clipboard::Mem(content.into());
+            let key = Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts)
+                .await
+                .unwrap();
+            keys.insert(hash, key);
+        }
+
+        let drops = store.public_drops();
+        let listed: Vec<_> = drops
+            .iter()
+            .map(|d| (d.hash.as_str(), d.snippet.as_deref()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("pub4", Some("pub4")),
+                ("pub3", None),
+                ("pub0", Some("pub0"))
+            ]
+        );
+
+        // Replaced by a post that was not public, or removed
+        let opts = StoreOpts {
+            owner_key: keys["pub4"].clone(),
+            ..StoreOpts::default()
+        };
+        let clipboard = Clipboard::Mem("private".into());
+        Store::store_new_clipboard(store.clone(), "pub4", "private", clipboard, dur, opts)
+            .await
+            .unwrap();
+        store.remove_clipboard("pub0").await.unwrap();
+
+        let drops = store.public_drops();
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0].hash, "pub3");
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(60);
+        let encrypted = StoreOpts {
+            encryption: Some("aes-256-gcm:iv".to_string()),
+            ..StoreOpts::default()
+        };
+
+        let clipboards = [
+            (
+                "srch0",
+                Clipboard::Mem("needle in a needle".into()),
+                StoreOpts::default(),
+            ),
+            (
+                "srch1",
+                Clipboard::Persist("haystack needle".into()),
+                StoreOpts::default(),
+            ),
+            (
+                "srch2",
+                Clipboard::Mem("nothing here".into()),
+                StoreOpts::default(),
+            ),
+            (
+                "srch3",
+                Clipboard::Mem("encrypted needle".into()),
+                encrypted,
+            ),
+            (
+                "team/srch4",
+                Clipboard::Mem("tenant needle".into()),
+                StoreOpts::default(),
+            ),
+        ];
+
+        let mut keys = HashMap::new();
+        for (hash, clipboard, opts) in clipboards {
+            let key = Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts)
+                .await
+                .unwrap();
+            keys.insert(hash, key.unwrap());
+        }
+
+        let needle = search::pattern("needle", false).unwrap();
+        let found = |hits: Vec<SearchHit>| -> Vec<(String, Vec<u64>)> {
+            hits.into_iter()
+                .map(|hit| (hit.hash, hit.offsets))
+                .collect()
+        };
+
+        assert_eq!(
+            found(store.search(&needle, None, false).await),
+            [("srch0".to_string(), vec![0, 12])]
+        );
+        assert_eq!(
+            found(store.search(&needle, None, true).await),
+            [
+                ("srch0".to_string(), vec![0, 12]),
+                ("srch1".to_string(), vec![9])
+            ]
+        );
+
+        // Owners only find their own clipboards
+        let owner = owner::digest(&keys["srch1"]);
+        let hits = store.search(&needle, Some(&owner), true).await;
+        assert_eq!(found(hits), [("srch1".to_string(), vec![9])]);
+
+        // Searching does not count as a view
+        assert_eq!(store.meta("srch1").unwrap().views, 0);
+        store.remove_clipboard("srch1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tags() {
+        let store = Arc::new(Store::new());
+        let dur = Duration::from_secs(60);
+        let tagged = |tags: &[&str]| StoreOpts {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..StoreOpts::default()
+        };
+
+        let clipboards = [
+            ("tag0", tagged(&["work", "logs"])),
+            ("tag1", tagged(&["logs"])),
+            ("tag2", tagged(&[])),
+            ("team/tag3", tagged(&["logs"])),
+        ];
+
+        let mut keys = HashMap::new();
+        for (hash, opts) in clipboards {
+            let clipboard = Clipboard::Mem(hash.into());
+            let key = Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts)
+                .await
+                .unwrap();
+            keys.insert(hash, key.unwrap());
+        }
+
+        let hashes = |drops: Vec<IndexEntry>| -> Vec<String> {
+            drops.into_iter().map(|drop| drop.hash).collect()
+        };
+
+        assert_eq!(hashes(store.drops(Some("logs"), None)), ["tag0", "tag1"]);
+        assert_eq!(hashes(store.drops(Some("work"), None)), ["tag0"]);
+        assert_eq!(hashes(store.drops(None, None)), ["tag0", "tag1", "tag2"]);
+        assert_eq!(store.meta("tag0").unwrap().tags, ["work", "logs"]);
+
+        let owner = owner::digest(&keys["tag1"]);
+        assert_eq!(hashes(store.drops(Some("logs"), Some(&owner))), ["tag1"]);
+        assert!(store.drops(Some("work"), Some(&owner)).is_empty());
+
+        // Tags go with the clipboard when it's replaced or removed
+        let opts = StoreOpts {
+            owner_key: Some(keys["tag0"].clone()),
+            ..tagged(&["work"])
+        };
+        let clipboard = Clipboard::Mem("replaced".into());
+        Store::store_new_clipboard(store.clone(), "tag0", "replaced", clipboard, dur, opts)
+            .await
+            .unwrap();
+        store.remove_clipboard("tag1").await.unwrap();
+
+        assert!(store.drops(Some("logs"), None).is_empty());
+        assert_eq!(hashes(store.drops(Some("work"), None)), ["tag0"]);
+        // Only the tenant clipboard is left with the tag
+        assert_eq!(store.tags.get("logs").unwrap().len(), 1);
+        store.remove_clipboard("team/tag3").await.unwrap();
+        assert!(!store.tags.contains_key("logs"));
+    }
+
+    #[tokio::test]
+    async fn test_dedupe() {
+        let (store, _, clock) = mock_store(StoreConfig {
+            dedupe: true,
+            ..StoreConfig::default()
+        });
+        let dur200 = Duration::from_millis(200);
+        let dur400 = Duration::from_millis(400);
+
+        let mut keys = HashMap::new();
+        for hash in ["ddp0", "team/ddp0"] {
+            let clipboard = Clipboard::Mem("dedupe".into());
+            let key = Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                "ddp-digest",
+                clipboard,
+                dur200,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+            keys.insert(hash, key.unwrap());
+        }
+
+        // Content is found under its live key, e.g. after hash_len changed, but only in its keyspace
+        let mut events = store.events();
+        let duplicate = |hash: &str, digest: &str, dur: Duration, owner_key: Option<&str>| {
+            Store::extend_duplicate(&store, hash, digest, dur, None, owner_key)
+        };
+
+        let key = duplicate("ddp0aa", "ddp-digest", dur400, None);
+        assert_eq!(key.as_deref(), Some("ddp0"));
+        let key = duplicate("team/ddp0aa", "ddp-digest", dur400, None);
+        assert_eq!(key.as_deref(), Some("team/ddp0"));
+        assert!(duplicate("other/ddp0", "ddp-digest", dur400, None).is_none());
+        assert!(duplicate("ddp1", "other-digest", dur400, None).is_none());
+
+        // Only posts with the owner key extend the timer, which is never shortened
+        assert!(events.try_recv().is_err(), "extended without the owner key");
+        for hash in ["ddp0", "team/ddp0"] {
+            assert!(duplicate(hash, "ddp-digest", dur400, Some(keys[hash].as_str())).is_some());
+            assert_eq!(events.try_recv().unwrap().event, EventKind::Extended);
+        }
+
+        assert!(duplicate("ddp0", "ddp-digest", dur200, Some(keys["ddp0"].as_str())).is_some());
+        assert!(events.try_recv().is_err(), "timer was shortened");
+        clock.advance(Duration::from_millis(300));
+        tokio::task::yield_now().await;
+        assert!(store.meta("ddp0").is_some());
+
+        // Appended clipboards no longer match their content
+        store
+            .append_clipboard("ddp0", b"+", Some(keys["ddp0"].as_str()))
+            .await
+            .unwrap();
+        assert!(duplicate("ddp0", "ddp-digest", dur400, None).is_none());
+        assert!(!store.digests.contains_key("ddp-digest"));
+
+        store.remove_clipboard("team/ddp0").await.unwrap();
+        assert!(store.digests.is_empty());
+
+        let disabled = Arc::new(Store::new());
+        let clipboard = Clipboard::Mem("dedupe".into());
+        Store::store_new_clipboard(
+            disabled.clone(),
+            "ddp0",
+            "ddp-digest",
+            clipboard,
+            dur400,
+            StoreOpts::default(),
+        )
+        .await
+        .unwrap();
+        let key = Store::extend_duplicate(&disabled, "ddp0", "ddp-digest", dur400, None, None);
+        assert!(key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage() {
+        persist::assert_dir(None);
+
+        let store = Arc::new(Store::with_config(StoreConfig {
+            max_disk_bytes: Some(10),
+            ..StoreConfig::default()
+        }));
+        let dur = Duration::from_secs(60);
+        let post = |hash: &'static str, clipboard: Clipboard, owner_key: Option<String>| {
+            let opts = StoreOpts {
+                owner_key,
+                ..StoreOpts::default()
+            };
+
+            Store::store_new_clipboard(store.clone(), hash, hash, clipboard, dur, opts)
+        };
+
+        let key = post("dsk0", Clipboard::Persist("sixsix".into()), None)
+            .await
+            .unwrap();
+        assert_eq!(store.disk_bytes(), 6);
+        assert!(matches!(
+            post("dsk1", Clipboard::Persist("sixsix".into()), None).await,
+            Err(StoreError::DiskFull(10))
+        ));
+        assert!(!persist::clipboard_file_exists(
+            Path::new(persist::DIR),
+            "dsk1"
+        ));
+
+        // In-memory clipboards do not count
+        post("dsk1", Clipboard::Mem("sixsix".into()), None)
+            .await
+            .unwrap();
+        assert_eq!(store.disk_bytes(), 6);
+
+        // Replaced clipboards no longer count
+        post("dsk0", Clipboard::Persist("eighteig".into()), key.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.disk_bytes(), 8);
+
+        assert!(matches!(
+            store.append_clipboard("dsk0", b"abc", key.as_deref()).await,
+            Err(StoreError::DiskFull(10))
+        ));
+        store
+            .append_clipboard("dsk0", b"ab", key.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(store.disk_bytes(), 10);
+
+        store.remove_clipboard("dsk0").await.unwrap();
+        store.remove_clipboard("dsk1").await.unwrap();
+        assert_eq!(store.disk_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_available_at() {
+        let (store, _, clock) = mock_store(StoreConfig::default());
+        let mut events = store.events();
+        let hash = "held00";
+        let at = index::to_timestamp(clock.now()) + 10;
+        let opts = StoreOpts {
+            available_at: Some(at),
+            public: true,
+            ..StoreOpts::default()
+        };
+        let post = |dur| {
+            Store::store_new_clipboard(
+                store.clone(),
+                hash,
+                hash,
+                Clipboard::Mem("embargoed".into()),
+                dur,
+                opts.clone(),
+            )
+        };
+
+        // Clipboards must be available before they expire
+        assert!(matches!(
+            post(Duration::from_secs(5)).await,
+            Err(StoreError::InvalidTtl(_))
+        ));
+
+        post(Duration::from_secs(60)).await.unwrap();
+        assert!(store.get_clipboard(hash).await.is_none());
+        assert!(matches!(store.not_found(hash), StoreError::TooEarly(t) if t == at));
+        assert!(store.meta(hash).is_none() && store.peek_clipboard(hash).await.is_none());
+        assert!(store.versions(hash).is_none() && store.find_digest(hash).is_none());
+        assert!(store.drops(None, None).is_empty());
+        assert_eq!(store.export_meta(hash).unwrap().available_at, Some(at));
+        assert_eq!(&*store.export_clipboard(hash).await.unwrap(), b"embargoed");
+        assert!(store.public_drops().is_empty());
+
+        clock.advance(Duration::from_secs(10));
+        loop {
+            let event = events.recv().await.unwrap();
+            if event.event == EventKind::Available && event.hash == hash {
+                break;
+            }
+        }
 
-        tokio::spawn(tokio::time::sleep(dur200)).await.unwrap();
+        assert_eq!(&*store.get_clipboard(hash).await.unwrap(), b"embargoed");
+        assert_eq!(store.available_at(hash), None);
+        assert_eq!(store.public_drops().len(), 1);
+        assert_eq!(store.versions(hash).unwrap().len(), 1);
+        assert_eq!(store.find_digest(hash).as_deref(), Some(hash));
 
-        assert!(store.get_clipboard(hash).is_none());
+        // The clipboard still expires on time
+        clock.advance(Duration::from_secs(50));
+        expired(&mut events, hash).await;
+        assert!(store.get_clipboard(hash).await.is_none());
     }
 }
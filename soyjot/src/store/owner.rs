@@ -0,0 +1,39 @@
+//! Owner keys are secrets handed to the creator of a clipboard, which must be presented
+//! to replace, extend, append to or delete the clipboard (see `StoreOpts::owner_key`).
+//! Only the SHA-256 digest of a key is kept in the store and its index.
+
+use crate::hash::{HashAlgo, Hasher};
+
+/// Number of random bytes in an owner key, which is sent hex-encoded
+const KEY_BYTES: usize = 32;
+
+/// new_key returns a new random hex-encoded owner key
+pub fn new_key() -> String {
+    let mut key = [0u8; KEY_BYTES];
+    getrandom::getrandom(&mut key).expect("failed to get random bytes for owner key");
+
+    key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// digest returns the hex-encoded digest of `key` that is stored with its clipboard
+pub fn digest(key: &str) -> String {
+    let mut hasher = Hasher::new(HashAlgo::Sha256);
+    hasher.update(key.as_bytes());
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_key() {
+        let (a, b) = (new_key(), new_key());
+
+        assert_eq!(a.len(), KEY_BYTES * 2);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+        assert_eq!(digest(&a), digest(&a));
+        assert_ne!(digest(&a), digest(&b));
+    }
+}
@@ -1,15 +1,265 @@
 use std::env;
-use std::path::Path;
+use std::future::Future;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
+use futures_util::future::BoxFuture;
+
+use super::checksum;
+use super::compress::{self, CompressConfig};
 use super::error::StoreError;
+use super::index::{IndexEntry, INDEX_FILE, META_SUFFIX};
+use super::persist_async::{self, TMP_PREFIX};
+use crate::tenant;
+
+mod cache;
+mod memory;
+
+pub use cache::ReadCache;
+pub use memory::InMemoryFs;
+
+// Default storage directory, relative to the working directory.
+pub const DIR: &str = "./drop";
+
+// Characters of a clipboard hash naming each level of its shard directories, see `file_path`
+const SHARD_LEN: usize = 2;
+
+/// DiskUsage accounts for the total size of persisted clipboards in bytes,
+/// and is updated as clipboard files are written and removed (see `Store::disk_bytes`).
+/// Clipboards count with their full size, even if their files are compressed.
+#[derive(Debug, Default)]
+pub struct DiskUsage {
+    bytes: AtomicU64,
+}
 
-// Default hard-coded storage directory.
-const DIR: &'static str = "./drop";
+impl DiskUsage {
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
 
-pub fn assert_dir(conf_dir: Option<String>) {
+    pub fn sub(&self, bytes: u64) {
+        // Never fails, since the closure always returns Some
+        let _ = self
+            .bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    /// fits reports whether `bytes` more can be written without going over `max`,
+    /// once `freed` bytes of a replaced clipboard are removed
+    pub fn fits(&self, bytes: u64, freed: u64, max: Option<u64>) -> bool {
+        max.is_none_or(|max| self.bytes().saturating_sub(freed) + bytes <= max)
+    }
+}
+
+/// Persist holds the clipboard files of a `Store`. `DiskFs` keeps them in the storage
+/// directory, and `InMemoryFs` in memory, e.g. so that tests don't touch the filesystem.
+/// Files are named by clipboard key, and hold the content compressed according to
+/// the `CompressConfig` they were written with (see `compress::encode`).
+pub trait Persist: Send + Sync {
+    /// write writes `content` to clipboard file `name`, replacing any previous content
+    fn write<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+        conf: &'a CompressConfig,
+    ) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// read returns the decompressed content of clipboard file `name`
+    fn read<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<u8>, StoreError>>;
+
+    /// append appends `content` to the end of clipboard file `name`, keeping its compression
+    fn append<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// remove removes clipboard file `name`
+    fn remove<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// adopt moves temporary file `tmp` (see `persist_async::create_tmp_file`)
+    /// to clipboard file `name` as-is
+    fn adopt<'a>(&'a self, tmp: PathBuf, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// exists reports whether there's a clipboard file `name`
+    fn exists(&self, name: &str) -> bool;
+
+    /// size returns the size of the decompressed content of clipboard file `name` in bytes
+    fn size(&self, name: &str) -> Result<u64, StoreError>;
+
+    /// remove_blocking removes clipboard file `name` like `remove`, blocking the thread
+    fn remove_blocking(&self, name: &str) -> Result<(), StoreError>;
+
+    /// write_meta keeps `meta` with clipboard file `name`, replacing any previous metadata,
+    /// until the file is removed
+    fn write_meta<'a>(
+        &'a self,
+        name: &'a str,
+        meta: &'a IndexEntry,
+    ) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// read_meta returns the metadata last kept with clipboard file `name`, if any,
+    /// blocking the thread
+    fn read_meta(&self, name: &str) -> Result<Option<IndexEntry>, StoreError>;
+
+    /// dir returns the directory the files are kept in, if they are kept on disk,
+    /// e.g. so that uploads can be streamed there and files served from there directly
+    fn dir(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// DiskFs keeps clipboard files in its storage directory `root`, see `file_path`.
+/// Each instance has its own directory, so instances with different directories
+/// can be used side by side in one process.
+#[derive(Debug)]
+pub struct DiskFs {
+    root: PathBuf,
+    /// Sync files to disk before writes return, see `persist_async::write_clipboard_file`
+    fsync: bool,
+    cache: ReadCache,
+}
+
+impl DiskFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            fsync: false,
+            cache: ReadCache::default(),
+        }
+    }
+
+    /// fsync sets whether clipboard files are synced to disk before writes return,
+    /// so that they survive power loss, at the cost of slower writes
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// read_cache caches up to `max_bytes` of recently read clipboards in memory
+    /// (see `ReadCache`), which is disabled with 0, the default
+    pub fn read_cache(mut self, max_bytes: u64) -> Self {
+        self.cache = ReadCache::new(max_bytes);
+        self
+    }
+
+    /// invalidating runs `op` on clipboard file `name`, and then drops the file from the cache.
+    /// Reads that finish while `op` runs may still cache the old content until then.
+    fn invalidating<'a>(
+        &'a self,
+        name: &'a str,
+        op: impl Future<Output = Result<(), StoreError>> + Send + 'a,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let result = op.await;
+            self.cache.invalidate(name);
+
+            result
+        })
+    }
+}
+
+impl Default for DiskFs {
+    fn default() -> Self {
+        Self::new(DIR)
+    }
+}
+
+impl Persist for DiskFs {
+    fn write<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+        conf: &'a CompressConfig,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        self.invalidating(
+            name,
+            persist_async::write_clipboard_file(&self.root, name, content, conf, self.fsync),
+        )
+    }
+
+    fn read<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<u8>, StoreError>> {
+        Box::pin(async move {
+            if let Some(content) = self.cache.get(name) {
+                return Ok(content);
+            }
+
+            let generation = self.cache.generation();
+            let content = persist_async::read_clipboard_file(&self.root, name).await?;
+            self.cache.insert(name, &content, generation);
+
+            Ok(content)
+        })
+    }
+
+    fn append<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        self.invalidating(
+            name,
+            persist_async::append_clipboard_file(&self.root, name, content, self.fsync),
+        )
+    }
+
+    fn remove<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        self.invalidating(name, persist_async::rm_clipboard_file(&self.root, name))
+    }
+
+    fn adopt<'a>(&'a self, tmp: PathBuf, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        self.invalidating(
+            name,
+            persist_async::rename_tmp_file(&self.root, tmp, name, self.fsync),
+        )
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        clipboard_file_exists(&self.root, name)
+    }
+
+    fn size(&self, name: &str) -> Result<u64, StoreError> {
+        clipboard_size(&self.root, name)
+    }
+
+    fn remove_blocking(&self, name: &str) -> Result<(), StoreError> {
+        let result = rm_clipboard_file(&self.root, name);
+        self.cache.invalidate(name);
+
+        result
+    }
+
+    fn write_meta<'a>(
+        &'a self,
+        name: &'a str,
+        meta: &'a IndexEntry,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(persist_async::write_meta_file(
+            &self.root, name, meta, self.fsync,
+        ))
+    }
+
+    fn read_meta(&self, name: &str) -> Result<Option<IndexEntry>, StoreError> {
+        read_meta_file(&self.root, name)
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
+
+pub fn assert_dir(conf_dir: Option<&Path>) {
     let dir = match conf_dir {
-        Some(d) if !d.is_empty() => d,
-        _ => DIR.to_string(),
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from(DIR),
     };
 
     let create_dir = |d| {
@@ -21,47 +271,449 @@ pub fn assert_dir(conf_dir: Option<String>) {
         Ok(false) => create_dir(dir),
 
         Err(err) => {
-            panic!("bad directory: {}", err.to_string());
+            panic!("bad directory: {err}");
         }
 
         _ => {}
     }
 }
 
-pub fn write_clipboard_file<S>(name: S, content: &[u8]) -> Result<(), StoreError>
+/// file_path returns the path of clipboard file `name` in storage directory `dir`,
+/// which is sharded by the clipboard's hash into two levels of subdirectories,
+/// e.g. `ab/cd/abcdef`, so that no directory holds too many files.
+/// Hashes shorter than the shards are padded with `_`. Tenant clipboards
+/// are sharded within the tenant's subdirectory, e.g. `{tenant}/ab/cd/abcdef`.
+pub fn file_path<S>(dir: &Path, name: S) -> PathBuf
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(name.as_ref());
-    std::fs::write(path, content)?;
+    let (tenant, hash) = tenant::split(name.as_ref());
+    let (first, second) = shards(hash);
 
-    Ok(())
+    let mut path = dir.to_path_buf();
+    path.extend(tenant);
+    path.extend([first.as_str(), second.as_str(), hash]);
+
+    path
 }
 
-pub fn read_clipboard_file<S>(id: S) -> Result<Vec<u8>, StoreError>
+/// shards returns the names of the two shard directories of clipboard `hash`
+fn shards(hash: &str) -> (String, String) {
+    let mut chars = hash.chars().chain(std::iter::repeat('_'));
+    let first = chars.by_ref().take(SHARD_LEN).collect();
+    let second = chars.take(SHARD_LEN).collect();
+
+    (first, second)
+}
+
+/// write_clipboard_file writes `content` to clipboard file `name`,
+/// compressing it according to `conf` (see `compress::encode`).
+/// The file's shard directories are created if needed.
+/// Like `persist_async::write_clipboard_file`, the content is written to a temporary file
+/// that is then renamed into place, and synced to disk first with `fsync`.
+pub fn write_clipboard_file<S>(
+    dir: &Path,
+    name: S,
+    content: &[u8],
+    conf: &CompressConfig,
+    fsync: bool,
+) -> Result<(), StoreError>
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let path = file_path(dir, name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    rm_sidecar(&path)?;
+    write_atomic(dir, &path, &compress::encode(content, conf)?, fsync)?;
+
+    let sum = checksum::checksum(content);
+    write_atomic(dir, &checksum::sidecar(&path), sum.as_bytes(), fsync)
+}
+
+/// write_atomic writes `data` to a temporary file in `dir`, and then renames it to `path`
+fn write_atomic(dir: &Path, path: &Path, data: &[u8], fsync: bool) -> Result<(), StoreError> {
+    use std::io::Write;
+
+    let tmp = persist_async::tmp_path(dir);
+    let written = (|| -> Result<(), StoreError> {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(data)?;
+
+        if fsync {
+            file.sync_all()?;
+        }
+
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    })();
+
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    written
+}
+
+/// rm_sidecar removes the checksum file of clipboard file `path`, if there is one
+fn rm_sidecar(path: &Path) -> Result<(), StoreError> {
+    rm_if_exists(&checksum::sidecar(path))
+}
+
+fn rm_if_exists(path: &Path) -> Result<(), StoreError> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// meta_path returns the path of the metadata file of clipboard file `path`
+/// (see `Persist::write_meta`)
+pub fn meta_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(path.as_os_str());
+    name.push(META_SUFFIX);
+
+    PathBuf::from(name)
+}
+
+/// read_meta_file returns the metadata kept with clipboard file `id`, if there is any
+pub fn read_meta_file<S>(dir: &Path, id: S) -> Result<Option<IndexEntry>, StoreError>
+where
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(id.as_ref());
-    let data = std::fs::read(path)?;
+    let path = meta_path(&file_path(dir, id));
+
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// read_clipboard_file returns the content of clipboard file `id`, verified against
+/// its checksum like `persist_async::read_clipboard_file`. Corrupt files are removed.
+pub fn read_clipboard_file<S>(dir: &Path, id: S) -> Result<Vec<u8>, StoreError>
+where
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id);
+    let data = compress::decode(std::fs::read(&path)?)?;
+
+    let expected = match std::fs::read(checksum::sidecar(&path)) {
+        Ok(expected) => Some(expected),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    if let Err(err) = checksum::verify(&data, expected.as_deref()) {
+        rm_sidecar(&path)?;
+        std::fs::remove_file(path)?;
+
+        return Err(err);
+    }
 
     Ok(data)
 }
 
-pub fn rm_clipboard_file<S>(id: S) -> Result<(), StoreError>
+/// clipboard_file_len returns the size of clipboard file `id` in bytes
+pub fn clipboard_file_len<S>(dir: &Path, id: S) -> Result<u64, StoreError>
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id);
+    let metadata = std::fs::metadata(path)?;
+
+    Ok(metadata.len())
+}
+
+/// clipboard_size returns the size of the content of clipboard file `id` in bytes.
+/// Compressed files have to be read and decompressed to find it.
+pub fn clipboard_size<S>(dir: &Path, id: S) -> Result<u64, StoreError>
+where
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id.as_ref());
+    let mut header = [0; compress::HEADER_LEN];
+    let n = std::fs::File::open(&path)?.read(&mut header)?;
+
+    match compress::header(&header[..n]) {
+        Some(_) => Ok(read_clipboard_file(dir, id)?.len() as u64),
+        None => Ok(std::fs::metadata(path)?.len()),
+    }
+}
+
+pub fn rm_clipboard_file<S>(dir: &Path, id: S) -> Result<(), StoreError>
+where
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(id.as_ref());
+    let path = file_path(dir, id);
+    std::fs::remove_file(&path)?;
+
+    rm_sidecar(&path)?;
+    rm_if_exists(&meta_path(&path))
+}
+
+pub fn write_index(dir: &Path, entries: &[IndexEntry]) -> Result<(), StoreError> {
+    let path = dir.join(INDEX_FILE);
+    std::fs::write(path, serde_json::to_vec(entries)?)?;
+
+    Ok(())
+}
+
+/// read_index reads and then removes the index file,
+/// so that a stale index is never restored twice.
+/// If there's no index file, an empty index is returned.
+pub fn read_index(dir: &Path) -> Result<Vec<IndexEntry>, StoreError> {
+    let path = dir.join(INDEX_FILE);
+
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
     std::fs::remove_file(path)?;
 
+    Ok(serde_json::from_slice(&data)?)
+}
+
+pub fn clipboard_file_exists<S>(dir: &Path, id: S) -> bool
+where
+    S: AsRef<str>,
+{
+    file_path(dir, id).is_file()
+}
+
+/// scan_dir lists global clipboard files in the shards of storage directory `dir`
+/// with their modification times. Leftover temporary files (e.g. from uploads interrupted
+/// by a crash) are removed.
+pub fn scan_dir(dir: &Path) -> Result<Vec<(String, SystemTime)>, StoreError> {
+    read_dir(dir, true)
+}
+
+/// list_dir lists clipboard files like `scan_dir`, but skips temporary files,
+/// which may belong to uploads still in progress, e.g. while the server is running
+pub fn list_dir(dir: &Path) -> Result<Vec<(String, SystemTime)>, StoreError> {
+    read_dir(dir, false)
+}
+
+fn read_dir(dir: &Path, rm_tmp: bool) -> Result<Vec<(String, SystemTime)>, StoreError> {
+    let mut files = Vec::new();
+
+    for (first, path, is_dir) in dir_entries(dir)? {
+        if rm_tmp && !is_dir && first.starts_with(TMP_PREFIX) {
+            std::fs::remove_file(path)?;
+            continue;
+        }
+
+        // Tenant subdirectories are skipped, since their entries are not shard directories
+        if !is_dir || first.chars().count() != SHARD_LEN {
+            continue;
+        }
+
+        for (second, path, is_dir) in dir_entries(&path)? {
+            if !is_dir {
+                continue;
+            }
+
+            for (name, path, is_dir) in dir_entries(&path)? {
+                if is_dir {
+                    continue;
+                }
+
+                // Sidecars whose clipboard file is gone, e.g. after a crash, are removed too
+                if let Some(file) = name
+                    .strip_suffix(checksum::SUFFIX)
+                    .or_else(|| name.strip_suffix(META_SUFFIX))
+                {
+                    if rm_tmp && !path.with_file_name(file).exists() {
+                        std::fs::remove_file(&path)?;
+                    }
+
+                    continue;
+                }
+
+                if shards(&name) != (first.clone(), second.clone()) {
+                    continue;
+                }
+
+                files.push((name, std::fs::metadata(path)?.modified()?));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// migrate_flat_files moves clipboard files left unsharded in storage directory `dir`
+/// by older versions into their shards (see `file_path`), and returns how many were moved.
+/// Unsharded files are directly in `dir`, or directly in a tenant's subdirectory,
+/// where sharded layouts only have directories.
+pub fn migrate_flat_files(dir: &Path) -> Result<usize, StoreError> {
+    let mut moved = 0;
+
+    for (name, path, is_dir) in dir_entries(dir)? {
+        if !is_dir {
+            if name != INDEX_FILE && !name.starts_with(TMP_PREFIX) {
+                move_file(&path, &file_path(dir, &name))?;
+                moved += 1;
+            }
+
+            continue;
+        }
+
+        for (hash, path, is_dir) in dir_entries(&path)? {
+            if !is_dir {
+                move_file(&path, &file_path(dir, tenant::key(&name, &hash)))?;
+                moved += 1;
+            }
+        }
+    }
+
+    Ok(moved)
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<(), StoreError> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::rename(from, to)?;
+
     Ok(())
 }
 
-pub fn dir_exists(dst: &str) -> std::io::Result<bool> {
-    let mut pwd = env::current_dir()?;
-    pwd.push(dst);
-    let metadata = std::fs::metadata(pwd)?;
+/// dir_entries lists the entries of `dir` with UTF-8 names, with their paths
+/// and whether they are directories
+fn dir_entries(dir: &Path) -> Result<Vec<(String, PathBuf, bool)>, StoreError> {
+    let mut entries = Vec::new();
+
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let Ok(name) = dir_entry.file_name().into_string() else {
+            continue;
+        };
+
+        entries.push((name, dir_entry.path(), dir_entry.file_type()?.is_dir()));
+    }
+
+    Ok(entries)
+}
+
+pub fn dir_exists<S>(dst: S) -> std::io::Result<bool>
+where
+    S: AsRef<Path>,
+{
+    let metadata = std::fs::metadata(env::current_dir()?.join(dst))?;
 
     Ok(metadata.is_dir())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_path() {
+        let dir = Path::new(DIR);
+        let path = |parts: &[&str]| parts.iter().fold(dir.to_path_buf(), |path, p| path.join(p));
+
+        assert_eq!(file_path(dir, "abcdef"), path(&["ab", "cd", "abcdef"]));
+        assert_eq!(file_path(dir, "abc"), path(&["ab", "c_", "abc"]));
+        assert_eq!(
+            file_path(dir, "red/abcdef"),
+            path(&["red", "ab", "cd", "abcdef"])
+        );
+    }
+
+    #[test]
+    fn test_write_clipboard_file() {
+        let dir = Path::new(DIR).join("atomic");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let conf = CompressConfig::default();
+        for fsync in [false, true] {
+            write_clipboard_file(&dir, "atom0", b"atomic", &conf, fsync).unwrap();
+            assert_eq!(read_clipboard_file(&dir, "atom0").unwrap(), b"atomic");
+        }
+
+        // Only the clipboard's shard is left, no temporary files
+        let entries = dir_entries(&dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "at");
+    }
+
+    #[tokio::test]
+    async fn test_read_cache() {
+        let dir = Path::new(DIR).join("cached");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let files = DiskFs::new(&dir).read_cache(1024);
+        let conf = CompressConfig::default();
+        files.write("cach0", b"cached", &conf).await.unwrap();
+        assert_eq!(files.read("cach0").await.unwrap(), b"cached");
+
+        // Cached clipboards are served from memory
+        std::fs::write(file_path(&dir, "cach0"), b"behind the cache's back").unwrap();
+        assert_eq!(files.read("cach0").await.unwrap(), b"cached");
+
+        // and dropped from the cache when they are replaced or removed
+        files.write("cach0", b"replaced", &conf).await.unwrap();
+        assert_eq!(files.read("cach0").await.unwrap(), b"replaced");
+        files.append("cach0", b"!").await.unwrap();
+        assert_eq!(files.read("cach0").await.unwrap(), b"replaced!");
+
+        files.remove("cach0").await.unwrap();
+        assert!(files.read("cach0").await.is_err());
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let dir = Path::new(DIR).join("corrupt");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        write_clipboard_file(&dir, "crpt0", b"intact", &CompressConfig::default(), false).unwrap();
+        let path = file_path(&dir, "crpt0");
+        assert!(checksum::sidecar(&path).is_file());
+
+        std::fs::write(&path, b"flipped").unwrap();
+        assert!(matches!(
+            read_clipboard_file(&dir, "crpt0"),
+            Err(StoreError::Corrupt)
+        ));
+        assert!(!path.exists());
+        assert!(!checksum::sidecar(&path).exists());
+    }
+
+    #[test]
+    fn test_migrate_flat_files() {
+        let dir = Path::new(DIR).join("migrate");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("red")).unwrap();
+
+        let conf = CompressConfig::default();
+        for name in ["mig0", "red/mig1"] {
+            std::fs::write(dir.join(name), compress::encode(b"flat", &conf).unwrap()).unwrap();
+        }
+        std::fs::write(dir.join(INDEX_FILE), "[]").unwrap();
+
+        assert_eq!(migrate_flat_files(&dir).unwrap(), 2);
+        assert_eq!(migrate_flat_files(&dir).unwrap(), 0);
+
+        for name in ["mig0", "red/mig1"] {
+            assert_eq!(read_clipboard_file(&dir, name).unwrap(), b"flat");
+        }
+
+        // Only global clipboards are listed, from their shards
+        let files = scan_dir(&dir).unwrap();
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["mig0"]);
+        assert!(dir.join(INDEX_FILE).is_file());
+    }
+}
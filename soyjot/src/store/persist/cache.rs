@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct Lru {
+    /// Content of each cached file, with the tick it was last read at
+    files: HashMap<String, (Vec<u8>, u64)>,
+    /// Cached files by the tick they were last read at, least recently read first
+    order: BTreeMap<u64, String>,
+    /// Total size of the cached content in bytes
+    bytes: u64,
+    tick: u64,
+}
+
+impl Lru {
+    fn remove(&mut self, name: &str) {
+        if let Some((content, tick)) = self.files.remove(name) {
+            self.order.remove(&tick);
+            self.bytes -= content.len() as u64;
+        }
+    }
+}
+
+/// ReadCache keeps the decompressed content of recently read clipboard files in memory,
+/// up to `max_bytes` in total, so that popular clipboards are not read from disk
+/// on every view. The least recently read files are evicted first.
+/// Files must be invalidated whenever they are written or removed.
+#[derive(Debug, Default)]
+pub struct ReadCache {
+    max_bytes: u64,
+    lru: Mutex<Lru>,
+    /// Bumped by every invalidation, so that content read before one is not cached after it
+    generation: AtomicU64,
+}
+
+impl ReadCache {
+    /// new returns a cache of up to `max_bytes`, which caches nothing if `max_bytes` is 0
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.lru.lock().expect("read cache lock poisoned")
+    }
+
+    /// generation returns the generation to pass to `insert` for content read from now on
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// get returns the cached content of file `name`, marking it as recently read
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        let mut lru = self.lock();
+        lru.tick += 1;
+        let tick = lru.tick;
+
+        let (content, last) = lru.files.get_mut(name)?;
+        let (content, last) = (content.clone(), std::mem::replace(last, tick));
+        lru.order.remove(&last);
+        lru.order.insert(tick, name.to_owned());
+
+        Some(content)
+    }
+
+    /// insert caches `content` of file `name` that was read at `generation`,
+    /// unless a file was invalidated since or the content is larger than the whole cache
+    pub fn insert(&self, name: &str, content: &[u8], generation: u64) {
+        let size = content.len() as u64;
+        if self.max_bytes == 0 || size > self.max_bytes {
+            return;
+        }
+
+        let mut lru = self.lock();
+        // Checked under the lock, since invalidations take it too
+        if self.generation() != generation {
+            return;
+        }
+
+        lru.remove(name);
+        while lru.bytes + size > self.max_bytes {
+            let Some((_, evicted)) = lru.order.pop_first() else {
+                break;
+            };
+
+            if let Some((content, _)) = lru.files.remove(&evicted) {
+                lru.bytes -= content.len() as u64;
+            }
+        }
+
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.files.insert(name.to_owned(), (content.to_vec(), tick));
+        lru.order.insert(tick, name.to_owned());
+        lru.bytes += size;
+    }
+
+    /// invalidate forgets file `name`, which was just written or removed
+    pub fn invalidate(&self, name: &str) {
+        if self.max_bytes == 0 {
+            return;
+        }
+
+        let mut lru = self.lock();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        lru.remove(name);
+    }
+
+    /// bytes returns the total size of the cached content in bytes
+    pub fn bytes(&self) -> u64 {
+        self.lock().bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_cache() {
+        let cache = ReadCache::new(8);
+
+        let generation = cache.generation();
+        cache.insert("a", b"aaaa", generation);
+        cache.insert("b", b"bbbb", generation);
+        assert_eq!(cache.get("a").as_deref(), Some(&b"aaaa"[..]));
+
+        // b was read least recently, so it's evicted first
+        cache.insert("c", b"cc", generation);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.bytes(), 6);
+
+        // Files too large for the cache are not cached
+        cache.insert("d", b"ddddddddd", generation);
+        assert_eq!(cache.get("d"), None);
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+
+        // Content read before an invalidation is not cached
+        cache.insert("a", b"old", generation);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c").as_deref(), Some(&b"cc"[..]));
+
+        let disabled = ReadCache::new(0);
+        disabled.insert("a", b"", disabled.generation());
+        assert_eq!(disabled.get("a"), None);
+    }
+}
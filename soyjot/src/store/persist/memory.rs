@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use futures_util::future::{self, BoxFuture};
+
+use super::Persist;
+use crate::store::compress::{self, CompressConfig};
+use crate::store::error::StoreError;
+use crate::store::index::{IndexEntry, META_SUFFIX};
+
+/// InMemoryFs keeps clipboard files in memory, compressed like `DiskFs` would write them.
+/// Only temporary files adopted with `Persist::adopt` are read from the filesystem.
+/// Metadata is kept like a file named with `index::META_SUFFIX`.
+#[derive(Debug, Default)]
+pub struct InMemoryFs {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryFs {
+    /// file returns the bytes of clipboard file `name` as written, e.g. still compressed
+    pub fn file(&self, name: &str) -> Option<Vec<u8>> {
+        self.lock().get(name).cloned()
+    }
+
+    /// insert writes clipboard file `name` with `data` as-is, e.g. to set up a test
+    pub fn insert(&self, name: &str, data: Vec<u8>) {
+        self.lock().insert(name.to_owned(), data);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.files.lock().expect("in-memory files lock poisoned")
+    }
+
+    fn not_found(name: &str) -> StoreError {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no in-memory file {name}"),
+        )
+        .into()
+    }
+
+    fn encoded(&self, name: &str) -> Result<Vec<u8>, StoreError> {
+        self.file(name).ok_or_else(|| Self::not_found(name))
+    }
+
+    fn write_now(
+        &self,
+        name: &str,
+        content: &[u8],
+        conf: &CompressConfig,
+    ) -> Result<(), StoreError> {
+        let data = compress::encode(content, conf)?.into_owned();
+        self.insert(name, data);
+
+        Ok(())
+    }
+
+    fn append_now(&self, name: &str, content: &[u8]) -> Result<(), StoreError> {
+        let data = self.encoded(name)?;
+
+        match compress::header(&data) {
+            Some(algo) => {
+                let mut data = compress::decode(data)?;
+                data.extend_from_slice(content);
+                self.write_now(name, &data, &CompressConfig { algo, threshold: 0 })
+            }
+
+            None => {
+                self.lock()
+                    .entry(name.to_owned())
+                    .or_default()
+                    .extend_from_slice(content);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn adopt_now(&self, tmp: PathBuf, name: &str) -> Result<(), StoreError> {
+        let data = std::fs::read(&tmp)?;
+        std::fs::remove_file(tmp)?;
+        self.insert(name, data);
+
+        Ok(())
+    }
+}
+
+impl Persist for InMemoryFs {
+    fn write<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+        conf: &'a CompressConfig,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(future::ready(self.write_now(name, content, conf)))
+    }
+
+    fn read<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<u8>, StoreError>> {
+        Box::pin(future::ready(self.encoded(name).and_then(compress::decode)))
+    }
+
+    fn append<'a>(
+        &'a self,
+        name: &'a str,
+        content: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(future::ready(self.append_now(name, content)))
+    }
+
+    fn remove<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(future::ready(self.remove_blocking(name)))
+    }
+
+    fn adopt<'a>(&'a self, tmp: PathBuf, name: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(future::ready(self.adopt_now(tmp, name)))
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.lock().contains_key(name)
+    }
+
+    fn size(&self, name: &str) -> Result<u64, StoreError> {
+        let data = self.encoded(name)?;
+        Ok(compress::decode(data)?.len() as u64)
+    }
+
+    fn remove_blocking(&self, name: &str) -> Result<(), StoreError> {
+        let mut files = self.lock();
+        files.remove(&format!("{name}{META_SUFFIX}"));
+        files
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Self::not_found(name))
+    }
+
+    fn write_meta<'a>(
+        &'a self,
+        name: &'a str,
+        meta: &'a IndexEntry,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        let written = serde_json::to_vec(meta)
+            .map(|data| self.insert(&format!("{name}{META_SUFFIX}"), data))
+            .map_err(StoreError::from);
+
+        Box::pin(future::ready(written))
+    }
+
+    fn read_meta(&self, name: &str) -> Result<Option<IndexEntry>, StoreError> {
+        match self.file(&format!("{name}{META_SUFFIX}")) {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::compress::Compression;
+
+    #[tokio::test]
+    async fn test_in_memory_fs() {
+        let fs = InMemoryFs::default();
+        let zstd = CompressConfig {
+            algo: Compression::Zstd,
+            threshold: 0,
+        };
+
+        fs.write("plain", b"foo", &CompressConfig::default())
+            .await
+            .unwrap();
+        fs.write("packed", b"foo", &zstd).await.unwrap();
+        assert_ne!(fs.file("packed").unwrap(), b"foo");
+
+        for name in ["plain", "packed"] {
+            fs.append(name, b"bar").await.unwrap();
+            assert_eq!(fs.read(name).await.unwrap(), b"foobar");
+            assert_eq!(fs.size(name).unwrap(), 6);
+        }
+        assert!(compress::header(&fs.file("packed").unwrap()).is_some());
+
+        fs.remove("plain").await.unwrap();
+        assert!(!fs.exists("plain"));
+        assert!(fs.read("plain").await.is_err());
+        assert!(fs.remove_blocking("plain").is_err());
+        assert!(fs.append("plain", b"bar").await.is_err());
+    }
+}
@@ -1,24 +1,35 @@
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use tokio::fs;
 
+use super::checksum;
+use super::compress::{self, CompressConfig, Compression};
 use super::error::StoreError;
+use super::index::IndexEntry;
+use super::persist::{self, file_path, DIR};
+use crate::hash::Hasher;
 
-// Default hard-coded storage directory.
-const DIR: &'static str = "./drop";
+// Prefix for files still being written, e.g. streamed uploads whose hash is not yet known.
+pub const TMP_PREFIX: &str = ".tmp-";
 
-pub async fn assert_dir(conf_dir: Option<String>) {
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Size of the chunks clipboard files are read in to compute their checksums
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub async fn assert_dir(conf_dir: Option<&Path>) {
     let dir = match conf_dir {
-        Some(s) if !s.is_empty() => s,
-        _ => DIR.to_string(),
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from(DIR),
     };
 
     let result = match dir_exists(&dir).await {
         Ok(false) => create_dir(&dir).await,
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => create_dir(&dir).await,
         Err(err) => {
-            panic!("bad directory: {}", err.to_string());
+            panic!("bad directory: {err}");
         }
         _ => Ok(()),
     };
@@ -34,40 +45,417 @@ where
     Ok(())
 }
 
-pub async fn write_clipboard_file<S>(name: S, content: &[u8]) -> Result<(), StoreError>
+/// write_clipboard_file writes `content` to clipboard file `name`,
+/// compressing it according to `conf` (see `compress::encode`).
+/// The file's shard directories are created if needed.
+/// The content is written to a temporary file that is then renamed into place,
+/// so that a crash mid-write never leaves a truncated clipboard file behind.
+/// With `fsync`, the temporary file is synced to disk before it's renamed.
+/// The checksum of `content` is written to the file's sidecar (see `checksum::sidecar`)
+/// once the file is in place. The old sidecar is removed first, so that a crash
+/// in between leaves an unverified file rather than one failing verification.
+pub async fn write_clipboard_file<S>(
+    dir: &Path,
+    name: S,
+    content: &[u8],
+    conf: &CompressConfig,
+    fsync: bool,
+) -> Result<(), StoreError>
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let path = file_path(dir, name);
+    create_parent_dir(&path).await?;
+    rm_sidecar(&path).await?;
+
+    write_atomic(dir, &path, &compress::encode(content, conf)?, fsync).await?;
+    let sum = checksum::checksum(content);
+    write_atomic(dir, &checksum::sidecar(&path), sum.as_bytes(), fsync).await
+}
+
+/// write_meta_file keeps `meta` with clipboard file `id` (see `persist::meta_path`)
+pub async fn write_meta_file<S>(
+    dir: &Path,
+    id: S,
+    meta: &IndexEntry,
+    fsync: bool,
+) -> Result<(), StoreError>
+where
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(name.as_ref());
-    fs::write(path, content).await?;
+    let path = persist::meta_path(&file_path(dir, id));
+    write_atomic(dir, &path, &serde_json::to_vec(meta)?, fsync).await
+}
+
+/// write_atomic writes `data` to a temporary file in `dir`, and then renames it to `path`
+async fn write_atomic(dir: &Path, path: &Path, data: &[u8], fsync: bool) -> Result<(), StoreError> {
+    use tokio::io::AsyncWriteExt;
+
+    let tmp = tmp_path(dir);
+    let written: Result<(), StoreError> = async {
+        let mut file = fs::File::create(&tmp).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+
+        if fsync {
+            file.sync_all().await?;
+        }
+
+        fs::rename(&tmp, path).await?;
+
+        Ok(())
+    }
+    .await;
+
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp).await;
+    }
+
+    written
+}
+
+/// write_file_checksum writes the checksum of uncompressed clipboard file `path`
+/// to its sidecar, reading the file in chunks
+async fn write_file_checksum(dir: &Path, path: &Path, fsync: bool) -> Result<(), StoreError> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = fs::File::open(path).await?;
+    let mut sum = checksum::Checksum::default();
+    let mut buf = vec![0; CHUNK_SIZE];
+
+    loop {
+        match file.read(&mut buf).await? {
+            0 => break,
+            n => sum.update(&buf[..n]),
+        }
+    }
+
+    write_atomic(
+        dir,
+        &checksum::sidecar(path),
+        sum.finalize().as_bytes(),
+        fsync,
+    )
+    .await
+}
+
+/// rm_sidecar removes the checksum file of clipboard file `path`, if there is one
+async fn rm_sidecar(path: &Path) -> Result<(), StoreError> {
+    match fs::remove_file(checksum::sidecar(path)).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// tmp_path returns a new, unique path for a temporary file in storage directory `dir`,
+/// which `persist::scan_dir` removes if it's left behind
+pub fn tmp_path(dir: &Path) -> PathBuf {
+    let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{TMP_PREFIX}{}-{n}", std::process::id()))
+}
+
+/// create_tmp_file creates a new, uniquely named file in storage directory `dir`.
+/// Callers write the clipboard into it and later move it into place with `rename_tmp_file`,
+/// or remove it with `rm_tmp_file` if the write fails.
+pub async fn create_tmp_file(dir: &Path) -> Result<(PathBuf, fs::File), StoreError> {
+    let path = tmp_path(dir);
+    let file = fs::File::create(&path).await?;
+
+    Ok((path, file))
+}
+
+/// rename_tmp_file moves temporary file `tmp` to clipboard file `name` as-is.
+/// With `fsync`, the temporary file is synced to disk first, like in `write_clipboard_file`.
+pub async fn rename_tmp_file<S>(
+    dir: &Path,
+    tmp: PathBuf,
+    name: S,
+    fsync: bool,
+) -> Result<(), StoreError>
+where
+    S: AsRef<str>,
+{
+    if fsync {
+        fs::File::open(&tmp).await?.sync_all().await?;
+    }
+
+    let path = file_path(dir, name);
+    create_parent_dir(&path).await?;
+    rm_sidecar(&path).await?;
+    fs::rename(tmp, &path).await?;
+
+    write_file_checksum(dir, &path, fsync).await
+}
+
+/// create_parent_dir creates the directories of clipboard file `path`,
+/// i.e. its shard directories (see `persist::file_path`)
+async fn create_parent_dir(path: &Path) -> Result<(), StoreError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn rm_tmp_file(tmp: PathBuf) -> Result<(), StoreError> {
+    fs::remove_file(tmp).await?;
 
     Ok(())
 }
 
-pub async fn read_clipboard_file<S>(id: S) -> Result<Vec<u8>, StoreError>
+/// join_tmp_files concatenates temporary files `parts` in order into a new temporary file
+/// in `dir`, hashing the content with `hasher`, and returns the new file with the hex-encoded
+/// digest and length of its content. The parts are removed once they are joined.
+/// If joining fails, the new file is removed and the parts are kept.
+pub async fn join_tmp_files(
+    dir: &Path,
+    parts: &[PathBuf],
+    mut hasher: Hasher,
+) -> Result<(PathBuf, String, u64), StoreError> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (tmp, mut file) = create_tmp_file(dir).await?;
+    let joined: Result<u64, StoreError> = async {
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut written = 0;
+
+        for part in parts {
+            let mut part = fs::File::open(part).await?;
+            loop {
+                match part.read(&mut buf).await? {
+                    0 => break,
+                    n => {
+                        hasher.update(&buf[..n]);
+                        file.write_all(&buf[..n]).await?;
+                        written += n as u64;
+                    }
+                }
+            }
+        }
+
+        file.flush().await?;
+
+        Ok(written)
+    }
+    .await;
+
+    let written = match joined {
+        Ok(written) => written,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(err);
+        }
+    };
+
+    for part in parts {
+        fs::remove_file(part).await?;
+    }
+
+    Ok((tmp, hasher.finalize(), written))
+}
+
+/// open_clipboard_file opens clipboard file `id` for reading,
+/// e.g. to stream it in chunks instead of reading it whole with `read_clipboard_file`.
+/// The file is not decompressed, see `clipboard_file_compression`.
+pub async fn open_clipboard_file<S>(dir: &Path, id: S) -> Result<fs::File, StoreError>
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(id.as_ref());
-    let data = fs::read(path).await?;
+    let path = file_path(dir, id);
+    let file = fs::File::open(path).await?;
+
+    Ok(file)
+}
+
+/// read_clipboard_file returns the decompressed content of clipboard file `id`,
+/// verified against the checksum in its sidecar. Files failing verification are removed
+/// with their sidecars, and `StoreError::Corrupt` is returned.
+pub async fn read_clipboard_file<S>(dir: &Path, id: S) -> Result<Vec<u8>, StoreError>
+where
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id);
+    let data = compress::decode(fs::read(&path).await?)?;
+
+    let expected = match fs::read(checksum::sidecar(&path)).await {
+        Ok(expected) => Some(expected),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    // Corrupt files are removed, so that they are never served
+    if let Err(err) = checksum::verify(&data, expected.as_deref()) {
+        rm_sidecar(&path).await?;
+        fs::remove_file(path).await?;
+
+        return Err(err);
+    }
 
     Ok(data)
 }
 
-pub async fn rm_clipboard_file<S>(id: S) -> Result<(), StoreError>
+/// verify_clipboard_file checks clipboard file `id` against the checksum in its sidecar
+/// like `read_clipboard_file` does, without keeping its content, e.g. before it's streamed.
+/// Uncompressed files are read in chunks, and compressed ones are decompressed in memory.
+/// Corrupt files are removed like with `read_clipboard_file`.
+/// It returns the checksum, or `None` for files without a sidecar.
+pub async fn verify_clipboard_file<S>(dir: &Path, id: S) -> Result<Option<String>, StoreError>
 where
-    S: AsRef<Path>,
+    S: AsRef<str>,
 {
-    let path = Path::new(DIR).join(id.as_ref());
-    fs::remove_file(path).await?;
+    use tokio::io::AsyncReadExt;
 
-    Ok(())
+    let path = file_path(dir, id);
+    let expected = match fs::read(checksum::sidecar(&path)).await {
+        Ok(expected) => String::from_utf8_lossy(expected.trim_ascii()).into_owned(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut file = fs::File::open(&path).await?;
+    let mut sum = checksum::Checksum::default();
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut n = 0;
+    while n < compress::HEADER_LEN {
+        match file.read(&mut buf[n..]).await? {
+            0 => break,
+            read => n += read,
+        }
+    }
+
+    if compress::header(&buf[..n]).is_some() {
+        let mut data = buf[..n].to_vec();
+        file.read_to_end(&mut data).await?;
+        sum.update(&compress::decode(data)?);
+    } else {
+        while n > 0 {
+            sum.update(&buf[..n]);
+            n = file.read(&mut buf).await?;
+        }
+    }
+
+    // Corrupt files are removed, so that they are never served
+    if sum.finalize() != expected {
+        rm_sidecar(&path).await?;
+        fs::remove_file(path).await?;
+
+        return Err(StoreError::Corrupt);
+    }
+
+    Ok(Some(expected))
+}
+
+/// clipboard_file_compression returns how clipboard file `id` is compressed,
+/// or `None` if it's stored as-is.
+pub async fn clipboard_file_compression<S>(
+    dir: &Path,
+    id: S,
+) -> Result<Option<Compression>, StoreError>
+where
+    S: AsRef<str>,
+{
+    let (compression, _) = open_compressed_file(dir, id).await?;
+
+    Ok(compression)
+}
+
+/// open_compressed_file opens clipboard file `id` like `open_clipboard_file`,
+/// and returns how it's compressed. The header of compressed files is skipped,
+/// so that the file can be served as-is with a `Content-Encoding`.
+pub async fn open_compressed_file<S>(
+    dir: &Path,
+    id: S,
+) -> Result<(Option<Compression>, fs::File), StoreError>
+where
+    S: AsRef<str>,
+{
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = open_clipboard_file(dir, id).await?;
+    let mut header = [0; compress::HEADER_LEN];
+    let mut n = 0;
+
+    while n < header.len() {
+        match file.read(&mut header[n..]).await? {
+            0 => break,
+            read => n += read,
+        }
+    }
+
+    let compression = compress::header(&header[..n]);
+    if compression.is_none() {
+        file.rewind().await?;
+    }
+
+    Ok((compression, file))
+}
+
+/// clipboard_file_len returns the size of clipboard file `id` in bytes
+pub async fn clipboard_file_len<S>(dir: &Path, id: S) -> Result<u64, StoreError>
+where
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id);
+    let metadata = fs::metadata(path).await?;
+
+    Ok(metadata.len())
+}
+
+/// append_clipboard_file appends `content` to the end of clipboard file `id`.
+/// Compressed files are rewritten whole, so that they always hold a single compressed stream
+/// that can be served as-is (see `open_compressed_file`).
+/// With `fsync`, the file is synced to disk after the append.
+pub async fn append_clipboard_file<S>(
+    dir: &Path,
+    id: S,
+    content: &[u8],
+    fsync: bool,
+) -> Result<(), StoreError>
+where
+    S: AsRef<str>,
+{
+    use tokio::io::AsyncWriteExt;
+
+    if let Some(algo) = clipboard_file_compression(dir, &id).await? {
+        let mut data = read_clipboard_file(dir, &id).await?;
+        data.extend_from_slice(content);
+
+        let conf = CompressConfig { algo, threshold: 0 };
+        return write_clipboard_file(dir, id, &data, &conf, fsync).await;
+    }
+
+    let path = file_path(dir, id);
+    rm_sidecar(&path).await?;
+    let mut file = fs::OpenOptions::new().append(true).open(&path).await?;
+
+    file.write_all(content).await?;
+    file.flush().await?;
+
+    if fsync {
+        file.sync_all().await?;
+    }
+
+    write_file_checksum(dir, &path, fsync).await
 }
 
-pub async fn dir_exists(dst: &str) -> std::io::Result<bool> {
-    let mut pwd = env::current_dir()?;
-    pwd.push(dst);
+pub async fn rm_clipboard_file<S>(dir: &Path, id: S) -> Result<(), StoreError>
+where
+    S: AsRef<str>,
+{
+    let path = file_path(dir, id);
+    fs::remove_file(&path).await?;
+
+    rm_sidecar(&path).await?;
+    match fs::remove_file(persist::meta_path(&path)).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
 
-    let metadata = fs::metadata(pwd).await?;
+pub async fn dir_exists<S>(dst: S) -> std::io::Result<bool>
+where
+    S: AsRef<Path>,
+{
+    let metadata = fs::metadata(env::current_dir()?.join(dst)).await?;
     Ok(metadata.is_dir())
 }
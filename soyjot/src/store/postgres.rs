@@ -0,0 +1,321 @@
+//! PostgreSQL clipboard backend, enabled with the `postgres` feature.
+//!
+//! Clipboards are kept in a single table with their content, metadata and expiry timestamp,
+//! so deployments can rely on an existing database instead of local disk.
+//! Expired rows are never returned, and are deleted by the sweeper (see `PgStore::spawn_sweeper`).
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+
+use super::clipboard::{self, Clipboard};
+use super::error::StoreError;
+use super::index::{self, IndexEntry};
+use super::{Collision, StoreConfig, StoreOpts};
+
+// Maximum number of pooled database connections
+const MAX_CONNECTIONS: u32 = 8;
+
+/// `expires_at` of pinned clipboards, which are never swept (see `StoreOpts::pin`)
+const PINNED: i64 = i64::MAX;
+
+const MIGRATION: &str = r#"
+CREATE TABLE IF NOT EXISTS clipboards (
+    hash        TEXT PRIMARY KEY,
+    digest      TEXT NOT NULL,
+    content     BYTEA NOT NULL,
+    created_at  BIGINT NOT NULL,
+    expires_at  BIGINT NOT NULL,
+    max_views   BIGINT,
+    views       BIGINT NOT NULL DEFAULT 0,
+    last_access BIGINT
+);
+
+ALTER TABLE clipboards ADD COLUMN IF NOT EXISTS content_type TEXT;
+ALTER TABLE clipboards ADD COLUMN IF NOT EXISTS encryption TEXT;
+
+CREATE INDEX IF NOT EXISTS clipboards_expires_at ON clipboards (expires_at);
+"#;
+
+/// PgStore stores clipboards in PostgreSQL. All clipboards are persisted,
+/// regardless of whether they were posted as `Clipboard::Mem` or `Clipboard::Persist`.
+pub struct PgStore {
+    pool: PgPool,
+    conf: StoreConfig,
+}
+
+impl PgStore {
+    /// connect connects to the database at `url` and creates the clipboard table
+    /// if it does not exist yet.
+    pub async fn connect(url: &str, conf: StoreConfig) -> Result<Self, StoreError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await?;
+
+        sqlx::raw_sql(MIGRATION).execute(&pool).await?;
+
+        Ok(Self { pool, conf })
+    }
+
+    /// store_new_clipboard inserts or replaces clipboard `hash`, expiring after `dur`
+    /// or the lifetime given by `StoreConfig::retention`.
+    /// Collisions with a live clipboard of different content are handled according to
+    /// `StoreConfig::on_collision` unless `StoreOpts::force` is set, like `Store::store_new_clipboard`.
+    pub async fn store_new_clipboard(
+        &self,
+        hash: &str,
+        digest: &str,
+        clipboard: &Clipboard,
+        dur: Duration,
+        opts: StoreOpts,
+    ) -> Result<(), StoreError> {
+        if let Some(max) = opts.max_size.filter(|max| clipboard.len() as u64 > *max) {
+            return Err(StoreError::TooLarge(max));
+        }
+
+        if let Clipboard::Redirect(_) = clipboard {
+            let err = "redirects with the postgres backend".to_string();
+            return Err(StoreError::NotImplemented(err));
+        }
+
+        if opts.available_at.is_some() {
+            let err = "available_at with the postgres backend".to_string();
+            return Err(StoreError::NotImplemented(err));
+        }
+
+        let now = now();
+        let overwrite = opts.force || self.conf.on_collision == Collision::Overwrite;
+        let content: &[u8] = clipboard.as_ref();
+        let persisted = matches!(clipboard, Clipboard::Persist(_));
+        let dur = self.conf.retention.ttl(persisted, opts.ttl, dur);
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO clipboards
+                (hash, digest, content, created_at, expires_at, max_views, content_type, encryption)
+            VALUES ($1, $2, $3, $4, $5, $6, $8, $9)
+            ON CONFLICT (hash) DO UPDATE SET
+                digest = EXCLUDED.digest,
+                content = EXCLUDED.content,
+                created_at = EXCLUDED.created_at,
+                expires_at = EXCLUDED.expires_at,
+                max_views = EXCLUDED.max_views,
+                content_type = EXCLUDED.content_type,
+                encryption = EXCLUDED.encryption,
+                views = 0,
+                last_access = NULL
+            WHERE $7 OR clipboards.digest = EXCLUDED.digest OR clipboards.expires_at <= $4
+            "#,
+        )
+        .bind(hash)
+        .bind(digest)
+        .bind(content)
+        .bind(now)
+        .bind(match opts.pin {
+            true => PINNED,
+            false => now.saturating_add(dur.as_secs() as i64),
+        })
+        .bind(opts.max_views.filter(|_| !opts.pin).map(|max| max as i64))
+        .bind(overwrite)
+        .bind(opts.content_type)
+        .bind(opts.encryption)
+        .execute(&self.pool)
+        .await?;
+
+        match result.rows_affected() {
+            0 => Err(StoreError::Conflict),
+            _ => Ok(()),
+        }
+    }
+
+    /// get_clipboard gets live clipboard `hash` and counts the view.
+    /// Clipboards with `StoreOpts::max_views` are deleted after their last view.
+    pub async fn get_clipboard(&self, hash: &str) -> Result<Option<Clipboard>, StoreError> {
+        let row = sqlx::query(
+            r#"
+            UPDATE clipboards SET views = views + 1, last_access = $2
+            WHERE hash = $1 AND expires_at > $2 AND (max_views IS NULL OR views < max_views)
+            RETURNING content, views, max_views
+            "#,
+        )
+        .bind(hash)
+        .bind(now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let content: Vec<u8> = row.try_get("content")?;
+        let views: i64 = row.try_get("views")?;
+        let max_views: Option<i64> = row.try_get("max_views")?;
+
+        if max_views.is_some_and(|max| views >= max) {
+            self.remove_clipboard(hash).await?;
+        }
+
+        Ok(Some(Clipboard::Persist(content.into())))
+    }
+
+    /// remove_clipboard deletes clipboard `hash` before it expires,
+    /// and reports whether there was such a clipboard.
+    pub async fn remove_clipboard(&self, hash: &str) -> Result<bool, StoreError> {
+        let result = sqlx::query("DELETE FROM clipboards WHERE hash = $1")
+            .bind(hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// index lists all live clipboards, like `Store::index`
+    pub async fn index(&self) -> Result<Vec<IndexEntry>, StoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT hash, digest, created_at, expires_at, octet_length(content) AS size,
+                max_views, content_type, encryption, views, last_access
+            FROM clipboards WHERE expires_at > $1
+            "#,
+        )
+        .bind(now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let expires_at = row.try_get::<i64, _>("expires_at")?;
+                let pinned = expires_at == PINNED;
+
+                Ok(IndexEntry {
+                    hash: row.try_get("hash")?,
+                    storage: clipboard::PERSIST.to_string(),
+                    expires_at: if pinned { 0 } else { expires_at as u64 },
+                    created_at: Some(row.try_get::<i64, _>("created_at")? as u64),
+                    size: row.try_get::<i32, _>("size")? as u64,
+                    digest: row.try_get("digest")?,
+                    max_views: row
+                        .try_get::<Option<i64>, _>("max_views")?
+                        .map(|max| max as u64),
+                    content_type: row.try_get("content_type")?,
+                    filename: None,
+                    lang: None,
+                    notify: None,
+                    owner: None,
+                    encryption: row.try_get("encryption")?,
+                    tags: Vec::new(),
+                    views: row.try_get::<i64, _>("views")? as u64,
+                    last_access: row
+                        .try_get::<Option<i64>, _>("last_access")?
+                        .map(|at| at as u64),
+                    pinned,
+                    available_at: None,
+                })
+            })
+            .collect()
+    }
+
+    /// sweep deletes expired clipboards, and returns how many were deleted
+    pub async fn sweep(&self) -> Result<u64, StoreError> {
+        let result = sqlx::query("DELETE FROM clipboards WHERE expires_at <= $1")
+            .bind(now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// spawn_sweeper spawns a background task that calls `sweep` every `every`
+    pub fn spawn_sweeper(store: Arc<Self>, every: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(err) = store.sweep().await {
+                    eprintln!("postgres: failed to sweep expired clipboards: {err}");
+                }
+            }
+        })
+    }
+}
+
+fn now() -> i64 {
+    index::to_timestamp(SystemTime::now()) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test is skipped unless this points to a scratch database
+    const DATABASE_URL_ENV: &str = "DROP_TEST_DATABASE_URL";
+
+    #[tokio::test]
+    async fn test_pg_store() {
+        let Ok(url) = std::env::var(DATABASE_URL_ENV) else {
+            eprintln!("skipping test_pg_store: {DATABASE_URL_ENV} is not set");
+            return;
+        };
+
+        let conf = StoreConfig {
+            on_collision: Collision::Reject,
+            ..StoreConfig::default()
+        };
+        let store = PgStore::connect(&url, conf).await.unwrap();
+        let dur = Duration::from_secs(60);
+
+        let clipboard = Clipboard::Mem("pg clipboard".into());
+        store
+            .store_new_clipboard("pg00", "pg00a", &clipboard, dur, StoreOpts::default())
+            .await
+            .unwrap();
+
+        let got = store.get_clipboard("pg00").await.unwrap().unwrap();
+        assert_eq!(got.as_ref() as &[u8], b"pg clipboard");
+
+        // Collisions are rejected unless forced
+        let other = Clipboard::Mem("other".into());
+        let result = store
+            .store_new_clipboard("pg00", "pg00b", &other, dur, StoreOpts::default())
+            .await;
+        assert!(matches!(result, Err(StoreError::Conflict)));
+
+        let forced = StoreOpts {
+            force: true,
+            max_views: Some(1),
+            ..StoreOpts::default()
+        };
+        store
+            .store_new_clipboard("pg00", "pg00b", &other, dur, forced)
+            .await
+            .unwrap();
+
+        assert!(store.get_clipboard("pg00").await.unwrap().is_some());
+        assert!(store.get_clipboard("pg00").await.unwrap().is_none());
+
+        // Expired clipboards are never returned, and are swept
+        store
+            .store_new_clipboard(
+                "pg01",
+                "pg01",
+                &clipboard,
+                Duration::ZERO,
+                StoreOpts::default(),
+            )
+            .await
+            .unwrap();
+        assert!(store.get_clipboard("pg01").await.unwrap().is_none());
+        assert!(store
+            .index()
+            .await
+            .unwrap()
+            .iter()
+            .all(|entry| entry.hash != "pg01"));
+        assert!(store.sweep().await.unwrap() >= 1);
+    }
+}
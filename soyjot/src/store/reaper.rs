@@ -0,0 +1,168 @@
+//! The reaper is a single task that owns the expiry timers of every entry in a `Store`,
+//! in a queue ordered by deadline. Entries are scheduled with `Timers::start` when inserted,
+//! and unscheduled with `Timers::stop` when replaced or removed before they expire,
+//! so resetting a clipboard's timer is a queue update instead of a task per clipboard.
+//! Entries posted with `StoreOpts::available_at` also get a release timer with
+//! `Timers::release`, in the same queue.
+//! The reaper sleeps until the earliest deadline with the store's `Clock`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::SystemTime;
+
+use tokio::sync::mpsc;
+
+use super::clock::Clock;
+use super::Store;
+
+/// Deadline is what happens to an entry when one of its timers fires
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Deadline {
+    /// The entry becomes available, see `Store::make_available`
+    Release,
+    /// The entry expires, see `Store::expire`
+    Expire,
+}
+
+enum Timer {
+    Start {
+        hash: String,
+        id: u64,
+        deadline: SystemTime,
+        kind: Deadline,
+    },
+    /// Stops every timer of the entry
+    Stop { id: u64 },
+}
+
+/// Timers sends timer updates to the reaper of a `Store`
+pub(super) struct Timers {
+    tx: mpsc::UnboundedSender<Timer>,
+    /// Taken by the first `Timers::start`, which spawns the reaper,
+    /// so that stores can be created outside of a Tokio runtime
+    rx: Mutex<Option<mpsc::UnboundedReceiver<Timer>>>,
+}
+
+impl Timers {
+    pub(super) fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Self {
+            tx,
+            rx: Mutex::new(Some(rx)),
+        }
+    }
+
+    /// start schedules entry `id` for `hash` of `store` to expire at `deadline`,
+    /// instead of when it was scheduled to, if it was
+    pub(super) fn start(&self, store: &Arc<Store>, hash: &str, id: u64, deadline: SystemTime) {
+        self.schedule(store, hash, id, deadline, Deadline::Expire);
+    }
+
+    /// release schedules entry `id` for `hash` of `store` to become available at `deadline`
+    pub(super) fn release(&self, store: &Arc<Store>, hash: &str, id: u64, deadline: SystemTime) {
+        self.schedule(store, hash, id, deadline, Deadline::Release);
+    }
+
+    fn schedule(
+        &self,
+        store: &Arc<Store>,
+        hash: &str,
+        id: u64,
+        deadline: SystemTime,
+        kind: Deadline,
+    ) {
+        let rx = self.rx.lock().expect("failed to lock reaper").take();
+        if let Some(rx) = rx {
+            let clock = store.time.clone();
+            tokio::task::spawn(reap(Arc::downgrade(store), clock, rx));
+        }
+
+        // The reaper only stops once the store is dropped
+        let _ = self.tx.send(Timer::Start {
+            hash: hash.to_owned(),
+            id,
+            deadline,
+            kind,
+        });
+    }
+
+    /// stop unschedules the timers of entry `id` that have not fired yet
+    pub(super) fn stop(&self, id: u64) {
+        let _ = self.tx.send(Timer::Stop { id });
+    }
+}
+
+/// reap expires entries of `store` as their deadlines pass on `clock`, until the store
+/// is dropped. The reaper only holds a weak reference, so that timers never keep a store alive.
+async fn reap(
+    store: Weak<Store>,
+    clock: Arc<dyn Clock>,
+    mut timers: mpsc::UnboundedReceiver<Timer>,
+) {
+    // Deadlines are unique with the entry ID and kind, which are needed to unschedule them
+    let mut queue: BTreeMap<(SystemTime, u64, Deadline), String> = BTreeMap::new();
+    let mut deadlines: HashMap<(u64, Deadline), SystemTime> = HashMap::new();
+
+    loop {
+        let next = queue
+            .first_key_value()
+            .map(|((deadline, _, _), _)| *deadline);
+        let due = async {
+            match next {
+                Some(deadline) => clock.sleep_until(deadline).await,
+                // Until the next timer is started
+                None => std::future::pending().await,
+            }
+        };
+
+        // Pending timer updates are applied first, so that a timer that was just reset
+        // never fires at its old deadline
+        tokio::select! {
+            biased;
+
+            timer = timers.recv() => match timer {
+                Some(Timer::Start { hash, id, deadline, kind }) => {
+                    if let Some(old) = deadlines.insert((id, kind), deadline) {
+                        queue.remove(&(old, id, kind));
+                    }
+                    queue.insert((deadline, id, kind), hash);
+                }
+
+                Some(Timer::Stop { id }) => {
+                    for kind in [Deadline::Release, Deadline::Expire] {
+                        if let Some(deadline) = deadlines.remove(&(id, kind)) {
+                            queue.remove(&(deadline, id, kind));
+                        }
+                    }
+                }
+
+                // The store was dropped
+                None => return,
+            },
+
+            () = due => {
+                let Some(((_, id, kind), hash)) = queue.pop_first() else {
+                    continue;
+                };
+                deadlines.remove(&(id, kind));
+
+                let Some(store) = store.upgrade() else {
+                    return;
+                };
+
+                if kind == Deadline::Release {
+                    store.make_available(&hash, id);
+                    continue;
+                }
+
+                // Persisted entries wait for in-flight reads, which must not hold up other timers
+                tokio::task::spawn(async move {
+                    if let Err(err) = store.expire(&hash, id).await {
+                        eprintln!("reaper: failed to expire {hash}: {err}");
+                    }
+                });
+            }
+        }
+    }
+}
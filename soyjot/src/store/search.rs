@@ -0,0 +1,82 @@
+use regex::bytes::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use super::error::StoreError;
+
+/// Number of clipboards scanned at a time by `Store::search`
+pub const CONCURRENCY: usize = 4;
+
+/// Number of match offsets reported for each clipboard, later matches are not reported
+pub const MAX_OFFSETS: usize = 100;
+
+/// Longest query accepted by `pattern`
+pub const QUERY_MAX_LEN: usize = 256;
+
+/// Compiled patterns may use at most this many bytes, so that queries cannot exhaust memory
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// SearchHit is a clipboard matching a search, see `Store::search`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SearchHit {
+    pub hash: String,
+    /// Byte offsets of the first `MAX_OFFSETS` matches, in ascending order
+    pub offsets: Vec<u64>,
+}
+
+/// pattern compiles query `q` into the pattern searched for, which is either a regular
+/// expression, or a substring matched literally
+pub fn pattern(q: &str, regex: bool) -> Result<Regex, StoreError> {
+    if q.is_empty() || q.len() > QUERY_MAX_LEN {
+        return Err(StoreError::InvalidQuery(format!(
+            "query must be 1 to {QUERY_MAX_LEN} bytes long"
+        )));
+    }
+
+    let q = match regex {
+        true => q.to_string(),
+        false => regex::escape(q),
+    };
+
+    RegexBuilder::new(&q)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|err| StoreError::InvalidQuery(err.to_string()))
+}
+
+/// scan returns the offsets of the first `MAX_OFFSETS` matches of `pattern` in `content`
+pub(super) fn scan(pattern: &Regex, content: &[u8]) -> Vec<u64> {
+    pattern
+        .find_iter(content)
+        .take(MAX_OFFSETS)
+        .map(|m| m.start() as u64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern() {
+        let literal = pattern("a.c", false).unwrap();
+        assert_eq!(scan(&literal, b"abc a.c a.c"), [4, 8]);
+
+        let regex = pattern("a.c", true).unwrap();
+        assert_eq!(scan(&regex, b"abc a.c"), [0, 4]);
+
+        let many = "x".repeat(MAX_OFFSETS * 2);
+        assert_eq!(scan(&literal, many.as_bytes()), Vec::<u64>::new());
+        assert_eq!(
+            scan(&pattern("x", false).unwrap(), many.as_bytes()).len(),
+            MAX_OFFSETS
+        );
+
+        for (q, regex) in [("", false), ("(unclosed", true), ("x{100000}", true)] {
+            assert!(matches!(
+                pattern(q, regex),
+                Err(StoreError::InvalidQuery(_))
+            ));
+        }
+        assert!(pattern(&"x".repeat(QUERY_MAX_LEN + 1), false).is_err());
+    }
+}
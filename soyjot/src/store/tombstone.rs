@@ -0,0 +1,79 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Number of recently expired clipboards remembered, older ones are forgotten
+pub(super) const TOMBSTONES_LEN: usize = 1024;
+
+#[derive(Default)]
+struct Graves {
+    /// Expiry timestamp of each remembered clipboard
+    expired_at: HashMap<String, u64>,
+    /// Remembered clipboards with their expiry timestamps, oldest first
+    order: VecDeque<(String, u64)>,
+}
+
+/// Tombstones remembers when the last `TOMBSTONES_LEN` expired clipboards expired,
+/// so that lookups can tell clipboards that expired from ones that never existed
+#[derive(Default)]
+pub(super) struct Tombstones {
+    graves: Mutex<Graves>,
+}
+
+impl Tombstones {
+    /// bury records that clipboard `hash` expired at `expired_at`, forgetting the oldest
+    /// tombstone if there are too many
+    pub(super) fn bury(&self, hash: &str, expired_at: u64) {
+        let mut graves = self.graves.lock().expect("tombstones lock poisoned");
+
+        graves.expired_at.insert(hash.to_owned(), expired_at);
+        graves.order.push_back((hash.to_owned(), expired_at));
+
+        while graves.order.len() > TOMBSTONES_LEN {
+            let Some((hash, expired_at)) = graves.order.pop_front() else {
+                break;
+            };
+
+            // A hash buried again since is only forgotten with its newest tombstone
+            if graves.expired_at.get(&hash) == Some(&expired_at) {
+                graves.expired_at.remove(&hash);
+            }
+        }
+    }
+
+    /// exhume forgets the tombstone of `hash`, e.g. when a clipboard is posted there again
+    pub(super) fn exhume(&self, hash: &str) {
+        let mut graves = self.graves.lock().expect("tombstones lock poisoned");
+        graves.expired_at.remove(hash);
+    }
+
+    /// expired_at returns when clipboard `hash` expired, if it's remembered
+    pub(super) fn expired_at(&self, hash: &str) -> Option<u64> {
+        let graves = self.graves.lock().expect("tombstones lock poisoned");
+        graves.expired_at.get(hash).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tombstones() {
+        let tombstones = Tombstones::default();
+        for n in 0..TOMBSTONES_LEN as u64 + 2 {
+            tombstones.bury(&format!("h{n}"), n);
+        }
+
+        assert_eq!(tombstones.expired_at("h0"), None);
+        assert_eq!(tombstones.expired_at("h2"), Some(2));
+        assert_eq!(tombstones.expired_at("nope"), None);
+
+        tombstones.exhume("h2");
+        assert_eq!(tombstones.expired_at("h2"), None);
+
+        // Reburied hashes are not forgotten with their older tombstones
+        tombstones.bury("h3", 5000);
+        tombstones.bury("other", 5001);
+        assert_eq!(tombstones.expired_at("h3"), Some(5000));
+    }
+}
@@ -0,0 +1,27 @@
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use super::clipboard::Clipboard;
+
+/// Version is a previous clipboard kept in an entry's history
+/// after a clipboard with different content was posted with the same hash.
+pub(super) struct Version {
+    pub(super) number: u64,
+    pub(super) clipboard: Clipboard,
+    pub(super) created_at: SystemTime,
+}
+
+/// VersionInfo describes a clipboard version without its content.
+#[derive(Serialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionInfo {
+    /// Version number, starting at 1 for the first clipboard posted with a hash
+    pub version: u64,
+    /// Size in bytes
+    pub size: u64,
+    /// Creation timestamp as seconds since the UNIX epoch
+    pub created_at: u64,
+    /// Whether this is the clipboard currently served for the hash
+    pub current: bool,
+}
@@ -0,0 +1,88 @@
+//! Tenants are teams sharing one server in isolation. Each tenant has its own keyspace
+//! in the `Store`: its clipboards are kept under `{tenant}/{hash}` keys, which clipboard IDs
+//! of the global routes must never match, so they are rejected if they contain `/`
+//! (see `is_global`), even percent-encoded. Tenant clipboards are persisted
+//! in a `{tenant}` subdirectory of the storage directory.
+//!
+//! Tenants are configured with `AppConfig::tenants`, each with the token its members
+//! authenticate with, and optionally its own quota (see `quota::Client::Tenant`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::quota::QuotaConfig;
+
+/// Separates the tenant from the hash in keys of tenant clipboards
+pub const SEPARATOR: char = '/';
+
+/// Longest tenant name accepted in `AppConfig::tenants`
+pub const NAME_MAX_LEN: usize = 32;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TenantConfig {
+    /// Bearer token required for the tenant's routes.
+    /// It is never serialized, so that it does not show up in logs.
+    #[serde(skip_serializing, default)]
+    pub token: String,
+    /// Limits on the tenant's live clipboards, which count against the tenant as a whole
+    /// instead of their client IPs. Tenants without a quota are not limited.
+    pub quota: Option<QuotaConfig>,
+}
+
+/// key returns the store key of clipboard `hash` in the keyspace of `tenant`
+pub fn key(tenant: &str, hash: &str) -> String {
+    format!("{tenant}{SEPARATOR}{hash}")
+}
+
+/// split splits a store key into its tenant, if any, and its hash
+pub fn split(key: &str) -> (Option<&str>, &str) {
+    match key.split_once(SEPARATOR) {
+        Some((tenant, hash)) => (Some(tenant), hash),
+        None => (None, key),
+    }
+}
+
+/// is_global reports whether `id` may be the ID of a clipboard on the global routes,
+/// which must not reach the keys of tenant clipboards
+pub fn is_global(id: &str) -> bool {
+    !id.contains(SEPARATOR)
+}
+
+/// same_keyspace reports whether store keys `a` and `b` belong to the same tenant,
+/// or are both global
+pub fn same_keyspace(a: &str, b: &str) -> bool {
+    split(a).0 == split(b).0
+}
+
+/// valid_name reports whether `name` can be used as a tenant name in URLs and file names:
+/// up to `NAME_MAX_LEN` lowercase ASCII letters, digits, `-` and `_`
+pub fn valid_name(name: &str) -> bool {
+    (1..=NAME_MAX_LEN).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyspace() {
+        let team = key("team-a", "abcd");
+        assert_eq!(team, "team-a/abcd");
+        assert_eq!(split(&team), (Some("team-a"), "abcd"));
+        assert_eq!(split("abcd"), (None, "abcd"));
+
+        assert!(same_keyspace(&team, "team-a/ffff"));
+        assert!(!same_keyspace(&team, "abcd"));
+        assert!(!same_keyspace(&team, "team-b/abcd"));
+
+        assert!(is_global("abcd"));
+        assert!(!is_global(&team));
+
+        assert!(valid_name("team_1"));
+        for name in ["", "Team", "../etc", "a/b", &"a".repeat(NAME_MAX_LEN + 1)] {
+            assert!(!valid_name(name), "{name}");
+        }
+    }
+}